@@ -40,7 +40,9 @@ pub struct SocketState {
 #[unsafe(no_mangle)]
 pub extern "C" fn turn_off() -> SocketState {
     let mut state = STATE.lock().unwrap();
-    block_on(state.turn_off());
+    if let Err(e) = block_on(state.turn_off()) {
+        eprintln!("{}", e);
+    }
     let r = block_on(state.get_data());
     SocketState {
         power: r.power,
@@ -52,7 +54,9 @@ pub extern "C" fn turn_off() -> SocketState {
 #[unsafe(no_mangle)]
 pub extern "C" fn turn_on() -> SocketState {
     let mut state = STATE.lock().unwrap();
-    block_on(state.turn_on());
+    if let Err(e) = block_on(state.turn_on()) {
+        eprintln!("{}", e);
+    }
     let r = block_on(state.get_data());
     SocketState {
         power: r.power,
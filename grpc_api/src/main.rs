@@ -6,17 +6,20 @@ pub mod smart_home_contracts {
     tonic::include_proto!("smart_home.v1");
 }
 
+mod persistence;
 mod repository;
 mod store;
 
 use std::process::Command;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use std::{env, panic};
 
 use healthcheck::healthcheck_service_server::{HealthcheckService, HealthcheckServiceServer};
 use healthcheck::{CheckRequest, CheckResponse};
 use repository::Repository;
 use sh_lib::rich_console::{TextColor, colored_println};
+use tokio_stream::wrappers::ReceiverStream;
 use tonic::transport::Server;
 use tonic::{Request, Response, Status};
 use tonic_web::GrpcWebLayer;
@@ -64,6 +67,19 @@ fn init_emulators() {
                 );
             })
             .expect("Не удалось запустить эмулятор розетки 127.0.0.1:3001"),
+        Command::new("cargo")
+            .env("SH_LIGHT_EMULATOR_PORT", "3002")
+            .arg("run")
+            .arg("--bin")
+            .arg("sh_light_emulator")
+            .spawn()
+            .inspect(|c| {
+                colored_println(
+                    &format!("Эмулятор светильника 127.0.0.1:3002. pid {}", c.id()),
+                    TextColor::Magenta,
+                );
+            })
+            .expect("Не удалось запустить эмулятор светильника 127.0.0.1:3002"),
     ];
 
     let emulators = Arc::new(Mutex::new(handlers));
@@ -85,6 +101,38 @@ fn init_emulators() {
     }));
 }
 
+/// Выбрать хранилище по API_STORE_BACKEND: memory, json или sqlite.
+/// Без явного выбора используется json, если задан API_STORE_PATH, иначе memory
+async fn open_store() -> store::Store {
+    let path = env::var("API_STORE_PATH").ok();
+    let backend = env::var("API_STORE_BACKEND").unwrap_or_else(|_| {
+        match path {
+            Some(_) => "json",
+            None => "memory",
+        }
+        .to_string()
+    });
+
+    let path = match (backend.as_str(), path) {
+        ("memory", _) => return store::Store::new(),
+        (_, Some(path)) => path,
+        (_, None) => panic!("API_STORE_PATH must be set for the {backend} store"),
+    };
+
+    info!("Store: {} {}", backend, path);
+
+    let opened = match backend.as_str() {
+        "json" => store::Store::open(persistence::JsonFileStorage::new(path)).await,
+        "sqlite" => match persistence::SqliteStorage::open(path) {
+            Ok(storage) => store::Store::open(storage).await,
+            Err(err) => Err(err),
+        },
+        _ => panic!("API_STORE_BACKEND must be one of memory, json, sqlite"),
+    };
+
+    opened.unwrap_or_else(|err| panic!("Failed to open the {backend} store: {err}"))
+}
+
 #[tokio::main]
 async fn main() {
     init_emulators();
@@ -98,7 +146,7 @@ async fn main() {
         .expect("API_SERVE_ADDR must be a valid address, e.g. 0.0.0.0:50051");
 
     let health_checker = HealthChecker {};
-    let smart_home = store::Store::new();
+    let smart_home = open_store().await;
 
     info!("Server listening on {}", addr);
 
@@ -180,6 +228,21 @@ impl smart_home_contracts::home_service_server::HomeService for store::Store {
         Ok(smart_home_contracts::ListHomesResponse { items: homes }.into())
     }
 
+    type WatchHomeStream = ReceiverStream<Result<smart_home_contracts::WatchHomeResponse, Status>>;
+
+    async fn watch_home(
+        &self,
+        request: Request<smart_home_contracts::WatchHomeRequest>,
+    ) -> Result<Response<Self::WatchHomeStream>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        match Repository::watch_home(self, req.home_id).await {
+            Ok(stream) => Ok(Response::new(stream)),
+            Err(err) => Err(err),
+        }
+    }
+
     async fn add_room(
         &self,
         request: Request<smart_home_contracts::AddRoomRequest>,
@@ -277,6 +340,103 @@ impl smart_home_contracts::home_service_server::HomeService for store::Store {
         Ok(smart_home_contracts::ListDevicesResponse { items: devices }.into())
     }
 
+    async fn control_device(
+        &self,
+        request: Request<smart_home_contracts::ControlDeviceRequest>,
+    ) -> Result<Response<smart_home_contracts::ControlDeviceResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        match Repository::control_device(
+            self,
+            &req.home_id,
+            &req.room_id,
+            &req.device_id,
+            req.command(),
+            req.value,
+        )
+        .await
+        {
+            Ok(item) => Ok(smart_home_contracts::ControlDeviceResponse { item: Some(item) }.into()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn list_unassigned_devices(
+        &self,
+        request: Request<smart_home_contracts::ListUnassignedDevicesRequest>,
+    ) -> Result<Response<smart_home_contracts::ListUnassignedDevicesResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        match Repository::list_unassigned_devices(self).await {
+            Ok(devices) => {
+                Ok(smart_home_contracts::ListUnassignedDevicesResponse { devices }.into())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn update_connection_settings(
+        &self,
+        request: Request<smart_home_contracts::UpdateConnectionSettingsRequest>,
+    ) -> Result<Response<smart_home_contracts::UpdateConnectionSettingsResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        let settings = if let Some(settings) = req.settings {
+            settings
+        } else {
+            return Err(Status::invalid_argument("Connection settings are required"));
+        };
+
+        match Repository::update_connection_settings(
+            self,
+            &req.home_id,
+            &req.room_id,
+            &req.device_id,
+            settings,
+        )
+        .await
+        {
+            Ok(item) => {
+                Ok(
+                    smart_home_contracts::UpdateConnectionSettingsResponse { item: Some(item) }
+                        .into(),
+                )
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn get_device_history(
+        &self,
+        request: Request<smart_home_contracts::GetDeviceHistoryRequest>,
+    ) -> Result<Response<smart_home_contracts::GetDeviceHistoryResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        let step = match req.step_ms {
+            0 => None,
+            step => Some(Duration::from_millis(step as u64)),
+        };
+
+        match Repository::get_device_history(
+            self,
+            &req.home_id,
+            &req.room_id,
+            &req.device_id,
+            req.from,
+            req.to,
+            step,
+        )
+        .await
+        {
+            Ok(history) => Ok(history.into()),
+            Err(err) => Err(err),
+        }
+    }
+
     async fn get_report(
         &self,
         request: Request<smart_home_contracts::GetReportRequest>,
@@ -284,6 +444,285 @@ impl smart_home_contracts::home_service_server::HomeService for store::Store {
         let req = request.into_inner();
         info!("Got a request: {req:?}");
 
-        todo!()
+        let items = match Repository::get_report(self, &req.home_id).await {
+            Ok(items) => items,
+            Err(err) => return Err(err),
+        };
+
+        Ok(smart_home_contracts::GetReportResponse { items }.into())
+    }
+
+    async fn get_energy_usage(
+        &self,
+        request: Request<smart_home_contracts::GetEnergyUsageRequest>,
+    ) -> Result<Response<smart_home_contracts::GetEnergyUsageResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        match Repository::get_energy_usage(self, &req.home_id, &req.room_id, &req.device_id).await {
+            Ok(usage) => Ok(usage.into()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn set_tariff(
+        &self,
+        request: Request<smart_home_contracts::SetTariffRequest>,
+    ) -> Result<Response<smart_home_contracts::SetTariffResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        match Repository::set_tariff(self, &req.home_id, req.tariff).await {
+            Ok(_) => Ok(smart_home_contracts::SetTariffResponse {}.into()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn get_tariff(
+        &self,
+        request: Request<smart_home_contracts::GetTariffRequest>,
+    ) -> Result<Response<smart_home_contracts::GetTariffResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        match Repository::get_tariff(self, &req.home_id).await {
+            Ok(tariff) => Ok(smart_home_contracts::GetTariffResponse { tariff }.into()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn get_energy_cost(
+        &self,
+        request: Request<smart_home_contracts::GetEnergyCostRequest>,
+    ) -> Result<Response<smart_home_contracts::GetEnergyCostResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        match Repository::get_energy_cost(
+            self,
+            &req.home_id,
+            &req.room_id,
+            &req.device_id,
+            req.from,
+            req.to,
+        )
+        .await
+        {
+            Ok(cost) => Ok(cost.into()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn create_rule(
+        &self,
+        request: Request<smart_home_contracts::CreateRuleRequest>,
+    ) -> Result<Response<smart_home_contracts::CreateRuleResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        match Repository::create_rule(self, &req.home_id, req.rule).await {
+            Ok(rule_id) => Ok(smart_home_contracts::CreateRuleResponse { rule_id }.into()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn update_rule(
+        &self,
+        request: Request<smart_home_contracts::UpdateRuleRequest>,
+    ) -> Result<Response<smart_home_contracts::UpdateRuleResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        match Repository::update_rule(self, &req.home_id, req.rule).await {
+            Ok(_) => Ok(smart_home_contracts::UpdateRuleResponse {}.into()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn delete_rule(
+        &self,
+        request: Request<smart_home_contracts::DeleteRuleRequest>,
+    ) -> Result<Response<smart_home_contracts::DeleteRuleResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        match Repository::delete_rule(self, &req.home_id, &req.rule_id).await {
+            Ok(_) => Ok(smart_home_contracts::DeleteRuleResponse {}.into()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn list_rules(
+        &self,
+        request: Request<smart_home_contracts::ListRulesRequest>,
+    ) -> Result<Response<smart_home_contracts::ListRulesResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        match Repository::list_rules(self, &req.home_id).await {
+            Ok(rules) => Ok(smart_home_contracts::ListRulesResponse { rules }.into()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn get_rule_log(
+        &self,
+        request: Request<smart_home_contracts::GetRuleLogRequest>,
+    ) -> Result<Response<smart_home_contracts::GetRuleLogResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        match Repository::get_rule_log(self, &req.home_id, &req.rule_id).await {
+            Ok(executions) => Ok(smart_home_contracts::GetRuleLogResponse { executions }.into()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn create_schedule(
+        &self,
+        request: Request<smart_home_contracts::CreateScheduleRequest>,
+    ) -> Result<Response<smart_home_contracts::CreateScheduleResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        match Repository::create_schedule(self, &req.home_id, req.schedule).await {
+            Ok(schedule_id) => {
+                Ok(smart_home_contracts::CreateScheduleResponse { schedule_id }.into())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn list_schedules(
+        &self,
+        request: Request<smart_home_contracts::ListSchedulesRequest>,
+    ) -> Result<Response<smart_home_contracts::ListSchedulesResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        match Repository::list_schedules(self, &req.home_id).await {
+            Ok(schedules) => Ok(smart_home_contracts::ListSchedulesResponse { schedules }.into()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn pause_schedule(
+        &self,
+        request: Request<smart_home_contracts::PauseScheduleRequest>,
+    ) -> Result<Response<smart_home_contracts::PauseScheduleResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        match Repository::pause_schedule(self, &req.home_id, &req.schedule_id, req.paused).await {
+            Ok(_) => Ok(smart_home_contracts::PauseScheduleResponse {}.into()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn delete_schedule(
+        &self,
+        request: Request<smart_home_contracts::DeleteScheduleRequest>,
+    ) -> Result<Response<smart_home_contracts::DeleteScheduleResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        match Repository::delete_schedule(self, &req.home_id, &req.schedule_id).await {
+            Ok(_) => Ok(smart_home_contracts::DeleteScheduleResponse {}.into()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn create_scene(
+        &self,
+        request: Request<smart_home_contracts::CreateSceneRequest>,
+    ) -> Result<Response<smart_home_contracts::CreateSceneResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        match Repository::create_scene(self, &req.home_id, req.scene).await {
+            Ok(scene_id) => Ok(smart_home_contracts::CreateSceneResponse { scene_id }.into()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn list_scenes(
+        &self,
+        request: Request<smart_home_contracts::ListScenesRequest>,
+    ) -> Result<Response<smart_home_contracts::ListScenesResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        match Repository::list_scenes(self, &req.home_id).await {
+            Ok(scenes) => Ok(smart_home_contracts::ListScenesResponse { scenes }.into()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn delete_scene(
+        &self,
+        request: Request<smart_home_contracts::DeleteSceneRequest>,
+    ) -> Result<Response<smart_home_contracts::DeleteSceneResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        match Repository::delete_scene(self, &req.home_id, &req.scene_id).await {
+            Ok(_) => Ok(smart_home_contracts::DeleteSceneResponse {}.into()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn apply_scene(
+        &self,
+        request: Request<smart_home_contracts::ApplySceneRequest>,
+    ) -> Result<Response<smart_home_contracts::ApplySceneResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        match Repository::apply_scene(self, &req.home_id, &req.scene_id).await {
+            Ok(results) => Ok(smart_home_contracts::ApplySceneResponse { results }.into()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn set_thermostat(
+        &self,
+        request: Request<smart_home_contracts::SetThermostatRequest>,
+    ) -> Result<Response<smart_home_contracts::SetThermostatResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        match Repository::set_thermostat(self, &req.home_id, &req.room_id, req.thermostat).await {
+            Ok(_) => Ok(smart_home_contracts::SetThermostatResponse {}.into()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn get_thermostat(
+        &self,
+        request: Request<smart_home_contracts::GetThermostatRequest>,
+    ) -> Result<Response<smart_home_contracts::GetThermostatResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        match Repository::get_thermostat(self, &req.home_id, &req.room_id).await {
+            Ok(status) => Ok(smart_home_contracts::GetThermostatResponse {
+                status: Some(status),
+            }
+            .into()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn delete_thermostat(
+        &self,
+        request: Request<smart_home_contracts::DeleteThermostatRequest>,
+    ) -> Result<Response<smart_home_contracts::DeleteThermostatResponse>, Status> {
+        let req = request.into_inner();
+        info!("Got a request: {req:?}");
+
+        match Repository::delete_thermostat(self, &req.home_id, &req.room_id).await {
+            Ok(_) => Ok(smart_home_contracts::DeleteThermostatResponse {}.into()),
+            Err(err) => Err(err),
+        }
     }
 }
@@ -0,0 +1,196 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use super::{Change, Snapshot, Storage, StorageError};
+
+/// Снимок в JSON-файле. Каждое изменение переписывает файл целиком: запись идёт
+/// во временный файл рядом с основным, который после fsync атомарно
+/// переименовывается поверх основного
+pub struct JsonFileStorage {
+    path: PathBuf,
+    /// Содержимое файла после последней успешной записи
+    snapshot: Mutex<Snapshot>,
+}
+
+impl JsonFileStorage {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            snapshot: Mutex::new(Snapshot::default()),
+        }
+    }
+
+    fn tmp_path(&self) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(".tmp");
+        self.path.with_file_name(name)
+    }
+}
+
+impl JsonFileStorage {
+    fn read(&self) -> Result<Snapshot, StorageError> {
+        // Временный файл остаётся только после сбоя посреди записи, основной при этом цел
+        let _ = fs::remove_file(self.tmp_path());
+
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Snapshot::default()),
+            Err(err) => return Err(err.into()),
+        };
+
+        serde_json::from_slice(&data)
+            .map_err(|err| StorageError::Backend(format!("{}: {}", self.path.display(), err)))
+    }
+
+    fn write(&self, snapshot: &Snapshot) -> io::Result<()> {
+        let tmp_path = self.tmp_path();
+        let data = serde_json::to_vec_pretty(snapshot)?;
+
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(&data)?;
+        file.sync_all()?;
+        drop(file);
+
+        fs::rename(&tmp_path, &self.path)?;
+        sync_dir(&self.path)
+    }
+}
+
+impl Storage for JsonFileStorage {
+    fn load(&self) -> Result<Snapshot, StorageError> {
+        let snapshot = self.read()?;
+        *self.snapshot.lock().unwrap() = snapshot.clone();
+
+        Ok(snapshot)
+    }
+
+    fn apply(&self, change: &Change) -> Result<(), StorageError> {
+        let mut current = self.snapshot.lock().unwrap();
+
+        let mut next = current.clone();
+        next.apply(change)?;
+        self.write(&next)?;
+        *current = next;
+
+        Ok(())
+    }
+}
+
+/// Зафиксировать переименование на диске
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => File::open(dir)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persistence::{DeviceKind, DeviceRecord, HomeRecord, RoomRecord};
+
+    fn temp_path(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("grpc_api-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("store.json")
+    }
+
+    fn home() -> HomeRecord {
+        HomeRecord {
+            id: "home".to_string(),
+            name: "Дом".to_string(),
+            tariff: None,
+            rules: vec![],
+            schedules: vec![],
+            scenes: vec![],
+            rooms: vec![RoomRecord {
+                id: "room".to_string(),
+                name: "Кухня".to_string(),
+                devices: vec![DeviceRecord {
+                    id: "device".to_string(),
+                    name: "Чайник".to_string(),
+                    kind: DeviceKind::Socket,
+                    connection: None,
+                }],
+                thermostat: None,
+            }],
+        }
+    }
+
+    fn snapshot() -> Snapshot {
+        Snapshot {
+            homes: vec![home()],
+            ..Snapshot::default()
+        }
+    }
+
+    #[test]
+    fn missing_file_is_empty() {
+        let path = temp_path("missing");
+
+        assert_eq!(
+            JsonFileStorage::new(&path).load().unwrap(),
+            Snapshot::default()
+        );
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn apply_and_load() {
+        let path = temp_path("roundtrip");
+        let storage = JsonFileStorage::new(&path);
+        storage.load().unwrap();
+
+        storage.apply(&Change::AddHome(&home())).unwrap();
+        assert!(!storage.tmp_path().exists());
+
+        // Отклонённое изменение не трогает файл
+        assert!(matches!(
+            storage.apply(&Change::AddHome(&home())),
+            Err(StorageError::AlreadyExists(_))
+        ));
+
+        assert_eq!(JsonFileStorage::new(&path).load().unwrap(), snapshot());
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn interrupted_write_keeps_previous_snapshot() {
+        let path = temp_path("interrupted");
+        let storage = JsonFileStorage::new(&path);
+        storage.apply(&Change::AddHome(&home())).unwrap();
+
+        // Сбой до переименования: во временном файле обрывок нового снимка
+        fs::write(storage.tmp_path(), b"{\"version\": 1, \"ho").unwrap();
+
+        assert_eq!(storage.load().unwrap(), snapshot());
+        assert!(!storage.tmp_path().exists());
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn corrupted_file_is_an_error() {
+        let path = temp_path("corrupted");
+        fs::write(&path, b"not json").unwrap();
+
+        assert!(matches!(
+            JsonFileStorage::new(&path).load(),
+            Err(StorageError::Backend(_))
+        ));
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
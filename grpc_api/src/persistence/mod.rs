@@ -0,0 +1,869 @@
+mod json_file;
+mod sqlite;
+
+pub use json_file::JsonFileStorage;
+pub use sqlite::SqliteStorage;
+
+use std::{collections::HashMap, fmt, io, net::IpAddr, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use sh_lib::{
+    automation::Rule,
+    scene::Scene,
+    schedule::Schedule,
+    smart_device::{
+        SmartDevice, SmartDeviceType, SmartLight, SmartSocket, SmartThermometer,
+        online::{ConnectionType, PollingSettings, UdpRoute},
+    },
+    smart_home::SmartHome,
+    smart_room::SmartRoom,
+    tariff::TariffPlan,
+    thermostat::ThermostatConfig,
+};
+use tracing::warn;
+
+/// Текущая версия формата снимка. Во второй версии появились светильники
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+/// Долговременное хранилище дерева домов
+pub trait Storage: Send + Sync {
+    /// Прочитать сохранённое дерево, пустое если сохранений ещё не было
+    fn load(&self) -> Result<Snapshot, StorageError>;
+
+    /// Применить изменение целиком или не применять вовсе.
+    /// Уникальность имён и наличие родительских записей проверяет хранилище
+    fn apply(&self, change: &Change) -> Result<(), StorageError>;
+}
+
+/// Ошибка хранилища
+#[derive(Debug)]
+pub enum StorageError {
+    /// Запись с таким идентификатором или именем уже есть
+    AlreadyExists(String),
+    /// Изменяемой записи или её родителя нет
+    NotFound(String),
+    /// Хранилище недоступно или повреждено
+    Backend(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::AlreadyExists(what) => write!(f, "{what} already exists"),
+            StorageError::NotFound(what) => write!(f, "{what} not found"),
+            StorageError::Backend(message) => write!(f, "storage error: {message}"),
+        }
+    }
+}
+
+impl From<io::Error> for StorageError {
+    fn from(err: io::Error) -> Self {
+        StorageError::Backend(err.to_string())
+    }
+}
+
+/// Изменение дерева домов
+#[derive(Debug)]
+pub enum Change<'a> {
+    /// Добавить дом вместе с его комнатами и устройствами
+    AddHome(&'a HomeRecord),
+    DeleteHome {
+        home_id: &'a str,
+    },
+    /// Добавить комнату вместе с её устройствами
+    AddRoom {
+        home_id: &'a str,
+        room: &'a RoomRecord,
+    },
+    DeleteRoom {
+        home_id: &'a str,
+        room_id: &'a str,
+    },
+    AddDevice {
+        home_id: &'a str,
+        room_id: &'a str,
+        device: &'a DeviceRecord,
+    },
+    DeleteDevice {
+        home_id: &'a str,
+        room_id: &'a str,
+        device_id: &'a str,
+    },
+    UpdateConnection {
+        home_id: &'a str,
+        room_id: &'a str,
+        device_id: &'a str,
+        connection: &'a ConnectionRecord,
+    },
+    /// Задать тариф дома, `None` - убрать
+    SetTariff {
+        home_id: &'a str,
+        tariff: Option<&'a TariffPlan>,
+    },
+    AddRule {
+        home_id: &'a str,
+        rule: &'a Rule,
+    },
+    /// Заменить правило с тем же id
+    UpdateRule {
+        home_id: &'a str,
+        rule: &'a Rule,
+    },
+    DeleteRule {
+        home_id: &'a str,
+        rule_id: &'a str,
+    },
+    AddSchedule {
+        home_id: &'a str,
+        schedule: &'a Schedule,
+    },
+    /// Заменить расписание с тем же id: пауза или отметка о запуске
+    UpdateSchedule {
+        home_id: &'a str,
+        schedule: &'a Schedule,
+    },
+    DeleteSchedule {
+        home_id: &'a str,
+        schedule_id: &'a str,
+    },
+    AddScene {
+        home_id: &'a str,
+        scene: &'a Scene,
+    },
+    DeleteScene {
+        home_id: &'a str,
+        scene_id: &'a str,
+    },
+    /// Задать термостат комнаты, `None` - убрать
+    SetThermostat {
+        home_id: &'a str,
+        room_id: &'a str,
+        thermostat: Option<&'a ThermostatConfig>,
+    },
+}
+
+/// Снимок конфигурации: дома, комнаты, устройства и параметры подключения
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub homes: Vec<HomeRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HomeRecord {
+    pub id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tariff: Option<TariffPlan>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<Rule>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schedules: Vec<Schedule>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scenes: Vec<Scene>,
+    pub rooms: Vec<RoomRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomRecord {
+    pub id: String,
+    pub name: String,
+    pub devices: Vec<DeviceRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thermostat: Option<ThermostatConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceRecord {
+    pub id: String,
+    pub name: String,
+    pub kind: DeviceKind,
+    pub connection: Option<ConnectionRecord>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceKind {
+    Socket,
+    Thermometer,
+    Light,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Service {
+    Tcp,
+    Udp,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionRecord {
+    pub service: Service,
+    pub ip: IpAddr,
+    pub port: u16,
+    /// Серийный номер термометра на общем UDP-порту
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial: Option<String>,
+    pub poll_interval_ms: u64,
+    pub command_timeout_ms: u64,
+    pub offline_threshold_ms: u64,
+}
+
+impl Default for Snapshot {
+    fn default() -> Self {
+        Self {
+            version: SNAPSHOT_VERSION,
+            homes: vec![],
+        }
+    }
+}
+
+impl Snapshot {
+    /// Применить изменение к снимку с теми же проверками, что и у базы данных
+    pub fn apply(&mut self, change: &Change) -> Result<(), StorageError> {
+        match *change {
+            Change::AddHome(home) => {
+                if self
+                    .homes
+                    .iter()
+                    .any(|h| h.id == home.id || h.name == home.name)
+                {
+                    return Err(StorageError::AlreadyExists(format!("Home {}", home.name)));
+                }
+                check_unique_rooms(home)?;
+                self.homes.push(home.clone());
+            }
+            Change::DeleteHome { home_id } => {
+                self.home(home_id)?;
+                self.homes.retain(|h| h.id != home_id);
+            }
+            Change::AddRoom { home_id, room } => {
+                let home = self.home(home_id)?;
+                if home
+                    .rooms
+                    .iter()
+                    .any(|r| r.id == room.id || r.name == room.name)
+                {
+                    return Err(StorageError::AlreadyExists(format!("Room {}", room.name)));
+                }
+                check_unique_devices(room)?;
+                home.rooms.push(room.clone());
+            }
+            Change::DeleteRoom { home_id, room_id } => {
+                let home = self.home(home_id)?;
+                room(home, room_id)?;
+                home.rooms.retain(|r| r.id != room_id);
+            }
+            Change::AddDevice {
+                home_id,
+                room_id,
+                device,
+            } => {
+                let room = room(self.home(home_id)?, room_id)?;
+                if room
+                    .devices
+                    .iter()
+                    .any(|d| d.id == device.id || d.name == device.name)
+                {
+                    return Err(StorageError::AlreadyExists(format!(
+                        "Device {}",
+                        device.name
+                    )));
+                }
+                room.devices.push(device.clone());
+            }
+            Change::DeleteDevice {
+                home_id,
+                room_id,
+                device_id,
+            } => {
+                let room = room(self.home(home_id)?, room_id)?;
+                device(room, device_id)?;
+                room.devices.retain(|d| d.id != device_id);
+            }
+            Change::UpdateConnection {
+                home_id,
+                room_id,
+                device_id,
+                connection,
+            } => {
+                let room = room(self.home(home_id)?, room_id)?;
+                let device = device(room, device_id)?;
+                if device.connection.is_none() {
+                    return Err(StorageError::NotFound(format!(
+                        "Connection of device {device_id}"
+                    )));
+                }
+                device.connection = Some(connection.clone());
+            }
+            Change::SetTariff { home_id, tariff } => {
+                self.home(home_id)?.tariff = tariff.cloned();
+            }
+            Change::AddRule { home_id, rule } => {
+                let home = self.home(home_id)?;
+                if home.rules.iter().any(|r| r.id == rule.id) {
+                    return Err(StorageError::AlreadyExists(format!("Rule {}", rule.name)));
+                }
+                home.rules.push(rule.clone());
+            }
+            Change::UpdateRule { home_id, rule } => {
+                let home = self.home(home_id)?;
+                match home.rules.iter_mut().find(|r| r.id == rule.id) {
+                    Some(stored) => *stored = rule.clone(),
+                    None => return Err(StorageError::NotFound(format!("Rule {}", rule.id))),
+                }
+            }
+            Change::DeleteRule { home_id, rule_id } => {
+                let home = self.home(home_id)?;
+                if !home.rules.iter().any(|r| r.id == rule_id) {
+                    return Err(StorageError::NotFound(format!("Rule {rule_id}")));
+                }
+                home.rules.retain(|r| r.id != rule_id);
+            }
+            Change::AddSchedule { home_id, schedule } => {
+                let home = self.home(home_id)?;
+                if home.schedules.iter().any(|s| s.id == schedule.id) {
+                    return Err(StorageError::AlreadyExists(format!(
+                        "Schedule {}",
+                        schedule.name
+                    )));
+                }
+                home.schedules.push(schedule.clone());
+            }
+            Change::UpdateSchedule { home_id, schedule } => {
+                let home = self.home(home_id)?;
+                match home.schedules.iter_mut().find(|s| s.id == schedule.id) {
+                    Some(stored) => *stored = schedule.clone(),
+                    None => {
+                        return Err(StorageError::NotFound(format!("Schedule {}", schedule.id)));
+                    }
+                }
+            }
+            Change::DeleteSchedule {
+                home_id,
+                schedule_id,
+            } => {
+                let home = self.home(home_id)?;
+                if !home.schedules.iter().any(|s| s.id == schedule_id) {
+                    return Err(StorageError::NotFound(format!("Schedule {schedule_id}")));
+                }
+                home.schedules.retain(|s| s.id != schedule_id);
+            }
+            Change::AddScene { home_id, scene } => {
+                let home = self.home(home_id)?;
+                if home.scenes.iter().any(|s| s.id == scene.id) {
+                    return Err(StorageError::AlreadyExists(format!("Scene {}", scene.name)));
+                }
+                home.scenes.push(scene.clone());
+            }
+            Change::DeleteScene { home_id, scene_id } => {
+                let home = self.home(home_id)?;
+                if !home.scenes.iter().any(|s| s.id == scene_id) {
+                    return Err(StorageError::NotFound(format!("Scene {scene_id}")));
+                }
+                home.scenes.retain(|s| s.id != scene_id);
+            }
+            Change::SetThermostat {
+                home_id,
+                room_id,
+                thermostat,
+            } => {
+                room(self.home(home_id)?, room_id)?.thermostat = thermostat.cloned();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn home(&mut self, home_id: &str) -> Result<&mut HomeRecord, StorageError> {
+        self.homes
+            .iter_mut()
+            .find(|h| h.id == home_id)
+            .ok_or_else(|| StorageError::NotFound(format!("Home {home_id}")))
+    }
+
+    /// Восстановить дерево домов. Устройства создаются неподключёнными
+    pub fn restore(self) -> Result<HashMap<String, SmartHome>, StorageError> {
+        if self.version > SNAPSHOT_VERSION {
+            return Err(StorageError::Backend(format!(
+                "snapshot version {} is newer than supported {}",
+                self.version, SNAPSHOT_VERSION
+            )));
+        }
+
+        let mut homes = HashMap::new();
+
+        for home_record in self.homes {
+            let mut home = SmartHome::new(&home_record.name);
+            check_id("home", &home_record.id, &home.get_id().to_string());
+            home.set_tariff(home_record.tariff);
+            for rule in home_record.rules {
+                home.automation().put(rule);
+            }
+            for schedule in home_record.schedules {
+                home.scheduler().put(schedule);
+            }
+            for scene in home_record.scenes {
+                home.put_scene(scene);
+            }
+
+            for room_record in home_record.rooms {
+                let mut room = SmartRoom::new(&room_record.name);
+                check_id("room", &room_record.id, &room.get_id().to_string());
+
+                for device_record in room_record.devices {
+                    let device = device_record.to_device();
+                    check_id("device", &device_record.id, &device.get_id().to_string());
+                    room.add_device(device);
+                }
+                room.set_thermostat(room_record.thermostat);
+
+                home.add_room(room);
+            }
+
+            homes.insert(home.get_id().to_string(), home);
+        }
+
+        Ok(homes)
+    }
+}
+
+impl DeviceRecord {
+    /// Создать устройство по записи
+    fn to_device(&self) -> SmartDeviceType {
+        let connection = self
+            .connection
+            .as_ref()
+            .map(ConnectionRecord::to_connection);
+
+        match (self.kind, connection) {
+            (DeviceKind::Socket, Some(c)) => {
+                SmartDeviceType::Socket(SmartSocket::new_with_connection(&self.name, 0.0, false, c))
+            }
+            (DeviceKind::Socket, None) => {
+                SmartDeviceType::Socket(SmartSocket::new(&self.name, 0.0, false))
+            }
+            (DeviceKind::Thermometer, Some(c)) => SmartDeviceType::Thermometer(
+                SmartThermometer::new_with_connection(&self.name, 0.0, c),
+            ),
+            (DeviceKind::Thermometer, None) => {
+                SmartDeviceType::Thermometer(SmartThermometer::new(&self.name, 0.0))
+            }
+            (DeviceKind::Light, Some(c)) => {
+                SmartDeviceType::Light(SmartLight::new_with_connection(&self.name, 0, false, c))
+            }
+            (DeviceKind::Light, None) => {
+                SmartDeviceType::Light(SmartLight::new(&self.name, 0, false))
+            }
+        }
+    }
+}
+
+impl DeviceKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeviceKind::Socket => "socket",
+            DeviceKind::Thermometer => "thermometer",
+            DeviceKind::Light => "light",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "socket" => Some(DeviceKind::Socket),
+            "thermometer" => Some(DeviceKind::Thermometer),
+            "light" => Some(DeviceKind::Light),
+            _ => None,
+        }
+    }
+}
+
+impl Service {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Service::Tcp => "tcp",
+            Service::Udp => "udp",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "tcp" => Some(Service::Tcp),
+            "udp" => Some(Service::Udp),
+            _ => None,
+        }
+    }
+}
+
+impl ConnectionRecord {
+    /// Снять параметры подключения устройства
+    pub fn capture(connection: &ConnectionType) -> Self {
+        let polling = connection.polling().get();
+        let addr = connection.get_addr();
+
+        let (service, serial) = match connection {
+            ConnectionType::Tcp { .. } => (Service::Tcp, None),
+            ConnectionType::Udp {
+                route: UdpRoute::Serial(serial),
+                ..
+            } => (Service::Udp, Some(serial.clone())),
+            ConnectionType::Udp { .. } => (Service::Udp, None),
+        };
+
+        Self {
+            service,
+            ip: addr.ip(),
+            port: addr.port(),
+            serial,
+            poll_interval_ms: polling.poll_interval.as_millis() as u64,
+            command_timeout_ms: polling.command_timeout.as_millis() as u64,
+            offline_threshold_ms: polling.offline_threshold.as_millis() as u64,
+        }
+    }
+
+    fn to_connection(&self) -> ConnectionType {
+        let polling = PollingSettings {
+            poll_interval: Duration::from_millis(self.poll_interval_ms),
+            command_timeout: Duration::from_millis(self.command_timeout_ms),
+            offline_threshold: Duration::from_millis(self.offline_threshold_ms),
+        };
+
+        match self.service {
+            Service::Tcp => ConnectionType::tcp(self.ip, self.port),
+            Service::Udp => {
+                ConnectionType::udp(self.ip, self.port).with_route(match &self.serial {
+                    Some(serial) => UdpRoute::Serial(serial.clone()),
+                    None => UdpRoute::Any,
+                })
+            }
+        }
+        .with_polling(polling)
+    }
+}
+
+/// Снять запись дома вместе с комнатами и устройствами
+pub fn home_record(home: &SmartHome) -> HomeRecord {
+    let mut rooms: Vec<RoomRecord> = home.get_rooms().values().map(room_record).collect();
+    rooms.sort_by(|a, b| (&a.name, &a.id).cmp(&(&b.name, &b.id)));
+
+    HomeRecord {
+        id: home.get_id().to_string(),
+        name: home.get_name().to_string(),
+        tariff: home.tariff().cloned(),
+        rules: home.automation().rules(),
+        schedules: home.scheduler().schedules(),
+        scenes: home.scenes().into_iter().cloned().collect(),
+        rooms,
+    }
+}
+
+/// Снять запись комнаты вместе с устройствами
+pub fn room_record(room: &SmartRoom) -> RoomRecord {
+    let mut devices: Vec<DeviceRecord> = room
+        .get_devices()
+        .iter()
+        .map(|(device_id, device)| device_record(device_id, device))
+        .collect();
+    devices.sort_by(|a, b| (&a.name, &a.id).cmp(&(&b.name, &b.id)));
+
+    RoomRecord {
+        id: room.get_id().to_string(),
+        name: room.get_name().to_string(),
+        devices,
+        thermostat: room
+            .thermostat()
+            .map(|thermostat| thermostat.config().clone()),
+    }
+}
+
+/// Снять запись устройства
+pub fn device_record(device_id: &str, device: &SmartDeviceType) -> DeviceRecord {
+    DeviceRecord {
+        id: device_id.to_string(),
+        name: device.get_name().to_string(),
+        kind: match device {
+            SmartDeviceType::Socket(_) => DeviceKind::Socket,
+            SmartDeviceType::Thermometer(_) => DeviceKind::Thermometer,
+            SmartDeviceType::Light(_) => DeviceKind::Light,
+        },
+        connection: device.get_connection().map(ConnectionRecord::capture),
+    }
+}
+
+fn room<'a>(home: &'a mut HomeRecord, room_id: &str) -> Result<&'a mut RoomRecord, StorageError> {
+    home.rooms
+        .iter_mut()
+        .find(|r| r.id == room_id)
+        .ok_or_else(|| StorageError::NotFound(format!("Room {room_id}")))
+}
+
+fn device<'a>(
+    room: &'a mut RoomRecord,
+    device_id: &str,
+) -> Result<&'a mut DeviceRecord, StorageError> {
+    room.devices
+        .iter_mut()
+        .find(|d| d.id == device_id)
+        .ok_or_else(|| StorageError::NotFound(format!("Device {device_id}")))
+}
+
+fn check_unique_rooms(home: &HomeRecord) -> Result<(), StorageError> {
+    for (index, room) in home.rooms.iter().enumerate() {
+        if home.rooms[..index]
+            .iter()
+            .any(|r| r.id == room.id || r.name == room.name)
+        {
+            return Err(StorageError::AlreadyExists(format!("Room {}", room.name)));
+        }
+        check_unique_devices(room)?;
+    }
+
+    Ok(())
+}
+
+fn check_unique_devices(room: &RoomRecord) -> Result<(), StorageError> {
+    for (index, device) in room.devices.iter().enumerate() {
+        if room.devices[..index]
+            .iter()
+            .any(|d| d.id == device.id || d.name == device.name)
+        {
+            return Err(StorageError::AlreadyExists(format!(
+                "Device {}",
+                device.name
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Идентификаторы выводятся из имён, расхождение означает правку файла вручную
+fn check_id(kind: &str, stored: &str, restored: &str) {
+    if stored != restored {
+        warn!("Restored {kind} id {restored} differs from stored {stored}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use sh_lib::{
+        automation::{Action, Comparison, DeviceRef, Metric, SocketCommand, Trigger},
+        id::Id,
+        scene::SceneState,
+        schedule::{ScheduleAction, Target, When},
+        tariff::Tariff,
+        thermostat::ThermostatMode,
+    };
+
+    use super::*;
+
+    fn home() -> SmartHome {
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let socket = SmartSocket::new_with_connection(
+            "Розетка",
+            0.0,
+            false,
+            ConnectionType::tcp(ip, 3001).with_polling(PollingSettings {
+                poll_interval: Duration::from_millis(500),
+                ..PollingSettings::default()
+            }),
+        );
+        let thermometer = SmartThermometer::new_with_connection(
+            "Термометр",
+            0.0,
+            ConnectionType::udp(ip, 4001).with_route(UdpRoute::Serial("T-1".to_string())),
+        );
+        let offline = SmartSocket::new("Чайник", 0.0, false);
+
+        let mut kitchen = SmartRoom::new("Кухня");
+        kitchen.add_device(socket);
+        kitchen.add_device(thermometer);
+        kitchen.add_device(offline);
+        kitchen.set_thermostat(Some(ThermostatConfig {
+            thermometer_id: Id::from_string("Термометр").to_string(),
+            socket_id: Id::from_string("Розетка").to_string(),
+            mode: ThermostatMode::Heat,
+            setpoint: 21.0,
+            hysteresis: 0.5,
+            min_on_ms: 60_000,
+            min_off_ms: 60_000,
+        }));
+
+        let mut home = SmartHome::new("Дом");
+        home.add_room(kitchen);
+        home.add_room(SmartRoom::new_with_devices(
+            "Спальня",
+            &[
+                SmartLight::new_with_connection("Ночник", 0, false, ConnectionType::tcp(ip, 3002))
+                    .into(),
+            ],
+        ));
+        home.set_tariff(Some(TariffPlan::new("RUB", Tariff::Flat { price: 6.5 })));
+
+        let kitchen = Id::from_string("Кухня");
+        home.automation().put(Rule::new(
+            "Перегрев",
+            Trigger {
+                device: DeviceRef::new(&kitchen, &Id::from_string("Термометр")),
+                metric: Metric::Temperature,
+                comparison: Comparison::Above,
+                threshold: 25.0,
+                hysteresis: 1.0,
+                hold_ms: 0,
+            },
+            vec![Action {
+                device: DeviceRef::new(&kitchen, &Id::from_string("Розетка")),
+                command: SocketCommand::TurnOff,
+            }],
+        ));
+        home.scheduler().put(
+            Schedule::new(
+                "Обогреватель утром",
+                When::Cron {
+                    expression: "30 6 * * MON-FRI".to_string(),
+                },
+                vec![ScheduleAction {
+                    target: Target::Room {
+                        room_id: kitchen.to_string(),
+                    },
+                    command: SocketCommand::TurnOn,
+                }],
+            )
+            .with_time_zone("Europe/Moscow"),
+        );
+        home.put_scene(Scene::new(
+            "Ухожу",
+            vec![SceneState {
+                device: DeviceRef::new(&kitchen, &Id::from_string("Розетка")),
+                is_on: false,
+            }],
+        ));
+        home
+    }
+
+    #[test]
+    fn capture_restore_roundtrip() {
+        let snapshot = Snapshot {
+            homes: vec![home_record(&home())],
+            ..Snapshot::default()
+        };
+        let restored = snapshot.clone().restore().unwrap();
+
+        assert_eq!(
+            restored.values().map(home_record).collect::<Vec<_>>(),
+            snapshot.homes
+        );
+
+        let kitchen = &snapshot.homes[0].rooms[0];
+        assert_eq!(kitchen.name, "Кухня");
+        assert_eq!(
+            kitchen
+                .devices
+                .iter()
+                .map(|d| d.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Розетка", "Термометр", "Чайник"]
+        );
+
+        let socket = kitchen.devices[0].connection.as_ref().unwrap();
+        assert_eq!(socket.service, Service::Tcp);
+        assert_eq!(socket.poll_interval_ms, 500);
+
+        let thermometer = kitchen.devices[1].connection.as_ref().unwrap();
+        assert_eq!(thermometer.service, Service::Udp);
+        assert_eq!(thermometer.serial.as_deref(), Some("T-1"));
+
+        assert!(kitchen.devices[2].connection.is_none());
+        assert_eq!(snapshot.homes[0].tariff.as_ref().unwrap().currency, "RUB");
+        assert_eq!(snapshot.homes[0].rules[0].name, "Перегрев");
+        assert_eq!(snapshot.homes[0].schedules[0].time_zone, "Europe/Moscow");
+        assert_eq!(snapshot.homes[0].scenes[0].name, "Ухожу");
+        assert_eq!(kitchen.thermostat.as_ref().unwrap().setpoint, 21.0);
+
+        let light = &snapshot.homes[0].rooms[1].devices[0];
+        assert_eq!(light.kind, DeviceKind::Light);
+        assert_eq!(light.connection.as_ref().unwrap().port, 3002);
+    }
+
+    #[test]
+    fn rejects_newer_version() {
+        let snapshot = Snapshot {
+            version: SNAPSHOT_VERSION + 1,
+            homes: vec![],
+        };
+
+        assert!(matches!(snapshot.restore(), Err(StorageError::Backend(_))));
+    }
+
+    #[test]
+    fn apply_checks_uniqueness_and_parents() {
+        let home = home_record(&home());
+        let mut snapshot = Snapshot::default();
+
+        snapshot.apply(&Change::AddHome(&home)).unwrap();
+        assert!(matches!(
+            snapshot.apply(&Change::AddHome(&home)),
+            Err(StorageError::AlreadyExists(_))
+        ));
+
+        let kitchen = &home.rooms[0];
+        assert!(matches!(
+            snapshot.apply(&Change::AddDevice {
+                home_id: &home.id,
+                room_id: &kitchen.id,
+                device: &kitchen.devices[0],
+            }),
+            Err(StorageError::AlreadyExists(_))
+        ));
+        assert!(matches!(
+            snapshot.apply(&Change::AddRoom {
+                home_id: "missing",
+                room: kitchen,
+            }),
+            Err(StorageError::NotFound(_))
+        ));
+
+        snapshot
+            .apply(&Change::DeleteRoom {
+                home_id: &home.id,
+                room_id: &kitchen.id,
+            })
+            .unwrap();
+        assert!(matches!(
+            snapshot.apply(&Change::DeleteDevice {
+                home_id: &home.id,
+                room_id: &kitchen.id,
+                device_id: &kitchen.devices[0].id,
+            }),
+            Err(StorageError::NotFound(_))
+        ));
+
+        let mut duplicated = kitchen.clone();
+        duplicated.devices.push(kitchen.devices[0].clone());
+        assert!(matches!(
+            snapshot.apply(&Change::AddRoom {
+                home_id: &home.id,
+                room: &duplicated,
+            }),
+            Err(StorageError::AlreadyExists(_))
+        ));
+        assert_eq!(snapshot.homes[0].rooms.len(), 1);
+
+        snapshot
+            .apply(&Change::SetTariff {
+                home_id: &home.id,
+                tariff: None,
+            })
+            .unwrap();
+        assert!(snapshot.homes[0].tariff.is_none());
+        assert!(matches!(
+            snapshot.apply(&Change::SetTariff {
+                home_id: "missing",
+                tariff: None,
+            }),
+            Err(StorageError::NotFound(_))
+        ));
+    }
+}
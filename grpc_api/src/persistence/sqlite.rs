@@ -0,0 +1,999 @@
+use std::{path::Path, sync::Mutex};
+
+use rusqlite::{Connection, ErrorCode, OptionalExtension, Transaction, params};
+use sh_lib::{
+    automation::Rule, scene::Scene, schedule::Schedule, tariff::TariffPlan,
+    thermostat::ThermostatConfig,
+};
+
+use super::{
+    Change, ConnectionRecord, DeviceKind, DeviceRecord, HomeRecord, RoomRecord, SNAPSHOT_VERSION,
+    Service, Snapshot, Storage, StorageError,
+};
+
+/// Схема базы. Идентификаторы комнат и устройств выводятся из имён,
+/// поэтому уникальны только в пределах родителя
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS homes (
+    id   TEXT PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS rooms (
+    home_id TEXT NOT NULL REFERENCES homes (id) ON DELETE CASCADE,
+    id      TEXT NOT NULL,
+    name    TEXT NOT NULL,
+    PRIMARY KEY (home_id, id),
+    UNIQUE (home_id, name)
+);
+
+CREATE TABLE IF NOT EXISTS devices (
+    home_id TEXT NOT NULL,
+    room_id TEXT NOT NULL,
+    id      TEXT NOT NULL,
+    name    TEXT NOT NULL,
+    kind    TEXT NOT NULL CHECK (kind IN ('socket', 'thermometer', 'light')),
+    PRIMARY KEY (home_id, room_id, id),
+    UNIQUE (home_id, room_id, name),
+    FOREIGN KEY (home_id, room_id) REFERENCES rooms (home_id, id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS connections (
+    home_id              TEXT NOT NULL,
+    room_id              TEXT NOT NULL,
+    device_id            TEXT NOT NULL,
+    service              TEXT NOT NULL CHECK (service IN ('tcp', 'udp')),
+    ip                   TEXT NOT NULL,
+    port                 INTEGER NOT NULL,
+    serial               TEXT,
+    poll_interval_ms     INTEGER NOT NULL,
+    command_timeout_ms   INTEGER NOT NULL,
+    offline_threshold_ms INTEGER NOT NULL,
+    PRIMARY KEY (home_id, room_id, device_id),
+    FOREIGN KEY (home_id, room_id, device_id)
+        REFERENCES devices (home_id, room_id, id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS tariffs (
+    home_id TEXT PRIMARY KEY REFERENCES homes (id) ON DELETE CASCADE,
+    plan    TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS rules (
+    home_id TEXT NOT NULL REFERENCES homes (id) ON DELETE CASCADE,
+    id      TEXT NOT NULL,
+    rule    TEXT NOT NULL,
+    PRIMARY KEY (home_id, id)
+);
+
+CREATE TABLE IF NOT EXISTS schedules (
+    home_id  TEXT NOT NULL REFERENCES homes (id) ON DELETE CASCADE,
+    id       TEXT NOT NULL,
+    schedule TEXT NOT NULL,
+    PRIMARY KEY (home_id, id)
+);
+
+CREATE TABLE IF NOT EXISTS scenes (
+    home_id TEXT NOT NULL REFERENCES homes (id) ON DELETE CASCADE,
+    id      TEXT NOT NULL,
+    scene   TEXT NOT NULL,
+    PRIMARY KEY (home_id, id)
+);
+
+CREATE TABLE IF NOT EXISTS thermostats (
+    home_id TEXT NOT NULL,
+    room_id TEXT NOT NULL,
+    config  TEXT NOT NULL,
+    PRIMARY KEY (home_id, room_id),
+    FOREIGN KEY (home_id, room_id) REFERENCES rooms (home_id, id) ON DELETE CASCADE
+);
+";
+
+/// Переход с первой версии: в таблицу устройств добавляются светильники.
+/// Ограничение CHECK в SQLite не меняется, поэтому таблица пересоздается
+/// при отключенных внешних ключах, чтобы не удалить подключения каскадом
+const MIGRATE_TO_V2: &str = "
+BEGIN;
+CREATE TABLE devices_v2 (
+    home_id TEXT NOT NULL,
+    room_id TEXT NOT NULL,
+    id      TEXT NOT NULL,
+    name    TEXT NOT NULL,
+    kind    TEXT NOT NULL CHECK (kind IN ('socket', 'thermometer', 'light')),
+    PRIMARY KEY (home_id, room_id, id),
+    UNIQUE (home_id, room_id, name),
+    FOREIGN KEY (home_id, room_id) REFERENCES rooms (home_id, id) ON DELETE CASCADE
+);
+INSERT INTO devices_v2 (home_id, room_id, id, name, kind)
+    SELECT home_id, room_id, id, name, kind FROM devices;
+DROP TABLE devices;
+ALTER TABLE devices_v2 RENAME TO devices;
+COMMIT;
+";
+
+/// Дерево домов во встроенной базе SQLite. Каждое изменение выполняется
+/// в отдельной транзакции, уникальность имён обеспечивают ограничения схемы
+pub struct SqliteStorage {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    /// Открыть базу, при необходимости создав файл и таблицы
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        let connection = Connection::open(path)?;
+
+        let version: u32 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version > SNAPSHOT_VERSION {
+            return Err(StorageError::Backend(format!(
+                "database version {} is newer than supported {}",
+                version, SNAPSHOT_VERSION
+            )));
+        }
+        if version == 1 {
+            // Иначе удаление старой таблицы каскадно удалит подключения устройств
+            connection.pragma_update(None, "foreign_keys", false)?;
+            connection.execute_batch(MIGRATE_TO_V2)?;
+        }
+
+        connection.pragma_update(None, "foreign_keys", true)?;
+        connection.execute_batch(SCHEMA)?;
+        connection.pragma_update(None, "user_version", SNAPSHOT_VERSION)?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn load(&self) -> Result<Snapshot, StorageError> {
+        let mut connection = self.connection.lock().unwrap();
+        let tx = connection.transaction()?;
+
+        let mut homes: Vec<HomeRecord> = tx
+            .prepare("SELECT id, name FROM homes ORDER BY name")?
+            .query_map([], |row| {
+                Ok(HomeRecord {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    tariff: None,
+                    rules: vec![],
+                    schedules: vec![],
+                    scenes: vec![],
+                    rooms: vec![],
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+
+        let mut tariffs = tx.prepare("SELECT plan FROM tariffs WHERE home_id = ?1")?;
+        let mut rules = tx.prepare("SELECT rule FROM rules WHERE home_id = ?1 ORDER BY id")?;
+        let mut schedules =
+            tx.prepare("SELECT schedule FROM schedules WHERE home_id = ?1 ORDER BY id")?;
+        let mut scenes = tx.prepare("SELECT scene FROM scenes WHERE home_id = ?1 ORDER BY id")?;
+
+        let mut rooms =
+            tx.prepare("SELECT id, name FROM rooms WHERE home_id = ?1 ORDER BY name")?;
+        let mut thermostats =
+            tx.prepare("SELECT config FROM thermostats WHERE home_id = ?1 AND room_id = ?2")?;
+        let mut devices = tx.prepare(
+            "SELECT d.id, d.name, d.kind, c.service, c.ip, c.port, c.serial,
+                    c.poll_interval_ms, c.command_timeout_ms, c.offline_threshold_ms
+             FROM devices d
+             LEFT JOIN connections c
+               ON c.home_id = d.home_id AND c.room_id = d.room_id AND c.device_id = d.id
+             WHERE d.home_id = ?1 AND d.room_id = ?2
+             ORDER BY d.name",
+        )?;
+
+        for home in &mut homes {
+            let plan: Option<String> =
+                tariffs.query_row([&home.id], |row| row.get(0)).optional()?;
+            home.tariff = plan
+                .map(|plan| serde_json::from_str(&plan))
+                .transpose()
+                .map_err(|err| StorageError::Backend(format!("invalid tariff: {err}")))?;
+
+            let stored: Vec<String> = rules
+                .query_map([&home.id], |row| row.get(0))?
+                .collect::<Result<_, _>>()?;
+            home.rules = stored
+                .iter()
+                .map(|rule| serde_json::from_str(rule))
+                .collect::<Result<_, _>>()
+                .map_err(|err| StorageError::Backend(format!("invalid rule: {err}")))?;
+
+            let stored: Vec<String> = schedules
+                .query_map([&home.id], |row| row.get(0))?
+                .collect::<Result<_, _>>()?;
+            home.schedules = stored
+                .iter()
+                .map(|schedule| serde_json::from_str(schedule))
+                .collect::<Result<_, _>>()
+                .map_err(|err| StorageError::Backend(format!("invalid schedule: {err}")))?;
+
+            let stored: Vec<String> = scenes
+                .query_map([&home.id], |row| row.get(0))?
+                .collect::<Result<_, _>>()?;
+            home.scenes = stored
+                .iter()
+                .map(|scene| serde_json::from_str(scene))
+                .collect::<Result<_, _>>()
+                .map_err(|err| StorageError::Backend(format!("invalid scene: {err}")))?;
+
+            home.rooms = rooms
+                .query_map([&home.id], |row| {
+                    Ok(RoomRecord {
+                        id: row.get(0)?,
+                        name: row.get(1)?,
+                        devices: vec![],
+                        thermostat: None,
+                    })
+                })?
+                .collect::<Result<_, _>>()?;
+
+            for room in &mut home.rooms {
+                let config: Option<String> = thermostats
+                    .query_row(params![home.id, room.id], |row| row.get(0))
+                    .optional()?;
+                room.thermostat = config
+                    .map(|config| serde_json::from_str(&config))
+                    .transpose()
+                    .map_err(|err| StorageError::Backend(format!("invalid thermostat: {err}")))?;
+
+                let mut rows = devices.query(params![home.id, room.id])?;
+
+                while let Some(row) = rows.next()? {
+                    let kind: String = row.get(2)?;
+                    let service: Option<String> = row.get(3)?;
+
+                    let connection = match service {
+                        Some(service) => {
+                            let ip: String = row.get(4)?;
+                            Some(ConnectionRecord {
+                                service: Service::parse(&service).ok_or_else(|| {
+                                    StorageError::Backend(format!("unknown service {service}"))
+                                })?,
+                                ip: ip.parse().map_err(|_| {
+                                    StorageError::Backend(format!("invalid address {ip}"))
+                                })?,
+                                port: row.get(5)?,
+                                serial: row.get(6)?,
+                                poll_interval_ms: row.get(7)?,
+                                command_timeout_ms: row.get(8)?,
+                                offline_threshold_ms: row.get(9)?,
+                            })
+                        }
+                        None => None,
+                    };
+
+                    room.devices.push(DeviceRecord {
+                        id: row.get(0)?,
+                        name: row.get(1)?,
+                        kind: DeviceKind::parse(&kind).ok_or_else(|| {
+                            StorageError::Backend(format!("unknown device kind {kind}"))
+                        })?,
+                        connection,
+                    });
+                }
+            }
+        }
+
+        Ok(Snapshot {
+            version: SNAPSHOT_VERSION,
+            homes,
+        })
+    }
+
+    fn apply(&self, change: &Change) -> Result<(), StorageError> {
+        let mut connection = self.connection.lock().unwrap();
+        // При ошибке транзакция откатывается в drop
+        let tx = connection.transaction()?;
+
+        match *change {
+            Change::AddHome(home) => insert_home(&tx, home)?,
+            Change::DeleteHome { home_id } => {
+                let deleted = tx.execute("DELETE FROM homes WHERE id = ?1", [home_id])?;
+                found(deleted, || format!("Home {home_id}"))?;
+            }
+            Change::AddRoom { home_id, room } => {
+                home_exists(&tx, home_id)?;
+                insert_room(&tx, home_id, room)?;
+            }
+            Change::DeleteRoom { home_id, room_id } => {
+                let deleted = tx.execute(
+                    "DELETE FROM rooms WHERE home_id = ?1 AND id = ?2",
+                    [home_id, room_id],
+                )?;
+                found(deleted, || format!("Room {room_id}"))?;
+            }
+            Change::AddDevice {
+                home_id,
+                room_id,
+                device,
+            } => {
+                room_exists(&tx, home_id, room_id)?;
+                insert_device(&tx, home_id, room_id, device)?;
+            }
+            Change::DeleteDevice {
+                home_id,
+                room_id,
+                device_id,
+            } => {
+                let deleted = tx.execute(
+                    "DELETE FROM devices WHERE home_id = ?1 AND room_id = ?2 AND id = ?3",
+                    [home_id, room_id, device_id],
+                )?;
+                found(deleted, || format!("Device {device_id}"))?;
+            }
+            Change::UpdateConnection {
+                home_id,
+                room_id,
+                device_id,
+                connection,
+            } => {
+                let updated = tx.execute(
+                    "UPDATE connections
+                     SET service = ?4, ip = ?5, port = ?6, serial = ?7, poll_interval_ms = ?8,
+                         command_timeout_ms = ?9, offline_threshold_ms = ?10
+                     WHERE home_id = ?1 AND room_id = ?2 AND device_id = ?3",
+                    params![
+                        home_id,
+                        room_id,
+                        device_id,
+                        connection.service.as_str(),
+                        connection.ip.to_string(),
+                        connection.port,
+                        connection.serial,
+                        connection.poll_interval_ms,
+                        connection.command_timeout_ms,
+                        connection.offline_threshold_ms,
+                    ],
+                )?;
+                found(updated, || format!("Connection of device {device_id}"))?;
+            }
+            Change::SetTariff { home_id, tariff } => {
+                home_exists(&tx, home_id)?;
+                tx.execute("DELETE FROM tariffs WHERE home_id = ?1", [home_id])?;
+                if let Some(tariff) = tariff {
+                    insert_tariff(&tx, home_id, tariff)?;
+                }
+            }
+            Change::AddRule { home_id, rule } => {
+                home_exists(&tx, home_id)?;
+                insert_rule(&tx, home_id, rule)?;
+            }
+            Change::UpdateRule { home_id, rule } => {
+                let updated = tx.execute(
+                    "UPDATE rules SET rule = ?3 WHERE home_id = ?1 AND id = ?2",
+                    [home_id, &rule.id, &rule_json(rule)?],
+                )?;
+                found(updated, || format!("Rule {}", rule.id))?;
+            }
+            Change::DeleteRule { home_id, rule_id } => {
+                let deleted = tx.execute(
+                    "DELETE FROM rules WHERE home_id = ?1 AND id = ?2",
+                    [home_id, rule_id],
+                )?;
+                found(deleted, || format!("Rule {rule_id}"))?;
+            }
+            Change::AddSchedule { home_id, schedule } => {
+                home_exists(&tx, home_id)?;
+                insert_schedule(&tx, home_id, schedule)?;
+            }
+            Change::UpdateSchedule { home_id, schedule } => {
+                let updated = tx.execute(
+                    "UPDATE schedules SET schedule = ?3 WHERE home_id = ?1 AND id = ?2",
+                    [home_id, &schedule.id, &schedule_json(schedule)?],
+                )?;
+                found(updated, || format!("Schedule {}", schedule.id))?;
+            }
+            Change::DeleteSchedule {
+                home_id,
+                schedule_id,
+            } => {
+                let deleted = tx.execute(
+                    "DELETE FROM schedules WHERE home_id = ?1 AND id = ?2",
+                    [home_id, schedule_id],
+                )?;
+                found(deleted, || format!("Schedule {schedule_id}"))?;
+            }
+            Change::AddScene { home_id, scene } => {
+                home_exists(&tx, home_id)?;
+                insert_scene(&tx, home_id, scene)?;
+            }
+            Change::DeleteScene { home_id, scene_id } => {
+                let deleted = tx.execute(
+                    "DELETE FROM scenes WHERE home_id = ?1 AND id = ?2",
+                    [home_id, scene_id],
+                )?;
+                found(deleted, || format!("Scene {scene_id}"))?;
+            }
+            Change::SetThermostat {
+                home_id,
+                room_id,
+                thermostat,
+            } => {
+                room_exists(&tx, home_id, room_id)?;
+                tx.execute(
+                    "DELETE FROM thermostats WHERE home_id = ?1 AND room_id = ?2",
+                    [home_id, room_id],
+                )?;
+                if let Some(thermostat) = thermostat {
+                    insert_thermostat(&tx, home_id, room_id, thermostat)?;
+                }
+            }
+        }
+
+        tx.commit()?;
+
+        Ok(())
+    }
+}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(err: rusqlite::Error) -> Self {
+        match err.sqlite_error_code() {
+            Some(ErrorCode::ConstraintViolation) => StorageError::AlreadyExists(err.to_string()),
+            _ => StorageError::Backend(err.to_string()),
+        }
+    }
+}
+
+fn found(rows: usize, what: impl FnOnce() -> String) -> Result<(), StorageError> {
+    if rows == 0 {
+        Err(StorageError::NotFound(what()))
+    } else {
+        Ok(())
+    }
+}
+
+/// Проверить родителя заранее, чтобы отличить его отсутствие от нарушения уникальности
+fn home_exists(tx: &Transaction, home_id: &str) -> Result<(), StorageError> {
+    let exists = tx
+        .query_row("SELECT 1 FROM homes WHERE id = ?1", [home_id], |_| Ok(()))
+        .optional()?;
+
+    found(exists.map_or(0, |_| 1), || format!("Home {home_id}"))
+}
+
+fn room_exists(tx: &Transaction, home_id: &str, room_id: &str) -> Result<(), StorageError> {
+    let exists = tx
+        .query_row(
+            "SELECT 1 FROM rooms WHERE home_id = ?1 AND id = ?2",
+            [home_id, room_id],
+            |_| Ok(()),
+        )
+        .optional()?;
+
+    found(exists.map_or(0, |_| 1), || format!("Room {room_id}"))
+}
+
+fn insert_home(tx: &Transaction, home: &HomeRecord) -> Result<(), StorageError> {
+    tx.execute(
+        "INSERT INTO homes (id, name) VALUES (?1, ?2)",
+        [&home.id, &home.name],
+    )?;
+
+    if let Some(tariff) = &home.tariff {
+        insert_tariff(tx, &home.id, tariff)?;
+    }
+
+    for rule in &home.rules {
+        insert_rule(tx, &home.id, rule)?;
+    }
+
+    for schedule in &home.schedules {
+        insert_schedule(tx, &home.id, schedule)?;
+    }
+
+    for scene in &home.scenes {
+        insert_scene(tx, &home.id, scene)?;
+    }
+
+    for room in &home.rooms {
+        insert_room(tx, &home.id, room)?;
+    }
+
+    Ok(())
+}
+
+/// Тариф хранится одной строкой JSON: его вид определяет набор полей
+fn insert_tariff(tx: &Transaction, home_id: &str, tariff: &TariffPlan) -> Result<(), StorageError> {
+    let plan =
+        serde_json::to_string(tariff).map_err(|err| StorageError::Backend(err.to_string()))?;
+    tx.execute(
+        "INSERT INTO tariffs (home_id, plan) VALUES (?1, ?2)",
+        [home_id, &plan],
+    )?;
+
+    Ok(())
+}
+
+/// Правило, как и тариф, хранится одной строкой JSON
+fn rule_json(rule: &Rule) -> Result<String, StorageError> {
+    serde_json::to_string(rule).map_err(|err| StorageError::Backend(err.to_string()))
+}
+
+fn insert_rule(tx: &Transaction, home_id: &str, rule: &Rule) -> Result<(), StorageError> {
+    tx.execute(
+        "INSERT INTO rules (home_id, id, rule) VALUES (?1, ?2, ?3)",
+        [home_id, &rule.id, &rule_json(rule)?],
+    )?;
+
+    Ok(())
+}
+
+fn schedule_json(schedule: &Schedule) -> Result<String, StorageError> {
+    serde_json::to_string(schedule).map_err(|err| StorageError::Backend(err.to_string()))
+}
+
+fn insert_schedule(
+    tx: &Transaction,
+    home_id: &str,
+    schedule: &Schedule,
+) -> Result<(), StorageError> {
+    tx.execute(
+        "INSERT INTO schedules (home_id, id, schedule) VALUES (?1, ?2, ?3)",
+        [home_id, &schedule.id, &schedule_json(schedule)?],
+    )?;
+
+    Ok(())
+}
+
+fn insert_scene(tx: &Transaction, home_id: &str, scene: &Scene) -> Result<(), StorageError> {
+    let json =
+        serde_json::to_string(scene).map_err(|err| StorageError::Backend(err.to_string()))?;
+    tx.execute(
+        "INSERT INTO scenes (home_id, id, scene) VALUES (?1, ?2, ?3)",
+        [home_id, &scene.id, &json],
+    )?;
+
+    Ok(())
+}
+
+fn insert_room(tx: &Transaction, home_id: &str, room: &RoomRecord) -> Result<(), StorageError> {
+    tx.execute(
+        "INSERT INTO rooms (home_id, id, name) VALUES (?1, ?2, ?3)",
+        [home_id, &room.id, &room.name],
+    )?;
+
+    for device in &room.devices {
+        insert_device(tx, home_id, &room.id, device)?;
+    }
+
+    if let Some(thermostat) = &room.thermostat {
+        insert_thermostat(tx, home_id, &room.id, thermostat)?;
+    }
+
+    Ok(())
+}
+
+fn insert_thermostat(
+    tx: &Transaction,
+    home_id: &str,
+    room_id: &str,
+    thermostat: &ThermostatConfig,
+) -> Result<(), StorageError> {
+    let json =
+        serde_json::to_string(thermostat).map_err(|err| StorageError::Backend(err.to_string()))?;
+    tx.execute(
+        "INSERT INTO thermostats (home_id, room_id, config) VALUES (?1, ?2, ?3)",
+        [home_id, room_id, &json],
+    )?;
+
+    Ok(())
+}
+
+fn insert_device(
+    tx: &Transaction,
+    home_id: &str,
+    room_id: &str,
+    device: &DeviceRecord,
+) -> Result<(), StorageError> {
+    tx.execute(
+        "INSERT INTO devices (home_id, room_id, id, name, kind) VALUES (?1, ?2, ?3, ?4, ?5)",
+        [
+            home_id,
+            room_id,
+            &device.id,
+            &device.name,
+            device.kind.as_str(),
+        ],
+    )?;
+
+    if let Some(connection) = &device.connection {
+        tx.execute(
+            "INSERT INTO connections (home_id, room_id, device_id, service, ip, port, serial,
+                                      poll_interval_ms, command_timeout_ms, offline_threshold_ms)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                home_id,
+                room_id,
+                device.id,
+                connection.service.as_str(),
+                connection.ip.to_string(),
+                connection.port,
+                connection.serial,
+                connection.poll_interval_ms,
+                connection.command_timeout_ms,
+                connection.offline_threshold_ms,
+            ],
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use sh_lib::{
+        automation::{Action, Comparison, DeviceRef, Metric, SocketCommand, Trigger},
+        scene::SceneState,
+        schedule::{ScheduleAction, Target, When},
+        tariff::{Tariff, TariffBand},
+        thermostat::ThermostatMode,
+    };
+
+    use super::*;
+
+    fn rule() -> Rule {
+        let socket = DeviceRef {
+            room_id: "room".to_string(),
+            device_id: "socket".to_string(),
+        };
+
+        Rule::new(
+            "Перегрузка",
+            Trigger {
+                device: socket.clone(),
+                metric: Metric::Power,
+                comparison: Comparison::Above,
+                threshold: 1800.0,
+                hysteresis: 100.0,
+                hold_ms: 30_000,
+            },
+            vec![Action {
+                device: socket,
+                command: SocketCommand::TurnOff,
+            }],
+        )
+    }
+
+    fn schedule() -> Schedule {
+        Schedule::new(
+            "Все выключить",
+            When::Cron {
+                expression: "0 23 * * *".to_string(),
+            },
+            vec![ScheduleAction {
+                target: Target::Home,
+                command: SocketCommand::TurnOff,
+            }],
+        )
+        .with_time_zone("Europe/Moscow")
+        .with_catch_up_ms(600_000)
+    }
+
+    fn scene() -> Scene {
+        Scene::new(
+            "Ухожу",
+            vec![SceneState {
+                device: DeviceRef {
+                    room_id: "room".to_string(),
+                    device_id: "socket".to_string(),
+                },
+                is_on: false,
+            }],
+        )
+    }
+
+    fn home() -> HomeRecord {
+        HomeRecord {
+            id: "home".to_string(),
+            name: "Дом".to_string(),
+            tariff: Some(TariffPlan::new(
+                "RUB",
+                Tariff::TimeOfUse {
+                    base_price: 5.0,
+                    bands: vec![TariffBand {
+                        start_minute: 23 * 60,
+                        end_minute: 7 * 60,
+                        price: 2.5,
+                    }],
+                },
+            )),
+            rules: vec![rule()],
+            schedules: vec![schedule()],
+            scenes: vec![scene()],
+            rooms: vec![RoomRecord {
+                id: "room".to_string(),
+                name: "Кухня".to_string(),
+                devices: vec![
+                    DeviceRecord {
+                        id: "socket".to_string(),
+                        name: "Розетка".to_string(),
+                        kind: DeviceKind::Socket,
+                        connection: Some(ConnectionRecord {
+                            service: Service::Tcp,
+                            ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                            port: 3001,
+                            serial: None,
+                            poll_interval_ms: 2000,
+                            command_timeout_ms: 5000,
+                            offline_threshold_ms: 10000,
+                        }),
+                    },
+                    DeviceRecord {
+                        id: "kettle".to_string(),
+                        name: "Чайник".to_string(),
+                        kind: DeviceKind::Thermometer,
+                        connection: None,
+                    },
+                ],
+                thermostat: Some(ThermostatConfig {
+                    thermometer_id: "kettle".to_string(),
+                    socket_id: "socket".to_string(),
+                    mode: ThermostatMode::Heat,
+                    setpoint: 21.0,
+                    hysteresis: 0.5,
+                    min_on_ms: 60_000,
+                    min_off_ms: 30_000,
+                }),
+            }],
+        }
+    }
+
+    fn count(storage: &SqliteStorage, table: &str) -> i64 {
+        storage
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+                row.get(0)
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn apply_and_load() {
+        let storage = SqliteStorage::open(":memory:").unwrap();
+        storage.apply(&Change::AddHome(&home())).unwrap();
+
+        let snapshot = storage.load().unwrap();
+        assert_eq!(snapshot.homes, vec![home()]);
+
+        let mut connection = home().rooms[0].devices[0].connection.clone().unwrap();
+        connection.poll_interval_ms = 500;
+        storage
+            .apply(&Change::UpdateConnection {
+                home_id: "home",
+                room_id: "room",
+                device_id: "socket",
+                connection: &connection,
+            })
+            .unwrap();
+
+        let snapshot = storage.load().unwrap();
+        assert_eq!(
+            snapshot.homes[0].rooms[0].devices[0].connection,
+            Some(connection)
+        );
+
+        storage
+            .apply(&Change::SetTariff {
+                home_id: "home",
+                tariff: None,
+            })
+            .unwrap();
+        assert!(storage.load().unwrap().homes[0].tariff.is_none());
+
+        let disabled = Rule {
+            enabled: false,
+            ..rule()
+        };
+        storage
+            .apply(&Change::UpdateRule {
+                home_id: "home",
+                rule: &disabled,
+            })
+            .unwrap();
+        assert_eq!(storage.load().unwrap().homes[0].rules, vec![disabled]);
+
+        let mut paused = schedule();
+        paused.set_paused(true, 1000);
+        storage
+            .apply(&Change::UpdateSchedule {
+                home_id: "home",
+                schedule: &paused,
+            })
+            .unwrap();
+        assert_eq!(storage.load().unwrap().homes[0].schedules, vec![paused]);
+
+        storage
+            .apply(&Change::SetThermostat {
+                home_id: "home",
+                room_id: "room",
+                thermostat: None,
+            })
+            .unwrap();
+        assert!(
+            storage.load().unwrap().homes[0].rooms[0]
+                .thermostat
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn constraints() {
+        let storage = SqliteStorage::open(":memory:").unwrap();
+        storage.apply(&Change::AddHome(&home())).unwrap();
+
+        assert!(matches!(
+            storage.apply(&Change::AddHome(&HomeRecord {
+                id: "other".to_string(),
+                ..home()
+            })),
+            Err(StorageError::AlreadyExists(_))
+        ));
+        assert!(matches!(
+            storage.apply(&Change::AddDevice {
+                home_id: "home",
+                room_id: "room",
+                device: &DeviceRecord {
+                    id: "other".to_string(),
+                    ..home().rooms[0].devices[1].clone()
+                },
+            }),
+            Err(StorageError::AlreadyExists(_))
+        ));
+        assert!(matches!(
+            storage.apply(&Change::AddRoom {
+                home_id: "missing",
+                room: &home().rooms[0],
+            }),
+            Err(StorageError::NotFound(_))
+        ));
+        assert!(matches!(
+            storage.apply(&Change::UpdateConnection {
+                home_id: "home",
+                room_id: "room",
+                device_id: "kettle",
+                connection: home().rooms[0].devices[0].connection.as_ref().unwrap(),
+            }),
+            Err(StorageError::NotFound(_))
+        ));
+        assert!(matches!(
+            storage.apply(&Change::AddRule {
+                home_id: "home",
+                rule: &rule(),
+            }),
+            Err(StorageError::AlreadyExists(_))
+        ));
+        assert!(matches!(
+            storage.apply(&Change::DeleteRule {
+                home_id: "home",
+                rule_id: "missing",
+            }),
+            Err(StorageError::NotFound(_))
+        ));
+        assert!(matches!(
+            storage.apply(&Change::AddSchedule {
+                home_id: "home",
+                schedule: &schedule(),
+            }),
+            Err(StorageError::AlreadyExists(_))
+        ));
+        assert!(matches!(
+            storage.apply(&Change::DeleteSchedule {
+                home_id: "home",
+                schedule_id: "missing",
+            }),
+            Err(StorageError::NotFound(_))
+        ));
+        assert!(matches!(
+            storage.apply(&Change::AddScene {
+                home_id: "home",
+                scene: &scene(),
+            }),
+            Err(StorageError::AlreadyExists(_))
+        ));
+        assert!(matches!(
+            storage.apply(&Change::SetThermostat {
+                home_id: "home",
+                room_id: "missing",
+                thermostat: home().rooms[0].thermostat.as_ref(),
+            }),
+            Err(StorageError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn failed_change_is_rolled_back() {
+        let storage = SqliteStorage::open(":memory:").unwrap();
+
+        // Второе устройство с тем же именем ломает вставку посреди транзакции
+        let mut home = home();
+        home.rooms[0].devices[1].name = "Розетка".to_string();
+
+        assert!(matches!(
+            storage.apply(&Change::AddHome(&home)),
+            Err(StorageError::AlreadyExists(_))
+        ));
+        assert_eq!(count(&storage, "homes"), 0);
+        assert_eq!(count(&storage, "devices"), 0);
+    }
+
+    #[test]
+    fn delete_cascades() {
+        let storage = SqliteStorage::open(":memory:").unwrap();
+        storage.apply(&Change::AddHome(&home())).unwrap();
+
+        storage
+            .apply(&Change::DeleteHome { home_id: "home" })
+            .unwrap();
+
+        for table in [
+            "homes",
+            "rooms",
+            "devices",
+            "connections",
+            "tariffs",
+            "rules",
+            "schedules",
+            "scenes",
+            "thermostats",
+        ] {
+            assert_eq!(count(&storage, table), 0, "{table}");
+        }
+        assert!(matches!(
+            storage.apply(&Change::DeleteHome { home_id: "home" }),
+            Err(StorageError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn migrates_devices_from_v1() {
+        let path =
+            std::env::temp_dir().join(format!("grpc_api-sqlite-v1-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        // База первой версии: светильников в таблице устройств нет
+        let v1 = Connection::open(&path).unwrap();
+        v1.execute_batch(&SCHEMA.replace(", 'light'", "")).unwrap();
+        v1.execute_batch(
+            "INSERT INTO homes VALUES ('home', 'Дом');
+             INSERT INTO rooms VALUES ('home', 'room', 'Кухня');
+             INSERT INTO devices VALUES ('home', 'room', 'socket', 'Розетка', 'socket');
+             INSERT INTO connections VALUES
+                 ('home', 'room', 'socket', 'tcp', '127.0.0.1', 3001, NULL, 2000, 5000, 10000);
+             PRAGMA user_version = 1;",
+        )
+        .unwrap();
+        drop(v1);
+
+        let storage = SqliteStorage::open(&path).unwrap();
+        let light = DeviceRecord {
+            id: "light".to_string(),
+            name: "Люстра".to_string(),
+            kind: DeviceKind::Light,
+            connection: None,
+        };
+        storage
+            .apply(&Change::AddDevice {
+                home_id: "home",
+                room_id: "room",
+                device: &light,
+            })
+            .unwrap();
+
+        let devices = &storage.load().unwrap().homes[0].rooms[0].devices;
+        assert_eq!(devices[0], light);
+        assert_eq!(devices[1].connection.as_ref().unwrap().port, 3001);
+        assert_eq!(count(&storage, "connections"), 1);
+
+        drop(storage);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,38 +1,10 @@
+use std::time::Duration;
+
+use tokio_stream::wrappers::ReceiverStream;
 use tonic::Status;
 
 use super::smart_home_contracts;
 
-// #[derive(Debug)]
-// pub struct Connection {
-//     pub ip: String,
-//     pub port: String,
-//     pub service: String,
-// }
-
-// pub enum DeviceData {
-//     Socket(SocketData),
-//     Thermometer(ThermometerData),
-// }
-
-// pub enum ItemType {
-//     Home,
-//     Room,
-//     Socket,
-//     Thermo,
-// }
-
-// pub struct ReportItem {
-//     pub id: String,
-//     pub name: String,
-//     pub item_type: ItemType,
-//     pub device_connection: Option<Connection>,
-//     pub device_data: Option<DeviceData>,
-// }
-
-// pub struct Report {
-//     pub items: Vec<ReportItem>,
-// }
-
 pub trait Repository {
     async fn add_home(&self, name: impl Into<String>) -> Result<String, Status>;
     async fn delete_home(&self, home_id: impl Into<String>) -> Result<(), Status>;
@@ -72,4 +44,166 @@ pub trait Repository {
         home_id: impl Into<String>,
         room_id: impl Into<String>,
     ) -> Result<Vec<smart_home_contracts::Item>, Status>;
+    async fn control_device(
+        &self,
+        home_id: impl Into<String>,
+        room_id: impl Into<String>,
+        device_id: impl Into<String>,
+        command: smart_home_contracts::DeviceCommand,
+        value: u32,
+    ) -> Result<smart_home_contracts::Item, Status>;
+    async fn list_unassigned_devices(
+        &self,
+    ) -> Result<Vec<smart_home_contracts::UnassignedDevice>, Status>;
+    async fn update_connection_settings(
+        &self,
+        home_id: impl Into<String>,
+        room_id: impl Into<String>,
+        device_id: impl Into<String>,
+        settings: smart_home_contracts::ConnectionSettings,
+    ) -> Result<smart_home_contracts::Item, Status>;
+
+    /// Показания устройства за интервал `[from, to)`, `to = 0` - до текущего момента
+    async fn get_device_history(
+        &self,
+        home_id: impl Into<String>,
+        room_id: impl Into<String>,
+        device_id: impl Into<String>,
+        from: u64,
+        to: u64,
+        step: Option<Duration>,
+    ) -> Result<smart_home_contracts::GetDeviceHistoryResponse, Status>;
+
+    /// Поток событий дома, завершается при удалении дома
+    async fn watch_home(
+        &self,
+        home_id: impl Into<String>,
+    ) -> Result<ReceiverStream<Result<smart_home_contracts::WatchHomeResponse, Status>>, Status>;
+
+    async fn get_report(
+        &self,
+        home_id: impl Into<String>,
+    ) -> Result<Vec<smart_home_contracts::Item>, Status>;
+
+    /// Потребление дома, комнаты (`device_id` пуст) или розетки
+    async fn get_energy_usage(
+        &self,
+        home_id: impl Into<String>,
+        room_id: impl Into<String>,
+        device_id: impl Into<String>,
+    ) -> Result<smart_home_contracts::GetEnergyUsageResponse, Status>;
+
+    /// Задать тариф дома, `None` - убрать
+    async fn set_tariff(
+        &self,
+        home_id: impl Into<String>,
+        tariff: Option<smart_home_contracts::Tariff>,
+    ) -> Result<(), Status>;
+    async fn get_tariff(
+        &self,
+        home_id: impl Into<String>,
+    ) -> Result<Option<smart_home_contracts::Tariff>, Status>;
+
+    /// Стоимость энергии дома, комнаты (`device_id` пуст) или розетки за `[from, to)`,
+    /// `to = 0` - до текущего момента
+    async fn get_energy_cost(
+        &self,
+        home_id: impl Into<String>,
+        room_id: impl Into<String>,
+        device_id: impl Into<String>,
+        from: u64,
+        to: u64,
+    ) -> Result<smart_home_contracts::GetEnergyCostResponse, Status>;
+
+    /// Добавить правило автоматизации, возвращает его id
+    async fn create_rule(
+        &self,
+        home_id: impl Into<String>,
+        rule: Option<smart_home_contracts::Rule>,
+    ) -> Result<String, Status>;
+    /// Заменить правило с id из `rule.id`
+    async fn update_rule(
+        &self,
+        home_id: impl Into<String>,
+        rule: Option<smart_home_contracts::Rule>,
+    ) -> Result<(), Status>;
+    async fn delete_rule(
+        &self,
+        home_id: impl Into<String>,
+        rule_id: impl Into<String>,
+    ) -> Result<(), Status>;
+    async fn list_rules(
+        &self,
+        home_id: impl Into<String>,
+    ) -> Result<Vec<smart_home_contracts::Rule>, Status>;
+    /// Журнал срабатываний правила, пустой `rule_id` - всех правил дома
+    async fn get_rule_log(
+        &self,
+        home_id: impl Into<String>,
+        rule_id: impl Into<String>,
+    ) -> Result<Vec<smart_home_contracts::RuleExecution>, Status>;
+
+    /// Добавить расписание, возвращает его id
+    async fn create_schedule(
+        &self,
+        home_id: impl Into<String>,
+        schedule: Option<smart_home_contracts::Schedule>,
+    ) -> Result<String, Status>;
+    async fn list_schedules(
+        &self,
+        home_id: impl Into<String>,
+    ) -> Result<Vec<smart_home_contracts::Schedule>, Status>;
+    /// Приостановить расписание, `paused = false` - возобновить
+    async fn pause_schedule(
+        &self,
+        home_id: impl Into<String>,
+        schedule_id: impl Into<String>,
+        paused: bool,
+    ) -> Result<(), Status>;
+    async fn delete_schedule(
+        &self,
+        home_id: impl Into<String>,
+        schedule_id: impl Into<String>,
+    ) -> Result<(), Status>;
+
+    /// Добавить сцену, возвращает ее id
+    async fn create_scene(
+        &self,
+        home_id: impl Into<String>,
+        scene: Option<smart_home_contracts::Scene>,
+    ) -> Result<String, Status>;
+    async fn list_scenes(
+        &self,
+        home_id: impl Into<String>,
+    ) -> Result<Vec<smart_home_contracts::Scene>, Status>;
+    async fn delete_scene(
+        &self,
+        home_id: impl Into<String>,
+        scene_id: impl Into<String>,
+    ) -> Result<(), Status>;
+    /// Переключить розетки сцены одновременно, результаты - в порядке состояний сцены
+    async fn apply_scene(
+        &self,
+        home_id: impl Into<String>,
+        scene_id: impl Into<String>,
+    ) -> Result<Vec<smart_home_contracts::SceneResult>, Status>;
+
+    /// Создать или заменить термостат комнаты
+    async fn set_thermostat(
+        &self,
+        home_id: impl Into<String>,
+        room_id: impl Into<String>,
+        thermostat: Option<smart_home_contracts::Thermostat>,
+    ) -> Result<(), Status>;
+    /// Настройки термостата и его состояние на момент последней проверки
+    async fn get_thermostat(
+        &self,
+        home_id: impl Into<String>,
+        room_id: impl Into<String>,
+    ) -> Result<smart_home_contracts::ThermostatStatus, Status>;
+    async fn delete_thermostat(
+        &self,
+        home_id: impl Into<String>,
+        room_id: impl Into<String>,
+    ) -> Result<(), Status>;
 }
@@ -1,222 +1,2176 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use sh_lib::{
+    automation::{self, DeviceRef, RuleExecution},
+    errors::SmartHomeErrors,
+    events::SmartHomeEvent,
+    id::{self, Id},
+    reporter::Unit,
+    scene::{Scene, SceneResult, SceneState},
+    schedule::{self, ScheduleJob},
+    smart_device::{
+        SmartDevice, SmartDeviceType, SmartLight, SmartSocket, SmartThermometer, contracts,
+        energy::{EnergyBucket, EnergyUsage},
+        history::HistoryPoint,
+        online::{self, ConnectionType, OnlineDevice, PollingSettings, UdpRoute},
+        smart_light::{check_brightness, check_color_temperature},
+    },
+    smart_home::SmartHome,
+    smart_room::SmartRoom,
+    tariff::{Cost, Tariff, TariffBand, TariffPlan},
+    thermostat::{ThermostatConfig, ThermostatMode, ThermostatReport},
+};
+use tokio::{
+    sync::{RwLock, broadcast, mpsc},
+    time::MissedTickBehavior,
+};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::Status;
+use tracing::{error, info, warn};
+
+use crate::smart_home_contracts::{
+    self, GetDeviceHistoryResponse, GetEnergyCostResponse, GetEnergyUsageResponse, HomeEventType,
+    Item, ItemType, LightValue, ThermometrValue, WatchHomeResponse,
+    rule_condition::Kind as ConditionKind, schedule::When, tariff::Kind,
+};
+use crate::{
+    persistence::{
+        Change, ConnectionRecord, Storage, StorageError, device_record, home_record, room_record,
+    },
+    repository::Repository,
+    smart_home_contracts::{ConnectionSettings, SocketValue, item::Value},
+};
+
+/// Сколько сообщений WatchHome может ждать отправки медленному клиенту
+const WATCH_BUFFER: usize = 64;
+
+/// Как часто правила проверяются без изменения показаний: нужно для выдержки и паузы.
+/// С тем же периодом проверяются расписания
+const AUTOMATION_TICK: Duration = Duration::from_secs(1);
+
+pub struct Store {
+    _inner: Arc<RwLock<HashMap<String, SmartHome>>>,
+    storage: Option<Arc<dyn Storage>>,
+}
+
+impl Store {
+    pub fn new() -> Self {
+        Self {
+            _inner: Arc::new(RwLock::new(HashMap::new())),
+            storage: None,
+        }
+    }
+
+    /// Создать хранилище с сохранением на диск: дерево восстанавливается
+    /// из сохранённого, устройства с параметрами подключения переподключаются
+    pub async fn open(storage: impl Storage + 'static) -> Result<Self, StorageError> {
+        let homes = storage.load()?.restore()?;
+
+        for home in homes.values() {
+            for room in home.get_rooms().values() {
+                for (device_id, device) in room.get_devices() {
+                    if device.get_connection().is_none() {
+                        continue;
+                    }
+
+                    info!("Try connecting restored device: {device_id}");
+                    if let Err(err) = device.connect().await {
+                        warn!("Failed to connect device: {device_id}, {err}");
+                    }
+                }
+            }
+        }
+
+        info!("Restored {} homes", homes.len());
+
+        let events: Vec<_> = homes
+            .iter()
+            .map(|(home_id, home)| (home_id.clone(), home.listen()))
+            .collect();
+        let homes = Arc::new(RwLock::new(homes));
+        let storage: Arc<dyn Storage> = Arc::new(storage);
+
+        for (home_id, events) in events {
+            spawn_automation(
+                Arc::clone(&homes),
+                Some(Arc::clone(&storage)),
+                home_id,
+                events,
+            );
+        }
+
+        Ok(Self {
+            _inner: homes,
+            storage: Some(storage),
+        })
+    }
+
+    /// Сохранить изменение до того, как оно попадёт в дерево в памяти.
+    /// Вызывается под блокировкой на запись, поэтому изменения пишутся строго по очереди
+    fn record(&self, change: &Change) -> Result<(), Status> {
+        let storage = if let Some(storage) = &self.storage {
+            storage
+        } else {
+            return Ok(());
+        };
+
+        match storage.apply(change) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(storage_error_to_status(err)),
+        }
+    }
+}
+
+/// Проверять правила и термостаты дома при изменении показаний и по таймеру, а расписания -
+/// по таймеру, пока дом существует. Действия выполняются без блокировки хранилища
+fn spawn_automation(
+    homes: Arc<RwLock<HashMap<String, SmartHome>>>,
+    storage: Option<Arc<dyn Storage>>,
+    home_id: String,
+    mut events: broadcast::Receiver<SmartHomeEvent>,
+) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(AUTOMATION_TICK);
+        tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            let ticked = tokio::select! {
+                event = events.recv() => match event {
+                    Ok(SmartHomeEvent::StateChanged { .. })
+                    | Err(broadcast::error::RecvError::Lagged(_)) => false,
+                    Ok(_) => continue,
+                    // Дом удален
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = tick.tick() => true,
+            };
+
+            let (jobs, thermostat_jobs) = {
+                let homes = homes.read().await;
+
+                match homes.get(&home_id) {
+                    Some(home) => (home.automate().await, home.regulate().await),
+                    None => break,
+                }
+            };
+
+            for job in jobs {
+                let execution = job.run().await;
+                info!(
+                    "Rule {} fired in home {home_id}: {}",
+                    execution.rule_name, execution.reason
+                );
+            }
+
+            for job in thermostat_jobs {
+                let command = job.command();
+                match job.run().await {
+                    Ok(()) => info!("Thermostat in home {home_id}: {command:?}"),
+                    Err(err) => warn!("Thermostat in home {home_id} failed to {command:?}: {err}"),
+                }
+            }
+
+            if !ticked {
+                continue;
+            }
+
+            for job in due_schedules(&homes, storage.as_deref(), &home_id).await {
+                let name = job.scheduled_run().schedule_name.clone();
+                let results = job.run().await;
+
+                let failed: Vec<String> = results
+                    .iter()
+                    .filter_map(|result| {
+                        let err = result.error.as_ref()?;
+                        Some(format!("{}: {err}", result.action.device))
+                    })
+                    .collect();
+                if failed.is_empty() {
+                    info!(
+                        "Schedule {name} ran in home {home_id}: {} commands",
+                        results.len()
+                    );
+                } else {
+                    warn!(
+                        "Schedule {name} ran in home {home_id} with errors: {}",
+                        failed.join(", ")
+                    );
+                }
+            }
+        }
+    });
+}
+
+/// Задания наступивших расписаний дома. Отметка о запуске сохраняется до выполнения,
+/// чтобы после перезапуска сервера запуск не повторился
+async fn due_schedules(
+    homes: &RwLock<HashMap<String, SmartHome>>,
+    storage: Option<&dyn Storage>,
+    home_id: &str,
+) -> Vec<ScheduleJob> {
+    let now = now_millis();
+
+    let due = homes
+        .read()
+        .await
+        .get(home_id)
+        .is_some_and(|home| home.scheduler().has_due(now));
+    if !due {
+        return vec![];
+    }
+
+    // Блокировка на запись: отметки пишутся по очереди с изменениями из запросов
+    let homes = homes.write().await;
+    let home = if let Some(home) = homes.get(home_id) {
+        home
+    } else {
+        return vec![];
+    };
+
+    let jobs = home.schedule_at(now);
+
+    if let Some(storage) = storage {
+        for job in &jobs {
+            let schedule_id = &job.scheduled_run().schedule_id;
+            let Some(schedule) = home.scheduler().schedule(schedule_id) else {
+                continue;
+            };
+
+            if let Err(err) = storage.apply(&Change::UpdateSchedule {
+                home_id,
+                schedule: &schedule,
+            }) {
+                warn!("Failed to save run of schedule {schedule_id}: {err}");
+            }
+        }
+    }
+
+    jobs
+}
+
+/// Перевести ошибку устройства в gRPC-статус
+fn device_error_to_status(err: SmartHomeErrors) -> Status {
+    match err {
+        SmartHomeErrors::RoomNotFound(_) | SmartHomeErrors::DeviceNotFound(_) => {
+            Status::not_found(err.to_string())
+        }
+        SmartHomeErrors::ConnectionError(_) => Status::unavailable(err.to_string()),
+        SmartHomeErrors::EmulatorError(_) | SmartHomeErrors::ProtocolError(_) => {
+            Status::failed_precondition(err.to_string())
+        }
+        SmartHomeErrors::InvalidValue(_) => Status::invalid_argument(err.to_string()),
+        SmartHomeErrors::DecodeMessageError(_) | SmartHomeErrors::GettingStatusError(_) => {
+            Status::internal(err.to_string())
+        }
+    }
+}
+
+/// Перевести ошибку хранилища в gRPC-статус
+fn storage_error_to_status(err: StorageError) -> Status {
+    match err {
+        StorageError::AlreadyExists(_) => Status::already_exists(err.to_string()),
+        StorageError::NotFound(_) => Status::not_found(err.to_string()),
+        StorageError::Backend(_) => {
+            error!("Failed to save store: {err}");
+            Status::internal("Failed to save store")
+        }
+    }
+}
+
+/// Отключить все устройства комнаты
+async fn disconnect_room(room: &SmartRoom) {
+    for device in room.get_devices().values() {
+        device.disconnect().await;
+    }
+}
+
+/// Перевести состояние соединения в контракт
+fn connection_state(state: contracts::ConnectionState) -> smart_home_contracts::ConnectionState {
+    match state {
+        contracts::ConnectionState::Offline => smart_home_contracts::ConnectionState::Offline,
+        contracts::ConnectionState::Connecting => smart_home_contracts::ConnectionState::Connecting,
+        contracts::ConnectionState::Online => smart_home_contracts::ConnectionState::Online,
+        contracts::ConnectionState::Backoff => smart_home_contracts::ConnectionState::Backoff,
+    }
+}
+
+/// Получить адрес устройства из контракта
+fn device_addr(settings: &ConnectionSettings) -> Result<(IpAddr, u16), Status> {
+    let ip = settings
+        .ip
+        .parse()
+        .map_err(|_| Status::invalid_argument("Invalid device IP address"))?;
+    let port = settings
+        .port
+        .parse()
+        .map_err(|_| Status::invalid_argument("Invalid device port"))?;
+
+    Ok((ip, port))
+}
+
+/// Получить параметры опроса из контракта, нули заменяются значениями по умолчанию
+fn polling_settings(settings: &ConnectionSettings) -> PollingSettings {
+    let default = PollingSettings::default();
+    let millis = |value: u32, default: Duration| {
+        if value == 0 {
+            default
+        } else {
+            Duration::from_millis(value as u64)
+        }
+    };
+
+    PollingSettings {
+        poll_interval: millis(settings.poll_interval_ms, default.poll_interval),
+        command_timeout: millis(settings.command_timeout_ms, default.command_timeout),
+        offline_threshold: millis(settings.offline_threshold_ms, default.offline_threshold),
+    }
+}
+
+/// Перевести паспорт устройства в контракт
+fn device_info(info: contracts::DeviceInfo) -> smart_home_contracts::DeviceInfo {
+    smart_home_contracts::DeviceInfo {
+        serial: info.serial,
+        model: info.model,
+        firmware: info.firmware,
+    }
+}
+
+/// Сформировать элемент ответа по дому
+fn home_item(home: &SmartHome) -> Item {
+    Item {
+        id: home.get_id().to_string(),
+        name: home.get_name().to_string(),
+        item_type: ItemType::Home.into(),
+        device_connection: None,
+        value: None,
+        parent_id: String::new(),
+        device_info: None,
+    }
+}
+
+/// Сформировать элемент ответа по комнате
+fn room_item(home_id: &str, room: &SmartRoom) -> Item {
+    Item {
+        id: room.get_id().to_string(),
+        name: room.get_name().to_string(),
+        item_type: ItemType::Room.into(),
+        device_connection: None,
+        value: room
+            .thermostat()
+            .map(|thermostat| Value::ThermostatValue(thermostat_status(thermostat.report()))),
+        parent_id: home_id.to_string(),
+        device_info: None,
+    }
+}
+
+/// Сформировать элемент ответа по устройству
+async fn device_item(room_id: &str, device_id: &str, device: &SmartDeviceType) -> Item {
+    let connection: Option<ConnectionSettings> = device.get_connection().map(|connection| {
+        let polling = connection.polling().get();
+
+        ConnectionSettings {
+            ip: connection.get_addr().ip().to_string(),
+            port: format!("{}", connection.get_addr().port()),
+            service: match connection {
+                ConnectionType::Tcp { .. } => "TCP".to_string(),
+                ConnectionType::Udp { .. } => "UDP".to_string(),
+            },
+            poll_interval_ms: polling.poll_interval.as_millis() as u32,
+            command_timeout_ms: polling.command_timeout.as_millis() as u32,
+            offline_threshold_ms: polling.offline_threshold.as_millis() as u32,
+            serial: match connection {
+                ConnectionType::Udp {
+                    route: UdpRoute::Serial(serial),
+                    ..
+                } => serial.clone(),
+                _ => String::new(),
+            },
+        }
+    });
+
+    let device_data = device.get_data().await;
+    let device_info = device.get_info().await.map(device_info);
+
+    Item {
+        id: device_id.to_string(),
+        name: device.get_name().to_string(),
+        item_type: match device {
+            SmartDeviceType::Socket(_) => ItemType::Socket.into(),
+            SmartDeviceType::Thermometer(_) => ItemType::Thermo.into(),
+            SmartDeviceType::Light(_) => ItemType::Light.into(),
+        },
+        device_connection: connection,
+        value: Some(device_value(&device_data)),
+        parent_id: room_id.to_string(),
+        device_info,
+    }
+}
+
+/// Перевести данные устройства в контракт, мощность выключенной розетки - 0
+fn device_value(device_data: &contracts::DeviceData) -> Value {
+    match device_data {
+        contracts::DeviceData::Socket(data) => Value::SocketValue(SocketValue {
+            is_on: data.is_on,
+            power: if data.is_on { data.power } else { 0.0 },
+            timestamp: data.timestamp,
+            is_online: data.is_online,
+            connection_state: connection_state(data.connection_state).into(),
+        }),
+        contracts::DeviceData::Thermometer(data) => Value::ThermoValue(ThermometrValue {
+            is_online: data.is_online,
+            temp: data.temp,
+            timestamp: data.timestamp,
+            connection_state: connection_state(data.connection_state).into(),
+        }),
+        contracts::DeviceData::Light(data) => Value::LightValue(LightValue {
+            is_on: data.is_on,
+            brightness: data.brightness.into(),
+            color_temperature: data.color_temperature.unwrap_or_default().into(),
+            timestamp: data.timestamp,
+            is_online: data.is_online,
+            connection_state: connection_state(data.connection_state).into(),
+        }),
+    }
+}
+
+/// Перевести точку истории в контракт
+fn history_point(point: HistoryPoint) -> smart_home_contracts::HistoryPoint {
+    smart_home_contracts::HistoryPoint {
+        timestamp: point.timestamp,
+        min: point.min,
+        max: point.max,
+        avg: point.avg,
+        last: point.last,
+        count: point.count,
+    }
+}
+
+/// Обозначение единицы измерения в ответе API
+fn unit_code(unit: Unit) -> String {
+    match unit {
+        Unit::Watt => "W",
+        Unit::Celsius => "°C",
+        Unit::KilowattHour => "kWh",
+        Unit::Percent => "%",
+        Unit::Kelvin => "K",
+    }
+    .to_string()
+}
+
+fn energy_response(usage: EnergyUsage) -> GetEnergyUsageResponse {
+    fn buckets(
+        buckets: Vec<EnergyBucket>,
+        format: &str,
+    ) -> Vec<smart_home_contracts::EnergyBucket> {
+        buckets
+            .into_iter()
+            .map(|bucket| smart_home_contracts::EnergyBucket {
+                period: bucket.start.format(format).to_string(),
+                kwh: bucket.kwh,
+            })
+            .collect()
+    }
+
+    GetEnergyUsageResponse {
+        total_kwh: usage.total_kwh,
+        daily: buckets(usage.daily, "%Y-%m-%d"),
+        monthly: buckets(usage.monthly, "%Y-%m"),
+    }
+}
+
+/// Тариф из контракта, с проверкой цен, зон и валюты
+fn tariff_plan(tariff: smart_home_contracts::Tariff) -> Result<TariffPlan, Status> {
+    let kind = if let Some(kind) = tariff.kind {
+        kind
+    } else {
+        return Err(Status::invalid_argument("Tariff kind is required"));
+    };
+
+    let plan = TariffPlan::new(
+        tariff.currency,
+        match kind {
+            Kind::Flat(flat) => Tariff::Flat { price: flat.price },
+            Kind::DayNight(day_night) => Tariff::DayNight {
+                day_price: day_night.day_price,
+                night_price: day_night.night_price,
+                night_start_minute: day_night.night_start_minute,
+                night_end_minute: day_night.night_end_minute,
+            },
+            Kind::TimeOfUse(time_of_use) => Tariff::TimeOfUse {
+                base_price: time_of_use.base_price,
+                bands: time_of_use
+                    .bands
+                    .into_iter()
+                    .map(|band| TariffBand {
+                        start_minute: band.start_minute,
+                        end_minute: band.end_minute,
+                        price: band.price,
+                    })
+                    .collect(),
+            },
+        },
+    )
+    .with_utc_offset(tariff.utc_offset_minutes);
+
+    match plan.validate() {
+        Ok(_) => Ok(plan),
+        Err(err) => Err(Status::invalid_argument(err.0)),
+    }
+}
+
+fn tariff_message(plan: &TariffPlan) -> smart_home_contracts::Tariff {
+    let kind = match &plan.tariff {
+        Tariff::Flat { price } => Kind::Flat(smart_home_contracts::FlatTariff { price: *price }),
+        Tariff::DayNight {
+            day_price,
+            night_price,
+            night_start_minute,
+            night_end_minute,
+        } => Kind::DayNight(smart_home_contracts::DayNightTariff {
+            day_price: *day_price,
+            night_price: *night_price,
+            night_start_minute: *night_start_minute,
+            night_end_minute: *night_end_minute,
+        }),
+        Tariff::TimeOfUse { base_price, bands } => {
+            Kind::TimeOfUse(smart_home_contracts::TimeOfUseTariff {
+                base_price: *base_price,
+                bands: bands
+                    .iter()
+                    .map(|band| smart_home_contracts::TariffBand {
+                        start_minute: band.start_minute,
+                        end_minute: band.end_minute,
+                        price: band.price,
+                    })
+                    .collect(),
+            })
+        }
+    };
+
+    smart_home_contracts::Tariff {
+        currency: plan.currency.clone(),
+        utc_offset_minutes: plan.utc_offset_minutes,
+        kind: Some(kind),
+    }
+}
+
+fn device_ref(device: Option<smart_home_contracts::DeviceRef>) -> Result<DeviceRef, Status> {
+    match device {
+        Some(device) => Ok(DeviceRef {
+            room_id: device.room_id,
+            device_id: device.device_id,
+        }),
+        None => Err(Status::invalid_argument("Device reference is required")),
+    }
+}
+
+fn device_ref_message(device: &DeviceRef) -> smart_home_contracts::DeviceRef {
+    smart_home_contracts::DeviceRef {
+        room_id: device.room_id.clone(),
+        device_id: device.device_id.clone(),
+    }
+}
+
+fn metric(metric: smart_home_contracts::Metric) -> Result<automation::Metric, Status> {
+    match metric {
+        smart_home_contracts::Metric::Temperature => Ok(automation::Metric::Temperature),
+        smart_home_contracts::Metric::Power => Ok(automation::Metric::Power),
+        smart_home_contracts::Metric::Unspecified => {
+            Err(Status::invalid_argument("Invalid metric"))
+        }
+    }
+}
+
+fn metric_message(metric: automation::Metric) -> smart_home_contracts::Metric {
+    match metric {
+        automation::Metric::Temperature => smart_home_contracts::Metric::Temperature,
+        automation::Metric::Power => smart_home_contracts::Metric::Power,
+    }
+}
+
+fn comparison(
+    comparison: smart_home_contracts::Comparison,
+) -> Result<automation::Comparison, Status> {
+    match comparison {
+        smart_home_contracts::Comparison::Above => Ok(automation::Comparison::Above),
+        smart_home_contracts::Comparison::Below => Ok(automation::Comparison::Below),
+        smart_home_contracts::Comparison::Unspecified => {
+            Err(Status::invalid_argument("Invalid comparison"))
+        }
+    }
+}
+
+fn comparison_message(comparison: automation::Comparison) -> smart_home_contracts::Comparison {
+    match comparison {
+        automation::Comparison::Above => smart_home_contracts::Comparison::Above,
+        automation::Comparison::Below => smart_home_contracts::Comparison::Below,
+    }
+}
+
+fn socket_command(
+    command: smart_home_contracts::DeviceCommand,
+) -> Result<automation::SocketCommand, Status> {
+    match command {
+        smart_home_contracts::DeviceCommand::TurnOn => Ok(automation::SocketCommand::TurnOn),
+        smart_home_contracts::DeviceCommand::TurnOff => Ok(automation::SocketCommand::TurnOff),
+        smart_home_contracts::DeviceCommand::SetBrightness
+        | smart_home_contracts::DeviceCommand::SetColorTemperature => Err(
+            Status::invalid_argument("Only turn on and turn off commands are supported"),
+        ),
+        smart_home_contracts::DeviceCommand::Unspecified => {
+            Err(Status::invalid_argument("Invalid device command"))
+        }
+    }
+}
+
+fn command_message(command: automation::SocketCommand) -> smart_home_contracts::DeviceCommand {
+    match command {
+        automation::SocketCommand::TurnOn => smart_home_contracts::DeviceCommand::TurnOn,
+        automation::SocketCommand::TurnOff => smart_home_contracts::DeviceCommand::TurnOff,
+    }
+}
+
+fn action_message(action: &automation::Action) -> smart_home_contracts::RuleAction {
+    smart_home_contracts::RuleAction {
+        device: Some(device_ref_message(&action.device)),
+        command: command_message(action.command).into(),
+    }
+}
+
+/// Правило из контракта. Id выводится из имени, ссылки на устройства проверяет дом
+fn rule(rule: smart_home_contracts::Rule) -> Result<automation::Rule, Status> {
+    let trigger = if let Some(trigger) = rule.trigger {
+        trigger
+    } else {
+        return Err(Status::invalid_argument("Rule trigger is required"));
+    };
+
+    let trigger = automation::Trigger {
+        metric: metric(trigger.metric())?,
+        comparison: comparison(trigger.comparison())?,
+        device: device_ref(trigger.device)?,
+        threshold: trigger.threshold,
+        hysteresis: trigger.hysteresis,
+        hold_ms: trigger.hold_ms,
+    };
+
+    let actions = rule
+        .actions
+        .into_iter()
+        .map(|action| {
+            Ok(automation::Action {
+                command: socket_command(action.command())?,
+                device: device_ref(action.device)?,
+            })
+        })
+        .collect::<Result<_, Status>>()?;
+
+    let mut result =
+        automation::Rule::new(rule.name, trigger, actions).with_cooldown_ms(rule.cooldown_ms);
+    result.enabled = !rule.disabled;
+
+    for condition in rule.conditions {
+        result = result.with_condition(match condition.kind {
+            Some(ConditionKind::Reading(reading)) => automation::Condition::Reading {
+                metric: metric(reading.metric())?,
+                comparison: comparison(reading.comparison())?,
+                device: device_ref(reading.device)?,
+                threshold: reading.threshold,
+            },
+            Some(ConditionKind::Socket(socket)) => automation::Condition::Socket {
+                device: device_ref(socket.device)?,
+                is_on: socket.is_on,
+            },
+            None => return Err(Status::invalid_argument("Rule condition kind is required")),
+        });
+    }
+
+    Ok(result)
+}
+
+fn rule_message(rule: &automation::Rule) -> smart_home_contracts::Rule {
+    let trigger = &rule.trigger;
+
+    smart_home_contracts::Rule {
+        id: rule.id.clone(),
+        name: rule.name.clone(),
+        disabled: !rule.enabled,
+        trigger: Some(smart_home_contracts::RuleTrigger {
+            device: Some(device_ref_message(&trigger.device)),
+            metric: metric_message(trigger.metric).into(),
+            comparison: comparison_message(trigger.comparison).into(),
+            threshold: trigger.threshold,
+            hysteresis: trigger.hysteresis,
+            hold_ms: trigger.hold_ms,
+        }),
+        conditions: rule
+            .conditions
+            .iter()
+            .map(|condition| smart_home_contracts::RuleCondition {
+                kind: Some(match condition {
+                    automation::Condition::Reading {
+                        device,
+                        metric,
+                        comparison,
+                        threshold,
+                    } => ConditionKind::Reading(smart_home_contracts::ReadingCondition {
+                        device: Some(device_ref_message(device)),
+                        metric: metric_message(*metric).into(),
+                        comparison: comparison_message(*comparison).into(),
+                        threshold: *threshold,
+                    }),
+                    automation::Condition::Socket { device, is_on } => {
+                        ConditionKind::Socket(smart_home_contracts::SocketCondition {
+                            device: Some(device_ref_message(device)),
+                            is_on: *is_on,
+                        })
+                    }
+                }),
+            })
+            .collect(),
+        actions: rule.actions.iter().map(action_message).collect(),
+        cooldown_ms: rule.cooldown_ms,
+    }
+}
+
+fn execution_message(execution: RuleExecution) -> smart_home_contracts::RuleExecution {
+    smart_home_contracts::RuleExecution {
+        rule_id: execution.rule_id,
+        rule_name: execution.rule_name,
+        timestamp: execution.timestamp,
+        reason: execution.reason,
+        results: execution
+            .results
+            .into_iter()
+            .map(|result| smart_home_contracts::RuleActionResult {
+                action: Some(action_message(&result.action)),
+                error: result.error.unwrap_or_default(),
+            })
+            .collect(),
+    }
+}
+
+/// Расписание из контракта. Id выводится из имени, ссылки на розетки проверяет дом
+fn schedule(schedule: smart_home_contracts::Schedule) -> Result<schedule::Schedule, Status> {
+    let when = match schedule.when {
+        Some(When::Cron(expression)) => schedule::When::Cron { expression },
+        Some(When::OnceAt(at)) => schedule::When::Once { at },
+        None => return Err(Status::invalid_argument("Schedule time is required")),
+    };
+
+    let actions = schedule
+        .actions
+        .iter()
+        .map(|action| {
+            let target = match (action.room_id.is_empty(), action.device_id.is_empty()) {
+                (true, true) => schedule::Target::Home,
+                (false, true) => schedule::Target::Room {
+                    room_id: action.room_id.clone(),
+                },
+                (false, false) => schedule::Target::Socket {
+                    device: DeviceRef {
+                        room_id: action.room_id.clone(),
+                        device_id: action.device_id.clone(),
+                    },
+                },
+                (true, false) => {
+                    return Err(Status::invalid_argument("Room id is required for a device"));
+                }
+            };
+
+            Ok(schedule::ScheduleAction {
+                target,
+                command: socket_command(action.command())?,
+            })
+        })
+        .collect::<Result<_, _>>()?;
+
+    let mut result = schedule::Schedule::new(schedule.name, when, actions)
+        .with_catch_up_ms(schedule.catch_up_ms);
+    if !schedule.time_zone.is_empty() {
+        result = result.with_time_zone(schedule.time_zone);
+    }
+    result.paused = schedule.paused;
+
+    Ok(result)
+}
+
+fn schedule_message(schedule: &schedule::Schedule) -> smart_home_contracts::Schedule {
+    let next_run = if schedule.paused {
+        None
+    } else {
+        schedule.next_run()
+    };
+
+    smart_home_contracts::Schedule {
+        id: schedule.id.clone(),
+        name: schedule.name.clone(),
+        when: Some(match &schedule.when {
+            schedule::When::Cron { expression } => When::Cron(expression.clone()),
+            schedule::When::Once { at } => When::OnceAt(*at),
+        }),
+        time_zone: schedule.time_zone.clone(),
+        actions: schedule
+            .actions
+            .iter()
+            .map(|action| {
+                let (room_id, device_id) = match &action.target {
+                    schedule::Target::Home => (String::new(), String::new()),
+                    schedule::Target::Room { room_id } => (room_id.clone(), String::new()),
+                    schedule::Target::Socket { device } => {
+                        (device.room_id.clone(), device.device_id.clone())
+                    }
+                };
+
+                smart_home_contracts::ScheduleAction {
+                    room_id,
+                    device_id,
+                    command: command_message(action.command).into(),
+                }
+            })
+            .collect(),
+        catch_up_ms: schedule.catch_up_ms,
+        paused: schedule.paused,
+        next_run: next_run.unwrap_or_default(),
+        last_run: schedule.last_run.unwrap_or_default(),
+    }
+}
+
+/// Сцена из контракта. Id выводится из имени, ссылки на розетки проверяет дом
+fn scene(scene: smart_home_contracts::Scene) -> Result<Scene, Status> {
+    let states = scene
+        .states
+        .into_iter()
+        .map(|state| {
+            Ok(SceneState {
+                device: device_ref(state.device)?,
+                is_on: state.is_on,
+            })
+        })
+        .collect::<Result<_, Status>>()?;
+
+    Ok(Scene::new(scene.name, states))
+}
+
+fn scene_state_message(state: &SceneState) -> smart_home_contracts::SceneState {
+    smart_home_contracts::SceneState {
+        device: Some(device_ref_message(&state.device)),
+        is_on: state.is_on,
+    }
+}
+
+fn scene_message(scene: &Scene) -> smart_home_contracts::Scene {
+    smart_home_contracts::Scene {
+        id: scene.id.clone(),
+        name: scene.name.clone(),
+        states: scene.states.iter().map(scene_state_message).collect(),
+    }
+}
+
+fn scene_result_message(result: SceneResult) -> smart_home_contracts::SceneResult {
+    smart_home_contracts::SceneResult {
+        state: Some(scene_state_message(&result.state)),
+        error: result.error.unwrap_or_default(),
+    }
+}
+
+fn thermostat(thermostat: smart_home_contracts::Thermostat) -> Result<ThermostatConfig, Status> {
+    let mode = match thermostat.mode() {
+        smart_home_contracts::ThermostatMode::Off => ThermostatMode::Off,
+        smart_home_contracts::ThermostatMode::Heat => ThermostatMode::Heat,
+        smart_home_contracts::ThermostatMode::Cool => ThermostatMode::Cool,
+        smart_home_contracts::ThermostatMode::Unspecified => {
+            return Err(Status::invalid_argument("Invalid thermostat mode"));
+        }
+    };
+
+    Ok(ThermostatConfig {
+        thermometer_id: thermostat.thermometer_id,
+        socket_id: thermostat.socket_id,
+        mode,
+        setpoint: thermostat.setpoint,
+        hysteresis: thermostat.hysteresis,
+        min_on_ms: thermostat.min_on_ms,
+        min_off_ms: thermostat.min_off_ms,
+    })
+}
+
+fn thermostat_message(config: &ThermostatConfig) -> smart_home_contracts::Thermostat {
+    let mode = match config.mode {
+        ThermostatMode::Off => smart_home_contracts::ThermostatMode::Off,
+        ThermostatMode::Heat => smart_home_contracts::ThermostatMode::Heat,
+        ThermostatMode::Cool => smart_home_contracts::ThermostatMode::Cool,
+    };
+
+    smart_home_contracts::Thermostat {
+        thermometer_id: config.thermometer_id.clone(),
+        socket_id: config.socket_id.clone(),
+        mode: mode.into(),
+        setpoint: config.setpoint,
+        hysteresis: config.hysteresis,
+        min_on_ms: config.min_on_ms,
+        min_off_ms: config.min_off_ms,
+    }
+}
+
+fn thermostat_status(report: ThermostatReport) -> smart_home_contracts::ThermostatStatus {
+    smart_home_contracts::ThermostatStatus {
+        thermostat: Some(thermostat_message(&report.config)),
+        sensor_online: report.temperature.is_some(),
+        temperature: report.temperature.unwrap_or_default(),
+        output_on: report.output_on,
+        fault: report.fault.unwrap_or_default(),
+    }
+}
+
+fn cost_response(cost: Cost) -> GetEnergyCostResponse {
+    GetEnergyCostResponse {
+        kwh: cost.kwh,
+        amount: cost.amount,
+        currency: cost.currency,
+        partial: cost.partial,
+    }
+}
+
+/// Элемент, от которого известны только id и родитель: удаленный или уже не найденный
+fn bare_item(id: &Id, parent_id: &Id, item_type: ItemType) -> Item {
+    Item {
+        id: id.to_string(),
+        item_type: item_type.into(),
+        parent_id: parent_id.to_string(),
+        ..Item::default()
+    }
+}
+
+/// Перевести событие дома в сообщение потока WatchHome.
+///
+/// Устройство ищется в доме, чтобы передать его имя, подключение и паспорт;
+/// показания берутся из самого события.
+async fn watch_response(home: Option<&SmartHome>, event: SmartHomeEvent) -> WatchHomeResponse {
+    let room_id = event.room_id().clone();
+    let device = match (home, event.device_id()) {
+        (Some(home), Some(device_id)) => home.get_device(&room_id, device_id).ok(),
+        _ => None,
+    };
+    let home_id = home.map(|home| home.get_id().clone()).unwrap_or_default();
+
+    let current = async |device_id: &Id| match device {
+        Some(device) => device_item(&room_id.to_string(), &device_id.to_string(), device).await,
+        None => bare_item(device_id, &room_id, ItemType::Unspecified),
+    };
+
+    let (event_type, item) = match &event {
+        SmartHomeEvent::RoomAdded { room_id, name } => (
+            HomeEventType::RoomAdded,
+            Item {
+                name: name.clone(),
+                ..bare_item(room_id, &home_id, ItemType::Room)
+            },
+        ),
+        SmartHomeEvent::RoomRemoved { room_id } => (
+            HomeEventType::RoomRemoved,
+            bare_item(room_id, &home_id, ItemType::Room),
+        ),
+        SmartHomeEvent::DeviceAdded {
+            device_id, name, ..
+        } => (
+            HomeEventType::DeviceAdded,
+            Item {
+                name: name.clone(),
+                ..current(device_id).await
+            },
+        ),
+        SmartHomeEvent::DeviceRemoved { device_id, .. } => (
+            HomeEventType::DeviceRemoved,
+            bare_item(device_id, &room_id, ItemType::Unspecified),
+        ),
+        SmartHomeEvent::StateChanged { device_id, new, .. } => (
+            HomeEventType::StateChanged,
+            Item {
+                value: Some(device_value(new)),
+                ..current(device_id).await
+            },
+        ),
+        SmartHomeEvent::ConnectionLost { device_id, .. } => {
+            (HomeEventType::ConnectionLost, current(device_id).await)
+        }
+        SmartHomeEvent::ConnectionRestored { device_id, .. } => {
+            (HomeEventType::ConnectionRestored, current(device_id).await)
+        }
+    };
+
+    WatchHomeResponse {
+        event_type: event_type.into(),
+        item: Some(item),
+        timestamp: now_millis(),
+        missed: 0,
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+impl Repository for Store {
+    async fn add_home(&self, name: impl Into<String>) -> Result<String, Status> {
+        let mut homes = self._inner.write().await;
+        let new_home = SmartHome::new(name);
+
+        if homes.contains_key(&new_home.get_id().to_string()) {
+            return Err(Status::already_exists("Home already exists"));
+        }
+
+        self.record(&Change::AddHome(&home_record(&new_home)))?;
+
+        let home_id = new_home.get_id().clone();
+        spawn_automation(
+            Arc::clone(&self._inner),
+            self.storage.clone(),
+            home_id.to_string(),
+            new_home.listen(),
+        );
+        homes.insert(home_id.to_string(), new_home);
+
+        Ok(home_id.to_string())
+    }
+
+    async fn delete_home(&self, home_id: impl Into<String>) -> Result<(), Status> {
+        let mut homes = self._inner.write().await;
+        let home_id = home_id.into();
+
+        let home = if let Some(home) = homes.get(&home_id) {
+            home
+        } else {
+            return Err(Status::not_found("Home not found"));
+        };
+
+        self.record(&Change::DeleteHome { home_id: &home_id })?;
+
+        for room in home.get_rooms().values() {
+            disconnect_room(room).await;
+        }
+        homes.remove(&home_id);
+
+        Ok(())
+    }
+
+    async fn add_room(
+        &self,
+        home_id: impl Into<String>,
+        name: impl Into<String>,
+    ) -> Result<String, Status> {
+        let mut homes = self._inner.write().await;
+
+        let home = if let Some(home) = homes.get_mut(&home_id.into()) {
+            home
+        } else {
+            return Err(Status::not_found("Home not found"));
+        };
+
+        let room = SmartRoom::new(name);
+
+        if home.get_room(room.get_id()).is_some() {
+            return Err(Status::already_exists("Room already exists in home"));
+        }
+
+        self.record(&Change::AddRoom {
+            home_id: &home.get_id().to_string(),
+            room: &room_record(&room),
+        })?;
+
+        let room_id = home.add_room(room);
+
+        Ok(room_id.to_string())
+    }
+
+    async fn delete_room(
+        &self,
+        home_id: impl Into<String>,
+        room_id: impl Into<String>,
+    ) -> Result<(), Status> {
+        let mut homes = self._inner.write().await;
+
+        let home = if let Some(home) = homes.get_mut(&home_id.into()) {
+            home
+        } else {
+            return Err(Status::not_found("Home not found"));
+        };
+
+        let room_id = Id::with_inner(room_id);
+
+        let room = if let Some(room) = home.get_room(&room_id) {
+            room
+        } else {
+            return Err(Status::not_found("Room not found"));
+        };
+
+        self.record(&Change::DeleteRoom {
+            home_id: &home.get_id().to_string(),
+            room_id: &room_id.to_string(),
+        })?;
+
+        disconnect_room(room).await;
+        home.delete_room(&room_id);
+
+        Ok(())
+    }
+
+    async fn add_device(
+        &self,
+        home_id: impl Into<String>,
+        room_id: impl Into<String>,
+        device_type: smart_home_contracts::DeviceType,
+        device_name: String,
+        connection: Option<smart_home_contracts::ConnectionSettings>,
+    ) -> Result<String, Status> {
+        let mut homes = self._inner.write().await;
+
+        let home = if let Some(home) = homes.get_mut(&home_id.into()) {
+            home
+        } else {
+            return Err(Status::not_found("Home not found"));
+        };
+
+        let home_id = home.get_id().to_string();
+
+        let room = if let Some(room) = home.get_room_mut(&Id::with_inner(room_id)) {
+            room
+        } else {
+            return Err(Status::not_found("Room not found"));
+        };
+
+        if room
+            .get_device(&id::Id::from_string(device_name.clone()))
+            .is_some()
+        {
+            return Err(Status::already_exists("Device already exists in room"));
+        }
+
+        let device = match device_type {
+            smart_home_contracts::DeviceType::Socket => match connection {
+                Some(c) => {
+                    let (ip, port) = device_addr(&c)?;
+                    SmartDeviceType::Socket(SmartSocket::new_with_connection(
+                        device_name,
+                        0.0,
+                        false,
+                        ConnectionType::tcp(ip, port).with_polling(polling_settings(&c)),
+                    ))
+                }
+                None => SmartDeviceType::Socket(SmartSocket::new(device_name, 0.0, false)),
+            },
+            smart_home_contracts::DeviceType::Thermo => match connection {
+                Some(c) => {
+                    let (ip, port) = device_addr(&c)?;
+                    SmartDeviceType::Thermometer(SmartThermometer::new_with_connection(
+                        device_name,
+                        0.0,
+                        ConnectionType::udp(ip, port)
+                            .with_route(if c.serial.is_empty() {
+                                UdpRoute::Any
+                            } else {
+                                UdpRoute::Serial(c.serial.clone())
+                            })
+                            .with_polling(polling_settings(&c)),
+                    ))
+                }
+                None => SmartDeviceType::Thermometer(SmartThermometer::new(device_name, 0.0)),
+            },
+            smart_home_contracts::DeviceType::Light => match connection {
+                Some(c) => {
+                    let (ip, port) = device_addr(&c)?;
+                    SmartDeviceType::Light(SmartLight::new_with_connection(
+                        device_name,
+                        0,
+                        false,
+                        ConnectionType::tcp(ip, port).with_polling(polling_settings(&c)),
+                    ))
+                }
+                None => SmartDeviceType::Light(SmartLight::new(device_name, 0, false)),
+            },
+            _ => {
+                return Err(Status::invalid_argument("Invalid device type"));
+            }
+        };
+
+        self.record(&Change::AddDevice {
+            home_id: &home_id,
+            room_id: &room.get_id().to_string(),
+            device: &device_record(&device.get_id().to_string(), &device),
+        })?;
+
+        let device_id = room.add_device(device);
+
+        if let Some(device) = room.get_device(&device_id) {
+            info!("Try connecting device: {device_id}");
+            match device.connect().await {
+                Ok(_) => (),
+                Err(err) => {
+                    warn!("Failed to connect device: {device_id}, {err}");
+                }
+            };
+        }
+
+        Ok(device_id.to_string())
+    }
+
+    async fn delete_device(
+        &self,
+        home_id: impl Into<String>,
+        room_id: impl Into<String>,
+        device_id: impl Into<String>,
+    ) -> Result<(), Status> {
+        let mut homes = self._inner.write().await;
+
+        let home = if let Some(home) = homes.get_mut(&home_id.into()) {
+            home
+        } else {
+            return Err(Status::not_found("Home not found"));
+        };
+
+        let home_id = home.get_id().to_string();
+
+        let room = if let Some(room) = home.get_room_mut(&Id::with_inner(room_id)) {
+            room
+        } else {
+            return Err(Status::not_found("Room not found"));
+        };
+
+        let device_id = Id::with_inner(device_id.into());
+
+        let device = if let Some(device) = room.get_device(&device_id) {
+            device
+        } else {
+            return Err(Status::not_found("Device not found"));
+        };
+
+        self.record(&Change::DeleteDevice {
+            home_id: &home_id,
+            room_id: &room.get_id().to_string(),
+            device_id: &device_id.to_string(),
+        })?;
+
+        device.disconnect().await;
+        room.delete_device(&device_id);
+
+        Ok(())
+    }
+
+    async fn list_homes(&self) -> Result<Vec<Item>, Status> {
+        let homes = self._inner.read().await;
+
+        let mut items: Vec<Item> = homes.values().map(home_item).collect();
+
+        items.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(items)
+    }
+
+    async fn list_rooms(&self, home_id: impl Into<String>) -> Result<Vec<Item>, Status> {
+        let homes = self._inner.read().await;
+
+        let home = if let Some(home) = homes.get(&home_id.into()) {
+            home
+        } else {
+            return Err(Status::not_found("Home not found"));
+        };
+
+        let home_id = home.get_id().to_string();
+        let mut items: Vec<Item> = home
+            .get_rooms()
+            .values()
+            .map(|room| room_item(&home_id, room))
+            .collect();
+
+        items.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(items)
+    }
+
+    async fn list_devices(
+        &self,
+        home_id: impl Into<String>,
+        room_id: impl Into<String>,
+    ) -> Result<Vec<Item>, Status> {
+        let homes = self._inner.read().await;
+
+        let home = if let Some(home) = homes.get(&home_id.into()) {
+            home
+        } else {
+            return Err(Status::not_found("Home not found"));
+        };
+
+        let room = if let Some(room) = home.get_room(&Id::with_inner(room_id)) {
+            room
+        } else {
+            return Err(Status::not_found("Room not found"));
+        };
+
+        let mut items: Vec<Item> = vec![];
+
+        let room_id = room.get_id().to_string();
+        for (device_id, device) in room.get_devices() {
+            items.push(device_item(&room_id, device_id, device).await);
+        }
+
+        items.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(items)
+    }
+
+    async fn control_device(
+        &self,
+        home_id: impl Into<String>,
+        room_id: impl Into<String>,
+        device_id: impl Into<String>,
+        command: smart_home_contracts::DeviceCommand,
+        value: u32,
+    ) -> Result<Item, Status> {
+        let device_id = device_id.into();
+
+        // Клон устройства разделяет состояние и соединение с оригиналом,
+        // поэтому команду можно отправить без блокировки всего хранилища
+        let room_id = room_id.into();
+
+        let device = {
+            let homes = self._inner.read().await;
+
+            let home = if let Some(home) = homes.get(&home_id.into()) {
+                home
+            } else {
+                return Err(Status::not_found("Home not found"));
+            };
+
+            match home.get_device(&Id::with_inner(&room_id), &Id::with_inner(&device_id)) {
+                Ok(device) => device.clone(),
+                Err(err) => return Err(device_error_to_status(err)),
+            }
+        };
+
+        let result = match (device.clone(), command) {
+            (_, smart_home_contracts::DeviceCommand::Unspecified) => {
+                return Err(Status::invalid_argument("Invalid device command"));
+            }
+            (SmartDeviceType::Socket(mut socket), smart_home_contracts::DeviceCommand::TurnOn) => {
+                socket.turn_on().await
+            }
+            (SmartDeviceType::Socket(mut socket), smart_home_contracts::DeviceCommand::TurnOff) => {
+                socket.turn_off().await
+            }
+            (SmartDeviceType::Light(mut light), smart_home_contracts::DeviceCommand::TurnOn) => {
+                light.turn_on().await
+            }
+            (SmartDeviceType::Light(mut light), smart_home_contracts::DeviceCommand::TurnOff) => {
+                light.turn_off().await
+            }
+            (
+                SmartDeviceType::Light(mut light),
+                smart_home_contracts::DeviceCommand::SetBrightness,
+            ) => match check_brightness(value) {
+                Ok(brightness) => light.set_brightness(brightness).await,
+                Err(err) => Err(err),
+            },
+            (
+                SmartDeviceType::Light(mut light),
+                smart_home_contracts::DeviceCommand::SetColorTemperature,
+            ) => match check_color_temperature(value) {
+                Ok(kelvin) => light.set_color_temperature(kelvin).await,
+                Err(err) => Err(err),
+            },
+            _ => {
+                return Err(Status::invalid_argument(
+                    "Device does not support this command",
+                ));
+            }
+        };
+
+        if let Err(err) = result {
+            warn!("Failed to control device: {device_id}, {err}");
+            return Err(device_error_to_status(err));
+        }
+
+        Ok(device_item(&room_id, &device_id, &device).await)
+    }
+
+    async fn list_unassigned_devices(
+        &self,
+    ) -> Result<Vec<smart_home_contracts::UnassignedDevice>, Status> {
+        let devices = online::unassigned_senders()
+            .await
+            .into_iter()
+            .map(|sender| smart_home_contracts::UnassignedDevice {
+                listen_addr: sender.listener.to_string(),
+                sender_addr: sender.sender.to_string(),
+                device_info: sender.info.map(device_info),
+                last_seen: sender.last_seen,
+                packets: sender.packets,
+            })
+            .collect();
+
+        Ok(devices)
+    }
+
+    async fn update_connection_settings(
+        &self,
+        home_id: impl Into<String>,
+        room_id: impl Into<String>,
+        device_id: impl Into<String>,
+        settings: ConnectionSettings,
+    ) -> Result<Item, Status> {
+        let room_id = room_id.into();
+        let device_id = device_id.into();
+
+        // Блокировка на запись нужна, чтобы изменения сохранялись по очереди
+        let homes = self._inner.write().await;
+
+        let home = if let Some(home) = homes.get(&home_id.into()) {
+            home
+        } else {
+            return Err(Status::not_found("Home not found"));
+        };
+
+        let device = match home.get_device(&Id::with_inner(&room_id), &Id::with_inner(&device_id)) {
+            Ok(device) => device,
+            Err(err) => return Err(device_error_to_status(err)),
+        };
+
+        let connection = if let Some(connection) = device.get_connection() {
+            connection
+        } else {
+            return Err(Status::failed_precondition("Device has no connection"));
+        };
+
+        // Параметры общие для всех клонов устройства,
+        // мониторинг подхватит их на следующей итерации
+        let previous = connection.polling().get();
+        connection.polling().set(polling_settings(&settings));
+
+        if let Err(err) = self.record(&Change::UpdateConnection {
+            home_id: &home.get_id().to_string(),
+            room_id: &room_id,
+            device_id: &device_id,
+            connection: &ConnectionRecord::capture(connection),
+        }) {
+            connection.polling().set(previous);
+            return Err(err);
+        }
+
+        Ok(device_item(&room_id, &device_id, device).await)
+    }
+
+    async fn get_device_history(
+        &self,
+        home_id: impl Into<String>,
+        room_id: impl Into<String>,
+        device_id: impl Into<String>,
+        from: u64,
+        to: u64,
+        step: Option<Duration>,
+    ) -> Result<GetDeviceHistoryResponse, Status> {
+        let room_id = room_id.into();
+        let device_id = device_id.into();
+        let to = if to == 0 { now_millis() + 1 } else { to };
+
+        if from >= to {
+            return Err(Status::invalid_argument("Invalid history interval"));
+        }
+
+        // История разделяется клонами устройства, хранилище не держится во время выборки
+        let history = {
+            let homes = self._inner.read().await;
+
+            let home = if let Some(home) = homes.get(&home_id.into()) {
+                home
+            } else {
+                return Err(Status::not_found("Home not found"));
+            };
+
+            match home.get_device(&Id::with_inner(&room_id), &Id::with_inner(&device_id)) {
+                Ok(device) => device.history().clone(),
+                Err(err) => return Err(device_error_to_status(err)),
+            }
+        };
+
+        Ok(GetDeviceHistoryResponse {
+            unit: history.unit().map(unit_code).unwrap_or_default(),
+            summary: history.summary(from, to).map(history_point),
+            points: history
+                .points(from, to, step)
+                .into_iter()
+                .map(history_point)
+                .collect(),
+        })
+    }
+
+    async fn watch_home(
+        &self,
+        home_id: impl Into<String>,
+    ) -> Result<ReceiverStream<Result<WatchHomeResponse, Status>>, Status> {
+        let home_id = home_id.into();
+
+        let mut events = {
+            let homes = self._inner.read().await;
+
+            if let Some(home) = homes.get(&home_id) {
+                home.listen()
+            } else {
+                return Err(Status::not_found("Home not found"));
+            }
+        };
+
+        let homes = Arc::clone(&self._inner);
+        let (tx, rx) = mpsc::channel(WATCH_BUFFER);
+
+        tokio::spawn(async move {
+            loop {
+                let event = tokio::select! {
+                    event = events.recv() => event,
+                    // Клиент отключился
+                    _ = tx.closed() => break,
+                };
+
+                let response = match event {
+                    Ok(event) => {
+                        let homes = homes.read().await;
+                        watch_response(homes.get(&home_id), event).await
+                    }
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("Watcher of home {home_id} missed {missed} events");
+                        WatchHomeResponse {
+                            event_type: HomeEventType::Lagged.into(),
+                            item: None,
+                            timestamp: now_millis(),
+                            missed,
+                        }
+                    }
+                    // Дом удален
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+
+                if tx.send(Ok(response)).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(ReceiverStream::new(rx))
+    }
+
+    async fn get_report(&self, home_id: impl Into<String>) -> Result<Vec<Item>, Status> {
+        let homes = self._inner.read().await;
+
+        let home = if let Some(home) = homes.get(&home_id.into()) {
+            home
+        } else {
+            return Err(Status::not_found("Home not found"));
+        };
+
+        let home_id = home.get_id().to_string();
+        let mut items: Vec<Item> = vec![home_item(home)];
+
+        // Обход в глубину: за каждой комнатой следуют её устройства,
+        // комнаты и устройства упорядочены по имени, затем по id
+        let mut rooms: Vec<&SmartRoom> = home.get_rooms().values().collect();
+        rooms.sort_by(|a, b| {
+            (a.get_name(), a.get_id().to_string()).cmp(&(b.get_name(), b.get_id().to_string()))
+        });
+
+        for room in rooms {
+            let room_id = room.get_id().to_string();
+            items.push(room_item(&home_id, room));
+
+            let mut devices: Vec<(&String, &SmartDeviceType)> = room.get_devices().iter().collect();
+            devices.sort_by(|a, b| (a.1.get_name(), a.0).cmp(&(b.1.get_name(), b.0)));
+
+            for (device_id, device) in devices {
+                items.push(device_item(&room_id, device_id, device).await);
+            }
+        }
+
+        Ok(items)
+    }
+
+    async fn get_energy_usage(
+        &self,
+        home_id: impl Into<String>,
+        room_id: impl Into<String>,
+        device_id: impl Into<String>,
+    ) -> Result<GetEnergyUsageResponse, Status> {
+        let room_id = room_id.into();
+        let device_id = device_id.into();
+
+        let homes = self._inner.read().await;
+
+        let home = if let Some(home) = homes.get(&home_id.into()) {
+            home
+        } else {
+            return Err(Status::not_found("Home not found"));
+        };
+
+        if room_id.is_empty() {
+            return Ok(energy_response(home.energy_usage()));
+        }
+
+        let room = if let Some(room) = home.get_room(&Id::with_inner(&room_id)) {
+            room
+        } else {
+            return Err(Status::not_found("Room not found"));
+        };
+
+        if device_id.is_empty() {
+            return Ok(energy_response(room.energy_usage()));
+        }
+
+        let device = if let Some(device) = room.get_device(&Id::with_inner(&device_id)) {
+            device
+        } else {
+            return Err(Status::not_found("Device not found"));
+        };
+
+        match device.energy() {
+            Some(meter) => Ok(energy_response(meter.usage())),
+            None => Err(Status::invalid_argument("Device does not meter energy")),
+        }
+    }
+
+    async fn set_tariff(
+        &self,
+        home_id: impl Into<String>,
+        tariff: Option<smart_home_contracts::Tariff>,
+    ) -> Result<(), Status> {
+        let home_id = home_id.into();
+        let plan = match tariff {
+            Some(tariff) => Some(tariff_plan(tariff)?),
+            None => None,
+        };
+
+        let mut homes = self._inner.write().await;
+
+        let home = if let Some(home) = homes.get_mut(&home_id) {
+            home
+        } else {
+            return Err(Status::not_found("Home not found"));
+        };
+
+        self.record(&Change::SetTariff {
+            home_id: &home_id,
+            tariff: plan.as_ref(),
+        })?;
+
+        home.set_tariff(plan);
+
+        Ok(())
+    }
+
+    async fn get_tariff(
+        &self,
+        home_id: impl Into<String>,
+    ) -> Result<Option<smart_home_contracts::Tariff>, Status> {
+        let homes = self._inner.read().await;
+
+        match homes.get(&home_id.into()) {
+            Some(home) => Ok(home.tariff().map(tariff_message)),
+            None => Err(Status::not_found("Home not found")),
+        }
+    }
+
+    async fn get_energy_cost(
+        &self,
+        home_id: impl Into<String>,
+        room_id: impl Into<String>,
+        device_id: impl Into<String>,
+        from: u64,
+        to: u64,
+    ) -> Result<GetEnergyCostResponse, Status> {
+        let room_id = room_id.into();
+        let device_id = device_id.into();
+        let now = now_millis();
+        let to = if to == 0 { now } else { to };
+
+        if from >= to {
+            return Err(Status::invalid_argument("Invalid cost interval"));
+        }
+
+        let homes = self._inner.read().await;
+
+        let home = if let Some(home) = homes.get(&home_id.into()) {
+            home
+        } else {
+            return Err(Status::not_found("Home not found"));
+        };
+
+        let plan = if let Some(plan) = home.tariff() {
+            plan
+        } else {
+            return Err(Status::failed_precondition("Home has no tariff"));
+        };
+
+        if room_id.is_empty() {
+            return Ok(cost_response(home.energy_cost_at(plan, from, to, now)));
+        }
+
+        let room = if let Some(room) = home.get_room(&Id::with_inner(&room_id)) {
+            room
+        } else {
+            return Err(Status::not_found("Room not found"));
+        };
+
+        if device_id.is_empty() {
+            return Ok(cost_response(room.energy_cost_at(plan, from, to, now)));
+        }
+
+        let device = if let Some(device) = room.get_device(&Id::with_inner(&device_id)) {
+            device
+        } else {
+            return Err(Status::not_found("Device not found"));
+        };
+
+        match device.energy() {
+            Some(meter) => Ok(cost_response(plan.meter_cost(meter, from, to, now))),
+            None => Err(Status::invalid_argument("Device does not meter energy")),
+        }
+    }
+
+    async fn create_rule(
+        &self,
+        home_id: impl Into<String>,
+        new_rule: Option<smart_home_contracts::Rule>,
+    ) -> Result<String, Status> {
+        let home_id = home_id.into();
+        let new_rule = match new_rule {
+            Some(new_rule) => rule(new_rule)?,
+            None => return Err(Status::invalid_argument("Rule is required")),
+        };
+
+        let homes = self._inner.write().await;
+
+        let home = if let Some(home) = homes.get(&home_id) {
+            home
+        } else {
+            return Err(Status::not_found("Home not found"));
+        };
+
+        if home.automation().rule(&new_rule.id).is_some() {
+            return Err(Status::already_exists("Rule already exists"));
+        }
+
+        if let Err(err) = home.check_rule(&new_rule) {
+            return Err(Status::invalid_argument(err.to_string()));
+        }
+
+        self.record(&Change::AddRule {
+            home_id: &home_id,
+            rule: &new_rule,
+        })?;
+
+        let rule_id = new_rule.id.clone();
+        home.automation().put(new_rule);
+
+        Ok(rule_id)
+    }
+
+    async fn update_rule(
+        &self,
+        home_id: impl Into<String>,
+        updated: Option<smart_home_contracts::Rule>,
+    ) -> Result<(), Status> {
+        let home_id = home_id.into();
+        let (rule_id, mut updated) = match updated {
+            Some(updated) => (updated.id.clone(), rule(updated)?),
+            None => return Err(Status::invalid_argument("Rule is required")),
+        };
 
-use sh_lib::{
-    id::{self, Id},
-    smart_device::{
-        SmartDevice, SmartDeviceType, SmartSocket, SmartThermometer,
-        online::{ConnectionType, OnlineDevice},
-    },
-    smart_home::SmartHome,
-    smart_room::SmartRoom,
-};
-use tokio::sync::RwLock;
-use tonic::Status;
-use tracing::{info, warn};
+        let homes = self._inner.write().await;
 
-use crate::smart_home_contracts::{self, Item, ItemType, ThermometrValue};
-use crate::{
-    repository::Repository,
-    smart_home_contracts::{ConnectionSettings, SocketValue, item::Value},
-};
+        let home = if let Some(home) = homes.get(&home_id) {
+            home
+        } else {
+            return Err(Status::not_found("Home not found"));
+        };
 
-pub struct Store {
-    _inner: Arc<RwLock<HashMap<String, SmartHome>>>,
-}
+        // Id выводится из имени, поэтому имя правила не меняется
+        let existing = if let Some(existing) = home.automation().rule(&rule_id) {
+            existing
+        } else {
+            return Err(Status::not_found("Rule not found"));
+        };
+        updated.id = existing.id;
+        updated.name = existing.name;
 
-impl Store {
-    pub fn new() -> Self {
-        Self {
-            _inner: Arc::new(RwLock::new(HashMap::new())),
+        if let Err(err) = home.check_rule(&updated) {
+            return Err(Status::invalid_argument(err.to_string()));
         }
+
+        self.record(&Change::UpdateRule {
+            home_id: &home_id,
+            rule: &updated,
+        })?;
+
+        home.automation().put(updated);
+
+        Ok(())
     }
-}
 
-impl Repository for Store {
-    async fn add_home(&self, name: impl Into<String>) -> Result<String, Status> {
-        let mut homes = self._inner.write().await;
-        let new_home = SmartHome::new(name);
+    async fn delete_rule(
+        &self,
+        home_id: impl Into<String>,
+        rule_id: impl Into<String>,
+    ) -> Result<(), Status> {
+        let home_id = home_id.into();
+        let rule_id = rule_id.into();
 
-        if homes.contains_key(&new_home.get_id().to_string()) {
-            return Err(Status::already_exists("Home already exists"));
+        let homes = self._inner.write().await;
+
+        let home = if let Some(home) = homes.get(&home_id) {
+            home
+        } else {
+            return Err(Status::not_found("Home not found"));
+        };
+
+        if home.automation().rule(&rule_id).is_none() {
+            return Err(Status::not_found("Rule not found"));
         }
 
-        let home_id = new_home.get_id().clone();
-        homes.insert(home_id.to_string(), new_home);
+        self.record(&Change::DeleteRule {
+            home_id: &home_id,
+            rule_id: &rule_id,
+        })?;
 
-        Ok(home_id.to_string())
+        home.automation().remove(&rule_id);
+
+        Ok(())
     }
 
-    async fn delete_home(&self, home_id: impl Into<String>) -> Result<(), Status> {
-        let mut homes = self._inner.write().await;
+    async fn list_rules(
+        &self,
+        home_id: impl Into<String>,
+    ) -> Result<Vec<smart_home_contracts::Rule>, Status> {
+        let homes = self._inner.read().await;
 
-        match homes.remove(&home_id.into()) {
-            Some(_) => Ok(()),
+        match homes.get(&home_id.into()) {
+            Some(home) => Ok(home.automation().rules().iter().map(rule_message).collect()),
             None => Err(Status::not_found("Home not found")),
         }
     }
 
-    async fn add_room(
+    async fn get_rule_log(
         &self,
         home_id: impl Into<String>,
-        name: impl Into<String>,
+        rule_id: impl Into<String>,
+    ) -> Result<Vec<smart_home_contracts::RuleExecution>, Status> {
+        let rule_id = rule_id.into();
+        let homes = self._inner.read().await;
+
+        let home = if let Some(home) = homes.get(&home_id.into()) {
+            home
+        } else {
+            return Err(Status::not_found("Home not found"));
+        };
+
+        Ok(home
+            .automation()
+            .log()
+            .into_iter()
+            .filter(|execution| rule_id.is_empty() || execution.rule_id == rule_id)
+            .map(execution_message)
+            .collect())
+    }
+
+    async fn create_schedule(
+        &self,
+        home_id: impl Into<String>,
+        new_schedule: Option<smart_home_contracts::Schedule>,
     ) -> Result<String, Status> {
-        let mut homes = self._inner.write().await;
+        let home_id = home_id.into();
+        let mut new_schedule = match new_schedule {
+            Some(new_schedule) => schedule(new_schedule)?,
+            None => return Err(Status::invalid_argument("Schedule is required")),
+        };
 
-        let home = if let Some(home) = homes.get_mut(&home_id.into()) {
+        let now = now_millis();
+        if matches!(new_schedule.when, schedule::When::Once { at } if at <= now) {
+            return Err(Status::invalid_argument("Schedule time is in the past"));
+        }
+        // Запуски до создания не считаются пропущенными
+        new_schedule.since = now;
+
+        let homes = self._inner.write().await;
+
+        let home = if let Some(home) = homes.get(&home_id) {
             home
         } else {
             return Err(Status::not_found("Home not found"));
         };
 
-        let room = SmartRoom::new(name);
+        if home.scheduler().schedule(&new_schedule.id).is_some() {
+            return Err(Status::already_exists("Schedule already exists"));
+        }
 
-        if home.get_room(room.get_id()).is_some() {
-            return Err(Status::already_exists("Room already exists in home"));
+        if let Err(err) = home.check_schedule(&new_schedule) {
+            return Err(Status::invalid_argument(err.to_string()));
         }
 
-        let room_id = home.add_room(room);
+        self.record(&Change::AddSchedule {
+            home_id: &home_id,
+            schedule: &new_schedule,
+        })?;
 
-        Ok(room_id.to_string())
+        let schedule_id = new_schedule.id.clone();
+        home.scheduler().put(new_schedule);
+
+        Ok(schedule_id)
     }
 
-    async fn delete_room(
+    async fn list_schedules(
         &self,
         home_id: impl Into<String>,
-        room_id: impl Into<String>,
+    ) -> Result<Vec<smart_home_contracts::Schedule>, Status> {
+        let homes = self._inner.read().await;
+
+        match homes.get(&home_id.into()) {
+            Some(home) => Ok(home
+                .scheduler()
+                .schedules()
+                .iter()
+                .map(schedule_message)
+                .collect()),
+            None => Err(Status::not_found("Home not found")),
+        }
+    }
+
+    async fn pause_schedule(
+        &self,
+        home_id: impl Into<String>,
+        schedule_id: impl Into<String>,
+        paused: bool,
     ) -> Result<(), Status> {
-        let mut homes = self._inner.write().await;
+        let home_id = home_id.into();
+        let schedule_id = schedule_id.into();
 
-        let home = if let Some(home) = homes.get_mut(&home_id.into()) {
+        let homes = self._inner.write().await;
+
+        let home = if let Some(home) = homes.get(&home_id) {
+            home
+        } else {
+            return Err(Status::not_found("Home not found"));
+        };
+
+        let mut updated = if let Some(existing) = home.scheduler().schedule(&schedule_id) {
+            existing
+        } else {
+            return Err(Status::not_found("Schedule not found"));
+        };
+        updated.set_paused(paused, now_millis());
+
+        self.record(&Change::UpdateSchedule {
+            home_id: &home_id,
+            schedule: &updated,
+        })?;
+
+        home.scheduler().put(updated);
+
+        Ok(())
+    }
+
+    async fn delete_schedule(
+        &self,
+        home_id: impl Into<String>,
+        schedule_id: impl Into<String>,
+    ) -> Result<(), Status> {
+        let home_id = home_id.into();
+        let schedule_id = schedule_id.into();
+
+        let homes = self._inner.write().await;
+
+        let home = if let Some(home) = homes.get(&home_id) {
             home
         } else {
             return Err(Status::not_found("Home not found"));
         };
 
-        match home.delete_room(&Id::with_inner(room_id)) {
-            Some(_) => Ok(()),
-            None => Err(Status::not_found("Room not found")),
+        if home.scheduler().schedule(&schedule_id).is_none() {
+            return Err(Status::not_found("Schedule not found"));
         }
+
+        self.record(&Change::DeleteSchedule {
+            home_id: &home_id,
+            schedule_id: &schedule_id,
+        })?;
+
+        home.scheduler().remove(&schedule_id);
+
+        Ok(())
     }
 
-    async fn add_device(
+    async fn create_scene(
         &self,
         home_id: impl Into<String>,
-        room_id: impl Into<String>,
-        device_type: smart_home_contracts::DeviceType,
-        device_name: String,
-        connection: Option<smart_home_contracts::ConnectionSettings>,
+        new_scene: Option<smart_home_contracts::Scene>,
     ) -> Result<String, Status> {
+        let home_id = home_id.into();
+        let new_scene = match new_scene {
+            Some(new_scene) => scene(new_scene)?,
+            None => return Err(Status::invalid_argument("Scene is required")),
+        };
+
         let mut homes = self._inner.write().await;
 
-        let home = if let Some(home) = homes.get_mut(&home_id.into()) {
+        let home = if let Some(home) = homes.get_mut(&home_id) {
             home
         } else {
             return Err(Status::not_found("Home not found"));
         };
 
-        let room = if let Some(room) = home.get_room_mut(&Id::with_inner(room_id)) {
-            room
+        if home.scene(&new_scene.id).is_some() {
+            return Err(Status::already_exists("Scene already exists"));
+        }
+
+        if let Err(err) = home.check_scene(&new_scene) {
+            return Err(Status::invalid_argument(err.to_string()));
+        }
+
+        self.record(&Change::AddScene {
+            home_id: &home_id,
+            scene: &new_scene,
+        })?;
+
+        let scene_id = new_scene.id.clone();
+        home.put_scene(new_scene);
+
+        Ok(scene_id)
+    }
+
+    async fn list_scenes(
+        &self,
+        home_id: impl Into<String>,
+    ) -> Result<Vec<smart_home_contracts::Scene>, Status> {
+        let homes = self._inner.read().await;
+
+        match homes.get(&home_id.into()) {
+            Some(home) => Ok(home.scenes().into_iter().map(scene_message).collect()),
+            None => Err(Status::not_found("Home not found")),
+        }
+    }
+
+    async fn delete_scene(
+        &self,
+        home_id: impl Into<String>,
+        scene_id: impl Into<String>,
+    ) -> Result<(), Status> {
+        let home_id = home_id.into();
+        let scene_id = scene_id.into();
+
+        let mut homes = self._inner.write().await;
+
+        let home = if let Some(home) = homes.get_mut(&home_id) {
+            home
         } else {
-            return Err(Status::not_found("Room not found"));
+            return Err(Status::not_found("Home not found"));
         };
 
-        if room
-            .get_device(&id::Id::from_string(device_name.clone()))
-            .is_some()
-        {
-            return Err(Status::already_exists("Device already exists in room"));
+        if home.scene(&scene_id).is_none() {
+            return Err(Status::not_found("Scene not found"));
         }
 
-        let device = match device_type {
-            smart_home_contracts::DeviceType::Socket => match connection {
-                Some(c) => SmartDeviceType::Socket(SmartSocket::new_with_connection(
-                    device_name,
-                    0.0,
-                    false,
-                    ConnectionType::Tcp {
-                        ip: c.ip.parse().unwrap(),
-                        port: c.port.parse().unwrap(),
-                    },
-                )),
-                None => SmartDeviceType::Socket(SmartSocket::new(device_name, 0.0, false)),
-            },
-            smart_home_contracts::DeviceType::Thermo => match connection {
-                Some(c) => SmartDeviceType::Thermometer(SmartThermometer::new_with_connection(
-                    device_name,
-                    0.0,
-                    ConnectionType::Udp {
-                        bind_ip: c.ip.parse().unwrap(),
-                        bind_port: c.port.parse().unwrap(),
-                    },
-                )),
-                None => SmartDeviceType::Thermometer(SmartThermometer::new(device_name, 0.0)),
-            },
-            _ => {
-                return Err(Status::invalid_argument("Invalid device type"));
-            }
-        };
+        self.record(&Change::DeleteScene {
+            home_id: &home_id,
+            scene_id: &scene_id,
+        })?;
 
-        let device_id = room.add_device(device);
+        home.remove_scene(&scene_id);
 
-        if let Some(device) = room.get_device(&device_id) {
-            info!("Try connecting device: {device_id}");
-            match device.connect().await {
-                Ok(_) => (),
-                Err(err) => {
-                    warn!("Failed to connect device: {device_id}, {err}");
-                }
+        Ok(())
+    }
+
+    async fn apply_scene(
+        &self,
+        home_id: impl Into<String>,
+        scene_id: impl Into<String>,
+    ) -> Result<Vec<smart_home_contracts::SceneResult>, Status> {
+        let scene_id = scene_id.into();
+
+        // Розетки переключаются без блокировки хранилища
+        let job = {
+            let homes = self._inner.read().await;
+
+            let home = if let Some(home) = homes.get(&home_id.into()) {
+                home
+            } else {
+                return Err(Status::not_found("Home not found"));
             };
-        }
 
-        Ok(device_id.to_string())
+            if let Some(job) = home.scene_job(&scene_id) {
+                job
+            } else {
+                return Err(Status::not_found("Scene not found"));
+            }
+        };
+
+        Ok(job
+            .run()
+            .await
+            .into_iter()
+            .map(scene_result_message)
+            .collect())
     }
 
-    async fn delete_device(
+    async fn set_thermostat(
         &self,
         home_id: impl Into<String>,
         room_id: impl Into<String>,
-        device_id: impl Into<String>,
+        new_thermostat: Option<smart_home_contracts::Thermostat>,
     ) -> Result<(), Status> {
+        let home_id = home_id.into();
+        let room_id = room_id.into();
+        let config = match new_thermostat {
+            Some(new_thermostat) => thermostat(new_thermostat)?,
+            None => return Err(Status::invalid_argument("Thermostat is required")),
+        };
+
         let mut homes = self._inner.write().await;
 
-        let home = if let Some(home) = homes.get_mut(&home_id.into()) {
+        let home = if let Some(home) = homes.get_mut(&home_id) {
             home
         } else {
             return Err(Status::not_found("Home not found"));
         };
 
-        let room = if let Some(room) = home.get_room_mut(&Id::with_inner(room_id)) {
+        let room = if let Some(room) = home.get_room_mut(&Id::with_inner(&room_id)) {
             room
         } else {
             return Err(Status::not_found("Room not found"));
         };
 
-        match room.delete_device(&Id::with_inner(device_id.into())) {
-            Some(_) => Ok(()),
-            None => Err(Status::not_found("Device not found")),
+        if let Err(err) = room.check_thermostat(&config) {
+            return Err(Status::invalid_argument(err.to_string()));
         }
-    }
-
-    async fn list_homes(&self) -> Result<Vec<Item>, Status> {
-        let homes = self._inner.read().await;
 
-        let mut items: Vec<Item> = homes
-            .values()
-            .map(|home| Item {
-                id: home.get_id().to_string(),
-                name: home.get_name().to_string(),
-                item_type: ItemType::Home.into(),
-                device_connection: None,
-                value: None,
-            })
-            .collect();
+        self.record(&Change::SetThermostat {
+            home_id: &home_id,
+            room_id: &room_id,
+            thermostat: Some(&config),
+        })?;
 
-        items.sort_by(|a, b| a.name.cmp(&b.name));
+        room.set_thermostat(Some(config));
 
-        Ok(items)
+        Ok(())
     }
 
-    async fn list_rooms(&self, home_id: impl Into<String>) -> Result<Vec<Item>, Status> {
+    async fn get_thermostat(
+        &self,
+        home_id: impl Into<String>,
+        room_id: impl Into<String>,
+    ) -> Result<smart_home_contracts::ThermostatStatus, Status> {
+        let room_id = room_id.into();
+
         let homes = self._inner.read().await;
 
         let home = if let Some(home) = homes.get(&home_id.into()) {
@@ -225,95 +2179,144 @@ impl Repository for Store {
             return Err(Status::not_found("Home not found"));
         };
 
-        let mut items: Vec<Item> = home
-            .get_rooms()
-            .values()
-            .map(|room| Item {
-                id: room.get_id().to_string(),
-                name: room.get_name().to_string(),
-                item_type: ItemType::Room.into(),
-                device_connection: None,
-                value: None,
-            })
-            .collect();
-
-        items.sort_by(|a, b| a.name.cmp(&b.name));
+        let room = if let Some(room) = home.get_room(&Id::with_inner(&room_id)) {
+            room
+        } else {
+            return Err(Status::not_found("Room not found"));
+        };
 
-        Ok(items)
+        match room.thermostat() {
+            Some(thermostat) => Ok(thermostat_status(thermostat.report())),
+            None => Err(Status::not_found("Thermostat not found")),
+        }
     }
 
-    async fn list_devices(
+    async fn delete_thermostat(
         &self,
         home_id: impl Into<String>,
         room_id: impl Into<String>,
-    ) -> Result<Vec<Item>, Status> {
-        let homes = self._inner.read().await;
+    ) -> Result<(), Status> {
+        let home_id = home_id.into();
+        let room_id = room_id.into();
 
-        let home = if let Some(home) = homes.get(&home_id.into()) {
+        let mut homes = self._inner.write().await;
+
+        let home = if let Some(home) = homes.get_mut(&home_id) {
             home
         } else {
             return Err(Status::not_found("Home not found"));
         };
 
-        let room = if let Some(room) = home.get_room(&Id::with_inner(room_id)) {
+        let room = if let Some(room) = home.get_room_mut(&Id::with_inner(&room_id)) {
             room
         } else {
             return Err(Status::not_found("Room not found"));
         };
 
-        let mut items: Vec<Item> = vec![];
-
-        for (device_id, device) in room.get_devices() {
-            let data = device.get_data().await;
-            let connection: Option<ConnectionSettings> =
-                device
-                    .get_connection()
-                    .map(|connection| ConnectionSettings {
-                        ip: connection.get_addr().ip().to_string(),
-                        port: format!("{}", connection.get_addr().port()),
-                        service: match connection {
-                            ConnectionType::Tcp { .. } => "TCP".to_string(),
-                            ConnectionType::Udp { .. } => "UDP".to_string(),
-                        },
-                    });
-
-            println!("data: {:?}, connection: {:?}", data, connection);
-
-            let device_data = device.get_data().await;
-
-            match device {
-                SmartDeviceType::Socket(_) => {
-                    items.push(Item {
-                        id: device_id.to_string(),
-                        name: device.get_name().to_string(),
-                        item_type: ItemType::Socket.into(),
-                        device_connection: connection,
-                        value: Some(Value::SocketValue(SocketValue {
-                            is_on: device_data.as_socket().is_on,
-                            power: device_data.as_socket().power,
-                            timestamp: device_data.as_socket().timestamp,
-                            is_online: device_data.as_socket().is_online,
-                        })),
-                    });
-                }
-                SmartDeviceType::Thermometer(_) => {
-                    items.push(Item {
-                        id: device_id.to_string(),
-                        name: device.get_name().to_string(),
-                        item_type: ItemType::Thermo.into(),
-                        device_connection: connection,
-                        value: Some(Value::ThermoValue(ThermometrValue {
-                            is_online: device_data.as_thermometer().is_online,
-                            temp: device_data.as_thermometer().temp,
-                            timestamp: device_data.as_thermometer().timestamp,
-                        })),
-                    });
-                }
-            }
+        if room.thermostat().is_none() {
+            return Err(Status::not_found("Thermostat not found"));
         }
 
-        items.sort_by(|a, b| a.name.cmp(&b.name));
+        self.record(&Change::SetThermostat {
+            home_id: &home_id,
+            room_id: &room_id,
+            thermostat: None,
+        })?;
 
-        Ok(items)
+        room.set_thermostat(None);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+    use crate::persistence::{JsonFileStorage, SqliteStorage};
+
+    /// Дерево переживает перезапуск и отражает последние изменения
+    async fn reopen_restores_tree<S, F>(path: &Path, open: F)
+    where
+        S: Storage + 'static,
+        F: Fn(&Path) -> S,
+    {
+        let store = Store::open(open(path)).await.unwrap();
+        let home_id = store.add_home("Дом").await.unwrap();
+        let room_id = store.add_room(&home_id, "Кухня").await.unwrap();
+        store.add_room(&home_id, "Спальня").await.unwrap();
+        store
+            .add_device(
+                &home_id,
+                &room_id,
+                smart_home_contracts::DeviceType::Socket,
+                "Чайник".to_string(),
+                None,
+            )
+            .await
+            .unwrap();
+        let tree = |items: Vec<Item>| {
+            items
+                .into_iter()
+                .map(|item| (item.id, item.name, item.parent_id))
+                .collect::<Vec<_>>()
+        };
+        let tariff = smart_home_contracts::Tariff {
+            currency: "RUB".to_string(),
+            utc_offset_minutes: 180,
+            kind: Some(Kind::Flat(smart_home_contracts::FlatTariff { price: 6.5 })),
+        };
+        store
+            .set_tariff(&home_id, Some(tariff.clone()))
+            .await
+            .unwrap();
+        let report = tree(store.get_report(&home_id).await.unwrap());
+        drop(store);
+
+        let store = Store::open(open(path)).await.unwrap();
+        assert_eq!(tree(store.get_report(&home_id).await.unwrap()), report);
+        assert_eq!(store.get_tariff(&home_id).await.unwrap(), Some(tariff));
+        assert_eq!(
+            store.add_room(&home_id, "Кухня").await.unwrap_err().code(),
+            tonic::Code::AlreadyExists
+        );
+
+        store.delete_room(&home_id, &room_id).await.unwrap();
+        drop(store);
+
+        let store = Store::open(open(path)).await.unwrap();
+        let rooms = store.list_rooms(&home_id).await.unwrap();
+        assert_eq!(
+            rooms.iter().map(|r| r.name.as_str()).collect::<Vec<_>>(),
+            vec!["Спальня"]
+        );
+    }
+
+    fn temp_dir(test: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("grpc_api-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn json_store_survives_restart() {
+        let dir = temp_dir("store-json");
+
+        reopen_restores_tree(&dir.join("store.json"), |path| JsonFileStorage::new(path)).await;
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sqlite_store_survives_restart() {
+        let dir = temp_dir("store-sqlite");
+
+        reopen_restores_tree(&dir.join("store.db"), |path| {
+            SqliteStorage::open(path).unwrap()
+        })
+        .await;
+
+        fs::remove_dir_all(dir).unwrap();
     }
 }
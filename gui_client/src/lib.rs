@@ -15,6 +15,7 @@ impl From<i32> for DeviceType {
         match item_type {
             3 => DeviceType::Socket,
             4 => DeviceType::Thermo,
+            5 => DeviceType::Light,
             _ => panic!("Unknown device type"),
         }
     }
@@ -328,10 +329,37 @@ fn to_slint_device(device: smart_home_contracts::Item) -> Device {
             timestamp: "".into(),
         }
     };
+    let light_data = if let Some(smart_home_contracts::item::Value::LightValue(lv)) = device.value {
+        LightData {
+            is_on: lv.is_on,
+            brightness: lv.brightness.to_string().into(),
+            // 0 - светильник не поддерживает цветовую температуру
+            color_temperature: if lv.color_temperature == 0 {
+                "".into()
+            } else {
+                lv.color_temperature.to_string().into()
+            },
+            timestamp: chrono::Utc
+                .timestamp_millis_opt(lv.timestamp as i64)
+                .single()
+                .unwrap()
+                .to_string()
+                .into(),
+        }
+    } else {
+        LightData {
+            is_on: false,
+            brightness: "0".into(),
+            color_temperature: "".into(),
+            timestamp: "".into(),
+        }
+    };
     let is_online = if let Some(smart_home_contracts::item::Value::SocketValue(sv)) = device.value {
         sv.is_online
     } else if let Some(smart_home_contracts::item::Value::ThermoValue(tv)) = device.value {
         tv.is_online
+    } else if let Some(smart_home_contracts::item::Value::LightValue(lv)) = device.value {
+        lv.is_online
     } else {
         false
     };
@@ -347,6 +375,7 @@ fn to_slint_device(device: smart_home_contracts::Item) -> Device {
         },
         socket_data,
         thermo_data,
+        light_data,
         is_online,
     }
 }
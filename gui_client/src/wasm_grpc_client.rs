@@ -204,6 +204,7 @@ pub async fn add_device(
                 port,
                 // UDP или TCP будет выбрано на сервере, зависит от типа устройства
                 service: "".to_string(),
+                ..ConnectionSettings::default()
             })
         },
     });
@@ -33,8 +33,10 @@ fn make_home() -> SmartHome {
 async fn turn_off_all_sockets(home: &mut SmartHome) {
     for room in home.get_rooms_mut().values_mut() {
         for device in room.get_devices_mut().values_mut() {
-            if let SmartDeviceType::Socket(s) = device {
-                s.turn_off().await;
+            if let SmartDeviceType::Socket(s) = device
+                && let Err(e) = s.turn_off().await
+            {
+                eprintln!("{}", e);
             }
         }
     }
@@ -3,13 +3,10 @@ use std::process::Command;
 use std::sync::{Arc, Mutex};
 
 use sh_lib::{
-    create_room,
+    definition::HomeDefinition,
     reporter::Report,
     rich_console::{TextColor, colored_println},
-    smart_device::{
-        SmartSocket, SmartThermometer,
-        online::{ConnectionType, OnlineDevice},
-    },
+    smart_device::online::OnlineDevice,
     smart_home::SmartHome,
 };
 
@@ -18,82 +15,14 @@ pub async fn print_status_report(smart_object: &impl Report) {
     println!("{}", smart_object.get_status_report().await);
 }
 
+/// Дом описан в `home.toml` рядом с примером
 fn make_home() -> SmartHome {
-    SmartHome::new_with_rooms(
-        "Мой дом",
-        &[
-            create_room!(
-                "Кухня",
-                SmartThermometer::new_with_connection(
-                    "Термометр 1.1",
-                    24.0,
-                    ConnectionType::udp("127.0.0.1".parse().unwrap(), 4001)
-                ),
-                SmartSocket::new_with_connection(
-                    "Розетка 1.1",
-                    1000.0,
-                    true,
-                    ConnectionType::Tcp {
-                        ip: "127.0.0.1".parse().unwrap(),
-                        port: 3001,
-                    },
-                ),
-                SmartSocket::new_with_connection(
-                    "Розетка 1.2",
-                    2000.0,
-                    false,
-                    ConnectionType::Tcp {
-                        ip: "127.0.0.1".parse().unwrap(),
-                        port: 3001,
-                    },
-                ),
-                SmartSocket::new_with_connection(
-                    "Розетка 1.3",
-                    1100.25,
-                    true,
-                    ConnectionType::Tcp {
-                        ip: "127.0.0.1".parse().unwrap(),
-                        port: 3001,
-                    },
-                )
-            ),
-            create_room!(
-                "Кабинет",
-                SmartThermometer::new_with_connection(
-                    "Термометр 2.1",
-                    20.0,
-                    ConnectionType::udp("127.0.0.1".parse().unwrap(), 4002)
-                ),
-                SmartSocket::new_with_connection(
-                    "Розетка 2.1",
-                    1000.0,
-                    true,
-                    ConnectionType::Tcp {
-                        ip: "127.0.0.1".parse().unwrap(),
-                        port: 3001,
-                    },
-                ),
-                SmartSocket::new_with_connection(
-                    "Розетка 2.2",
-                    2000.0,
-                    false,
-                    ConnectionType::Tcp {
-                        ip: "127.0.0.1".parse().unwrap(),
-                        port: 3001,
-                    },
-                ),
-                SmartSocket::new_with_connection(
-                    "Розетка 2.3",
-                    1100.25,
-                    true,
-                    ConnectionType::Tcp {
-                        ip: "127.0.0.1".parse().unwrap(),
-                        port: 3001,
-                    },
-                ),
-            ),
-        ],
-    )
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/home.toml");
+
+    match HomeDefinition::load(path) {
+        Ok(definition) => definition.build(),
+        Err(e) => panic!("{path}: {e}"),
+    }
 }
 
 fn init_emulators() {
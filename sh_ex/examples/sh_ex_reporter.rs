@@ -1,5 +1,5 @@
 use sh_lib::{
-    reporter::{Report, Reporter},
+    reporter::{ConsoleRenderer, JsonRenderer, MarkdownRenderer, Report, Reporter},
     smart_device::{SmartSocket, SmartThermometer},
     smart_home::SmartHome,
     smart_room::SmartRoom,
@@ -29,4 +29,8 @@ async fn main() {
         .await;
 
     println!("{}", report);
+
+    println!("{}", home.render_report(&ConsoleRenderer).await);
+    println!("{}", home.render_report(&MarkdownRenderer).await);
+    println!("{}", room1.render_report(&JsonRenderer).await);
 }
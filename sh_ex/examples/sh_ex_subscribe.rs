@@ -1,5 +1,7 @@
 use sh_lib::{
+    events::SmartHomeEvent,
     smart_device::{SmartSocket, SmartThermometer},
+    smart_home::SmartHome,
     smart_room::SmartRoom,
     subscriber::Subscribe,
 };
@@ -7,17 +9,32 @@ use sh_lib::{
 struct MySubscriber {}
 
 impl Subscribe for MySubscriber {
-    fn on_event(&mut self, name: String) {
-        println!("MySubscriber: Device added: {}", name);
+    fn on_event(&mut self, event: &SmartHomeEvent) {
+        if let SmartHomeEvent::DeviceAdded { name, .. } = event {
+            println!("MySubscriber: Device added: {}", name);
+        }
     }
 }
 
 #[tokio::main]
 async fn main() {
-    let mut room = SmartRoom::new(String::from("Комната"));
-    room.subscribe(|device_name| println!("Device added: {}", device_name));
+    let mut home = SmartHome::new("Дом");
+    let mut events = home.listen();
+
+    let room_id = home.add_room(SmartRoom::new(String::from("Комната")));
+    let room = home.get_room_mut(&room_id).unwrap();
+
+    let subscription = room.subscribe(|event: &SmartHomeEvent| println!("Event: {:?}", event));
     room.subscribe(MySubscriber {});
 
     room.add_device(SmartThermometer::new(String::from("Термометр"), 24.0));
+    subscription.unsubscribe();
     room.add_device(SmartSocket::new(String::from("Розетка"), 1000.0, true));
+
+    home.delete_room(&room_id);
+
+    // Получатель дома видит события всех комнат
+    while let Ok(event) = events.try_recv() {
+        println!("Home event: {:?}", event);
+    }
 }
@@ -1,4 +1,5 @@
 use sh_lib::create_room;
+use sh_lib::definition::HomeDefinition;
 use sh_lib::smart_device::{SmartSocket, SmartThermometer};
 use sh_lib::smart_home::SmartHome;
 use sh_lib::smart_room::SmartRoom;
@@ -44,3 +45,14 @@ fn add_socket_to_room() {
     room.add_device(SmartSocket::new("Розетка", 1000.0, true));
     assert_eq!(room.get_devices().len(), 1);
 }
+
+#[test]
+fn example_home_definition_is_valid() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/home.toml");
+    let home = HomeDefinition::load(path).unwrap().build();
+
+    assert_eq!(home.get_rooms().len(), 2);
+    for room in home.get_rooms().values() {
+        assert_eq!(room.get_devices().len(), 4);
+    }
+}
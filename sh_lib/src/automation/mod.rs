@@ -0,0 +1,414 @@
+mod rule;
+
+pub use rule::{
+    Action, Comparison, Condition, DeviceRef, Metric, Rule, RuleError, SocketCommand, Trigger,
+};
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use serde::Serialize;
+
+use crate::smart_device::{SmartSocket, contracts::DeviceData};
+
+/// Сколько срабатываний хранит журнал
+const LOG_CAPACITY: usize = 200;
+
+/// Состояние правила между проверками
+#[derive(Debug, Clone, Default)]
+struct RuleState {
+    /// Правило сработало, и показание еще не вернулось за порог с учетом гистерезиса
+    fired: bool,
+    /// С какого момента порог пересечен, мс
+    pending_since: Option<u64>,
+    last_fired: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct AutomationInner {
+    rules: BTreeMap<String, (Rule, RuleState)>,
+    log: VecDeque<RuleExecution>,
+}
+
+/// Сработавшее правило
+#[derive(Debug, Clone, PartialEq)]
+pub struct Firing {
+    pub rule_id: String,
+    pub rule_name: String,
+    /// Время срабатывания, мс от начала эпохи
+    pub timestamp: u64,
+    /// Чем вызвано срабатывание, например "температура 26.5 > 25"
+    pub reason: String,
+    pub actions: Vec<Action>,
+}
+
+/// Результат действия, `error` не заполнен при успехе
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ActionResult {
+    pub action: Action,
+    pub error: Option<String>,
+}
+
+/// Запись журнала срабатываний
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RuleExecution {
+    pub rule_id: String,
+    pub rule_name: String,
+    pub timestamp: u64,
+    pub reason: String,
+    pub results: Vec<ActionResult>,
+}
+
+/// Правила автоматизации дома и журнал их срабатываний.
+///
+/// Правила проверяются по показаниям устройств: при каждом изменении и периодически,
+/// чтобы сработали правила с выдержкой `hold_ms`. Клоны разделяют правила и журнал.
+#[derive(Debug, Clone, Default)]
+pub struct Automation {
+    inner: Arc<Mutex<AutomationInner>>,
+}
+
+impl Automation {
+    /// Правила, упорядоченные по имени
+    pub fn rules(&self) -> Vec<Rule> {
+        let mut rules: Vec<Rule> = self
+            .inner
+            .lock()
+            .unwrap()
+            .rules
+            .values()
+            .map(|(rule, _)| rule.clone())
+            .collect();
+        rules.sort_by(|a, b| (&a.name, &a.id).cmp(&(&b.name, &b.id)));
+        rules
+    }
+
+    pub fn rule(&self, rule_id: &str) -> Option<Rule> {
+        self.inner
+            .lock()
+            .unwrap()
+            .rules
+            .get(rule_id)
+            .map(|(rule, _)| rule.clone())
+    }
+
+    /// Добавить или заменить правило, состояние замененного правила сбрасывается
+    pub fn put(&self, rule: Rule) {
+        self.inner
+            .lock()
+            .unwrap()
+            .rules
+            .insert(rule.id.clone(), (rule, RuleState::default()));
+    }
+
+    pub fn remove(&self, rule_id: &str) -> Option<Rule> {
+        self.inner
+            .lock()
+            .unwrap()
+            .rules
+            .remove(rule_id)
+            .map(|(rule, _)| rule)
+    }
+
+    /// Устройства, показания которых нужны включенным правилам
+    pub fn watched_devices(&self) -> BTreeSet<DeviceRef> {
+        self.inner
+            .lock()
+            .unwrap()
+            .rules
+            .values()
+            .filter(|(rule, _)| rule.enabled)
+            .flat_map(|(rule, _)| {
+                std::iter::once(rule.trigger.device.clone())
+                    .chain(rule.conditions.iter().map(|c| c.device().clone()))
+            })
+            .collect()
+    }
+
+    /// Проверить правила по показаниям в момент `now`.
+    ///
+    /// В `readings` - только показания устройств на связи: без показания
+    /// триггер не срабатывает, а условие считается невыполненным.
+    pub fn evaluate_at(&self, now: u64, readings: &HashMap<DeviceRef, DeviceData>) -> Vec<Firing> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut firings = vec![];
+
+        for (rule, state) in inner.rules.values_mut() {
+            if !rule.enabled {
+                *state = RuleState::default();
+                continue;
+            }
+
+            let trigger = &rule.trigger;
+            let value = match readings
+                .get(&trigger.device)
+                .and_then(|data| trigger.metric.value(data))
+            {
+                Some(value) => value,
+                None => {
+                    state.pending_since = None;
+                    continue;
+                }
+            };
+
+            if state.fired {
+                if !trigger.released(value) {
+                    continue;
+                }
+                state.fired = false;
+            }
+
+            if !trigger.comparison.holds(value, trigger.threshold) {
+                state.pending_since = None;
+                continue;
+            }
+
+            let since = *state.pending_since.get_or_insert(now);
+            if now.saturating_sub(since) < trigger.hold_ms {
+                continue;
+            }
+
+            let cooling_down = state
+                .last_fired
+                .is_some_and(|last| now.saturating_sub(last) < rule.cooldown_ms);
+            let conditions_hold = rule.conditions.iter().all(|condition| {
+                readings
+                    .get(condition.device())
+                    .is_some_and(|data| condition.holds(data))
+            });
+            if cooling_down || !conditions_hold {
+                continue;
+            }
+
+            *state = RuleState {
+                fired: true,
+                pending_since: None,
+                last_fired: Some(now),
+            };
+
+            firings.push(Firing {
+                rule_id: rule.id.clone(),
+                rule_name: rule.name.clone(),
+                timestamp: now,
+                reason: format!(
+                    "{} {} {} {}",
+                    trigger.metric, value, trigger.comparison, trigger.threshold
+                ),
+                actions: rule.actions.clone(),
+            });
+        }
+
+        firings
+    }
+
+    /// Записать срабатывание в журнал, старые записи вытесняются
+    pub fn record(&self, execution: RuleExecution) {
+        let mut inner = self.inner.lock().unwrap();
+
+        inner.log.push_back(execution);
+        if inner.log.len() > LOG_CAPACITY {
+            inner.log.pop_front();
+        }
+    }
+
+    /// Журнал срабатываний, от старых к новым
+    pub fn log(&self) -> Vec<RuleExecution> {
+        self.inner.lock().unwrap().log.iter().cloned().collect()
+    }
+}
+
+/// Сработавшее правило вместе с розетками для его действий.
+///
+/// Розетки - клоны устройств дома, поэтому задание выполняется без блокировки дома.
+#[derive(Debug)]
+pub struct AutomationJob {
+    pub(crate) firing: Firing,
+    /// Розетка для каждого действия или причина, по которой ее нет
+    pub(crate) sockets: Vec<Result<SmartSocket, String>>,
+    pub(crate) automation: Automation,
+}
+
+impl AutomationJob {
+    pub fn firing(&self) -> &Firing {
+        &self.firing
+    }
+
+    /// Выполнить действия по очереди и записать результат в журнал
+    pub async fn run(self) -> RuleExecution {
+        let results = run_actions(self.firing.actions, self.sockets).await;
+
+        let execution = RuleExecution {
+            rule_id: self.firing.rule_id,
+            rule_name: self.firing.rule_name,
+            timestamp: self.firing.timestamp,
+            reason: self.firing.reason,
+            results,
+        };
+        self.automation.record(execution.clone());
+
+        execution
+    }
+}
+
+/// Выполнить действия по очереди, `sockets` - розетка для каждого действия
+pub(crate) async fn run_actions(
+    actions: Vec<Action>,
+    sockets: Vec<Result<SmartSocket, String>>,
+) -> Vec<ActionResult> {
+    let mut results = vec![];
+
+    for (action, socket) in actions.into_iter().zip(sockets) {
+        let result = match socket {
+            Ok(mut socket) => match action.command {
+                SocketCommand::TurnOn => socket.turn_on().await,
+                SocketCommand::TurnOff => socket.turn_off().await,
+            }
+            .map_err(|err| err.to_string()),
+            Err(err) => Err(err),
+        };
+
+        results.push(ActionResult {
+            action,
+            error: result.err(),
+        });
+    }
+
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        id::Id,
+        smart_device::{
+            SmartThermometer, smart_socket::SocketData, smart_thermometer::ThermometerData,
+        },
+        smart_home::SmartHome,
+        smart_room::SmartRoom,
+    };
+
+    const SECOND: u64 = 1000;
+
+    fn device(name: &str) -> DeviceRef {
+        DeviceRef::new(&Id::from_string("Кухня"), &Id::from_string(name))
+    }
+
+    fn overheat() -> Rule {
+        Rule::new(
+            "Перегрев",
+            Trigger {
+                device: device("Термометр"),
+                metric: Metric::Temperature,
+                comparison: Comparison::Above,
+                threshold: 25.0,
+                hysteresis: 1.0,
+                hold_ms: 0,
+            },
+            vec![Action {
+                device: device("Розетка"),
+                command: SocketCommand::TurnOff,
+            }],
+        )
+    }
+
+    fn temp(value: f32) -> HashMap<DeviceRef, DeviceData> {
+        HashMap::from([(
+            device("Термометр"),
+            DeviceData::Thermometer(ThermometerData::new(value)),
+        )])
+    }
+
+    #[test]
+    fn hysteresis_rearms_rule() {
+        let automation = Automation::default();
+        automation.put(overheat());
+
+        assert!(automation.evaluate_at(0, &temp(24.0)).is_empty());
+        let firings = automation.evaluate_at(SECOND, &temp(25.5));
+        assert_eq!(firings.len(), 1);
+        assert_eq!(firings[0].reason, "температура 25.5 > 25");
+
+        // Колебания около порога не вызывают повторных срабатываний
+        assert!(automation.evaluate_at(2 * SECOND, &temp(24.5)).is_empty());
+        assert!(automation.evaluate_at(3 * SECOND, &temp(26.0)).is_empty());
+
+        assert!(automation.evaluate_at(4 * SECOND, &temp(24.0)).is_empty());
+        assert_eq!(automation.evaluate_at(5 * SECOND, &temp(26.0)).len(), 1);
+    }
+
+    #[test]
+    fn hold_and_cooldown_delay_firing() {
+        let automation = Automation::default();
+        let mut rule = overheat().with_cooldown_ms(60 * SECOND);
+        rule.trigger.hold_ms = 30 * SECOND;
+        automation.put(rule);
+
+        assert!(automation.evaluate_at(0, &temp(26.0)).is_empty());
+        // Провал ниже порога сбрасывает выдержку
+        assert!(automation.evaluate_at(10 * SECOND, &temp(24.9)).is_empty());
+        assert!(automation.evaluate_at(20 * SECOND, &temp(26.0)).is_empty());
+        assert!(automation.evaluate_at(45 * SECOND, &temp(26.0)).is_empty());
+        assert_eq!(automation.evaluate_at(50 * SECOND, &temp(26.0)).len(), 1);
+
+        assert!(automation.evaluate_at(55 * SECOND, &temp(20.0)).is_empty());
+        assert!(automation.evaluate_at(70 * SECOND, &temp(26.0)).is_empty());
+        // Выдержка прошла, но пауза после прошлого срабатывания - еще нет
+        assert!(automation.evaluate_at(105 * SECOND, &temp(26.0)).is_empty());
+        assert_eq!(automation.evaluate_at(110 * SECOND, &temp(26.0)).len(), 1);
+    }
+
+    #[test]
+    fn conditions_need_fresh_readings() {
+        let automation = Automation::default();
+        automation.put(overheat().with_condition(Condition::Socket {
+            device: device("Розетка"),
+            is_on: true,
+        }));
+
+        // Показаний розетки нет - условие не выполнено, правило ждет
+        assert!(automation.evaluate_at(0, &temp(26.0)).is_empty());
+
+        let mut readings = temp(26.0);
+        readings.insert(
+            device("Розетка"),
+            DeviceData::Socket(SocketData::new(1500.0, true)),
+        );
+        assert_eq!(automation.evaluate_at(SECOND, &readings).len(), 1);
+        assert_eq!(
+            automation.watched_devices(),
+            BTreeSet::from([device("Розетка"), device("Термометр")])
+        );
+    }
+
+    #[tokio::test]
+    async fn home_runs_socket_commands_and_logs_them() {
+        let socket = SmartSocket::new("Розетка", 1500.0, true);
+        let kitchen = SmartRoom::new_with_devices(
+            "Кухня",
+            &[
+                socket.clone().into(),
+                SmartThermometer::new("Термометр", 26.0).into(),
+            ],
+        );
+        let home = SmartHome::new_with_rooms("Дом", &[kitchen]);
+
+        let mut missing = overheat();
+        missing.actions[0].device = device("Чайник");
+        assert!(home.check_rule(&missing).is_err());
+
+        home.check_rule(&overheat()).unwrap();
+        home.automation().put(overheat());
+
+        let jobs = home.automate_at(SECOND).await;
+        assert_eq!(jobs.len(), 1);
+        let execution = jobs.into_iter().next().unwrap().run().await;
+
+        assert_eq!(execution.results[0].error, None);
+        assert!(!socket.is_on().await);
+        assert_eq!(home.automation().log(), vec![execution]);
+        assert!(home.automate_at(2 * SECOND).await.is_empty());
+    }
+}
@@ -0,0 +1,269 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{id::Id, smart_device::contracts::DeviceData};
+
+/// Устройство, на которое ссылается правило
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceRef {
+    pub room_id: String,
+    pub device_id: String,
+}
+
+impl DeviceRef {
+    pub fn new(room_id: &Id, device_id: &Id) -> Self {
+        Self {
+            room_id: room_id.to_string(),
+            device_id: device_id.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for DeviceRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.room_id, self.device_id)
+    }
+}
+
+/// Величина, за которой следит правило
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    /// Температура термометра, °C
+    Temperature,
+    /// Мощность розетки, Вт; у выключенной розетки - 0
+    Power,
+}
+
+impl Metric {
+    /// Значение величины в показаниях, `None` - устройство другого типа
+    pub fn value(&self, data: &DeviceData) -> Option<f32> {
+        match (self, data) {
+            (Metric::Temperature, DeviceData::Thermometer(t)) => Some(t.temp),
+            (Metric::Power, DeviceData::Socket(s)) => Some(if s.is_on { s.power } else { 0.0 }),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Metric::Temperature => write!(f, "температура"),
+            Metric::Power => write!(f, "мощность"),
+        }
+    }
+}
+
+/// Направление сравнения с порогом
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparison {
+    Above,
+    Below,
+}
+
+impl Comparison {
+    /// Выполняется ли сравнение `value` с `threshold`
+    pub fn holds(&self, value: f32, threshold: f32) -> bool {
+        match self {
+            Comparison::Above => value > threshold,
+            Comparison::Below => value < threshold,
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Comparison::Above => write!(f, ">"),
+            Comparison::Below => write!(f, "<"),
+        }
+    }
+}
+
+/// Условие срабатывания правила: показание устройства пересекло порог.
+///
+/// После срабатывания правило снова взводится, только когда показание вернется
+/// за порог с запасом `hysteresis`, например для `Above` - опустится до `threshold - hysteresis`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Trigger {
+    pub device: DeviceRef,
+    pub metric: Metric,
+    pub comparison: Comparison,
+    pub threshold: f32,
+    #[serde(default)]
+    pub hysteresis: f32,
+    /// Сколько порог должен оставаться пересеченным до срабатывания, мс
+    #[serde(default)]
+    pub hold_ms: u64,
+}
+
+impl Trigger {
+    /// Вернулось ли показание за порог с учетом гистерезиса
+    pub(crate) fn released(&self, value: f32) -> bool {
+        match self.comparison {
+            Comparison::Above => value <= self.threshold - self.hysteresis,
+            Comparison::Below => value >= self.threshold + self.hysteresis,
+        }
+    }
+}
+
+/// Дополнительное условие, проверяется в момент срабатывания
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Condition {
+    /// Показание другого устройства относительно порога
+    Reading {
+        device: DeviceRef,
+        metric: Metric,
+        comparison: Comparison,
+        threshold: f32,
+    },
+    /// Розетка включена или выключена
+    Socket { device: DeviceRef, is_on: bool },
+}
+
+impl Condition {
+    pub fn device(&self) -> &DeviceRef {
+        match self {
+            Condition::Reading { device, .. } | Condition::Socket { device, .. } => device,
+        }
+    }
+
+    /// Выполняется ли условие для показаний устройства
+    pub fn holds(&self, data: &DeviceData) -> bool {
+        match (self, data) {
+            (
+                Condition::Reading {
+                    metric,
+                    comparison,
+                    threshold,
+                    ..
+                },
+                data,
+            ) => metric
+                .value(data)
+                .is_some_and(|value| comparison.holds(value, *threshold)),
+            (Condition::Socket { is_on, .. }, DeviceData::Socket(s)) => s.is_on == *is_on,
+            (Condition::Socket { .. }, _) => false,
+        }
+    }
+}
+
+/// Команда розетке
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SocketCommand {
+    TurnOn,
+    TurnOff,
+}
+
+/// Действие сработавшего правила
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Action {
+    pub device: DeviceRef,
+    pub command: SocketCommand,
+}
+
+/// Правило автоматизации: триггер, условия и действия над розетками
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Выводится из имени, как и id комнат и устройств
+    pub id: String,
+    pub name: String,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    pub trigger: Trigger,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conditions: Vec<Condition>,
+    pub actions: Vec<Action>,
+    /// Наименьший интервал между срабатываниями, мс
+    #[serde(default)]
+    pub cooldown_ms: u64,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// Ошибка в описании правила
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleError(pub String);
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Некорректное правило: {}", self.0)
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+impl Rule {
+    /// Включенное правило без условий и паузы между срабатываниями
+    pub fn new(name: impl Into<String>, trigger: Trigger, actions: Vec<Action>) -> Self {
+        let name = name.into();
+        Self {
+            id: Id::from_string(&name).to_string(),
+            name,
+            enabled: true,
+            trigger,
+            conditions: vec![],
+            actions,
+            cooldown_ms: 0,
+        }
+    }
+
+    pub fn with_condition(mut self, condition: Condition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    pub fn with_cooldown_ms(mut self, cooldown_ms: u64) -> Self {
+        self.cooldown_ms = cooldown_ms;
+        self
+    }
+
+    /// Все устройства, на которые ссылается правило
+    pub fn devices(&self) -> impl Iterator<Item = &DeviceRef> {
+        std::iter::once(&self.trigger.device)
+            .chain(self.conditions.iter().map(Condition::device))
+            .chain(self.actions.iter().map(|action| &action.device))
+    }
+
+    /// Проверить имя, пороги и действия. Существование устройств проверяет дом
+    pub fn validate(&self) -> Result<(), RuleError> {
+        if self.name.trim().is_empty() {
+            return Err(RuleError("пустое имя".to_string()));
+        }
+
+        if !self.trigger.threshold.is_finite() {
+            return Err(RuleError("порог должен быть числом".to_string()));
+        }
+
+        if !self.trigger.hysteresis.is_finite() || self.trigger.hysteresis < 0.0 {
+            return Err(RuleError(
+                "гистерезис должен быть неотрицательным числом".to_string(),
+            ));
+        }
+
+        let thresholds_ok = self.conditions.iter().all(|condition| match condition {
+            Condition::Reading { threshold, .. } => threshold.is_finite(),
+            Condition::Socket { .. } => true,
+        });
+        if !thresholds_ok {
+            return Err(RuleError("порог условия должен быть числом".to_string()));
+        }
+
+        if self.actions.is_empty() {
+            return Err(RuleError("нет действий".to_string()));
+        }
+
+        Ok(())
+    }
+}
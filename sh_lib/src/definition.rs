@@ -0,0 +1,1003 @@
+use std::{collections::HashSet, fmt, marker::PhantomData, path::Path};
+
+use serde::{
+    Deserialize, Deserializer, Serialize,
+    de::{self, MapAccess, SeqAccess, Visitor},
+};
+
+use crate::{
+    smart_device::{
+        SmartDevice, SmartDeviceType, SmartLight, SmartSocket, SmartThermometer,
+        online::ConnectionType, smart_light,
+    },
+    smart_home::SmartHome,
+    smart_room::SmartRoom,
+    tariff::TariffPlan,
+};
+
+/// Описание дома: комнаты, устройства, их начальные значения и параметры подключения.
+///
+/// Загружается из JSON или TOML и превращается в [`SmartHome`] через [`HomeDefinition::build`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HomeDefinition {
+    #[serde(deserialize_with = "not_empty")]
+    pub name: String,
+    #[serde(default, deserialize_with = "unique_names")]
+    pub rooms: Vec<RoomDefinition>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "valid_tariff"
+    )]
+    pub tariff: Option<TariffPlan>,
+}
+
+/// Описание комнаты
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RoomDefinition {
+    #[serde(deserialize_with = "not_empty")]
+    pub name: String,
+    #[serde(default, deserialize_with = "unique_names")]
+    pub devices: Vec<DeviceDefinition>,
+}
+
+/// Описание устройства, вид задается полем `kind`.
+///
+/// Разбирается вручную: производный разбор enum с тегом буферизует поля
+/// и теряет позиции ошибок внутри устройства
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum DeviceDefinition {
+    Socket {
+        name: String,
+        power: f32,
+        is_on: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        connection: Option<ConnectionType>,
+    },
+    Thermometer {
+        name: String,
+        temp: f32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        connection: Option<ConnectionType>,
+    },
+    Light {
+        name: String,
+        brightness: u8,
+        is_on: bool,
+        /// Есть только у светильников с регулируемой цветовой температурой
+        #[serde(skip_serializing_if = "Option::is_none")]
+        color_temperature: Option<u16>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        connection: Option<ConnectionType>,
+    },
+}
+
+/// Формат файла с описанием дома
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionFormat {
+    Json,
+    Toml,
+}
+
+/// Ошибка загрузки описания с позицией в исходном тексте
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefinitionError {
+    /// Номер строки, начиная с 1, или 0, если позиция неизвестна
+    pub line: usize,
+    /// Номер столбца, начиная с 1
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for DefinitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.message)
+        } else {
+            write!(
+                f,
+                "строка {}, столбец {}: {}",
+                self.line, self.column, self.message
+            )
+        }
+    }
+}
+
+impl std::error::Error for DefinitionError {}
+
+impl DefinitionError {
+    fn without_position(message: impl Into<String>) -> Self {
+        Self {
+            line: 0,
+            column: 0,
+            message: message.into(),
+        }
+    }
+
+    fn from_json(err: serde_json::Error) -> Self {
+        // Позиция уже есть в полях, из текста ошибки её убираем
+        let suffix = format!(" at line {} column {}", err.line(), err.column());
+        let message = err.to_string();
+
+        Self {
+            line: err.line(),
+            column: err.column(),
+            message: message
+                .strip_suffix(&suffix)
+                .unwrap_or(&message)
+                .to_string(),
+        }
+    }
+
+    fn from_toml(err: toml::de::Error, text: &str) -> Self {
+        match err.span() {
+            Some(span) => {
+                let before = &text[..span.start.min(text.len())];
+                let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+                Self {
+                    line: before.matches('\n').count() + 1,
+                    column: before[line_start..].chars().count() + 1,
+                    message: err.message().to_string(),
+                }
+            }
+            None => Self::without_position(err.message()),
+        }
+    }
+}
+
+impl DefinitionFormat {
+    /// Определить формат по расширению файла
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "json" => Some(DefinitionFormat::Json),
+            "toml" => Some(DefinitionFormat::Toml),
+            _ => None,
+        }
+    }
+}
+
+impl HomeDefinition {
+    /// Разобрать описание из текста
+    pub fn parse(text: &str, format: DefinitionFormat) -> Result<Self, DefinitionError> {
+        match format {
+            DefinitionFormat::Json => {
+                serde_json::from_str(text).map_err(DefinitionError::from_json)
+            }
+            DefinitionFormat::Toml => {
+                toml::from_str(text).map_err(|err| DefinitionError::from_toml(err, text))
+            }
+        }
+    }
+
+    /// Записать описание в текст
+    pub fn render(&self, format: DefinitionFormat) -> Result<String, DefinitionError> {
+        match format {
+            DefinitionFormat::Json => serde_json::to_string_pretty(self)
+                .map_err(|err| DefinitionError::without_position(err.to_string())),
+            DefinitionFormat::Toml => toml::to_string_pretty(self)
+                .map_err(|err| DefinitionError::without_position(err.to_string())),
+        }
+    }
+
+    /// Загрузить описание из файла `.json` или `.toml`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, DefinitionError> {
+        let path = path.as_ref();
+        let format = format_of(path)?;
+
+        let text = std::fs::read_to_string(path).map_err(|err| {
+            DefinitionError::without_position(format!("{}: {}", path.display(), err))
+        })?;
+
+        Self::parse(&text, format)
+    }
+
+    /// Сохранить описание в файл `.json` или `.toml`
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), DefinitionError> {
+        let path = path.as_ref();
+        let text = self.render(format_of(path)?)?;
+
+        std::fs::write(path, text).map_err(|err| {
+            DefinitionError::without_position(format!("{}: {}", path.display(), err))
+        })
+    }
+
+    /// Описать существующий дом. Комнаты и устройства упорядочены по имени
+    pub async fn capture(home: &SmartHome) -> Self {
+        let mut rooms = vec![];
+        for room in home.get_rooms().values() {
+            rooms.push(RoomDefinition::capture(room).await);
+        }
+        rooms.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Self {
+            name: home.get_name().clone(),
+            rooms,
+            tariff: home.tariff().cloned(),
+        }
+    }
+
+    /// Создать дом по описанию. Устройства создаются неподключёнными
+    pub fn build(&self) -> SmartHome {
+        let rooms: Vec<SmartRoom> = self.rooms.iter().map(RoomDefinition::build).collect();
+
+        let mut home = SmartHome::new_with_rooms(&self.name, &rooms);
+        home.set_tariff(self.tariff.clone());
+        home
+    }
+}
+
+impl RoomDefinition {
+    /// Описать существующую комнату
+    pub async fn capture(room: &SmartRoom) -> Self {
+        let mut devices = vec![];
+        for device in room.get_devices().values() {
+            devices.push(DeviceDefinition::capture(device).await);
+        }
+        devices.sort_by(|a, b| a.name().cmp(b.name()));
+
+        Self {
+            name: room.get_name().clone(),
+            devices,
+        }
+    }
+
+    /// Создать комнату по описанию
+    pub fn build(&self) -> SmartRoom {
+        let devices: Vec<SmartDeviceType> =
+            self.devices.iter().map(DeviceDefinition::build).collect();
+
+        SmartRoom::new_with_devices(&self.name, &devices)
+    }
+}
+
+impl DeviceDefinition {
+    /// Имя устройства
+    pub fn name(&self) -> &str {
+        match self {
+            DeviceDefinition::Socket { name, .. }
+            | DeviceDefinition::Thermometer { name, .. }
+            | DeviceDefinition::Light { name, .. } => name,
+        }
+    }
+
+    /// Описать существующее устройство с его текущими значениями
+    pub async fn capture(device: &SmartDeviceType) -> Self {
+        let connection = device.get_connection().map(ConnectionType::detached);
+
+        match device {
+            SmartDeviceType::Socket(socket) => {
+                // Мощность берется без учета состояния, выключенная розетка помнит свою мощность
+                let value = socket.value.read().await;
+                DeviceDefinition::Socket {
+                    name: socket.get_name().clone(),
+                    power: value.power,
+                    is_on: value.is_on,
+                    connection,
+                }
+            }
+            SmartDeviceType::Thermometer(thermometer) => DeviceDefinition::Thermometer {
+                name: thermometer.get_name().clone(),
+                temp: thermometer.get_data().await.temp,
+                connection,
+            },
+            SmartDeviceType::Light(light) => {
+                let value = light.get_data().await;
+                DeviceDefinition::Light {
+                    name: light.get_name().clone(),
+                    brightness: value.brightness,
+                    is_on: value.is_on,
+                    color_temperature: value.color_temperature,
+                    connection,
+                }
+            }
+        }
+    }
+
+    /// Создать устройство по описанию
+    pub fn build(&self) -> SmartDeviceType {
+        match self {
+            DeviceDefinition::Socket {
+                name,
+                power,
+                is_on,
+                connection,
+            } => SmartDeviceType::Socket(match connection {
+                Some(connection) => {
+                    SmartSocket::new_with_connection(name, *power, *is_on, connection.detached())
+                }
+                None => SmartSocket::new(name, *power, *is_on),
+            }),
+            DeviceDefinition::Thermometer {
+                name,
+                temp,
+                connection,
+            } => SmartDeviceType::Thermometer(match connection {
+                Some(connection) => {
+                    SmartThermometer::new_with_connection(name, *temp, connection.detached())
+                }
+                None => SmartThermometer::new(name, *temp),
+            }),
+            DeviceDefinition::Light {
+                name,
+                brightness,
+                is_on,
+                color_temperature,
+                connection,
+            } => {
+                let light = match connection {
+                    Some(connection) => SmartLight::new_with_connection(
+                        name,
+                        *brightness,
+                        *is_on,
+                        connection.detached(),
+                    ),
+                    None => SmartLight::new(name, *brightness, *is_on),
+                };
+
+                SmartDeviceType::Light(match color_temperature {
+                    Some(kelvin) => light.with_color_temperature(*kelvin),
+                    None => light,
+                })
+            }
+        }
+    }
+}
+
+fn format_of(path: &Path) -> Result<DefinitionFormat, DefinitionError> {
+    DefinitionFormat::from_path(path).ok_or_else(|| {
+        DefinitionError::without_position(format!(
+            "{}: ожидается файл .json или .toml",
+            path.display()
+        ))
+    })
+}
+
+/// Элемент описания, имя которого должно быть уникальным среди соседей
+trait Named {
+    fn name(&self) -> &str;
+}
+
+impl Named for RoomDefinition {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Named for DeviceDefinition {
+    fn name(&self) -> &str {
+        DeviceDefinition::name(self)
+    }
+}
+
+fn valid_tariff<'de, D>(deserializer: D) -> Result<Option<TariffPlan>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let tariff = TariffPlan::deserialize(deserializer)?;
+    tariff.validate().map_err(de::Error::custom)?;
+
+    Ok(Some(tariff))
+}
+
+fn not_empty<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let name = String::deserialize(deserializer)?;
+
+    if name.trim().is_empty() {
+        return Err(de::Error::custom("имя не может быть пустым"));
+    }
+
+    Ok(name)
+}
+
+/// Имя элемента описания, не может быть пустым
+struct Name(String);
+
+impl<'de> Deserialize<'de> for Name {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        not_empty(deserializer).map(Name)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "snake_case")]
+enum DeviceField {
+    Kind,
+    Name,
+    Power,
+    IsOn,
+    Temp,
+    Brightness,
+    ColorTemperature,
+    Connection,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum DeviceKind {
+    Socket,
+    Thermometer,
+    Light,
+}
+
+impl<'de> Deserialize<'de> for DeviceDefinition {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(DeviceVisitor)
+    }
+}
+
+struct DeviceVisitor;
+
+impl<'de> Visitor<'de> for DeviceVisitor {
+    type Value = DeviceDefinition;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "описание устройства")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut kind: Option<DeviceKind> = None;
+        let mut name: Option<Name> = None;
+        let mut power: Option<f32> = None;
+        let mut is_on: Option<bool> = None;
+        let mut temp: Option<f32> = None;
+        let mut brightness: Option<u8> = None;
+        let mut color_temperature: Option<u16> = None;
+        let mut connection: Option<ConnectionType> = None;
+
+        // Каждое значение читается сразу из исходного текста, поэтому ошибка
+        // типа или значения указывает на само поле
+        while let Some(field) = map.next_key::<DeviceField>()? {
+            match field {
+                DeviceField::Kind => once(&mut kind, map.next_value()?, "kind")?,
+                DeviceField::Name => once(&mut name, map.next_value()?, "name")?,
+                DeviceField::Power => once(&mut power, map.next_value()?, "power")?,
+                DeviceField::IsOn => once(&mut is_on, map.next_value()?, "is_on")?,
+                DeviceField::Temp => once(&mut temp, map.next_value()?, "temp")?,
+                DeviceField::Brightness => {
+                    once(&mut brightness, map.next_value()?, "brightness")?;
+                }
+                DeviceField::ColorTemperature => {
+                    once(
+                        &mut color_temperature,
+                        map.next_value()?,
+                        "color_temperature",
+                    )?;
+                }
+                DeviceField::Connection => once(&mut connection, map.next_value()?, "connection")?,
+            }
+        }
+
+        let kind = kind.ok_or_else(|| de::Error::missing_field("kind"))?;
+        let Name(name) = name.ok_or_else(|| de::Error::missing_field("name"))?;
+
+        let has_light_fields = brightness.is_some() || color_temperature.is_some();
+
+        match kind {
+            DeviceKind::Socket => {
+                if has_light_fields {
+                    return Err(de::Error::custom(format!(
+                        "у розетки \"{name}\" нет полей brightness и color_temperature"
+                    )));
+                }
+                if temp.is_some() {
+                    return Err(de::Error::custom(format!(
+                        "у розетки \"{name}\" нет поля temp"
+                    )));
+                }
+                if matches!(connection, Some(ConnectionType::Udp { .. })) {
+                    return Err(de::Error::custom(format!(
+                        "розетка \"{name}\" подключается только по tcp"
+                    )));
+                }
+
+                Ok(DeviceDefinition::Socket {
+                    name,
+                    power: power.unwrap_or_default(),
+                    is_on: is_on.unwrap_or_default(),
+                    connection,
+                })
+            }
+            DeviceKind::Thermometer => {
+                if has_light_fields {
+                    return Err(de::Error::custom(format!(
+                        "у термометра \"{name}\" нет полей brightness и color_temperature"
+                    )));
+                }
+                if power.is_some() || is_on.is_some() {
+                    return Err(de::Error::custom(format!(
+                        "у термометра \"{name}\" нет полей power и is_on"
+                    )));
+                }
+                if matches!(connection, Some(ConnectionType::Tcp { .. })) {
+                    return Err(de::Error::custom(format!(
+                        "термометр \"{name}\" подключается только по udp"
+                    )));
+                }
+
+                Ok(DeviceDefinition::Thermometer {
+                    name,
+                    temp: temp.unwrap_or_default(),
+                    connection,
+                })
+            }
+            DeviceKind::Light => {
+                if power.is_some() || temp.is_some() {
+                    return Err(de::Error::custom(format!(
+                        "у светильника \"{name}\" нет полей power и temp"
+                    )));
+                }
+                if matches!(connection, Some(ConnectionType::Udp { .. })) {
+                    return Err(de::Error::custom(format!(
+                        "светильник \"{name}\" подключается только по tcp"
+                    )));
+                }
+
+                let brightness = smart_light::check_brightness(
+                    brightness.unwrap_or(smart_light::MAX_BRIGHTNESS).into(),
+                )
+                .map_err(de::Error::custom)?;
+                if let Some(kelvin) = color_temperature {
+                    smart_light::check_color_temperature(kelvin.into())
+                        .map_err(de::Error::custom)?;
+                }
+
+                Ok(DeviceDefinition::Light {
+                    name,
+                    brightness,
+                    is_on: is_on.unwrap_or_default(),
+                    color_temperature,
+                    connection,
+                })
+            }
+        }
+    }
+}
+
+fn once<T, E>(slot: &mut Option<T>, value: T, field: &'static str) -> Result<(), E>
+where
+    E: de::Error,
+{
+    if slot.is_some() {
+        return Err(E::duplicate_field(field));
+    }
+
+    *slot = Some(value);
+    Ok(())
+}
+
+/// Разобрать список, проверяя уникальность имён по мере чтения,
+/// чтобы ошибка указывала на повторяющийся элемент, а не на конец списка
+fn unique_names<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Named + Deserialize<'de>,
+{
+    struct UniqueNames<T>(PhantomData<T>);
+
+    impl<'de, T> Visitor<'de> for UniqueNames<T>
+    where
+        T: Named + Deserialize<'de>,
+    {
+        type Value = Vec<T>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "список с уникальными именами")
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let mut names = HashSet::new();
+            let mut items = vec![];
+
+            while let Some(item) = seq.next_element::<T>()? {
+                if !names.insert(item.name().to_string()) {
+                    return Err(de::Error::custom(format!(
+                        "элемент {}: имя \"{}\" уже используется",
+                        items.len() + 1,
+                        item.name()
+                    )));
+                }
+                items.push(item);
+            }
+
+            Ok(items)
+        }
+    }
+
+    deserializer.deserialize_seq(UniqueNames(PhantomData))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::smart_device::online::{PollingSettings, ReconnectPolicy, UdpRoute};
+    use crate::tariff::Tariff;
+
+    fn home() -> HomeDefinition {
+        let ip = "127.0.0.1".parse().unwrap();
+
+        HomeDefinition {
+            name: "Дом".to_string(),
+            rooms: vec![
+                RoomDefinition {
+                    name: "Кабинет".to_string(),
+                    devices: vec![],
+                },
+                RoomDefinition {
+                    name: "Кухня".to_string(),
+                    devices: vec![
+                        DeviceDefinition::Socket {
+                            name: "Розетка".to_string(),
+                            power: 1100.25,
+                            is_on: true,
+                            connection: Some(ConnectionType::Tcp {
+                                ip,
+                                port: 3001,
+                                reconnect: ReconnectPolicy {
+                                    max_attempts: Some(5),
+                                    ..ReconnectPolicy::default()
+                                },
+                                polling: Default::default(),
+                            }),
+                        },
+                        DeviceDefinition::Thermometer {
+                            name: "Термометр".to_string(),
+                            temp: 21.5,
+                            connection: Some(
+                                ConnectionType::udp(ip, 4001)
+                                    .with_route(UdpRoute::Serial("T-1".to_string()))
+                                    .with_polling(PollingSettings {
+                                        offline_threshold: Duration::from_secs(3),
+                                        ..PollingSettings::default()
+                                    }),
+                            ),
+                        },
+                        DeviceDefinition::Socket {
+                            name: "Чайник".to_string(),
+                            power: 2000.0,
+                            is_on: false,
+                            connection: None,
+                        },
+                        DeviceDefinition::Thermometer {
+                            name: "Уличный термометр".to_string(),
+                            temp: -5.0,
+                            connection: None,
+                        },
+                        DeviceDefinition::Light {
+                            name: "Люстра".to_string(),
+                            brightness: 60,
+                            is_on: true,
+                            color_temperature: Some(2700),
+                            connection: Some(ConnectionType::tcp(ip, 3002)),
+                        },
+                    ],
+                },
+            ],
+            tariff: Some(
+                TariffPlan::new(
+                    "RUB",
+                    Tariff::DayNight {
+                        day_price: 7.5,
+                        night_price: 2.5,
+                        night_start_minute: 23 * 60,
+                        night_end_minute: 7 * 60,
+                    },
+                )
+                .with_utc_offset(180),
+            ),
+        }
+    }
+
+    const TOML: &str = r#"
+name = "Дом"
+
+[[rooms]]
+name = "Кухня"
+
+[[rooms.devices]]
+kind = "socket"
+name = "Розетка"
+power = 1000.0
+is_on = true
+connection = { type = "tcp", ip = "127.0.0.1", port = 3001 }
+
+[[rooms.devices]]
+kind = "thermometer"
+name = "Термометр"
+temp = 24.0
+
+[rooms.devices.connection]
+type = "udp"
+ip = "127.0.0.1"
+port = 4001
+route = { serial = "T-1" }
+polling = { offline_threshold_ms = 3000 }
+"#;
+
+    #[test]
+    fn json_roundtrip() {
+        let text = home().render(DefinitionFormat::Json).unwrap();
+
+        assert_eq!(
+            HomeDefinition::parse(&text, DefinitionFormat::Json).unwrap(),
+            home()
+        );
+    }
+
+    #[test]
+    fn toml_roundtrip() {
+        let text = home().render(DefinitionFormat::Toml).unwrap();
+
+        assert_eq!(
+            HomeDefinition::parse(&text, DefinitionFormat::Toml).unwrap(),
+            home()
+        );
+    }
+
+    #[tokio::test]
+    async fn build_capture_roundtrip() {
+        let built = home().build();
+
+        let mut expected = home();
+        expected.rooms[1]
+            .devices
+            .sort_by(|a, b| a.name().cmp(b.name()));
+
+        assert_eq!(HomeDefinition::capture(&built).await, expected);
+    }
+
+    #[test]
+    fn built_devices_do_not_share_polling() {
+        let definition = home();
+        let first = definition.build();
+        let second = definition.build();
+
+        let polling = |home: &SmartHome| {
+            home.get_rooms()
+                .values()
+                .flat_map(|room| room.get_devices().values())
+                .find(|device| device.get_name() == "Розетка")
+                .and_then(|device| device.get_connection().map(|c| c.polling().clone()))
+                .unwrap()
+        };
+
+        polling(&first).set(PollingSettings {
+            poll_interval: Duration::from_millis(1),
+            ..PollingSettings::default()
+        });
+        assert_eq!(polling(&second).get(), PollingSettings::default());
+    }
+
+    #[test]
+    fn parses_handwritten_toml() {
+        let home = HomeDefinition::parse(TOML, DefinitionFormat::Toml).unwrap();
+        let devices = &home.rooms[0].devices;
+
+        assert_eq!(devices.len(), 2);
+        match &devices[1] {
+            DeviceDefinition::Thermometer {
+                connection: Some(connection),
+                ..
+            } => {
+                assert_eq!(connection.get_addr().port(), 4001);
+                assert_eq!(
+                    connection.polling().get().offline_threshold,
+                    Duration::from_secs(3)
+                );
+                assert_eq!(
+                    connection.polling().get().poll_interval,
+                    PollingSettings::default().poll_interval
+                );
+            }
+            other => panic!("unexpected device {other:?}"),
+        }
+    }
+
+    #[test]
+    fn json_errors_point_to_line() {
+        let text = r#"{
+  "name": "Дом",
+  "rooms": [
+    {
+      "name": "Кухня",
+      "devices": [
+        { "kind": "socket", "name": "Розетка" },
+        { "kind": "lamp", "name": "Лампа" }
+      ]
+    }
+  ]
+}"#;
+
+        let err = HomeDefinition::parse(text, DefinitionFormat::Json).unwrap_err();
+        assert_eq!(err.line, 8);
+        assert!(err.message.contains("lamp"), "{err}");
+    }
+
+    #[test]
+    fn json_duplicate_names_point_to_duplicate() {
+        let text = r#"{
+  "name": "Дом",
+  "rooms": [
+    { "name": "Кухня" },
+    { "name": "Спальня" },
+    { "name": "Кухня" }
+  ]
+}"#;
+
+        // serde_json относит ошибку списка к его концу, поэтому элемент
+        // указывается в сообщении
+        let err = HomeDefinition::parse(text, DefinitionFormat::Json).unwrap_err();
+        assert_eq!(err.line, 7);
+        assert!(err.message.contains("элемент 3"), "{err}");
+        assert!(err.message.contains("Кухня"), "{err}");
+    }
+
+    #[test]
+    fn toml_errors_point_to_line() {
+        let text = TOML.replace("port = 4001", "port = 0");
+
+        // Подключение проверяется целиком, ошибка указывает на его таблицу
+        let err = HomeDefinition::parse(&text, DefinitionFormat::Toml).unwrap_err();
+        assert_eq!(err.line, 19);
+        assert!(err.message.contains("порт"), "{err}");
+
+        let text = TOML.replace("power = 1000.0", "power = \"много\"");
+
+        let err = HomeDefinition::parse(&text, DefinitionFormat::Toml).unwrap_err();
+        assert_eq!(err.line, 10);
+        assert_eq!(err.column, 9);
+    }
+
+    #[test]
+    fn connection_type_fits_device_kind() {
+        let text = TOML
+            .replace("type = \"udp\"", "type = \"tcp\"")
+            .replace("route = { serial = \"T-1\" }\n", "");
+
+        let err = HomeDefinition::parse(&text, DefinitionFormat::Toml).unwrap_err();
+        assert_eq!(err.line, 14);
+        assert!(err.message.contains("только по udp"), "{err}");
+
+        let text = TOML.replace("{ type = \"tcp\"", "{ type = \"udp\"");
+
+        let err = HomeDefinition::parse(&text, DefinitionFormat::Toml).unwrap_err();
+        assert_eq!(err.line, 7);
+        assert!(err.message.contains("только по tcp"), "{err}");
+
+        let err = HomeDefinition::parse(
+            r#"{ "name": "Дом", "rooms": [ { "name": "Кухня", "devices": [
+                { "kind": "light", "name": "Люстра",
+                  "connection": { "type": "udp", "ip": "127.0.0.1", "port": 3002 } }
+            ] } ] }"#,
+            DefinitionFormat::Json,
+        )
+        .unwrap_err();
+        assert_eq!(err.line, 3);
+        assert!(err.message.contains("светильник"), "{err}");
+    }
+
+    #[test]
+    fn zero_polling_durations_are_rejected() {
+        for field in [
+            "poll_interval_ms",
+            "command_timeout_ms",
+            "offline_threshold_ms",
+        ] {
+            let text = TOML.replace(
+                "polling = { offline_threshold_ms = 3000 }",
+                &format!("polling = {{ {field} = 0 }}"),
+            );
+
+            // Как и порт, ошибка указывает на таблицу подключения
+            let err = HomeDefinition::parse(&text, DefinitionFormat::Toml).unwrap_err();
+            assert_eq!(err.line, 19);
+            assert!(err.message.contains(field), "{err}");
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_fields_and_empty_names() {
+        let err = HomeDefinition::parse(
+            r#"{ "name": "Дом", "rooms": [ { "name": "Кухня", "color": "red" } ] }"#,
+            DefinitionFormat::Json,
+        )
+        .unwrap_err();
+        assert!(err.message.contains("color"), "{err}");
+
+        let err = HomeDefinition::parse("name = \" \"", DefinitionFormat::Toml).unwrap_err();
+        assert_eq!(err.line, 1);
+        assert!(err.message.contains("пустым"), "{err}");
+    }
+
+    #[test]
+    fn light_values_are_checked() {
+        let light = |fields: &str| {
+            HomeDefinition::parse(
+                &format!(
+                    r#"{{ "name": "Дом", "rooms": [ {{ "name": "Кухня", "devices": [
+                        {{ "kind": "light", "name": "Люстра", {fields} }}
+                    ] }} ] }}"#
+                ),
+                DefinitionFormat::Json,
+            )
+        };
+
+        let home = light(r#""is_on": true"#).unwrap();
+        assert_eq!(
+            home.rooms[0].devices[0],
+            DeviceDefinition::Light {
+                name: "Люстра".to_string(),
+                brightness: 100,
+                is_on: true,
+                color_temperature: None,
+                connection: None,
+            }
+        );
+
+        let err = light(r#""brightness": 150"#).unwrap_err();
+        assert!(err.message.contains("яркость"), "{err}");
+
+        let err = light(r#""color_temperature": 200"#).unwrap_err();
+        assert!(err.message.contains("цветовая температура"), "{err}");
+
+        let err = light(r#""temp": 20.0"#).unwrap_err();
+        assert!(err.message.contains("нет полей power и temp"), "{err}");
+    }
+
+    #[test]
+    fn tariff_is_validated_and_built() {
+        let text = r#"
+name = "Дом"
+
+[tariff]
+currency = "RUB"
+kind = "flat"
+price = -1.0
+"#;
+
+        let err = HomeDefinition::parse(text, DefinitionFormat::Toml).unwrap_err();
+        assert_eq!(err.line, 4);
+        assert!(err.message.contains("цена"), "{err}");
+
+        let home = HomeDefinition::parse(&text.replace("-1.0", "6.0"), DefinitionFormat::Toml)
+            .unwrap()
+            .build();
+        assert_eq!(home.tariff().unwrap().currency, "RUB");
+    }
+
+    #[test]
+    fn load_and_save_by_extension() {
+        let dir = std::env::temp_dir().join(format!("sh_lib-definition-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for file in ["home.json", "home.toml"] {
+            let path = dir.join(file);
+            home().save(&path).unwrap();
+            assert_eq!(HomeDefinition::load(&path).unwrap(), home());
+        }
+
+        assert!(home().save(dir.join("home.yaml")).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -6,6 +6,9 @@ const DEVICE_NOT_FOUND_ERR_CODE: &str = "1002";
 const DECODE_MESSAGE_ERROR: &str = "1003";
 const GETTING_STATUS_ERROR: &str = "1004";
 const SOME_EMULATOR_ERROR: &str = "1005";
+const CONNECTION_ERROR: &str = "1006";
+const PROTOCOL_ERROR: &str = "1007";
+const INVALID_VALUE_ERROR: &str = "1008";
 
 pub struct ErrorInfo {
     pub code: String,
@@ -18,6 +21,9 @@ pub enum SmartHomeErrors {
     DecodeMessageError(ErrorInfo),
     GettingStatusError(ErrorInfo),
     EmulatorError(ErrorInfo),
+    ConnectionError(ErrorInfo),
+    ProtocolError(ErrorInfo),
+    InvalidValue(ErrorInfo),
 }
 
 impl SmartHomeErrors {
@@ -55,6 +61,27 @@ impl SmartHomeErrors {
             message: format!(r#"Ошибка в удаленном устройстве: {}"#, e),
         })
     }
+
+    pub fn connection_error(e: String) -> Self {
+        Self::ConnectionError(ErrorInfo {
+            code: String::from(CONNECTION_ERROR),
+            message: format!(r#"Ошибка соединения с устройством: {}"#, e),
+        })
+    }
+
+    pub fn protocol_error(e: String) -> Self {
+        Self::ProtocolError(ErrorInfo {
+            code: String::from(PROTOCOL_ERROR),
+            message: format!(r#"Ошибка протокола обмена с устройством: {}"#, e),
+        })
+    }
+
+    pub fn invalid_value(e: String) -> Self {
+        Self::InvalidValue(ErrorInfo {
+            code: String::from(INVALID_VALUE_ERROR),
+            message: format!(r#"Недопустимое значение: {}"#, e),
+        })
+    }
 }
 
 impl Display for SmartHomeErrors {
@@ -64,7 +91,10 @@ impl Display for SmartHomeErrors {
             | SmartHomeErrors::DeviceNotFound(err)
             | SmartHomeErrors::DecodeMessageError(err)
             | SmartHomeErrors::GettingStatusError(err)
-            | SmartHomeErrors::EmulatorError(err) => {
+            | SmartHomeErrors::EmulatorError(err)
+            | SmartHomeErrors::ConnectionError(err)
+            | SmartHomeErrors::ProtocolError(err)
+            | SmartHomeErrors::InvalidValue(err) => {
                 write!(f, "{ERR_PREFIX}[{}]: {}", err.code, err.message)
             }
         }
@@ -0,0 +1,399 @@
+use std::{
+    fmt,
+    sync::{
+        Arc, Mutex, RwLock, Weak,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+};
+
+use tokio::sync::broadcast;
+
+use crate::{
+    id::Id,
+    smart_device::contracts::{ConnectionState, DeviceData},
+    subscriber::Subscribe,
+};
+
+/// Сколько событий хранит канал для отстающего получателя
+const EVENTS_CAPACITY: usize = 256;
+
+/// Событие умного дома
+#[derive(Debug, Clone)]
+pub enum SmartHomeEvent {
+    /// В дом добавлена комната
+    RoomAdded { room_id: Id, name: String },
+    /// Комната удалена из дома
+    RoomRemoved { room_id: Id },
+    /// В комнату добавлено устройство
+    DeviceAdded {
+        room_id: Id,
+        device_id: Id,
+        name: String,
+    },
+    /// Устройство удалено из комнаты
+    DeviceRemoved { room_id: Id, device_id: Id },
+    /// Изменились показания устройства
+    StateChanged {
+        room_id: Id,
+        device_id: Id,
+        old: DeviceData,
+        new: DeviceData,
+    },
+    /// Устройство перестало выходить на связь
+    ConnectionLost { room_id: Id, device_id: Id },
+    /// Связь с устройством восстановлена
+    ConnectionRestored { room_id: Id, device_id: Id },
+}
+
+impl SmartHomeEvent {
+    /// Комната, к которой относится событие
+    pub fn room_id(&self) -> &Id {
+        match self {
+            SmartHomeEvent::RoomAdded { room_id, .. }
+            | SmartHomeEvent::RoomRemoved { room_id }
+            | SmartHomeEvent::DeviceAdded { room_id, .. }
+            | SmartHomeEvent::DeviceRemoved { room_id, .. }
+            | SmartHomeEvent::StateChanged { room_id, .. }
+            | SmartHomeEvent::ConnectionLost { room_id, .. }
+            | SmartHomeEvent::ConnectionRestored { room_id, .. } => room_id,
+        }
+    }
+
+    /// Устройство, к которому относится событие
+    pub fn device_id(&self) -> Option<&Id> {
+        match self {
+            SmartHomeEvent::RoomAdded { .. } | SmartHomeEvent::RoomRemoved { .. } => None,
+            SmartHomeEvent::DeviceAdded { device_id, .. }
+            | SmartHomeEvent::DeviceRemoved { device_id, .. }
+            | SmartHomeEvent::StateChanged { device_id, .. }
+            | SmartHomeEvent::ConnectionLost { device_id, .. }
+            | SmartHomeEvent::ConnectionRestored { device_id, .. } => Some(device_id),
+        }
+    }
+}
+
+struct BusInner {
+    sender: broadcast::Sender<SmartHomeEvent>,
+    subscribers: Mutex<Vec<(u64, Box<dyn Subscribe + Send>)>>,
+    next_id: AtomicU64,
+    /// Канал дома, в который пересылаются события комнаты
+    parent: Mutex<Option<EventBus>>,
+}
+
+/// Канал событий дома или комнаты.
+///
+/// Клоны разделяют канал и подписчиков, поэтому подписка на комнату
+/// переживает ее копирование в дом.
+#[derive(Clone)]
+pub struct EventBus {
+    inner: Arc<BusInner>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventBus")
+            .field("receivers", &self.inner.sender.receiver_count())
+            .field("subscribers", &self.inner.subscribers.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(BusInner {
+                sender: broadcast::channel(EVENTS_CAPACITY).0,
+                subscribers: Mutex::new(Vec::new()),
+                next_id: AtomicU64::new(0),
+                parent: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Получатель событий для асинхронного чтения.
+    ///
+    /// Видит только события, отправленные после вызова. Отписка - удаление получателя.
+    pub fn listen(&self) -> broadcast::Receiver<SmartHomeEvent> {
+        self.inner.sender.subscribe()
+    }
+
+    /// Подписать обработчик, который вызывается синхронно при каждом событии.
+    ///
+    /// Обработчик не должен обращаться к этому же каналу: он вызывается под его блокировкой.
+    pub fn subscribe<S>(&self, subscriber: S) -> Subscription
+    where
+        S: Subscribe + Send + 'static,
+    {
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+
+        self.inner
+            .subscribers
+            .lock()
+            .unwrap()
+            .push((id, Box::new(subscriber)));
+
+        Subscription {
+            bus: Arc::downgrade(&self.inner),
+            id,
+        }
+    }
+
+    /// Отправить событие подписчикам этого канала и родительского
+    pub fn emit(&self, event: SmartHomeEvent) {
+        for (_, subscriber) in self.inner.subscribers.lock().unwrap().iter_mut() {
+            subscriber.on_event(&event);
+        }
+
+        let parent = self.inner.parent.lock().unwrap().clone();
+
+        match parent {
+            Some(parent) => {
+                let _ = self.inner.sender.send(event.clone());
+                parent.emit(event);
+            }
+            // Отсутствие получателей - не ошибка
+            None => {
+                let _ = self.inner.sender.send(event);
+            }
+        }
+    }
+
+    /// Пересылать события в канал дома
+    pub(crate) fn attach(&self, parent: &EventBus) {
+        *self.inner.parent.lock().unwrap() = Some(parent.clone());
+    }
+
+    /// Перестать пересылать события в канал дома
+    pub(crate) fn detach(&self) {
+        self.inner.parent.lock().unwrap().take();
+    }
+}
+
+/// Подписка обработчика на канал событий.
+///
+/// Если подписку просто отбросить, обработчик продолжит получать события.
+#[derive(Debug)]
+pub struct Subscription {
+    bus: Weak<BusInner>,
+    id: u64,
+}
+
+impl Subscription {
+    /// Отписать обработчик
+    pub fn unsubscribe(self) {
+        if let Some(bus) = self.bus.upgrade() {
+            bus.subscribers
+                .lock()
+                .unwrap()
+                .retain(|(id, _)| *id != self.id);
+        }
+    }
+}
+
+#[derive(Default)]
+struct DeviceEventsInner {
+    /// Канал комнаты и ее id, задаются при добавлении устройства в комнату
+    target: RwLock<Option<(EventBus, Id)>>,
+    /// Было ли отправлено `ConnectionLost` без последующего `ConnectionRestored`
+    lost: AtomicBool,
+}
+
+/// Источник событий устройства.
+///
+/// Клоны устройства разделяют его, поэтому события фонового мониторинга
+/// попадают в комнату, в которую добавлена любая из копий.
+#[derive(Clone, Default)]
+pub struct DeviceEvents {
+    inner: Arc<DeviceEventsInner>,
+}
+
+impl fmt::Debug for DeviceEvents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeviceEvents")
+            .field(
+                "room_id",
+                &self.inner.target.read().unwrap().as_ref().map(|(_, id)| id),
+            )
+            .finish()
+    }
+}
+
+impl DeviceEvents {
+    pub(crate) fn attach(&self, bus: &EventBus, room_id: &Id) {
+        *self.inner.target.write().unwrap() = Some((bus.clone(), room_id.clone()));
+    }
+
+    pub(crate) fn detach(&self) {
+        self.inner.target.write().unwrap().take();
+    }
+
+    fn emit(&self, event: impl FnOnce(Id) -> SmartHomeEvent) {
+        let target = self.inner.target.read().unwrap().clone();
+
+        if let Some((bus, room_id)) = target {
+            bus.emit(event(room_id));
+        }
+    }
+
+    /// Сообщить об изменении показаний, если они действительно изменились
+    pub(crate) fn state_changed(&self, device_id: &Id, old: DeviceData, new: DeviceData) {
+        if old.same_readings(&new) {
+            return;
+        }
+
+        self.emit(|room_id| SmartHomeEvent::StateChanged {
+            room_id,
+            device_id: device_id.clone(),
+            old,
+            new,
+        });
+    }
+
+    /// Сообщить о потере или восстановлении связи при смене состояния соединения
+    pub(crate) fn connection_changed(
+        &self,
+        device_id: &Id,
+        old: ConnectionState,
+        new: ConnectionState,
+    ) {
+        if old == ConnectionState::Online && new != ConnectionState::Online {
+            self.inner.lost.store(true, Ordering::Relaxed);
+            self.emit(|room_id| SmartHomeEvent::ConnectionLost {
+                room_id,
+                device_id: device_id.clone(),
+            });
+        } else if new == ConnectionState::Online && self.inner.lost.swap(false, Ordering::Relaxed) {
+            self.emit(|room_id| SmartHomeEvent::ConnectionRestored {
+                room_id,
+                device_id: device_id.clone(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::smart_device::smart_thermometer::ThermometerData;
+
+    fn room_added(name: &str) -> SmartHomeEvent {
+        SmartHomeEvent::RoomAdded {
+            room_id: Id::from_string(name),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn unsubscribed_handler_gets_nothing() {
+        let bus = EventBus::new();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+
+        let subscription = bus.subscribe({
+            let seen = seen.clone();
+            move |event: &SmartHomeEvent| seen.lock().unwrap().push(event.room_id().clone())
+        });
+
+        bus.emit(room_added("Кухня"));
+        subscription.unsubscribe();
+        bus.emit(room_added("Спальня"));
+
+        assert_eq!(*seen.lock().unwrap(), vec![Id::from_string("Кухня")]);
+    }
+
+    #[tokio::test]
+    async fn events_reach_parent_until_detached() {
+        let home = EventBus::new();
+        let room = EventBus::new();
+        let mut home_events = home.listen();
+        let mut room_events = room.listen();
+
+        room.attach(&home);
+        room.emit(room_added("Кухня"));
+        room.detach();
+        room.emit(room_added("Спальня"));
+
+        assert_eq!(
+            home_events.recv().await.unwrap().room_id(),
+            &Id::from_string("Кухня")
+        );
+        assert!(home_events.try_recv().is_err());
+
+        assert_eq!(
+            room_events.recv().await.unwrap().room_id(),
+            &Id::from_string("Кухня")
+        );
+        assert_eq!(
+            room_events.recv().await.unwrap().room_id(),
+            &Id::from_string("Спальня")
+        );
+    }
+
+    #[tokio::test]
+    async fn connection_restored_only_after_loss() {
+        let bus = EventBus::new();
+        let mut events = bus.listen();
+        let device = DeviceEvents::default();
+        let device_id = Id::from_string("Термометр");
+        device.attach(&bus, &Id::from_string("Кухня"));
+
+        // Первое подключение - не восстановление
+        device.connection_changed(
+            &device_id,
+            ConnectionState::Connecting,
+            ConnectionState::Online,
+        );
+        device.connection_changed(
+            &device_id,
+            ConnectionState::Online,
+            ConnectionState::Backoff,
+        );
+        device.connection_changed(
+            &device_id,
+            ConnectionState::Backoff,
+            ConnectionState::Connecting,
+        );
+        device.connection_changed(
+            &device_id,
+            ConnectionState::Connecting,
+            ConnectionState::Online,
+        );
+
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            SmartHomeEvent::ConnectionLost { .. }
+        ));
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            SmartHomeEvent::ConnectionRestored { .. }
+        ));
+        assert!(events.try_recv().is_err());
+
+        // Повтор тех же показаний с новой меткой времени - не изменение
+        let old = DeviceData::Thermometer(ThermometerData::new(24.0));
+        let same = DeviceData::Thermometer(ThermometerData {
+            timestamp: 0,
+            ..ThermometerData::new(24.0)
+        });
+        device.state_changed(&device_id, old.clone(), same);
+        device.state_changed(
+            &device_id,
+            old,
+            DeviceData::Thermometer(ThermometerData::new(25.0)),
+        );
+
+        match events.recv().await.unwrap() {
+            SmartHomeEvent::StateChanged { old, new, .. } => {
+                assert_eq!(old.as_thermometer().temp, 24.0);
+                assert_eq!(new.as_thermometer().temp, 25.0);
+            }
+            event => panic!("{event:?}"),
+        }
+        assert!(events.try_recv().is_err());
+    }
+}
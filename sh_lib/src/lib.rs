@@ -1,12 +1,19 @@
+pub mod automation;
 pub mod builder;
+pub mod definition;
 pub mod errors;
+pub mod events;
 pub mod id;
 pub mod reporter;
 pub mod rich_console;
+pub mod scene;
+pub mod schedule;
 pub mod smart_device;
 pub mod smart_home;
 pub mod smart_room;
 pub mod subscriber;
+pub mod tariff;
+pub mod thermostat;
 
 /// Макрос для создания комнат
 #[macro_export]
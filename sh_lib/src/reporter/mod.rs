@@ -0,0 +1,94 @@
+mod model;
+mod render;
+
+pub use model::{
+    DEVICE_REPORT_TIMEOUT, DeviceReport, DeviceValue, HomeReport, Measurement, ReportNode,
+    RoomReport, Unit,
+};
+pub use render::{
+    ConsoleRenderer, JsonRenderer, MarkdownRenderer, Render, TextRenderer, value_text,
+};
+
+pub(crate) use model::sort_by_name;
+
+pub trait Report {
+    /// Структурированный отчет о состоянии
+    fn report(&self) -> impl std::future::Future<Output = ReportNode>;
+
+    /// Отчет о состоянии в виде простого текста
+    fn get_status_report(&self) -> impl std::future::Future<Output = String> {
+        async { TextRenderer.render(&self.report().await) }
+    }
+
+    /// Отчет о состоянии в представлении `renderer`
+    fn render_report<R: Render>(&self, renderer: &R) -> impl std::future::Future<Output = String> {
+        async move { renderer.render(&self.report().await) }
+    }
+}
+
+impl<T: Report> Report for &T {
+    async fn report(&self) -> ReportNode {
+        T::report(self).await
+    }
+}
+
+pub struct Reporter<T> {
+    inner: T,
+}
+
+impl Reporter<Identity> {
+    pub fn new() -> Reporter<Identity> {
+        Reporter { inner: Identity }
+    }
+}
+
+impl Default for Reporter<Identity> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Reporter<T> {
+    pub fn add_item<U: Report>(self, item: U) -> Reporter<Both<T, U>> {
+        Reporter {
+            inner: Both::new(self.inner, item),
+        }
+    }
+}
+
+impl<T: Report> Report for Reporter<T> {
+    async fn report(&self) -> ReportNode {
+        self.inner.report().await
+    }
+}
+
+pub struct Identity;
+
+impl Report for Identity {
+    async fn report(&self) -> ReportNode {
+        ReportNode::List { items: vec![] }
+    }
+}
+
+pub struct Both<R1, R2> {
+    inner1: R1,
+    inner2: R2,
+}
+
+impl<R1, R2> Both<R1, R2> {
+    fn new(inner1: R1, inner2: R2) -> Self {
+        Both { inner1, inner2 }
+    }
+}
+
+impl<R1: Report, R2: Report> Report for Both<R1, R2> {
+    /// Части отчета собираются одновременно
+    async fn report(&self) -> ReportNode {
+        let (first, second) = tokio::join!(self.inner1.report(), self.inner2.report());
+
+        let mut items = first.into_items();
+        items.extend(second.into_items());
+
+        ReportNode::List { items }
+    }
+}
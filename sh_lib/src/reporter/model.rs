@@ -0,0 +1,202 @@
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::{smart_device::contracts::ConnectionState, tariff::Cost, thermostat::ThermostatReport};
+
+/// Единица измерения показаний
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Unit {
+    #[serde(rename = "W")]
+    Watt,
+    #[serde(rename = "°C")]
+    Celsius,
+    #[serde(rename = "kWh")]
+    KilowattHour,
+    #[serde(rename = "%")]
+    Percent,
+    #[serde(rename = "K")]
+    Kelvin,
+}
+
+impl Unit {
+    /// Обозначение единицы в текстовом отчете
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Unit::Watt => "Вт",
+            Unit::Celsius => "C°",
+            Unit::KilowattHour => "кВт·ч",
+            Unit::Percent => "%",
+            Unit::Kelvin => "K",
+        }
+    }
+}
+
+/// Значение с единицей измерения
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Measurement {
+    pub value: f32,
+    pub unit: Unit,
+}
+
+impl Measurement {
+    pub fn watts(value: f32) -> Self {
+        Self {
+            value,
+            unit: Unit::Watt,
+        }
+    }
+
+    pub fn celsius(value: f32) -> Self {
+        Self {
+            value,
+            unit: Unit::Celsius,
+        }
+    }
+
+    pub fn kilowatt_hours(value: f64) -> Self {
+        Self {
+            value: value as f32,
+            unit: Unit::KilowattHour,
+        }
+    }
+
+    pub fn percent(value: f32) -> Self {
+        Self {
+            value,
+            unit: Unit::Percent,
+        }
+    }
+
+    pub fn kelvin(value: f32) -> Self {
+        Self {
+            value,
+            unit: Unit::Kelvin,
+        }
+    }
+}
+
+/// Показания устройства
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum DeviceValue {
+    Socket {
+        is_on: bool,
+        power: Measurement,
+        /// Всего потреблено с начала учета
+        energy: Measurement,
+    },
+    Thermometer {
+        temperature: Measurement,
+    },
+    Light {
+        is_on: bool,
+        brightness: Measurement,
+        /// Цветовая температура, если светильник ее поддерживает
+        #[serde(skip_serializing_if = "Option::is_none")]
+        color_temperature: Option<Measurement>,
+    },
+}
+
+/// Сколько по умолчанию ждать отчет одного устройства
+pub const DEVICE_REPORT_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Отчет по устройству
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeviceReport {
+    pub id: String,
+    pub name: String,
+    /// `None` - устройство не отдало показания за отведенное время
+    pub value: Option<DeviceValue>,
+    pub online: bool,
+    pub connection_state: ConnectionState,
+    /// Время последнего обновления показаний, мс от начала эпохи
+    pub timestamp: u64,
+    /// Стоимость потребленной энергии, есть у розеток в доме с тарифом
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<Cost>,
+}
+
+impl DeviceReport {
+    /// Отчет по устройству, которое не отдало показания вовремя
+    pub fn unavailable(id: impl Into<String>, name: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            name: name.into(),
+            value: None,
+            online: false,
+            connection_state: ConnectionState::Offline,
+            timestamp: 0,
+            cost: None,
+        }
+    }
+
+    /// Отчет устройства, собранный не дольше `timeout`
+    pub(crate) async fn within(
+        id: impl Into<String>,
+        name: impl Into<String>,
+        timeout: Duration,
+        report: impl Future<Output = DeviceReport>,
+    ) -> Self {
+        match tokio::time::timeout(timeout, report).await {
+            Ok(report) => report,
+            Err(_) => Self::unavailable(id, name),
+        }
+    }
+}
+
+/// Отчет по комнате, устройства упорядочены по имени, затем по id
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RoomReport {
+    pub id: String,
+    pub name: String,
+    pub devices: Vec<DeviceReport>,
+    /// Всего потреблено розетками комнаты
+    pub energy: Measurement,
+    /// Стоимость энергии, есть у комнат дома с тарифом
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<Cost>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thermostat: Option<ThermostatReport>,
+}
+
+/// Отчет по дому, комнаты упорядочены по имени, затем по id
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HomeReport {
+    pub id: String,
+    pub name: String,
+    pub rooms: Vec<RoomReport>,
+    /// Всего потреблено розетками дома
+    pub energy: Measurement,
+    /// Стоимость энергии, если у дома задан тариф
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<Cost>,
+}
+
+/// Узел структурированного отчета
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ReportNode {
+    Home(HomeReport),
+    Room(RoomReport),
+    Device(DeviceReport),
+    /// Несколько отчетов подряд, например собранных `Reporter`
+    List {
+        items: Vec<ReportNode>,
+    },
+}
+
+impl ReportNode {
+    /// Отчеты этого узла в виде списка: список раскрывается, остальные узлы - один элемент
+    pub fn into_items(self) -> Vec<ReportNode> {
+        match self {
+            ReportNode::List { items } => items,
+            node => vec![node],
+        }
+    }
+}
+
+/// Упорядочить элементы отчета по имени, затем по id
+pub(crate) fn sort_by_name<T>(items: &mut [T], key: impl Fn(&T) -> (&str, &str)) {
+    items.sort_by(|a, b| key(a).cmp(&key(b)));
+}
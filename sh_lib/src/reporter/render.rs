@@ -0,0 +1,547 @@
+use std::fmt::Write;
+
+use crate::{
+    rich_console::{TextColor, colored},
+    smart_device::contracts::ConnectionState,
+    tariff::Cost,
+    thermostat::{ThermostatMode, ThermostatReport},
+};
+
+use super::model::{DeviceReport, DeviceValue, HomeReport, ReportNode, RoomReport, Unit};
+
+/// Представление структурированного отчета в виде строки
+pub trait Render {
+    fn render(&self, report: &ReportNode) -> String;
+}
+
+/// Показания устройства в текстовом виде: "Вкл, 1000 Вт", "Выкл", "24 C°", "Вкл, 80 %, 2700 K"
+pub fn value_text(value: &DeviceValue) -> String {
+    match value {
+        DeviceValue::Socket {
+            is_on: true, power, ..
+        } => {
+            format!("Вкл, {} {}", power.value, power.unit.symbol())
+        }
+        DeviceValue::Socket { is_on: false, .. } => "Выкл".to_string(),
+        DeviceValue::Thermometer { temperature } => {
+            format!("{} {}", temperature.value, temperature.unit.symbol())
+        }
+        DeviceValue::Light {
+            is_on: true,
+            brightness,
+            color_temperature,
+        } => {
+            let mut text = format!("Вкл, {} {}", brightness.value, brightness.unit.symbol());
+            if let Some(color_temperature) = color_temperature {
+                write!(
+                    text,
+                    ", {} {}",
+                    color_temperature.value,
+                    color_temperature.unit.symbol()
+                )
+                .unwrap();
+            }
+            text
+        }
+        DeviceValue::Light { is_on: false, .. } => "Выкл".to_string(),
+    }
+}
+
+/// Показания или отметка о недоступном устройстве
+fn reading_text(device: &DeviceReport) -> String {
+    match &device.value {
+        Some(value) => value_text(value),
+        None => "недоступно".to_string(),
+    }
+}
+
+fn device_line(device: &DeviceReport) -> String {
+    format!("{}: {}", device.name, reading_text(device))
+}
+
+/// Состояние термостата: "Термостат: нагрев, уставка 21 C° ± 0.5, сейчас 19 C°, розетка Вкл"
+fn thermostat_line(thermostat: &ThermostatReport) -> String {
+    let config = &thermostat.config;
+    let celsius = Unit::Celsius.symbol();
+    let mut line = format!("Термостат: {}", config.mode);
+
+    if config.mode != ThermostatMode::Off {
+        write!(line, ", уставка {} {celsius}", config.setpoint).unwrap();
+        if config.hysteresis > 0.0 {
+            write!(line, " ± {}", config.hysteresis).unwrap();
+        }
+    }
+    match thermostat.temperature {
+        Some(temperature) => write!(line, ", сейчас {temperature} {celsius}").unwrap(),
+        None => line.push_str(", нет показаний"),
+    }
+    line.push_str(if thermostat.output_on {
+        ", розетка Вкл"
+    } else {
+        ", розетка Выкл"
+    });
+    if let Some(fault) = &thermostat.fault {
+        write!(line, ", {fault}").unwrap();
+    }
+
+    line
+}
+
+/// Время обновления в UTC, пустая строка для нулевой метки
+fn timestamp_text(timestamp: u64) -> String {
+    if timestamp == 0 {
+        return String::new();
+    }
+
+    chrono::DateTime::from_timestamp_millis(timestamp as i64)
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_default()
+}
+
+/// Простой текст в прежнем формате отчетов
+pub struct TextRenderer;
+
+impl TextRenderer {
+    fn room(output: &mut String, room: &RoomReport) {
+        writeln!(output, r#"Отчет по комнате "{}""#, room.name).unwrap();
+
+        for (i, device) in room.devices.iter().enumerate() {
+            writeln!(output, "{}. {}", i + 1, device_line(device)).unwrap();
+        }
+
+        if let Some(thermostat) = &room.thermostat {
+            writeln!(output, "{}", thermostat_line(thermostat)).unwrap();
+        }
+    }
+
+    fn home(output: &mut String, home: &HomeReport) {
+        writeln!(output, r#"Отчет по дому "{}""#, home.name).unwrap();
+
+        for room in &home.rooms {
+            Self::room(output, room);
+        }
+    }
+}
+
+impl Render for TextRenderer {
+    fn render(&self, report: &ReportNode) -> String {
+        let mut output = String::new();
+
+        match report {
+            ReportNode::Home(home) => Self::home(&mut output, home),
+            ReportNode::Room(room) => Self::room(&mut output, room),
+            ReportNode::Device(device) => output = device_line(device),
+            ReportNode::List { items } => {
+                output = items
+                    .iter()
+                    .map(|item| self.render(item))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+            }
+        }
+
+        output
+    }
+}
+
+/// JSON с отступами
+pub struct JsonRenderer;
+
+impl Render for JsonRenderer {
+    fn render(&self, report: &ReportNode) -> String {
+        serde_json::to_string_pretty(report).expect("отчет всегда сериализуется в JSON")
+    }
+}
+
+/// Markdown: заголовок на дом, таблица устройств на комнату
+pub struct MarkdownRenderer;
+
+impl MarkdownRenderer {
+    /// Экранировать символы, ломающие таблицу
+    fn cell(text: &str) -> String {
+        text.replace('|', "\\|").replace('\n', " ")
+    }
+
+    /// Таблица устройств, столбец стоимости - только если она известна
+    fn table(output: &mut String, devices: &[DeviceReport]) {
+        if devices.is_empty() {
+            writeln!(output, "_Нет устройств_").unwrap();
+            return;
+        }
+
+        let with_cost = devices.iter().any(|device| device.cost.is_some());
+
+        if with_cost {
+            writeln!(
+                output,
+                "| Устройство | Показания | Связь | Обновлено | Стоимость |"
+            )
+            .unwrap();
+            writeln!(output, "|---|---|---|---|---|").unwrap();
+        } else {
+            writeln!(output, "| Устройство | Показания | Связь | Обновлено |").unwrap();
+            writeln!(output, "|---|---|---|---|").unwrap();
+        }
+
+        for device in devices {
+            write!(
+                output,
+                "| {} | {} | {} | {} |",
+                Self::cell(&device.name),
+                reading_text(device),
+                if device.online {
+                    "на связи"
+                } else {
+                    "нет связи"
+                },
+                timestamp_text(device.timestamp)
+            )
+            .unwrap();
+
+            if with_cost {
+                let cost = device.cost.as_ref().map(Cost::to_string);
+                write!(output, " {} |", cost.unwrap_or_default()).unwrap();
+            }
+
+            output.push('\n');
+        }
+    }
+
+    fn room(output: &mut String, room: &RoomReport) {
+        writeln!(output, "## {}\n", Self::cell(&room.name)).unwrap();
+        Self::table(output, &room.devices);
+
+        if let Some(thermostat) = &room.thermostat {
+            writeln!(output, "\n_{}_", Self::cell(&thermostat_line(thermostat))).unwrap();
+        }
+    }
+}
+
+impl Render for MarkdownRenderer {
+    fn render(&self, report: &ReportNode) -> String {
+        let mut output = String::new();
+
+        match report {
+            ReportNode::Home(home) => {
+                writeln!(output, "# {}", Self::cell(&home.name)).unwrap();
+
+                for room in &home.rooms {
+                    output.push('\n');
+                    Self::room(&mut output, room);
+                }
+            }
+            ReportNode::Room(room) => Self::room(&mut output, room),
+            ReportNode::Device(device) => Self::table(&mut output, std::slice::from_ref(device)),
+            ReportNode::List { items } => {
+                output = items
+                    .iter()
+                    .map(|item| self.render(item))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+            }
+        }
+
+        output
+    }
+}
+
+/// Цветное дерево для терминала: цвет устройства зависит от состояния соединения
+pub struct ConsoleRenderer;
+
+impl ConsoleRenderer {
+    fn device_color(device: &DeviceReport) -> TextColor {
+        match device.connection_state {
+            ConnectionState::Online => TextColor::Green,
+            ConnectionState::Connecting | ConnectionState::Backoff => TextColor::Yellow,
+            ConnectionState::Offline => TextColor::Red,
+        }
+    }
+
+    /// Вывести устройства под узлом, `indent` - префикс уровня
+    fn devices(output: &mut String, devices: &[DeviceReport], indent: &str) {
+        if devices.is_empty() {
+            writeln!(output, "{}└─ (нет устройств)", indent).unwrap();
+            return;
+        }
+
+        for (i, device) in devices.iter().enumerate() {
+            let branch = if i + 1 == devices.len() {
+                "└─"
+            } else {
+                "├─"
+            };
+            let line = colored(&device_line(device), Self::device_color(device));
+            writeln!(output, "{}{} {}", indent, branch, line).unwrap();
+        }
+    }
+
+    /// Термостат комнаты под ее устройствами
+    fn thermostat(output: &mut String, room: &RoomReport, indent: &str) {
+        if let Some(thermostat) = &room.thermostat {
+            let color = if thermostat.fault.is_some() {
+                TextColor::Red
+            } else {
+                TextColor::Magenta
+            };
+            let line = colored(&thermostat_line(thermostat), color);
+            writeln!(output, "{}   {}", indent, line).unwrap();
+        }
+    }
+
+    fn room(output: &mut String, room: &RoomReport) {
+        writeln!(output, "{}", colored(&room.name, TextColor::Blue)).unwrap();
+        Self::devices(output, &room.devices, "");
+        Self::thermostat(output, room, "");
+    }
+}
+
+impl Render for ConsoleRenderer {
+    fn render(&self, report: &ReportNode) -> String {
+        let mut output = String::new();
+
+        match report {
+            ReportNode::Home(home) => {
+                writeln!(output, "{}", colored(&home.name, TextColor::Cyan)).unwrap();
+
+                for (i, room) in home.rooms.iter().enumerate() {
+                    let last = i + 1 == home.rooms.len();
+                    let branch = if last { "└─" } else { "├─" };
+                    let indent = if last { "   " } else { "│  " };
+
+                    writeln!(
+                        output,
+                        "{} {}",
+                        branch,
+                        colored(&room.name, TextColor::Blue)
+                    )
+                    .unwrap();
+                    Self::devices(&mut output, &room.devices, indent);
+                    Self::thermostat(&mut output, room, indent);
+                }
+            }
+            ReportNode::Room(room) => Self::room(&mut output, room),
+            ReportNode::Device(device) => {
+                writeln!(
+                    output,
+                    "{}",
+                    colored(&device_line(device), Self::device_color(device))
+                )
+                .unwrap();
+            }
+            ReportNode::List { items } => {
+                output = items.iter().map(|item| self.render(item)).collect();
+            }
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        reporter::Report,
+        smart_device::{SmartSocket, SmartThermometer},
+        smart_home::SmartHome,
+        smart_room::SmartRoom,
+    };
+
+    fn home() -> SmartHome {
+        SmartHome::new_with_rooms(
+            "Дом",
+            &[
+                SmartRoom::new_with_devices(
+                    "Спальня",
+                    &[SmartThermometer::new("Термометр", 21.5).into()],
+                ),
+                SmartRoom::new_with_devices(
+                    "Кухня",
+                    &[
+                        SmartSocket::new("Чайник", 2000.0, false).into(),
+                        SmartSocket::new("Лампа | 1", 60.0, true).into(),
+                    ],
+                ),
+            ],
+        )
+    }
+
+    #[tokio::test]
+    async fn text_keeps_format_and_orders_by_name() {
+        assert_eq!(
+            home().get_status_report().await,
+            "Отчет по дому \"Дом\"\n\
+             Отчет по комнате \"Кухня\"\n\
+             1. Лампа | 1: Вкл, 60 Вт\n\
+             2. Чайник: Выкл\n\
+             Отчет по комнате \"Спальня\"\n\
+             1. Термометр: 21.5 C°\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn json_has_typed_values() {
+        let json: serde_json::Value =
+            serde_json::from_str(&home().render_report(&JsonRenderer).await).unwrap();
+
+        assert_eq!(json["type"], "home");
+        let kettle = &json["rooms"][0]["devices"][1];
+        assert_eq!(kettle["name"], "Чайник");
+        assert_eq!(kettle["value"]["kind"], "socket");
+        assert_eq!(kettle["value"]["is_on"], false);
+        assert_eq!(kettle["value"]["power"]["value"], 0.0);
+        assert_eq!(kettle["value"]["power"]["unit"], "W");
+        assert_eq!(kettle["value"]["energy"]["unit"], "kWh");
+        assert_eq!(json["energy"]["value"], 0.0);
+        assert_eq!(kettle["online"], false);
+        assert_eq!(kettle["connection_state"], "offline");
+
+        let thermometer = &json["rooms"][1]["devices"][0]["value"];
+        assert_eq!(thermometer["temperature"]["unit"], "°C");
+    }
+
+    #[tokio::test]
+    async fn markdown_renders_room_tables() {
+        let markdown = home().render_report(&MarkdownRenderer).await;
+        let lines: Vec<&str> = markdown.lines().collect();
+
+        assert_eq!(lines[0], "# Дом");
+        assert_eq!(lines[2], "## Кухня");
+        assert_eq!(lines[4], "| Устройство | Показания | Связь | Обновлено |");
+        assert!(
+            lines[6].starts_with("| Лампа \\| 1 | Вкл, 60 Вт | нет связи | "),
+            "{}",
+            lines[6]
+        );
+        assert_eq!(
+            SmartRoom::new("Пусто")
+                .render_report(&MarkdownRenderer)
+                .await,
+            "## Пусто\n\n_Нет устройств_\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn markdown_adds_cost_column_with_tariff() {
+        use crate::tariff::{Tariff, TariffPlan};
+
+        let kettle = SmartSocket::new("Чайник", 2000.0, false);
+        let mut home = SmartHome::new_with_rooms(
+            "Дом",
+            &[SmartRoom::new_with_devices(
+                "Кухня",
+                &[
+                    kettle.clone().into(),
+                    SmartThermometer::new("Термометр", 21.5).into(),
+                ],
+            )],
+        );
+        home.set_tariff(Some(TariffPlan::new("RUB", Tariff::Flat { price: 5.0 })));
+
+        let hour = 60 * 60 * 1000;
+        let now = chrono::Utc::now().timestamp_millis() as u64;
+        kettle.energy.observe_at(now - 2 * hour, 2000.0);
+        kettle.energy.observe_at(now - hour, 0.0);
+
+        let markdown = home.render_report(&MarkdownRenderer).await;
+        let lines: Vec<&str> = markdown.lines().collect();
+
+        assert_eq!(
+            lines[4],
+            "| Устройство | Показания | Связь | Обновлено | Стоимость |"
+        );
+        // Термометр без стоимости, затем чайник
+        assert!(lines[6].ends_with(" |  |"), "{}", lines[6]);
+        assert!(lines[7].ends_with(" | 10.00 RUB |"), "{}", lines[7]);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&home.render_report(&JsonRenderer).await).unwrap();
+        assert_eq!(json["cost"]["amount"], 10.0);
+        assert_eq!(json["rooms"][0]["cost"]["currency"], "RUB");
+    }
+
+    #[tokio::test]
+    async fn renders_room_thermostat() {
+        use crate::{
+            id::Id,
+            thermostat::{ThermostatConfig, ThermostatMode},
+        };
+
+        let mut room = SmartRoom::new_with_devices(
+            "Спальня",
+            &[
+                SmartThermometer::new("Термометр", 19.0).into(),
+                SmartSocket::new("Обогреватель", 1500.0, false).into(),
+            ],
+        );
+        room.set_thermostat(Some(ThermostatConfig {
+            thermometer_id: Id::from_string("Термометр").to_string(),
+            socket_id: Id::from_string("Обогреватель").to_string(),
+            mode: ThermostatMode::Heat,
+            setpoint: 21.0,
+            hysteresis: 0.5,
+            min_on_ms: 0,
+            min_off_ms: 0,
+        }));
+        room.thermostat_job_at(0)
+            .await
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+
+        let text = room.get_status_report().await;
+        assert!(
+            text.ends_with("Термостат: нагрев, уставка 21 C° ± 0.5, сейчас 19 C°, розетка Вкл\n"),
+            "{text}"
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&room.render_report(&JsonRenderer).await).unwrap();
+        assert_eq!(json["thermostat"]["mode"], "heat");
+        assert_eq!(json["thermostat"]["output_on"], true);
+    }
+
+    #[tokio::test]
+    async fn console_draws_colored_tree() {
+        let tree = home().render_report(&ConsoleRenderer).await;
+        let lines: Vec<&str> = tree.lines().collect();
+
+        assert_eq!(lines.len(), 6);
+        assert!(lines[1].starts_with("├─ ") && lines[1].contains("Кухня"));
+        assert!(lines[2].starts_with("│  ├─ \x1b[31mЛампа | 1: Вкл, 60 Вт"));
+        assert!(lines[3].starts_with("│  └─ "));
+        assert!(lines[4].starts_with("└─ ") && lines[4].contains("Спальня"));
+        assert!(lines[5].starts_with("   └─ "));
+    }
+
+    #[tokio::test]
+    async fn reporter_collects_items_in_order() {
+        use crate::reporter::Reporter;
+
+        let socket = SmartSocket::new("Розетка", 1000.0, true);
+        let thermometer = SmartThermometer::new("Термометр", 24.0);
+        let reporter = Reporter::new().add_item(&socket).add_item(&thermometer);
+
+        assert_eq!(
+            reporter.get_status_report().await,
+            "Розетка: Вкл, 1000 Вт\nТермометр: 24 C°"
+        );
+        match reporter.report().await {
+            ReportNode::List { items } => assert_eq!(items.len(), 2),
+            node => panic!("{node:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn unavailable_device_is_marked() {
+        let socket = SmartSocket::new("Розетка", 1000.0, true);
+        let _lock = socket.value.write().await;
+
+        assert_eq!(socket.get_status_report().await, "Розетка: недоступно");
+
+        let json: serde_json::Value =
+            serde_json::from_str(&socket.render_report(&JsonRenderer).await).unwrap();
+        assert!(json["value"].is_null());
+    }
+}
@@ -22,11 +22,16 @@ impl TextColor {
     }
 }
 
-pub fn colored_println(text: &str, color: TextColor) {
-    println!(
+/// Текст, окрашенный escape-последовательностями терминала
+pub fn colored(text: &str, color: TextColor) -> String {
+    format!(
         "\x1b[{}{}\x1b[{}",
         color.as_code(),
         text,
         TextColor::Reset.as_code()
-    );
+    )
+}
+
+pub fn colored_println(text: &str, color: TextColor) {
+    println!("{}", colored(text, color));
 }
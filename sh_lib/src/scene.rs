@@ -0,0 +1,210 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinSet;
+
+use crate::{automation::DeviceRef, id::Id, smart_device::SmartSocket};
+
+/// Целевое состояние розетки в сцене
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneState {
+    pub device: DeviceRef,
+    pub is_on: bool,
+}
+
+/// Сцена: именованный набор состояний розеток, применяется одним вызовом
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scene {
+    /// Выводится из имени, как и id правил и расписаний
+    pub id: String,
+    pub name: String,
+    pub states: Vec<SceneState>,
+}
+
+/// Ошибка в описании сцены
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneError(pub String);
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Некорректная сцена: {}", self.0)
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl Scene {
+    pub fn new(name: impl Into<String>, states: Vec<SceneState>) -> Self {
+        let name = name.into();
+        Self {
+            id: Id::from_string(&name).to_string(),
+            name,
+            states,
+        }
+    }
+
+    /// Проверить имя и состояния. Существование розеток проверяет дом
+    pub fn validate(&self) -> Result<(), SceneError> {
+        if self.name.trim().is_empty() {
+            return Err(SceneError("пустое имя".to_string()));
+        }
+
+        if self.states.is_empty() {
+            return Err(SceneError("нет устройств".to_string()));
+        }
+
+        for (i, state) in self.states.iter().enumerate() {
+            if self.states[..i].iter().any(|s| s.device == state.device) {
+                return Err(SceneError(format!(
+                    "устройство {} указано дважды",
+                    state.device
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Результат перевода розетки в состояние сцены, `error` не заполнен при успехе
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SceneResult {
+    pub state: SceneState,
+    pub error: Option<String>,
+}
+
+/// Сцена вместе с розетками для ее состояний.
+///
+/// Розетки - клоны устройств дома, поэтому сцена применяется без блокировки дома.
+#[derive(Debug)]
+pub struct SceneJob {
+    pub(crate) scene: Scene,
+    /// Розетка для каждого состояния или причина, по которой ее нет
+    pub(crate) sockets: Vec<Result<SmartSocket, String>>,
+}
+
+impl SceneJob {
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    /// Перевести все розетки в состояния сцены одновременно.
+    /// Результаты - в порядке состояний сцены
+    pub async fn run(self) -> Vec<SceneResult> {
+        let mut tasks = JoinSet::new();
+
+        for (index, (state, socket)) in self.scene.states.into_iter().zip(self.sockets).enumerate()
+        {
+            tasks.spawn(async move {
+                let result = match socket {
+                    Ok(mut socket) => if state.is_on {
+                        socket.turn_on().await
+                    } else {
+                        socket.turn_off().await
+                    }
+                    .map_err(|err| err.to_string()),
+                    Err(err) => Err(err),
+                };
+
+                (
+                    index,
+                    SceneResult {
+                        state,
+                        error: result.err(),
+                    },
+                )
+            });
+        }
+
+        let mut results = Vec::with_capacity(tasks.len());
+        while let Some(result) = tasks.join_next().await {
+            results.push(result.expect("команда розетке не паникует"));
+        }
+        results.sort_by_key(|(index, _)| *index);
+
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{smart_device::SmartThermometer, smart_home::SmartHome, smart_room::SmartRoom};
+
+    fn state(room: &str, device: &str, is_on: bool) -> SceneState {
+        SceneState {
+            device: DeviceRef::new(&Id::from_string(room), &Id::from_string(device)),
+            is_on,
+        }
+    }
+
+    #[test]
+    fn validate_rejects_duplicates() {
+        let scene = Scene::new(
+            "Ухожу",
+            vec![
+                state("Кухня", "Чайник", false),
+                state("Кухня", "Чайник", true),
+            ],
+        );
+        assert!(scene.validate().is_err());
+        assert!(Scene::new("Пусто", vec![]).validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn home_applies_scene_and_reports_each_device() {
+        let kettle = SmartSocket::new("Чайник", 2000.0, true);
+        let fridge = SmartSocket::new("Холодильник", 150.0, false);
+        let mut home = SmartHome::new_with_rooms(
+            "Дом",
+            &[SmartRoom::new_with_devices(
+                "Кухня",
+                &[
+                    kettle.clone().into(),
+                    fridge.clone().into(),
+                    SmartThermometer::new("Термометр", 20.0).into(),
+                ],
+            )],
+        );
+
+        let leaving = Scene::new(
+            "Ухожу",
+            vec![
+                state("Кухня", "Чайник", false),
+                state("Кухня", "Холодильник", true),
+            ],
+        );
+        home.check_scene(&leaving).unwrap();
+        assert!(
+            home.check_scene(&Scene::new(
+                "Термометр",
+                vec![state("Кухня", "Термометр", true)]
+            ))
+            .is_err()
+        );
+        assert!(
+            home.check_scene(&Scene::new("Гараж", vec![state("Гараж", "Чайник", true)]))
+                .is_err()
+        );
+
+        home.put_scene(leaving.clone());
+        let job = home.scene_job(&leaving.id).unwrap();
+
+        // Розетку удалили после создания сцены: остальные устройства все равно переключаются
+        home.get_room_mut(&Id::from_string("Кухня"))
+            .unwrap()
+            .delete_device(&Id::from_string("Чайник"));
+        let job_after_delete = home.scene_job(&leaving.id).unwrap();
+
+        let results = job.run().await;
+        assert!(results.iter().all(|result| result.error.is_none()));
+        assert!(!kettle.is_on().await);
+        assert!(fridge.is_on().await);
+
+        let results = job_after_delete.run().await;
+        assert!(results[0].error.is_some());
+        assert_eq!(results[1].error, None);
+    }
+}
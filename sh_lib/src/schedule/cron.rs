@@ -0,0 +1,335 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{Datelike, Days, LocalResult, NaiveDate, TimeZone};
+use chrono_tz::Tz;
+
+use super::ScheduleError;
+
+/// Сколько дней вперед ищется следующий запуск, прежде чем считать, что его не будет
+const SEARCH_DAYS: u64 = 366 * 8;
+
+/// Поле выражения: допустимые значения и разбор имен
+struct Field {
+    name: &'static str,
+    min: u32,
+    max: u32,
+    names: &'static [&'static str],
+}
+
+const MINUTE: Field = Field {
+    name: "минута",
+    min: 0,
+    max: 59,
+    names: &[],
+};
+const HOUR: Field = Field {
+    name: "час",
+    min: 0,
+    max: 23,
+    names: &[],
+};
+const DAY: Field = Field {
+    name: "день месяца",
+    min: 1,
+    max: 31,
+    names: &[],
+};
+const MONTH: Field = Field {
+    name: "месяц",
+    min: 1,
+    max: 12,
+    names: &[
+        "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+    ],
+};
+/// 0 и 7 - воскресенье
+const WEEKDAY: Field = Field {
+    name: "день недели",
+    min: 0,
+    max: 7,
+    names: &["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"],
+};
+
+impl Field {
+    fn value(&self, s: &str) -> Result<u32, ScheduleError> {
+        let upper = s.to_ascii_uppercase();
+        let value = match self.names.iter().position(|name| *name == upper) {
+            Some(index) => index as u32 + if self.min == 1 { 1 } else { 0 },
+            None => s.parse().map_err(|_| self.error(s))?,
+        };
+
+        if value < self.min || value > self.max {
+            return Err(self.error(s));
+        }
+        Ok(value)
+    }
+
+    /// Множество значений поля в виде битовой маски
+    fn parse(&self, s: &str) -> Result<u64, ScheduleError> {
+        let mut mask = 0;
+
+        for item in s.split(',') {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => {
+                    let step: u32 = step.parse().map_err(|_| self.error(item))?;
+                    if step == 0 {
+                        return Err(self.error(item));
+                    }
+                    (range, step)
+                }
+                None => (item, 1),
+            };
+
+            let (from, to) = if range == "*" {
+                (self.min, self.max)
+            } else if let Some((from, to)) = range.split_once('-') {
+                (self.value(from)?, self.value(to)?)
+            } else {
+                let from = self.value(range)?;
+                // "5/15" - с 5 до конца с шагом 15
+                (from, if step > 1 { self.max } else { from })
+            };
+            if from > to {
+                return Err(self.error(item));
+            }
+
+            for value in (from..=to).step_by(step as usize) {
+                mask |= 1 << value;
+            }
+        }
+
+        Ok(mask)
+    }
+
+    fn error(&self, s: &str) -> ScheduleError {
+        ScheduleError(format!(
+            "недопустимое значение \"{s}\" в поле \"{}\"",
+            self.name
+        ))
+    }
+}
+
+/// Выражение cron из пяти полей: минута, час, день месяца, месяц, день недели.
+///
+/// Поля поддерживают `*`, списки, диапазоны, шаги и имена (`MON-FRI`, `JAN`),
+/// а также сокращения `@hourly`, `@daily`, `@weekly`, `@monthly`, `@yearly`.
+/// Если ограничены и день месяца, и день недели, достаточно совпадения одного из них.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronExpr {
+    source: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl FromStr for CronExpr {
+    type Err = ScheduleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expanded = match s.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            other => other,
+        };
+
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(ScheduleError(format!(
+                "в выражении cron \"{s}\" должно быть 5 полей"
+            )));
+        };
+
+        let mut weekdays = WEEKDAY.parse(weekday)?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+
+        Ok(Self {
+            source: s.trim().to_string(),
+            minutes: MINUTE.parse(minute)?,
+            hours: HOUR.parse(hour)?,
+            days: DAY.parse(day)?,
+            months: MONTH.parse(month)?,
+            weekdays,
+            days_restricted: !day.starts_with('*'),
+            weekdays_restricted: !weekday.starts_with('*'),
+        })
+    }
+}
+
+impl fmt::Display for CronExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl CronExpr {
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        if self.months & (1 << date.month()) == 0 {
+            return false;
+        }
+
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        if self.days_restricted && self.weekdays_restricted {
+            day || weekday
+        } else {
+            day && weekday
+        }
+    }
+
+    /// Ближайший запуск строго позже `after` (мс от начала эпохи) по местному времени `tz`.
+    ///
+    /// Время, пропущенное при переходе на летнее время, не наступает, и запуск
+    /// в этот день пропускается; повторившееся при переходе на зимнее - выполняется один раз.
+    pub fn next_after(&self, after: u64, tz: &Tz) -> Option<u64> {
+        let start = tz.timestamp_millis_opt(after as i64).single()?.date_naive();
+
+        for offset in 0..SEARCH_DAYS {
+            let date = start.checked_add_days(Days::new(offset))?;
+            if !self.matches_date(date) {
+                continue;
+            }
+
+            for hour in 0..24 {
+                if self.hours & (1 << hour) == 0 {
+                    continue;
+                }
+                for minute in 0..60 {
+                    if self.minutes & (1 << minute) == 0 {
+                        continue;
+                    }
+
+                    let local = date.and_hms_opt(hour, minute, 0)?;
+                    let time = match tz.from_local_datetime(&local) {
+                        LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => time,
+                        LocalResult::None => continue,
+                    };
+                    let at = time.timestamp_millis() as u64;
+                    if at > after {
+                        return Some(at);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Последний запуск в интервале `(after, until]`.
+    ///
+    /// Ищется с конца интервала окном, которое расширяется, пока запуск не найдется.
+    pub fn last_between(&self, after: u64, until: u64, tz: &Tz) -> Option<u64> {
+        let mut window = 60 * 60 * 1000;
+
+        loop {
+            let from = until.saturating_sub(window).max(after);
+            let mut last = None;
+            let mut next = self.next_after(from, tz);
+            while let Some(at) = next.filter(|at| *at <= until) {
+                last = Some(at);
+                next = self.next_after(at, tz);
+            }
+
+            if last.is_some() || from == after {
+                return last;
+            }
+            window = window.saturating_mul(24);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(tz: &Tz, y: i32, m: u32, d: u32, h: u32, min: u32) -> u64 {
+        tz.with_ymd_and_hms(y, m, d, h, min, 0)
+            .single()
+            .unwrap()
+            .timestamp_millis() as u64
+    }
+
+    #[test]
+    fn parses_fields_names_and_macros() {
+        let weekdays: CronExpr = "30 6 * * MON-FRI".parse().unwrap();
+        assert_eq!(
+            weekdays.weekdays,
+            "30 6 * * 1-5".parse::<CronExpr>().unwrap().weekdays
+        );
+        assert_eq!(weekdays.to_string(), "30 6 * * MON-FRI");
+
+        let every_quarter: CronExpr = "*/15 8-18/2 1,15 * 7".parse().unwrap();
+        assert_eq!(every_quarter.minutes, 1 | 1 << 15 | 1 << 30 | 1 << 45);
+        assert_eq!(every_quarter.weekdays, 1);
+
+        let daily: CronExpr = "@daily".parse().unwrap();
+        assert_eq!(daily.minutes, 1);
+        assert_eq!(daily.hours, 1);
+
+        for invalid in [
+            "",
+            "* * * *",
+            "60 * * * *",
+            "* * 0 * *",
+            "5-1 * * * *",
+            "*/0 * * * *",
+        ] {
+            assert!(invalid.parse::<CronExpr>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn next_run_follows_weekdays_and_time_zone() {
+        let moscow: Tz = "Europe/Moscow".parse().unwrap();
+        let expr: CronExpr = "30 6 * * 1-5".parse().unwrap();
+
+        // Пятница, 17 октября 2025, 07:00 - следующий запуск в понедельник
+        let friday = at(&moscow, 2025, 10, 17, 7, 0);
+        let next = expr.next_after(friday, &moscow).unwrap();
+        assert_eq!(next, at(&moscow, 2025, 10, 20, 6, 30));
+
+        // Точно в момент запуска - следующий, а не тот же
+        assert_eq!(
+            expr.next_after(next, &moscow),
+            Some(at(&moscow, 2025, 10, 21, 6, 30))
+        );
+
+        assert_eq!(
+            expr.last_between(friday, at(&moscow, 2025, 10, 22, 12, 0), &moscow),
+            Some(at(&moscow, 2025, 10, 22, 6, 30))
+        );
+        assert_eq!(
+            expr.last_between(friday, at(&moscow, 2025, 10, 20, 6, 0), &moscow),
+            None
+        );
+    }
+
+    #[test]
+    fn daylight_saving_gaps_are_skipped_and_repeats_run_once() {
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+
+        // 30 марта 2025 часы переводятся с 02:00 на 03:00
+        let gap: CronExpr = "30 2 * * *".parse().unwrap();
+        let before = at(&berlin, 2025, 3, 29, 12, 0);
+        assert_eq!(
+            gap.next_after(before, &berlin),
+            Some(at(&berlin, 2025, 3, 31, 2, 30))
+        );
+
+        // 26 октября 2025 час с 02:00 до 03:00 повторяется
+        let repeat: CronExpr = "30 2 * * *".parse().unwrap();
+        let first = repeat
+            .next_after(at(&berlin, 2025, 10, 25, 12, 0), &berlin)
+            .unwrap();
+        let next = repeat.next_after(first, &berlin).unwrap();
+        assert_eq!(next - first, 24 * 60 * 60 * 1000 + 60 * 60 * 1000);
+    }
+}
@@ -0,0 +1,459 @@
+mod cron;
+
+pub use cron::CronExpr;
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    automation::{Action, ActionResult, DeviceRef, SocketCommand, run_actions},
+    id::Id,
+    smart_device::SmartSocket,
+};
+
+/// Насколько может опоздать запуск, чтобы не считаться пропущенным, мс.
+///
+/// Расписания проверяются периодически, поэтому запуск всегда немного опаздывает.
+pub const MISFIRE_GRACE_MS: u64 = 60 * 1000;
+
+/// Когда выполняется расписание
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum When {
+    /// По выражению cron в часовом поясе расписания, см. [`CronExpr`]
+    Cron { expression: String },
+    /// Один раз в момент `at`, мс от начала эпохи
+    Once { at: u64 },
+}
+
+/// Розетки, которым расписание отдает команду
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "scope", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Target {
+    /// Все розетки дома на момент запуска
+    Home,
+    /// Все розетки комнаты на момент запуска
+    Room {
+        room_id: String,
+    },
+    Socket {
+        device: DeviceRef,
+    },
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Home => write!(f, "все розетки дома"),
+            Target::Room { room_id } => write!(f, "розетки комнаты {room_id}"),
+            Target::Socket { device } => write!(f, "{device}"),
+        }
+    }
+}
+
+/// Команда расписания
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleAction {
+    pub target: Target,
+    pub command: SocketCommand,
+}
+
+/// Расписание команд розеткам
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schedule {
+    /// Выводится из имени, как и id правил
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub paused: bool,
+    pub when: When,
+    /// Часовой пояс IANA, например "Europe/Moscow"
+    #[serde(default = "utc")]
+    pub time_zone: String,
+    /// Насколько может опоздать запуск, пропущенный из-за простоя, чтобы все же выполниться, мс.
+    ///
+    /// Из нескольких пропущенных запусков выполняется только последний,
+    /// 0 - пропущенные запуски не выполняются.
+    #[serde(default)]
+    pub catch_up_ms: u64,
+    pub actions: Vec<ScheduleAction>,
+    /// Запуски до этого момента выполнены или пропущены, мс.
+    /// Сдвигается при создании, возобновлении и каждом запуске
+    #[serde(default)]
+    pub since: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run: Option<u64>,
+}
+
+fn utc() -> String {
+    "UTC".to_string()
+}
+
+/// Ошибка в описании расписания
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduleError(pub String);
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Некорректное расписание: {}", self.0)
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+impl Schedule {
+    /// Активное расписание в UTC без выполнения пропущенных запусков
+    pub fn new(name: impl Into<String>, when: When, actions: Vec<ScheduleAction>) -> Self {
+        let name = name.into();
+        Self {
+            id: Id::from_string(&name).to_string(),
+            name,
+            paused: false,
+            when,
+            time_zone: utc(),
+            catch_up_ms: 0,
+            actions,
+            since: 0,
+            last_run: None,
+        }
+    }
+
+    pub fn with_time_zone(mut self, time_zone: impl Into<String>) -> Self {
+        self.time_zone = time_zone.into();
+        self
+    }
+
+    pub fn with_catch_up_ms(mut self, catch_up_ms: u64) -> Self {
+        self.catch_up_ms = catch_up_ms;
+        self
+    }
+
+    pub fn tz(&self) -> Result<Tz, ScheduleError> {
+        self.time_zone
+            .parse()
+            .map_err(|_| ScheduleError(format!("неизвестный часовой пояс {}", self.time_zone)))
+    }
+
+    /// Проверить имя, время и часовой пояс. Существование розеток проверяет дом
+    pub fn validate(&self) -> Result<(), ScheduleError> {
+        if self.name.trim().is_empty() {
+            return Err(ScheduleError("пустое имя".to_string()));
+        }
+
+        self.tz()?;
+        if let When::Cron { expression } = &self.when {
+            expression.parse::<CronExpr>()?;
+        }
+
+        if self.actions.is_empty() {
+            return Err(ScheduleError("нет действий".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Приостановить или возобновить расписание. Запуски, пришедшиеся на паузу,
+    /// не выполняются
+    pub fn set_paused(&mut self, paused: bool, now: u64) {
+        if self.paused && !paused {
+            self.since = now;
+        }
+        self.paused = paused;
+    }
+
+    /// Ближайший запуск после `since`, `None` - запусков больше не будет
+    pub fn next_run(&self) -> Option<u64> {
+        match &self.when {
+            When::Cron { expression } => {
+                let expr: CronExpr = expression.parse().ok()?;
+                expr.next_after(self.since, &self.tz().ok()?)
+            }
+            When::Once { at } => (*at > self.since).then_some(*at),
+        }
+    }
+
+    /// Последний запуск в `(since, now]`
+    fn last_due(&self, now: u64) -> Option<u64> {
+        match &self.when {
+            When::Cron { expression } => {
+                let expr: CronExpr = expression.parse().ok()?;
+                expr.last_between(self.since, now, &self.tz().ok()?)
+            }
+            When::Once { at } => (*at > self.since && *at <= now).then_some(*at),
+        }
+    }
+}
+
+/// Запуск расписания
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduledRun {
+    pub schedule_id: String,
+    pub schedule_name: String,
+    /// На какой момент был назначен запуск, мс
+    pub scheduled_at: u64,
+    /// Когда запуск выполняется, мс
+    pub timestamp: u64,
+    pub actions: Vec<ScheduleAction>,
+}
+
+/// Расписания дома.
+///
+/// Расписания проверяются периодически. Запуск, опоздавший больше чем на
+/// [`MISFIRE_GRACE_MS`] и на `catch_up_ms` расписания, например из-за простоя сервера,
+/// пропускается. Клоны разделяют расписания.
+#[derive(Debug, Clone, Default)]
+pub struct Scheduler {
+    inner: Arc<Mutex<BTreeMap<String, Schedule>>>,
+}
+
+impl Scheduler {
+    /// Расписания, упорядоченные по имени
+    pub fn schedules(&self) -> Vec<Schedule> {
+        let mut schedules: Vec<Schedule> = self.inner.lock().unwrap().values().cloned().collect();
+        schedules.sort_by(|a, b| (&a.name, &a.id).cmp(&(&b.name, &b.id)));
+        schedules
+    }
+
+    pub fn schedule(&self, schedule_id: &str) -> Option<Schedule> {
+        self.inner.lock().unwrap().get(schedule_id).cloned()
+    }
+
+    /// Добавить или заменить расписание как есть, вместе с `since` и `last_run`
+    pub fn put(&self, schedule: Schedule) {
+        self.inner
+            .lock()
+            .unwrap()
+            .insert(schedule.id.clone(), schedule);
+    }
+
+    pub fn remove(&self, schedule_id: &str) -> Option<Schedule> {
+        self.inner.lock().unwrap().remove(schedule_id)
+    }
+
+    /// Есть ли расписания, которым пора выполниться или пропустить запуск
+    pub fn has_due(&self, now: u64) -> bool {
+        self.inner
+            .lock()
+            .unwrap()
+            .values()
+            .any(|schedule| !schedule.paused && schedule.next_run().is_some_and(|at| at <= now))
+    }
+
+    /// Запуски, которым пора выполниться в момент `now`.
+    ///
+    /// Сдвигает `since` всех наступивших расписаний, у выполняемых отмечает `last_run`
+    pub fn due_at(&self, now: u64) -> Vec<ScheduledRun> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut runs = vec![];
+
+        for schedule in inner.values_mut() {
+            if schedule.paused || schedule.next_run().is_none_or(|at| at > now) {
+                continue;
+            }
+
+            let due = schedule.last_due(now);
+            schedule.since = now;
+
+            let Some(scheduled_at) = due else {
+                continue;
+            };
+            if now.saturating_sub(scheduled_at) > schedule.catch_up_ms.max(MISFIRE_GRACE_MS) {
+                continue;
+            }
+
+            schedule.last_run = Some(now);
+            runs.push(ScheduledRun {
+                schedule_id: schedule.id.clone(),
+                schedule_name: schedule.name.clone(),
+                scheduled_at,
+                timestamp: now,
+                actions: schedule.actions.clone(),
+            });
+        }
+
+        runs
+    }
+}
+
+/// Запуск расписания вместе с розетками для его команд.
+///
+/// Розетки - клоны устройств дома, поэтому задание выполняется без блокировки дома.
+#[derive(Debug)]
+pub struct ScheduleJob {
+    pub(crate) run: ScheduledRun,
+    /// Команды, развернутые по розеткам
+    pub(crate) actions: Vec<Action>,
+    /// Розетка для каждой команды или причина, по которой ее нет
+    pub(crate) sockets: Vec<Result<SmartSocket, String>>,
+}
+
+impl ScheduleJob {
+    pub fn scheduled_run(&self) -> &ScheduledRun {
+        &self.run
+    }
+
+    /// Выполнить команды по очереди
+    pub async fn run(self) -> Vec<ActionResult> {
+        run_actions(self.actions, self.sockets).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{smart_device::SmartThermometer, smart_home::SmartHome, smart_room::SmartRoom};
+    use chrono::TimeZone;
+
+    const MINUTE: u64 = 60 * 1000;
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32) -> u64 {
+        let moscow: Tz = "Europe/Moscow".parse().unwrap();
+        moscow
+            .with_ymd_and_hms(y, m, d, h, min, 0)
+            .single()
+            .unwrap()
+            .timestamp_millis() as u64
+    }
+
+    fn heater() -> Schedule {
+        let mut schedule = Schedule::new(
+            "Обогреватель утром",
+            When::Cron {
+                expression: "30 6 * * MON-FRI".to_string(),
+            },
+            vec![ScheduleAction {
+                target: Target::Socket {
+                    device: DeviceRef::new(
+                        &Id::from_string("Спальня"),
+                        &Id::from_string("Обогреватель"),
+                    ),
+                },
+                command: SocketCommand::TurnOn,
+            }],
+        )
+        .with_time_zone("Europe/Moscow");
+        // Создано в пятницу вечером
+        schedule.since = at(2025, 10, 17, 20, 0);
+        schedule
+    }
+
+    #[test]
+    fn validate_checks_expression_and_time_zone() {
+        heater().validate().unwrap();
+        assert!(heater().with_time_zone("Mars/Olympus").validate().is_err());
+
+        let mut invalid = heater();
+        invalid.when = When::Cron {
+            expression: "30 25 * * *".to_string(),
+        };
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn runs_on_time_and_skips_missed_runs() {
+        let scheduler = Scheduler::default();
+        scheduler.put(heater());
+
+        let monday = at(2025, 10, 20, 6, 30);
+        let id = heater().id;
+        assert_eq!(scheduler.schedule(&id).unwrap().next_run(), Some(monday));
+
+        assert!(!scheduler.has_due(monday - 1));
+        let runs = scheduler.due_at(monday + 2000);
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].scheduled_at, monday);
+        assert!(scheduler.due_at(monday + 3000).is_empty());
+
+        // Сервер простоял со вторника до среды: запуск во вторник пропускается
+        let tuesday = at(2025, 10, 21, 6, 30);
+        assert!(scheduler.due_at(tuesday + 90 * MINUTE).is_empty());
+        let schedule = scheduler.schedule(&id).unwrap();
+        assert_eq!(schedule.last_run, Some(monday + 2000));
+        assert_eq!(schedule.next_run(), Some(at(2025, 10, 22, 6, 30)));
+    }
+
+    #[test]
+    fn catch_up_runs_last_missed_run_once() {
+        let scheduler = Scheduler::default();
+        scheduler.put(heater().with_catch_up_ms(2 * 60 * MINUTE));
+
+        // Пропущены запуски в понедельник и вторник, выполняется только вторничный
+        let tuesday = at(2025, 10, 21, 6, 30);
+        let runs = scheduler.due_at(tuesday + 90 * MINUTE);
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].scheduled_at, tuesday);
+    }
+
+    #[test]
+    fn paused_schedule_does_not_run_missed_runs_after_resume() {
+        let scheduler = Scheduler::default();
+        let mut schedule = heater().with_catch_up_ms(24 * 60 * MINUTE);
+
+        let monday = at(2025, 10, 20, 6, 30);
+        schedule.set_paused(true, monday - MINUTE);
+        scheduler.put(schedule.clone());
+        assert!(!scheduler.has_due(monday + MINUTE));
+        assert!(scheduler.due_at(monday + MINUTE).is_empty());
+
+        schedule.set_paused(false, monday + 2 * MINUTE);
+        scheduler.put(schedule);
+        assert!(scheduler.due_at(monday + 3 * MINUTE).is_empty());
+    }
+
+    #[tokio::test]
+    async fn one_shot_turns_off_every_socket_of_home() {
+        let socket = SmartSocket::new("Обогреватель", 1500.0, true);
+        let kettle = SmartSocket::new("Чайник", 2000.0, true);
+        let home = SmartHome::new_with_rooms(
+            "Дом",
+            &[
+                SmartRoom::new_with_devices(
+                    "Спальня",
+                    &[
+                        socket.clone().into(),
+                        SmartThermometer::new("Термометр", 20.0).into(),
+                    ],
+                ),
+                SmartRoom::new_with_devices("Кухня", &[kettle.clone().into()]),
+            ],
+        );
+
+        let night = at(2025, 10, 17, 23, 0);
+        let mut schedule = Schedule::new(
+            "Все выключить",
+            When::Once { at: night },
+            vec![ScheduleAction {
+                target: Target::Home,
+                command: SocketCommand::TurnOff,
+            }],
+        );
+        home.check_schedule(&schedule).unwrap();
+        schedule.since = night - 60 * MINUTE;
+        home.scheduler().put(schedule);
+
+        let mut missing = heater();
+        missing.actions[0].target = Target::Room {
+            room_id: Id::from_string("Гараж").to_string(),
+        };
+        assert!(home.check_schedule(&missing).is_err());
+
+        let jobs = home.schedule_at(night + 500);
+        assert_eq!(jobs.len(), 1);
+        let results = jobs.into_iter().next().unwrap().run().await;
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|result| result.error.is_none()));
+        assert!(!socket.is_on().await);
+        assert!(!kettle.is_on().await);
+
+        // Однократное расписание больше не запускается
+        assert!(home.schedule_at(night + 60 * MINUTE).is_empty());
+    }
+}
@@ -1,16 +1,46 @@
 use bincode::{Decode, Encode, config::Configuration};
+use serde::Serialize;
 
 use crate::{
     errors::SmartHomeErrors,
-    smart_device::{smart_socket::SocketData, smart_thermometer::ThermometerData},
+    smart_device::{
+        smart_light::LightData, smart_socket::SocketData, smart_thermometer::ThermometerData,
+    },
 };
 
 const ENCODING_CONFIG: Configuration = bincode::config::standard();
 
+/// Состояние соединения с устройством
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Encode, Decode, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionState {
+    /// Устройство не на связи
+    #[default]
+    Offline,
+    /// Идет подключение
+    Connecting,
+    /// Устройство на связи
+    Online,
+    /// Ожидание перед повторным подключением
+    Backoff,
+}
+
+/// Паспорт физического устройства
+#[derive(Clone, Debug, Default, PartialEq, Eq, Encode, Decode)]
+pub struct DeviceInfo {
+    /// Серийный номер
+    pub serial: String,
+    /// Модель
+    pub model: String,
+    /// Версия прошивки
+    pub firmware: String,
+}
+
 #[derive(Clone, Debug, Encode, Decode)]
 pub enum DeviceData {
     Socket(SocketData),
     Thermometer(ThermometerData),
+    Light(LightData),
 }
 
 impl DeviceData {
@@ -18,6 +48,31 @@ impl DeviceData {
         match self {
             DeviceData::Socket(s) => s.is_online = online,
             DeviceData::Thermometer(t) => t.is_online = online,
+            DeviceData::Light(l) => l.is_online = online,
+        }
+    }
+
+    pub fn is_online(&self) -> bool {
+        match self {
+            DeviceData::Socket(s) => s.is_online,
+            DeviceData::Thermometer(t) => t.is_online,
+            DeviceData::Light(l) => l.is_online,
+        }
+    }
+
+    /// Совпадают ли показания, без учета метки времени и состояния соединения
+    pub fn same_readings(&self, other: &DeviceData) -> bool {
+        match (self, other) {
+            (DeviceData::Socket(a), DeviceData::Socket(b)) => {
+                a.is_on == b.is_on && a.power == b.power
+            }
+            (DeviceData::Thermometer(a), DeviceData::Thermometer(b)) => a.temp == b.temp,
+            (DeviceData::Light(a), DeviceData::Light(b)) => {
+                a.is_on == b.is_on
+                    && a.brightness == b.brightness
+                    && a.color_temperature == b.color_temperature
+            }
+            _ => false,
         }
     }
 
@@ -34,11 +89,20 @@ impl DeviceData {
             _ => panic!("Неверный тип устройства"),
         }
     }
+
+    pub fn as_light(&self) -> LightData {
+        match self {
+            DeviceData::Light(l) => l.clone(),
+            _ => panic!("Неверный тип устройства"),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Encode, Decode)]
 pub struct DeviceResponse {
     pub data: Option<DeviceData>,
+    /// Паспорт устройства: в ответе на `GetInfo` и в каждой UDP-посылке
+    pub info: Option<DeviceInfo>,
     pub success: bool,
     pub error: Option<String>,
 }
@@ -76,6 +140,11 @@ pub enum Commands {
     TurnOn = 1,
     TurnOff = 2,
     GetStatus = 3,
+    GetInfo = 4,
+    /// Установить яркость, значение передается в кадре команды
+    SetBrightness = 5,
+    /// Установить цветовую температуру, значение передается в кадре команды
+    SetColorTemperature = 6,
 }
 
 impl From<i32> for Commands {
@@ -84,6 +153,9 @@ impl From<i32> for Commands {
             1 => Commands::TurnOn,
             2 => Commands::TurnOff,
             3 => Commands::GetStatus,
+            4 => Commands::GetInfo,
+            5 => Commands::SetBrightness,
+            6 => Commands::SetColorTemperature,
             _ => Commands::Unknown,
         }
     }
@@ -0,0 +1,462 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use chrono::{Datelike, NaiveDate};
+
+/// Длительность суток, мс
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// Длительность минуты, мс
+const MINUTE_MS: u64 = 60 * 1000;
+
+/// Минут в сутках
+const DAY_MINUTES: usize = 24 * 60;
+
+/// Сколько интервалов мощности хранит счетчик
+const TIMELINE_CAPACITY: usize = 10_000;
+
+/// Интервал `[start, end)` постоянной мощности, мс от начала эпохи
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PowerSegment {
+    pub start: u64,
+    pub end: u64,
+    /// Мощность в Вт
+    pub power: f32,
+}
+
+impl PowerSegment {
+    /// Потребленная за интервал энергия в кВт·ч
+    pub fn kwh(&self) -> f64 {
+        self.watt_ms() / 3_600_000_000.0
+    }
+
+    /// Потребленная за интервал энергия в Вт·мс
+    pub fn watt_ms(&self) -> f64 {
+        self.power as f64 * self.end.saturating_sub(self.start) as f64
+    }
+
+    /// Часть интервала внутри `[from, to)`
+    pub fn clip(&self, from: u64, to: u64) -> Option<PowerSegment> {
+        let start = self.start.max(from);
+        let end = self.end.min(to);
+
+        (start < end).then_some(PowerSegment {
+            start,
+            end,
+            power: self.power,
+        })
+    }
+}
+
+/// Потребление, разложенное по минутам суток UTC.
+///
+/// Цена тарифа меняется только на границе минуты, поэтому по профилю
+/// стоимость считается точно при любом тарифе и смещении часового пояса
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnergyProfile {
+    /// Энергия в Вт·мс за каждую минуту суток, `DAY_MINUTES` значений
+    pub watt_ms: Vec<f64>,
+    /// Часть интервала старше хранимых интервалов мощности не учтена
+    pub partial: bool,
+}
+
+impl Default for EnergyProfile {
+    fn default() -> Self {
+        Self {
+            watt_ms: vec![0.0; DAY_MINUTES],
+            partial: false,
+        }
+    }
+}
+
+impl EnergyProfile {
+    /// Учесть интервал, разбив его по минутам
+    pub fn add(&mut self, segment: &PowerSegment) {
+        let mut start = segment.start;
+
+        while start < segment.end {
+            let end = segment.end.min((start / MINUTE_MS + 1) * MINUTE_MS);
+            let watt_ms = PowerSegment {
+                start,
+                end,
+                ..*segment
+            }
+            .watt_ms();
+
+            self.watt_ms[(start % DAY_MS / MINUTE_MS) as usize] += watt_ms;
+            start = end;
+        }
+    }
+}
+
+/// Потребление за сутки или месяц
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnergyBucket {
+    /// Сутки или первый день месяца, UTC
+    pub start: NaiveDate,
+    pub kwh: f64,
+}
+
+/// Потребление энергии: всего, по суткам и по месяцам
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EnergyUsage {
+    pub total_kwh: f64,
+    /// Упорядочены по дате
+    pub daily: Vec<EnergyBucket>,
+    /// Упорядочены по дате
+    pub monthly: Vec<EnergyBucket>,
+}
+
+impl EnergyUsage {
+    /// Прибавить потребление другого устройства или комнаты
+    pub fn merge(&mut self, other: &EnergyUsage) {
+        fn merge_buckets(target: &mut Vec<EnergyBucket>, other: &[EnergyBucket]) {
+            let mut buckets: BTreeMap<NaiveDate, f64> =
+                target.iter().map(|b| (b.start, b.kwh)).collect();
+
+            for bucket in other {
+                *buckets.entry(bucket.start).or_default() += bucket.kwh;
+            }
+
+            *target = buckets
+                .into_iter()
+                .map(|(start, kwh)| EnergyBucket { start, kwh })
+                .collect();
+        }
+
+        self.total_kwh += other.total_kwh;
+        merge_buckets(&mut self.daily, &other.daily);
+        merge_buckets(&mut self.monthly, &other.monthly);
+    }
+}
+
+fn date_of(timestamp: u64) -> NaiveDate {
+    chrono::DateTime::from_timestamp_millis(timestamp as i64)
+        .unwrap_or_default()
+        .date_naive()
+}
+
+#[derive(Debug, Clone, Default)]
+struct MeterInner {
+    /// Начало текущего интервала и мощность на нем
+    open: Option<(u64, f32)>,
+    total_kwh: f64,
+    daily: BTreeMap<NaiveDate, f64>,
+    monthly: BTreeMap<NaiveDate, f64>,
+    /// Все потребление с начала учета, в отличие от ограниченного `timeline`
+    profile: EnergyProfile,
+    /// Начало первого учтенного интервала
+    first: Option<u64>,
+    timeline: VecDeque<PowerSegment>,
+    /// Интервалы до этого момента уже вытеснены из `timeline`
+    retained_from: u64,
+}
+
+impl MeterInner {
+    /// Учесть завершенный интервал, разбив его по суткам и минутам суток
+    fn close(&mut self, segment: PowerSegment) {
+        if segment.power <= 0.0 || segment.end <= segment.start {
+            return;
+        }
+
+        self.first.get_or_insert(segment.start);
+        self.profile.add(&segment);
+
+        let mut start = segment.start;
+
+        while start < segment.end {
+            let end = segment.end.min((start / DAY_MS + 1) * DAY_MS);
+            let kwh = PowerSegment {
+                start,
+                end,
+                ..segment
+            }
+            .kwh();
+            let date = date_of(start);
+
+            self.total_kwh += kwh;
+            *self.daily.entry(date).or_default() += kwh;
+            *self.monthly.entry(date.with_day(1).unwrap()).or_default() += kwh;
+            start = end;
+        }
+
+        match self.timeline.back_mut() {
+            Some(last) if last.end == segment.start && last.power == segment.power => {
+                last.end = segment.end;
+            }
+            _ => self.timeline.push_back(segment),
+        }
+
+        if self.timeline.len() > TIMELINE_CAPACITY
+            && let Some(dropped) = self.timeline.pop_front()
+        {
+            self.retained_from = dropped.end;
+        }
+    }
+
+    /// Состояние с текущим интервалом, закрытым в момент `now`
+    fn closed_at(&self, now: u64) -> MeterInner {
+        let mut inner = self.clone();
+
+        if let Some((start, power)) = inner.open.take() {
+            inner.close(PowerSegment {
+                start,
+                end: now,
+                power,
+            });
+        }
+
+        inner
+    }
+}
+
+/// Счетчик энергии розетки.
+///
+/// Мощность считается постоянной от одного показания до следующего.
+/// При потере связи текущий интервал обрывается: время без связи не учитывается.
+/// Сутки и месяцы отсчитываются в UTC. Клоны устройства разделяют счетчик.
+#[derive(Debug, Clone, Default)]
+pub struct EnergyMeter {
+    inner: Arc<Mutex<MeterInner>>,
+}
+
+impl EnergyMeter {
+    /// Учесть показание мощности в Вт, полученное сейчас; 0 - розетка выключена
+    pub fn observe(&self, power: f32) {
+        self.observe_at(now_millis(), power);
+    }
+
+    /// Учесть показание мощности, полученное в `timestamp` (мс от начала эпохи).
+    /// Показания старше текущего интервала пропускаются
+    pub fn observe_at(&self, timestamp: u64, power: f32) {
+        let mut inner = self.inner.lock().unwrap();
+
+        if let Some((start, last)) = inner.open {
+            if timestamp < start {
+                return;
+            }
+
+            inner.close(PowerSegment {
+                start,
+                end: timestamp,
+                power: last,
+            });
+        }
+
+        let power = if power.is_finite() {
+            power.max(0.0)
+        } else {
+            0.0
+        };
+        inner.open = Some((timestamp, power));
+    }
+
+    /// Оборвать текущий интервал: до следующего показания энергия не учитывается
+    pub fn interrupt(&self) {
+        self.inner.lock().unwrap().open.take();
+    }
+
+    /// Потребление с учетом текущего интервала до момента `now`
+    pub fn usage_at(&self, now: u64) -> EnergyUsage {
+        let inner = self.inner.lock().unwrap().closed_at(now);
+
+        EnergyUsage {
+            total_kwh: inner.total_kwh,
+            daily: inner
+                .daily
+                .into_iter()
+                .map(|(start, kwh)| EnergyBucket { start, kwh })
+                .collect(),
+            monthly: inner
+                .monthly
+                .into_iter()
+                .map(|(start, kwh)| EnergyBucket { start, kwh })
+                .collect(),
+        }
+    }
+
+    /// Потребление на текущий момент
+    pub fn usage(&self) -> EnergyUsage {
+        self.usage_at(now_millis())
+    }
+
+    /// Всего потреблено на текущий момент, кВт·ч
+    pub fn total_kwh(&self) -> f64 {
+        self.inner.lock().unwrap().closed_at(now_millis()).total_kwh
+    }
+
+    /// Интервалы ненулевой мощности внутри `[from, to)`, текущий - до момента `now`
+    pub fn segments_at(&self, from: u64, to: u64, now: u64) -> Vec<PowerSegment> {
+        let inner = self.inner.lock().unwrap();
+        let open = inner.open.and_then(|(start, power)| {
+            (power > 0.0 && start < now).then_some(PowerSegment {
+                start,
+                end: now,
+                power,
+            })
+        });
+
+        inner
+            .timeline
+            .iter()
+            .chain(open.iter())
+            .filter_map(|segment| segment.clip(from, to))
+            .collect()
+    }
+
+    /// Потребление за `[from, to)` по минутам суток, текущий интервал - до момента `now`.
+    ///
+    /// Все время учета берется из накопленного профиля, более короткий интервал
+    /// собирается из хранимых интервалов мощности. Если он начинается раньше
+    /// самого старого из них, результат помечается неполным
+    pub fn profile_at(&self, from: u64, to: u64, now: u64) -> EnergyProfile {
+        let inner = self.inner.lock().unwrap().closed_at(now);
+
+        if inner.first.is_none_or(|first| from <= first) && to >= now {
+            return inner.profile;
+        }
+
+        let mut profile = EnergyProfile {
+            partial: from < inner.retained_from,
+            ..EnergyProfile::default()
+        };
+
+        for segment in inner.timeline.iter().filter_map(|s| s.clip(from, to)) {
+            profile.add(&segment);
+        }
+
+        profile
+    }
+}
+
+fn now_millis() -> u64 {
+    chrono::Utc::now().timestamp_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: u64 = 60 * 60 * 1000;
+
+    /// 2026-01-31 00:00 UTC
+    const JAN_31: u64 = 1_769_817_600_000;
+
+    fn date(day: &str) -> NaiveDate {
+        day.parse().unwrap()
+    }
+
+    #[test]
+    fn energy_follows_on_off_transitions() {
+        let meter = EnergyMeter::default();
+        meter.observe_at(JAN_31, 1000.0);
+        meter.observe_at(JAN_31 + HOUR, 500.0);
+        // Выключение: до него розетка тратила 500 Вт
+        meter.observe_at(JAN_31 + 3 * HOUR, 0.0);
+        meter.observe_at(JAN_31 + 10 * HOUR, 0.0);
+
+        let usage = meter.usage_at(JAN_31 + 20 * HOUR);
+        assert!((usage.total_kwh - 2.0).abs() < 1e-9, "{}", usage.total_kwh);
+
+        // Включенная розетка копит энергию и без новых показаний
+        meter.observe_at(JAN_31 + 20 * HOUR, 2000.0);
+        let usage = meter.usage_at(JAN_31 + 21 * HOUR);
+        assert!((usage.total_kwh - 4.0).abs() < 1e-9, "{}", usage.total_kwh);
+    }
+
+    #[test]
+    fn offline_gap_is_not_counted() {
+        let meter = EnergyMeter::default();
+        meter.observe_at(JAN_31, 1000.0);
+        meter.observe_at(JAN_31 + HOUR, 1000.0);
+        meter.interrupt();
+        meter.observe_at(JAN_31 + 5 * HOUR, 1000.0);
+        meter.observe_at(JAN_31 + 6 * HOUR, 1000.0);
+        // Показание из прошлого не отматывает счетчик
+        meter.observe_at(JAN_31 + 2 * HOUR, 1000.0);
+
+        let usage = meter.usage_at(JAN_31 + 6 * HOUR);
+        assert!((usage.total_kwh - 2.0).abs() < 1e-9, "{}", usage.total_kwh);
+        assert_eq!(
+            meter.segments_at(0, u64::MAX, JAN_31 + 6 * HOUR),
+            vec![
+                PowerSegment {
+                    start: JAN_31,
+                    end: JAN_31 + HOUR,
+                    power: 1000.0
+                },
+                PowerSegment {
+                    start: JAN_31 + 5 * HOUR,
+                    end: JAN_31 + 6 * HOUR,
+                    power: 1000.0
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn profile_outlives_timeline_capacity() {
+        let meter = EnergyMeter::default();
+        let step = 2000;
+        let steps = TIMELINE_CAPACITY as u64 + 500;
+
+        // Мощность меняется на каждом показании, интервалы не сливаются
+        for i in 0..=steps {
+            meter.observe_at(JAN_31 + i * step, if i % 2 == 0 { 1000.0 } else { 2000.0 });
+        }
+
+        let now = JAN_31 + steps * step;
+        let total = meter.usage_at(now).total_kwh;
+        let kwh = |profile: &EnergyProfile| profile.watt_ms.iter().sum::<f64>() / 3_600_000_000.0;
+
+        let whole = meter.profile_at(0, now, now);
+        assert!(!whole.partial);
+        assert!(
+            (kwh(&whole) - total).abs() < 1e-9,
+            "{} {}",
+            kwh(&whole),
+            total
+        );
+
+        // Начало учета уже вытеснено из интервалов мощности
+        let early = meter.profile_at(JAN_31, JAN_31 + 100 * step, now);
+        assert!(early.partial);
+
+        let recent = meter.profile_at(now - 100 * step, now, now);
+        assert!(!recent.partial);
+        assert!((kwh(&recent) - 100.0 * 1.5 * step as f64 / 3_600_000.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn buckets_split_at_midnight_and_roll_up() {
+        let meter = EnergyMeter::default();
+        meter.observe_at(JAN_31 + 22 * HOUR, 1000.0);
+        meter.observe_at(JAN_31 + 27 * HOUR, 0.0);
+
+        let usage = meter.usage_at(JAN_31 + 30 * HOUR);
+        assert_eq!(
+            usage.daily,
+            vec![
+                EnergyBucket {
+                    start: date("2026-01-31"),
+                    kwh: 2.0
+                },
+                EnergyBucket {
+                    start: date("2026-02-01"),
+                    kwh: 3.0
+                },
+            ]
+        );
+        assert_eq!(usage.monthly[0].start, date("2026-01-01"));
+        assert_eq!(usage.monthly[1].kwh, 3.0);
+
+        let mut total = EnergyUsage::default();
+        total.merge(&usage);
+        total.merge(&usage);
+        assert_eq!(total.total_kwh, 10.0);
+        assert_eq!(total.daily.len(), 2);
+        assert_eq!(total.monthly[0].kwh, 4.0);
+    }
+}
@@ -0,0 +1,540 @@
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    errors::SmartHomeErrors,
+    smart_device::contracts::{Commands, DecodeEncode, DeviceResponse},
+};
+
+/// Сигнатура кадра
+pub const MAGIC: [u8; 2] = *b"SH";
+/// Текущая версия протокола
+pub const PROTOCOL_VERSION: u8 = 1;
+/// Минимальная поддерживаемая версия протокола
+pub const MIN_PROTOCOL_VERSION: u8 = 1;
+/// Размер заголовка кадра
+pub const HEADER_LEN: usize = 9;
+/// Размер контрольной суммы
+pub const CRC_LEN: usize = 4;
+/// Максимальный размер полезной нагрузки
+pub const MAX_PAYLOAD_LEN: u32 = 64 * 1024;
+/// Максимальный размер UDP-датаграммы с кадром
+pub const MAX_DATAGRAM_LEN: usize = 65_507;
+
+const FLAG_CRC: u8 = 0b0000_0001;
+
+/// Тип сообщения
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MessageType {
+    /// Согласование версии протокола
+    Hello = 1,
+    /// Команда устройству
+    Command = 2,
+    /// Ответ устройства
+    Response = 3,
+    /// Отказ в обработке, в нагрузке текст ошибки
+    Error = 4,
+}
+
+impl TryFrom<u8> for MessageType {
+    type Error = SmartHomeErrors;
+
+    fn try_from(value: u8) -> Result<Self, SmartHomeErrors> {
+        match value {
+            1 => Ok(MessageType::Hello),
+            2 => Ok(MessageType::Command),
+            3 => Ok(MessageType::Response),
+            4 => Ok(MessageType::Error),
+            _ => Err(SmartHomeErrors::protocol_error(format!(
+                "неизвестный тип сообщения {}",
+                value
+            ))),
+        }
+    }
+}
+
+/// Заголовок кадра
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FrameHeader {
+    pub version: u8,
+    pub message_type: MessageType,
+    pub flags: u8,
+    pub length: u32,
+}
+
+impl FrameHeader {
+    /// Разобрать и проверить заголовок
+    pub fn parse(bytes: &[u8; HEADER_LEN]) -> Result<Self, SmartHomeErrors> {
+        if bytes[0..2] != MAGIC {
+            return Err(SmartHomeErrors::protocol_error(
+                "неверная сигнатура кадра".to_string(),
+            ));
+        }
+
+        let version = bytes[2];
+
+        if !is_supported(version) {
+            return Err(unsupported_version(version));
+        }
+
+        let length = u32::from_be_bytes([bytes[5], bytes[6], bytes[7], bytes[8]]);
+
+        if length > MAX_PAYLOAD_LEN {
+            return Err(SmartHomeErrors::protocol_error(format!(
+                "слишком большой кадр: {} байт",
+                length
+            )));
+        }
+
+        Ok(Self {
+            version,
+            message_type: MessageType::try_from(bytes[3])?,
+            flags: bytes[4],
+            length,
+        })
+    }
+
+    pub fn has_crc(&self) -> bool {
+        self.flags & FLAG_CRC != 0
+    }
+
+    fn to_bytes(self) -> [u8; HEADER_LEN] {
+        let length = self.length.to_be_bytes();
+
+        [
+            MAGIC[0],
+            MAGIC[1],
+            self.version,
+            self.message_type as u8,
+            self.flags,
+            length[0],
+            length[1],
+            length[2],
+            length[3],
+        ]
+    }
+}
+
+/// Кадр протокола обмена с устройствами.
+///
+/// Формат не зависит от платформы, все числа передаются в big-endian:
+///
+/// | Смещение | Размер | Поле                                   |
+/// |----------|--------|----------------------------------------|
+/// | 0        | 2      | Сигнатура `SH`                         |
+/// | 2        | 1      | Версия протокола                       |
+/// | 3        | 1      | Тип сообщения, см. [`MessageType`]     |
+/// | 4        | 1      | Флаги, бит 0 - в конце кадра есть CRC  |
+/// | 5        | 4      | Длина полезной нагрузки, `u32`         |
+/// | 9        | N      | Полезная нагрузка                      |
+/// | 9 + N    | 4      | CRC32 заголовка и нагрузки, если есть  |
+///
+/// Перед обменом по TCP клиент отправляет [`MessageType::Hello`] с диапазоном
+/// поддерживаемых версий, устройство отвечает выбранной версией или
+/// [`MessageType::Error`], если общей версии нет.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub version: u8,
+    pub message_type: MessageType,
+    pub payload: Vec<u8>,
+    pub checksum: bool,
+}
+
+impl Frame {
+    pub fn new(message_type: MessageType, payload: Vec<u8>) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            message_type,
+            payload,
+            checksum: false,
+        }
+    }
+
+    /// Добавить к кадру контрольную сумму
+    pub fn with_checksum(mut self) -> Self {
+        self.checksum = true;
+        self
+    }
+
+    /// Предложение версий протокола, поддерживаемых клиентом
+    pub fn hello() -> Self {
+        Self::new(
+            MessageType::Hello,
+            vec![MIN_PROTOCOL_VERSION, PROTOCOL_VERSION],
+        )
+    }
+
+    pub fn command(cmd: Commands) -> Self {
+        Self::new(MessageType::Command, (cmd as i32).to_be_bytes().to_vec())
+    }
+
+    /// Команда со значением: за кодом команды следует `u32`
+    pub fn command_with_value(cmd: Commands, value: u32) -> Self {
+        let mut payload = (cmd as i32).to_be_bytes().to_vec();
+        payload.extend_from_slice(&value.to_be_bytes());
+
+        Self::new(MessageType::Command, payload)
+    }
+
+    pub fn response(response: &DeviceResponse) -> Result<Self, SmartHomeErrors> {
+        Ok(Self::new(MessageType::Response, response.encode()?))
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(MessageType::Error, message.into().into_bytes())
+    }
+
+    /// Команда из кадра [`MessageType::Command`], значение команды не учитывается
+    pub fn as_command(&self) -> Result<Commands, SmartHomeErrors> {
+        self.as_command_value().map(|(cmd, _)| cmd)
+    }
+
+    /// Команда и ее значение, если оно передано, из кадра [`MessageType::Command`]
+    pub fn as_command_value(&self) -> Result<(Commands, Option<u32>), SmartHomeErrors> {
+        self.expect(MessageType::Command)?;
+
+        let bytes = &self.payload;
+        if bytes.len() != 4 && bytes.len() != 8 {
+            return Err(SmartHomeErrors::protocol_error(
+                "неверная длина команды".to_string(),
+            ));
+        }
+
+        let cmd = Commands::from(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+        let value = (bytes.len() == 8)
+            .then(|| u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]));
+
+        Ok((cmd, value))
+    }
+
+    /// Ответ устройства из кадра [`MessageType::Response`].
+    ///
+    /// Кадр [`MessageType::Error`] превращается в ошибку протокола с текстом от устройства.
+    pub fn as_response(&self) -> Result<DeviceResponse, SmartHomeErrors> {
+        self.expect(MessageType::Response)?;
+        DeviceResponse::decode(&self.payload)
+    }
+
+    /// Версия, выбранная устройством в ответ на [`Frame::hello`]
+    pub fn as_hello_ack(&self) -> Result<u8, SmartHomeErrors> {
+        self.expect(MessageType::Hello)?;
+
+        match self.payload.as_slice() {
+            [version] if is_supported(*version) => Ok(*version),
+            [version] => Err(unsupported_version(*version)),
+            _ => Err(SmartHomeErrors::protocol_error(
+                "неверный ответ на согласование версии".to_string(),
+            )),
+        }
+    }
+
+    fn expect(&self, message_type: MessageType) -> Result<(), SmartHomeErrors> {
+        if self.message_type == MessageType::Error {
+            return Err(SmartHomeErrors::protocol_error(format!(
+                "устройство отклонило запрос: {}",
+                String::from_utf8_lossy(&self.payload)
+            )));
+        }
+
+        if self.message_type != message_type {
+            return Err(SmartHomeErrors::protocol_error(format!(
+                "ожидалось сообщение {:?}, получено {:?}",
+                message_type, self.message_type
+            )));
+        }
+
+        Ok(())
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let header = FrameHeader {
+            version: self.version,
+            message_type: self.message_type,
+            flags: if self.checksum { FLAG_CRC } else { 0 },
+            length: self.payload.len() as u32,
+        };
+
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.payload.len() + CRC_LEN);
+        bytes.extend_from_slice(&header.to_bytes());
+        bytes.extend_from_slice(&self.payload);
+
+        if self.checksum {
+            let crc = crc32fast::hash(&bytes);
+            bytes.extend_from_slice(&crc.to_be_bytes());
+        }
+
+        bytes
+    }
+
+    /// Закодировать кадр в одну UDP-датаграмму
+    pub fn to_datagram(&self) -> Result<Vec<u8>, SmartHomeErrors> {
+        let bytes = self.encode();
+
+        if bytes.len() > MAX_DATAGRAM_LEN {
+            return Err(SmartHomeErrors::protocol_error(format!(
+                "кадр {} байт не помещается в датаграмму ({} байт)",
+                bytes.len(),
+                MAX_DATAGRAM_LEN
+            )));
+        }
+
+        Ok(bytes)
+    }
+
+    /// Разобрать кадр, целиком лежащий в буфере, например в UDP-датаграмме
+    pub fn decode(bytes: &[u8]) -> Result<Self, SmartHomeErrors> {
+        if bytes.len() < HEADER_LEN {
+            return Err(SmartHomeErrors::protocol_error(format!(
+                "кадр короче заголовка: {} байт",
+                bytes.len()
+            )));
+        }
+
+        let header = FrameHeader::parse(bytes[..HEADER_LEN].try_into().unwrap())?;
+        let end = HEADER_LEN + header.length as usize;
+        let expected = end + if header.has_crc() { CRC_LEN } else { 0 };
+
+        if bytes.len() < expected {
+            return Err(SmartHomeErrors::protocol_error(format!(
+                "кадр обрезан: {} байт из {}",
+                bytes.len(),
+                expected
+            )));
+        }
+
+        if bytes.len() > expected {
+            return Err(SmartHomeErrors::protocol_error(format!(
+                "лишние данные после кадра: {} байт",
+                bytes.len() - expected
+            )));
+        }
+
+        if header.has_crc() {
+            verify_crc(&bytes[..end], bytes[end..].try_into().unwrap())?;
+        }
+
+        Ok(Self::from_parts(header, bytes[HEADER_LEN..end].to_vec()))
+    }
+
+    /// Прочитать кадр из потока
+    pub async fn read_from<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self, SmartHomeErrors> {
+        let mut header = [0u8; HEADER_LEN];
+        reader.read_exact(&mut header).await.map_err(io_error)?;
+        let parsed = FrameHeader::parse(&header)?;
+
+        let mut payload = vec![0u8; parsed.length as usize];
+        reader.read_exact(&mut payload).await.map_err(io_error)?;
+
+        if parsed.has_crc() {
+            let mut crc = [0u8; CRC_LEN];
+            reader.read_exact(&mut crc).await.map_err(io_error)?;
+            verify_crc(&[header.as_slice(), &payload].concat(), &crc)?;
+        }
+
+        Ok(Self::from_parts(parsed, payload))
+    }
+
+    /// Записать кадр в поток
+    pub async fn write_to<W: AsyncWrite + Unpin>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SmartHomeErrors> {
+        writer.write_all(&self.encode()).await.map_err(io_error)?;
+        writer.flush().await.map_err(io_error)
+    }
+
+    fn from_parts(header: FrameHeader, payload: Vec<u8>) -> Self {
+        Self {
+            version: header.version,
+            message_type: header.message_type,
+            payload,
+            checksum: header.has_crc(),
+        }
+    }
+}
+
+/// Ответ устройства на [`Frame::hello`]: наибольшая общая версия или отказ
+pub fn negotiate(hello: &Frame) -> Frame {
+    let (peer_min, peer_max) = match hello.payload.as_slice() {
+        [min, max] if hello.message_type == MessageType::Hello && min <= max => (*min, *max),
+        _ => return Frame::error("неверный запрос согласования версии"),
+    };
+
+    let version = peer_max.min(PROTOCOL_VERSION);
+
+    if version < peer_min.max(MIN_PROTOCOL_VERSION) {
+        return Frame::error(format!(
+            "нет общей версии протокола: клиент поддерживает {}..={}, устройство {}..={}",
+            peer_min, peer_max, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+        ));
+    }
+
+    Frame::new(MessageType::Hello, vec![version])
+}
+
+fn is_supported(version: u8) -> bool {
+    (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version)
+}
+
+fn unsupported_version(version: u8) -> SmartHomeErrors {
+    SmartHomeErrors::protocol_error(format!(
+        "несовместимая версия протокола {}, поддерживаются {}..={}",
+        version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+    ))
+}
+
+fn verify_crc(data: &[u8], crc: &[u8; CRC_LEN]) -> Result<(), SmartHomeErrors> {
+    if crc32fast::hash(data) != u32::from_be_bytes(*crc) {
+        return Err(SmartHomeErrors::protocol_error(
+            "контрольная сумма не совпадает".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn io_error(e: std::io::Error) -> SmartHomeErrors {
+    SmartHomeErrors::connection_error(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ack() -> DeviceResponse {
+        DeviceResponse {
+            data: None,
+            info: None,
+            success: true,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn header_layout() {
+        let bytes = Frame::command(Commands::TurnOn).encode();
+
+        assert_eq!(bytes, [b'S', b'H', 1, 2, 0, 0, 0, 0, 4, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn roundtrip_with_checksum() {
+        let frame = Frame::response(&ack()).unwrap().with_checksum();
+        let decoded = Frame::decode(&frame.encode()).unwrap();
+
+        assert_eq!(decoded, frame);
+        assert!(decoded.as_response().unwrap().success);
+    }
+
+    #[test]
+    fn corrupted_checksum() {
+        let mut bytes = Frame::command(Commands::GetStatus).with_checksum().encode();
+        bytes[HEADER_LEN] ^= 0xff;
+
+        assert!(matches!(
+            Frame::decode(&bytes),
+            Err(SmartHomeErrors::ProtocolError(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_magic_and_version() {
+        let mut bytes = Frame::command(Commands::TurnOff).encode();
+        bytes[0] = b'X';
+        assert!(Frame::decode(&bytes).is_err());
+
+        let mut bytes = Frame::command(Commands::TurnOff).encode();
+        bytes[2] = PROTOCOL_VERSION + 1;
+        match Frame::decode(&bytes) {
+            Err(e) => assert!(e.to_string().contains("несовместимая версия")),
+            Ok(_) => panic!("frame with unknown version accepted"),
+        }
+    }
+
+    #[test]
+    fn truncated_and_padded_frames() {
+        let bytes = Frame::response(&ack()).unwrap().with_checksum().encode();
+
+        match Frame::decode(&bytes[..bytes.len() - 1]) {
+            Err(e) => assert!(e.to_string().contains("кадр обрезан")),
+            Ok(_) => panic!("truncated frame accepted"),
+        }
+
+        let padded = [bytes.as_slice(), &[0u8; 3]].concat();
+        match Frame::decode(&padded) {
+            Err(e) => assert!(e.to_string().contains("лишние данные")),
+            Ok(_) => panic!("padded frame accepted"),
+        }
+    }
+
+    #[test]
+    fn datagram_size_limit() {
+        let fits = Frame::new(
+            MessageType::Response,
+            vec![0; MAX_DATAGRAM_LEN - HEADER_LEN],
+        );
+        assert_eq!(fits.to_datagram().unwrap().len(), MAX_DATAGRAM_LEN);
+
+        let oversized = fits.with_checksum();
+        assert!(matches!(
+            oversized.to_datagram(),
+            Err(SmartHomeErrors::ProtocolError(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_oversized_length() {
+        let mut bytes = Frame::command(Commands::TurnOn).encode();
+        bytes[5..9].copy_from_slice(&(MAX_PAYLOAD_LEN + 1).to_be_bytes());
+
+        assert!(Frame::decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn negotiation() {
+        assert_eq!(
+            negotiate(&Frame::hello()).as_hello_ack().unwrap(),
+            PROTOCOL_VERSION
+        );
+
+        let newer = Frame::new(
+            MessageType::Hello,
+            vec![PROTOCOL_VERSION + 1, PROTOCOL_VERSION + 2],
+        );
+        let reply = negotiate(&newer);
+        assert_eq!(reply.message_type, MessageType::Error);
+        assert!(reply.as_hello_ack().is_err());
+    }
+
+    #[tokio::test]
+    async fn stream_roundtrip() {
+        let (mut client, mut device) = tokio::io::duplex(1024);
+
+        Frame::command(Commands::GetStatus)
+            .with_checksum()
+            .write_to(&mut client)
+            .await
+            .unwrap();
+        let frame = Frame::read_from(&mut device).await.unwrap();
+
+        assert!(matches!(frame.as_command().unwrap(), Commands::GetStatus));
+    }
+
+    #[test]
+    fn command_with_value_roundtrip() {
+        let bytes = Frame::command_with_value(Commands::SetBrightness, 75).encode();
+        let frame = Frame::decode(&bytes).unwrap();
+
+        assert!(matches!(
+            frame.as_command_value().unwrap(),
+            (Commands::SetBrightness, Some(75))
+        ));
+        assert!(matches!(
+            frame.as_command().unwrap(),
+            Commands::SetBrightness
+        ));
+        assert!(matches!(
+            Frame::command(Commands::TurnOn).as_command_value().unwrap(),
+            (Commands::TurnOn, None)
+        ));
+    }
+}
@@ -0,0 +1,317 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::reporter::Unit;
+
+/// Параметры хранения истории показаний
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistorySettings {
+    /// Сколько хранить показания, отсчитывается от последнего показания
+    pub retention: Duration,
+    /// Шаг прореживания: показания внутри одного шага сворачиваются в одну точку
+    pub resolution: Duration,
+}
+
+impl Default for HistorySettings {
+    fn default() -> Self {
+        Self {
+            retention: Duration::from_secs(24 * 60 * 60),
+            resolution: Duration::from_secs(60),
+        }
+    }
+}
+
+impl HistorySettings {
+    fn resolution_ms(&self) -> u64 {
+        (self.resolution.as_millis() as u64).max(1)
+    }
+
+    /// Сколько точек помещается в срок хранения, с запасом на неполный шаг
+    fn capacity(&self) -> usize {
+        (self.retention.as_millis() as u64 / self.resolution_ms()) as usize + 1
+    }
+}
+
+/// Свернутые показания за интервал
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistoryPoint {
+    /// Начало интервала, мс от начала эпохи
+    pub timestamp: u64,
+    pub min: f32,
+    pub max: f32,
+    /// Среднее по всем показаниям интервала
+    pub avg: f32,
+    /// Последнее показание интервала
+    pub last: f32,
+    pub count: u64,
+}
+
+/// Агрегированные показания до прореживания
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    start: u64,
+    min: f32,
+    max: f32,
+    sum: f64,
+    count: u64,
+    last: f32,
+    last_at: u64,
+}
+
+impl Bucket {
+    fn new(start: u64, timestamp: u64, value: f32) -> Self {
+        Self {
+            start,
+            min: value,
+            max: value,
+            sum: value as f64,
+            count: 1,
+            last: value,
+            last_at: timestamp,
+        }
+    }
+
+    fn merge(&mut self, other: &Bucket) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+        self.count += other.count;
+
+        if other.last_at >= self.last_at {
+            self.last = other.last;
+            self.last_at = other.last_at;
+        }
+    }
+
+    fn point(&self) -> HistoryPoint {
+        HistoryPoint {
+            timestamp: self.start,
+            min: self.min,
+            max: self.max,
+            avg: (self.sum / self.count as f64) as f32,
+            last: self.last,
+            count: self.count,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct HistoryInner {
+    settings: HistorySettings,
+    unit: Option<Unit>,
+    /// Упорядочены по началу интервала
+    buckets: VecDeque<Bucket>,
+}
+
+/// История показаний устройства: мощность розетки или температура термометра.
+///
+/// Клоны устройства разделяют историю, в нее пишет фоновый мониторинг.
+#[derive(Debug, Clone)]
+pub struct DeviceHistory {
+    inner: Arc<Mutex<HistoryInner>>,
+}
+
+impl Default for DeviceHistory {
+    fn default() -> Self {
+        Self::new(HistorySettings::default())
+    }
+}
+
+impl DeviceHistory {
+    pub fn new(settings: HistorySettings) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(HistoryInner {
+                settings,
+                unit: None,
+                buckets: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Параметры хранения
+    pub fn settings(&self) -> HistorySettings {
+        self.inner.lock().unwrap().settings
+    }
+
+    /// Единица измерения показаний, известна после первой записи
+    pub fn unit(&self) -> Option<Unit> {
+        self.inner.lock().unwrap().unit
+    }
+
+    /// Записать показание, полученное сейчас
+    pub fn record(&self, value: f32, unit: Unit) {
+        self.record_at(chrono::Utc::now().timestamp_millis() as u64, value, unit);
+    }
+
+    /// Записать показание, полученное в `timestamp` (мс от начала эпохи)
+    pub fn record_at(&self, timestamp: u64, value: f32, unit: Unit) {
+        if value.is_nan() {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        let settings = inner.settings;
+        let start = timestamp - timestamp % settings.resolution_ms();
+        let sample = Bucket::new(start, timestamp, value);
+        inner.unit = Some(unit);
+
+        // Показания приходят почти всегда по порядку, поэтому поиск идет с конца
+        let position = inner
+            .buckets
+            .iter()
+            .rposition(|bucket| bucket.start <= start);
+
+        match position {
+            Some(i) if inner.buckets[i].start == start => inner.buckets[i].merge(&sample),
+            Some(i) => inner.buckets.insert(i + 1, sample),
+            None => inner.buckets.push_front(sample),
+        }
+
+        let newest = inner
+            .buckets
+            .back()
+            .map(|bucket| bucket.start)
+            .unwrap_or(start);
+        let oldest_kept = newest.saturating_sub(settings.retention.as_millis() as u64);
+
+        while inner
+            .buckets
+            .front()
+            .is_some_and(|bucket| bucket.start < oldest_kept)
+        {
+            inner.buckets.pop_front();
+        }
+
+        while inner.buckets.len() > settings.capacity() {
+            inner.buckets.pop_front();
+        }
+    }
+
+    /// Точки за интервал `[from, to)`, мс от начала эпохи.
+    /// `step` сворачивает точки в более крупные интервалы, но не мельче шага хранения
+    pub fn points(&self, from: u64, to: u64, step: Option<Duration>) -> Vec<HistoryPoint> {
+        let inner = self.inner.lock().unwrap();
+        let step = step
+            .map(|step| step.as_millis() as u64)
+            .unwrap_or(0)
+            .max(inner.settings.resolution_ms());
+
+        let mut points: Vec<Bucket> = Vec::new();
+
+        for bucket in inner
+            .buckets
+            .iter()
+            .filter(|bucket| bucket.start >= from && bucket.start < to)
+        {
+            let start = bucket.start - bucket.start % step;
+
+            match points.last_mut() {
+                Some(last) if last.start == start => last.merge(bucket),
+                _ => points.push(Bucket { start, ..*bucket }),
+            }
+        }
+
+        points.iter().map(Bucket::point).collect()
+    }
+
+    /// Минимум, максимум, среднее и последнее показание за интервал `[from, to)`
+    pub fn summary(&self, from: u64, to: u64) -> Option<HistoryPoint> {
+        let inner = self.inner.lock().unwrap();
+
+        let mut buckets = inner
+            .buckets
+            .iter()
+            .filter(|bucket| bucket.start >= from && bucket.start < to);
+
+        let mut total = *buckets.next()?;
+        for bucket in buckets {
+            total.merge(bucket);
+        }
+
+        Some(total.point())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: u64 = 60_000;
+
+    fn history() -> DeviceHistory {
+        DeviceHistory::new(HistorySettings {
+            retention: Duration::from_secs(60 * 60),
+            resolution: Duration::from_secs(60),
+        })
+    }
+
+    #[test]
+    fn samples_within_resolution_are_merged() {
+        let history = history();
+        history.record_at(10 * MINUTE + 1_000, 20.0, Unit::Celsius);
+        history.record_at(10 * MINUTE + 30_000, 24.0, Unit::Celsius);
+        history.record_at(10 * MINUTE + 20_000, 19.0, Unit::Celsius);
+        history.record_at(11 * MINUTE, 22.0, Unit::Celsius);
+
+        let points = history.points(0, u64::MAX, None);
+
+        assert_eq!(points.len(), 2);
+        assert_eq!(
+            points[0],
+            HistoryPoint {
+                timestamp: 10 * MINUTE,
+                min: 19.0,
+                max: 24.0,
+                avg: 21.0,
+                // Последнее по времени показание, а не по порядку записи
+                last: 24.0,
+                count: 3,
+            }
+        );
+        assert_eq!(points[1].timestamp, 11 * MINUTE);
+        assert_eq!(history.unit(), Some(Unit::Celsius));
+    }
+
+    #[test]
+    fn old_samples_are_dropped_after_retention() {
+        let history = history();
+
+        for minute in 0..120 {
+            history.record_at(minute * MINUTE, minute as f32, Unit::Watt);
+        }
+
+        let points = history.points(0, u64::MAX, None);
+        assert_eq!(points.len(), 61);
+        assert_eq!(points[0].timestamp, 59 * MINUTE);
+
+        // Опоздавшее показание старше срока хранения сразу отбрасывается
+        history.record_at(MINUTE, 1.0, Unit::Watt);
+        assert_eq!(history.points(0, u64::MAX, None).len(), 61);
+    }
+
+    #[test]
+    fn summary_and_steps_cover_window() {
+        let history = history();
+
+        for minute in 0..10 {
+            history.record_at(minute * MINUTE, minute as f32, Unit::Watt);
+        }
+
+        let summary = history.summary(2 * MINUTE, 6 * MINUTE).unwrap();
+        assert_eq!((summary.min, summary.max, summary.last), (2.0, 5.0, 5.0));
+        assert_eq!(summary.avg, 3.5);
+        assert_eq!(summary.count, 4);
+        assert!(history.summary(20 * MINUTE, 30 * MINUTE).is_none());
+
+        let points = history.points(0, 10 * MINUTE, Some(Duration::from_secs(5 * 60)));
+        assert_eq!(points.len(), 2);
+        assert_eq!((points[1].timestamp, points[1].avg), (5 * MINUTE, 7.0));
+
+        // Шаг мельче шага хранения не дробит точки
+        let points = history.points(0, 10 * MINUTE, Some(Duration::from_secs(1)));
+        assert_eq!(points.len(), 10);
+    }
+}
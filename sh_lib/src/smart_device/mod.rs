@@ -1,15 +1,28 @@
 pub mod contracts;
+pub mod energy;
+pub mod frame;
+pub mod history;
 pub mod online;
+pub mod smart_light;
 pub mod smart_socket;
 pub mod smart_thermometer;
 
+pub use smart_light::SmartLight;
 pub use smart_socket::SmartSocket;
 pub use smart_thermometer::SmartThermometer;
 
+use std::time::Duration;
+
 use crate::{
+    events::DeviceEvents,
     id::Id,
-    reporter::Report,
-    smart_device::{contracts::DeviceData, online::ConnectionType},
+    reporter::{DEVICE_REPORT_TIMEOUT, DeviceReport, Report, ReportNode},
+    smart_device::{
+        contracts::{DeviceData, DeviceInfo},
+        energy::EnergyMeter,
+        history::DeviceHistory,
+        online::ConnectionType,
+    },
 };
 
 /// Тип умного устройства
@@ -19,6 +32,8 @@ pub enum SmartDeviceType {
     Thermometer(SmartThermometer),
     /// Умная розетка
     Socket(SmartSocket),
+    /// Умный светильник
+    Light(SmartLight),
 }
 
 /// Умное устройство
@@ -33,6 +48,42 @@ impl SmartDeviceType {
         match self {
             SmartDeviceType::Socket(s) => DeviceData::Socket(s.get_data().await),
             SmartDeviceType::Thermometer(t) => DeviceData::Thermometer(t.get_data().await),
+            SmartDeviceType::Light(l) => DeviceData::Light(l.get_data().await),
+        }
+    }
+
+    /// Источник событий устройства
+    pub(crate) fn events(&self) -> &DeviceEvents {
+        match self {
+            SmartDeviceType::Socket(s) => &s.events,
+            SmartDeviceType::Thermometer(t) => &t.events,
+            SmartDeviceType::Light(l) => &l.events,
+        }
+    }
+
+    /// История показаний устройства
+    pub fn history(&self) -> &DeviceHistory {
+        match self {
+            SmartDeviceType::Socket(s) => &s.history,
+            SmartDeviceType::Thermometer(t) => &t.history,
+            SmartDeviceType::Light(l) => &l.history,
+        }
+    }
+
+    /// Счетчик энергии, есть только у розетки
+    pub fn energy(&self) -> Option<&EnergyMeter> {
+        match self {
+            SmartDeviceType::Socket(s) => Some(&s.energy),
+            SmartDeviceType::Thermometer(_) | SmartDeviceType::Light(_) => None,
+        }
+    }
+
+    /// Паспорт устройства, если он уже известен
+    pub async fn get_info(&self) -> Option<DeviceInfo> {
+        match self {
+            SmartDeviceType::Socket(s) => s.get_info().await,
+            SmartDeviceType::Thermometer(t) => t.get_info().await,
+            SmartDeviceType::Light(l) => l.get_info().await,
         }
     }
 }
@@ -42,6 +93,7 @@ impl SmartDevice for SmartDeviceType {
         match self {
             SmartDeviceType::Thermometer(t) => t.get_id(),
             SmartDeviceType::Socket(s) => s.get_id(),
+            SmartDeviceType::Light(l) => l.get_id(),
         }
     }
 
@@ -49,6 +101,7 @@ impl SmartDevice for SmartDeviceType {
         match self {
             SmartDeviceType::Thermometer(t) => t.get_name(),
             SmartDeviceType::Socket(s) => s.get_name(),
+            SmartDeviceType::Light(l) => l.get_name(),
         }
     }
 
@@ -56,15 +109,35 @@ impl SmartDevice for SmartDeviceType {
         match self {
             SmartDeviceType::Socket(s) => s.get_connection(),
             SmartDeviceType::Thermometer(t) => t.get_connection(),
+            SmartDeviceType::Light(l) => l.get_connection(),
         }
     }
 }
 
-impl Report for SmartDeviceType {
-    async fn get_status_report(&self) -> String {
+impl SmartDeviceType {
+    /// Отчет по устройству
+    pub async fn device_report(&self) -> DeviceReport {
         match self {
-            SmartDeviceType::Thermometer(t) => t.get_status_report().await,
-            SmartDeviceType::Socket(s) => s.get_status_report().await,
+            SmartDeviceType::Thermometer(t) => t.device_report().await,
+            SmartDeviceType::Socket(s) => s.device_report().await,
+            SmartDeviceType::Light(l) => l.device_report().await,
         }
     }
+
+    /// Отчет по устройству; если показания не получены за `timeout`, устройство недоступно
+    pub async fn device_report_within(&self, timeout: Duration) -> DeviceReport {
+        DeviceReport::within(
+            self.get_id().to_string(),
+            self.get_name(),
+            timeout,
+            self.device_report(),
+        )
+        .await
+    }
+}
+
+impl Report for SmartDeviceType {
+    async fn report(&self) -> ReportNode {
+        ReportNode::Device(self.device_report_within(DEVICE_REPORT_TIMEOUT).await)
+    }
 }
@@ -0,0 +1,17 @@
+use serde::{Deserialize, Deserializer, Serializer};
+use tokio::time::Duration;
+
+/// Интервал в описании устройства задается целым числом миллисекунд
+pub fn serialize<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_u64(duration.as_millis() as u64)
+}
+
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    u64::deserialize(deserializer).map(Duration::from_millis)
+}
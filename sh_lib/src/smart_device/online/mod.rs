@@ -1,34 +1,156 @@
+mod duration_ms;
+mod polling;
+mod reconnect;
+mod udp_listener;
+
+pub use polling::{Polling, PollingSettings};
+pub use reconnect::ReconnectPolicy;
+pub use udp_listener::{UdpRoute, UnassignedSender, unassigned_senders};
+
+use udp_listener::Subscription;
+
 use std::{
-    net::{IpAddr, SocketAddr, UdpSocket},
+    net::{IpAddr, SocketAddr},
     sync::Arc,
 };
 
-use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use tokio::io::BufReader;
 use tokio::net::TcpStream;
-use tokio::sync::Mutex;
-use tokio::time::Duration;
+use tokio::sync::{Mutex, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant};
 
 use crate::{
     errors::SmartHomeErrors,
+    events::DeviceEvents,
+    id::Id,
+    reporter::Unit,
     smart_device::{
-        SmartDevice, SmartDeviceType,
-        contracts::{Commands, DecodeEncode, DeviceData, DeviceResponse},
+        SmartDevice, SmartDeviceType, SmartLight, SmartSocket, SmartThermometer,
+        contracts::{Commands, ConnectionState, DeviceData, DeviceInfo, DeviceResponse},
+        energy::EnergyMeter,
+        frame::Frame,
+        history::DeviceHistory,
+        smart_light::LightData,
+        smart_socket::SocketData,
+        smart_thermometer::ThermometerData,
     },
 };
 
+/// Общий TCP-поток устройства.
+///
+/// Используется и циклом мониторинга, и командами управления,
+/// поэтому обмен "запрос-ответ" всегда выполняется под блокировкой.
+pub type SharedStream = Arc<Mutex<Option<TcpStream>>>;
+
+/// Как долго UDP-мониторинг ждет посылку, прежде чем проверить порог недоступности
+const UDP_READ_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Фоновая задача мониторинга устройства.
+///
+/// Клоны устройства разделяют одну задачу, поэтому остановить
+/// мониторинг можно через любой из них.
+#[derive(Debug, Clone, Default)]
+pub struct Monitoring {
+    task: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl Monitoring {
+    /// Запомнить задачу мониторинга, остановив предыдущую
+    async fn start(&self, task: JoinHandle<()>) {
+        if let Some(previous) = self.task.lock().await.replace(task) {
+            previous.abort();
+        }
+    }
+
+    /// Остановить задачу и дождаться освобождения ее ресурсов
+    pub async fn stop(&self) {
+        let task = self.task.lock().await.take();
+
+        if let Some(task) = task {
+            task.abort();
+            let _ = task.await;
+        }
+    }
+
+    /// Проверить, запущен ли мониторинг
+    pub async fn is_running(&self) -> bool {
+        match self.task.lock().await.as_ref() {
+            Some(task) => !task.is_finished(),
+            None => false,
+        }
+    }
+}
+
+/// Событие фонового мониторинга
+enum MonitoringEvent {
+    /// Получены данные от устройства
+    Data(DeviceData),
+    /// Получен паспорт устройства
+    Info(DeviceInfo),
+    /// Изменилось состояние соединения
+    State(ConnectionState),
+    /// Ошибка обмена с устройством
+    Error(String),
+}
+
 #[derive(Debug, Clone)]
 pub enum ConnectionType {
-    Tcp { ip: IpAddr, port: u16 },
-    Udp { bind_ip: IpAddr, bind_port: u16 },
+    Tcp {
+        ip: IpAddr,
+        port: u16,
+        reconnect: ReconnectPolicy,
+        polling: Polling,
+    },
+    Udp {
+        bind_ip: IpAddr,
+        bind_port: u16,
+        /// Какие посылки порта относятся к устройству
+        route: UdpRoute,
+        polling: Polling,
+    },
 }
 
 impl ConnectionType {
     pub fn tcp(ip: IpAddr, port: u16) -> Self {
-        ConnectionType::Tcp { ip, port }
+        ConnectionType::Tcp {
+            ip,
+            port,
+            reconnect: ReconnectPolicy::default(),
+            polling: Polling::default(),
+        }
     }
 
     pub fn udp(bind_ip: IpAddr, bind_port: u16) -> Self {
-        ConnectionType::Udp { bind_ip, bind_port }
+        ConnectionType::Udp {
+            bind_ip,
+            bind_port,
+            route: UdpRoute::default(),
+            polling: Polling::default(),
+        }
+    }
+
+    /// Задать фильтр посылок для UDP-устройства, для TCP ничего не меняет
+    pub fn with_route(mut self, new_route: UdpRoute) -> Self {
+        if let ConnectionType::Udp { route, .. } = &mut self {
+            *route = new_route;
+        }
+
+        self
+    }
+
+    /// Задать параметры опроса
+    pub fn with_polling(self, settings: PollingSettings) -> Self {
+        self.polling().set(settings);
+        self
+    }
+
+    /// Параметры опроса устройства
+    pub fn polling(&self) -> &Polling {
+        match self {
+            ConnectionType::Tcp { polling, .. } | ConnectionType::Udp { polling, .. } => polling,
+        }
     }
 
     pub fn get_addr(&self) -> SocketAddr {
@@ -41,153 +163,766 @@ impl ConnectionType {
     }
 }
 
-fn decode_result(message: Vec<u8>) -> Result<Option<DeviceData>, SmartHomeErrors> {
-    let decode_result = DeviceResponse::decode(&message);
+/// Параметры подключения в том виде, в каком они описываются в файлах
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum ConnectionDefinition {
+    Tcp {
+        ip: IpAddr,
+        port: u16,
+        #[serde(default)]
+        reconnect: ReconnectPolicy,
+        #[serde(default)]
+        polling: PollingSettings,
+    },
+    Udp {
+        ip: IpAddr,
+        port: u16,
+        #[serde(default)]
+        route: UdpRoute,
+        #[serde(default)]
+        polling: PollingSettings,
+    },
+}
+
+impl From<&ConnectionType> for ConnectionDefinition {
+    fn from(connection: &ConnectionType) -> Self {
+        match connection {
+            ConnectionType::Tcp {
+                ip,
+                port,
+                reconnect,
+                polling,
+            } => ConnectionDefinition::Tcp {
+                ip: *ip,
+                port: *port,
+                reconnect: *reconnect,
+                polling: polling.get(),
+            },
+            ConnectionType::Udp {
+                bind_ip,
+                bind_port,
+                route,
+                polling,
+            } => ConnectionDefinition::Udp {
+                ip: *bind_ip,
+                port: *bind_port,
+                route: route.clone(),
+                polling: polling.get(),
+            },
+        }
+    }
+}
+
+impl From<ConnectionDefinition> for ConnectionType {
+    fn from(definition: ConnectionDefinition) -> Self {
+        match definition {
+            ConnectionDefinition::Tcp {
+                ip,
+                port,
+                reconnect,
+                polling,
+            } => ConnectionType::Tcp {
+                ip,
+                port,
+                reconnect,
+                polling: Polling::new(polling),
+            },
+            ConnectionDefinition::Udp {
+                ip,
+                port,
+                route,
+                polling,
+            } => ConnectionType::udp(ip, port)
+                .with_route(route)
+                .with_polling(polling),
+        }
+    }
+}
+
+impl ConnectionType {
+    /// Копия параметров подключения со своими, не общими с оригиналом параметрами опроса
+    pub fn detached(&self) -> Self {
+        ConnectionDefinition::from(self).into()
+    }
+}
 
-    if let Err(e) = decode_result {
-        return Err(e);
+/// Подключения равны, если совпадают адреса и текущие параметры
+impl PartialEq for ConnectionType {
+    fn eq(&self, other: &Self) -> bool {
+        ConnectionDefinition::from(self) == ConnectionDefinition::from(other)
     }
+}
 
-    let device_response = decode_result.unwrap();
+impl Serialize for ConnectionType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        ConnectionDefinition::from(self).serialize(serializer)
+    }
+}
 
-    if !device_response.success
-        && let Some(e) = device_response.error
+impl<'de> Deserialize<'de> for ConnectionType {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
     {
-        return Err(SmartHomeErrors::emulator_error(e));
+        let definition = ConnectionDefinition::deserialize(deserializer)?;
+
+        let port = match &definition {
+            ConnectionDefinition::Tcp { port, .. } | ConnectionDefinition::Udp { port, .. } => {
+                *port
+            }
+        };
+        if port == 0 {
+            return Err(de::Error::custom("порт подключения не может быть 0"));
+        }
+
+        Ok(definition.into())
+    }
+}
+
+fn decode_result(device_response: DeviceResponse) -> Result<Option<DeviceData>, SmartHomeErrors> {
+    if !device_response.success {
+        return Err(SmartHomeErrors::emulator_error(
+            device_response
+                .error
+                .unwrap_or_else(|| "команда отклонена".to_string()),
+        ));
     }
 
     Ok(device_response.data)
 }
 
-async fn send_command(
-    stream: &Arc<Mutex<TcpStream>>,
-    cmd: Commands,
-) -> Result<Option<DeviceData>, anyhow::Error> {
-    let mut stream = stream.lock().await;
+/// Отправить кадр в поток и прочитать ответный кадр
+async fn exchange(stream: &mut TcpStream, request: Frame) -> Result<Frame, SmartHomeErrors> {
     let (reader, mut writer) = stream.split();
     let mut reader = BufReader::new(reader);
 
-    let bytes = (cmd as i32).to_be_bytes();
+    request.write_to(&mut writer).await?;
+    Frame::read_from(&mut reader).await
+}
 
-    if let Err(e) = writer.write_all(&bytes).await {
-        return Err(anyhow::anyhow!(e));
+/// Подключиться к устройству и согласовать версию протокола.
+///
+/// Подключение и согласование ограничены одним таймаутом: иначе к узлу, который
+/// молча отбрасывает пакеты, подключение ждало бы системного таймаута TCP
+async fn dial(addr: SocketAddr, timeout: Duration) -> Result<TcpStream, SmartHomeErrors> {
+    let handshake = async {
+        let mut stream = TcpStream::connect(&addr)
+            .await
+            .map_err(|e| SmartHomeErrors::connection_error(e.to_string()))?;
+
+        exchange(&mut stream, Frame::hello())
+            .await?
+            .as_hello_ack()?;
+
+        Ok(stream)
+    };
+
+    match tokio::time::timeout(timeout, handshake).await {
+        Ok(stream) => stream,
+        Err(_) => Err(SmartHomeErrors::connection_error(format!(
+            "не удалось подключиться и согласовать версию за {} мс",
+            timeout.as_millis()
+        ))),
+    }
+}
+
+/// Отправить кадр команды устройству и дождаться ответа.
+///
+/// При ошибке ввода-вывода или по таймауту поток сбрасывается: его состояние
+/// неизвестно, и мониторинг переподключится к устройству заново.
+async fn request(
+    stream: &SharedStream,
+    command: Frame,
+    timeout: Duration,
+) -> Result<DeviceResponse, SmartHomeErrors> {
+    let mut stream = stream.lock().await;
+
+    let connected = if let Some(connected) = stream.as_mut() {
+        connected
+    } else {
+        return Err(SmartHomeErrors::connection_error(
+            "устройство не подключено".to_string(),
+        ));
+    };
+
+    let reply = tokio::time::timeout(timeout, exchange(connected, command)).await;
+
+    match reply.map(|frame| frame.and_then(|frame| frame.as_response())) {
+        Ok(Ok(response)) => Ok(response),
+        Ok(Err(e)) => {
+            stream.take();
+            Err(e)
+        }
+        Err(_) => {
+            stream.take();
+            Err(SmartHomeErrors::connection_error(format!(
+                "нет ответа за {} мс",
+                timeout.as_millis()
+            )))
+        }
     }
+}
+
+/// Отправить команду устройству и получить данные из ответа
+pub(crate) async fn send_command(
+    stream: &SharedStream,
+    cmd: Commands,
+    timeout: Duration,
+) -> Result<Option<DeviceData>, SmartHomeErrors> {
+    decode_result(request(stream, Frame::command(cmd), timeout).await?)
+}
 
-    if let Err(e) = writer.flush().await {
-        return Err(anyhow::anyhow!(e));
+/// Отправить команду со значением и получить данные из ответа
+pub(crate) async fn send_command_with_value(
+    stream: &SharedStream,
+    cmd: Commands,
+    value: u32,
+    timeout: Duration,
+) -> Result<Option<DeviceData>, SmartHomeErrors> {
+    decode_result(request(stream, Frame::command_with_value(cmd, value), timeout).await?)
+}
+
+/// Запросить паспорт устройства
+async fn request_info(
+    stream: &SharedStream,
+    timeout: Duration,
+) -> Result<Option<DeviceInfo>, SmartHomeErrors> {
+    let response = request(stream, Frame::command(Commands::GetInfo), timeout).await?;
+    let info = response.info.clone();
+
+    decode_result(response)?;
+
+    Ok(info)
+}
+
+/// Ошибка: за адресом оказалось устройство другого типа
+fn unexpected_data(expected: &str, data: &DeviceData) -> SmartHomeErrors {
+    let actual = match data {
+        DeviceData::Socket(_) => "розетка",
+        DeviceData::Thermometer(_) => "термометр",
+        DeviceData::Light(_) => "светильник",
+    };
+
+    SmartHomeErrors::protocol_error(format!(
+        "ожидались данные устройства \"{}\", получены данные устройства \"{}\"",
+        expected, actual
+    ))
+}
+
+/// Данные устройства, которые обновляет мониторинг
+trait MonitoredValue {
+    fn connection_state(&self) -> ConnectionState;
+    fn set_connection_state(&mut self, state: ConnectionState);
+    /// Обновить значение; ошибка, если устройство прислало данные другого типа
+    fn update_from(&mut self, data: &DeviceData) -> Result<(), SmartHomeErrors>;
+    fn to_data(&self) -> DeviceData;
+    /// Показание для истории
+    fn reading(&self) -> (f32, Unit);
+}
+
+impl MonitoredValue for SocketData {
+    fn connection_state(&self) -> ConnectionState {
+        self.connection_state
     }
 
-    let mut message_length = [0u8; size_of::<usize>()];
-    if let Err(e) = reader.read_exact(&mut message_length).await {
-        return Err(anyhow::anyhow!(e));
+    fn set_connection_state(&mut self, state: ConnectionState) {
+        SocketData::set_connection_state(self, state);
     }
 
-    let message_length = usize::from_be_bytes(message_length);
+    fn update_from(&mut self, data: &DeviceData) -> Result<(), SmartHomeErrors> {
+        match data {
+            DeviceData::Socket(data) => {
+                self.update(data.clone());
+                Ok(())
+            }
+            other => Err(unexpected_data("розетка", other)),
+        }
+    }
 
-    let mut message = vec![0u8; message_length];
-    if let Err(e) = reader.read_exact(&mut message).await {
-        return Err(anyhow::anyhow!(e));
+    fn to_data(&self) -> DeviceData {
+        DeviceData::Socket(self.clone())
     }
 
-    match decode_result(message) {
-        Ok(device_response) => Ok(device_response),
-        Err(e) => Err(anyhow::anyhow!(e)),
+    fn reading(&self) -> (f32, Unit) {
+        (if self.is_on { self.power } else { 0.0 }, Unit::Watt)
     }
 }
 
-async fn start_tcp_monitoring<Fut, F>(stream: Arc<tokio::sync::Mutex<TcpStream>>, mut callback: F)
+impl MonitoredValue for ThermometerData {
+    fn connection_state(&self) -> ConnectionState {
+        self.connection_state
+    }
+
+    fn set_connection_state(&mut self, state: ConnectionState) {
+        ThermometerData::set_connection_state(self, state);
+    }
+
+    fn update_from(&mut self, data: &DeviceData) -> Result<(), SmartHomeErrors> {
+        match data {
+            DeviceData::Thermometer(data) => {
+                self.update(data.clone());
+                Ok(())
+            }
+            other => Err(unexpected_data("термометр", other)),
+        }
+    }
+
+    fn to_data(&self) -> DeviceData {
+        DeviceData::Thermometer(self.clone())
+    }
+
+    fn reading(&self) -> (f32, Unit) {
+        (self.temp, Unit::Celsius)
+    }
+}
+
+impl MonitoredValue for LightData {
+    fn connection_state(&self) -> ConnectionState {
+        self.connection_state
+    }
+
+    fn set_connection_state(&mut self, state: ConnectionState) {
+        LightData::set_connection_state(self, state);
+    }
+
+    fn update_from(&mut self, data: &DeviceData) -> Result<(), SmartHomeErrors> {
+        match data {
+            DeviceData::Light(data) => {
+                self.update(data.clone());
+                Ok(())
+            }
+            other => Err(unexpected_data("светильник", other)),
+        }
+    }
+
+    fn to_data(&self) -> DeviceData {
+        DeviceData::Light(self.clone())
+    }
+
+    fn reading(&self) -> (f32, Unit) {
+        (LightData::reading(self), Unit::Percent)
+    }
+}
+
+/// Получатели изменений устройства, общие для всех его клонов
+#[derive(Clone)]
+struct Observers {
+    events: DeviceEvents,
+    history: DeviceHistory,
+    /// Счетчик энергии есть только у розетки
+    energy: Option<EnergyMeter>,
+}
+
+impl Observers {
+    fn socket(socket: &SmartSocket) -> Self {
+        Self {
+            events: socket.events.clone(),
+            history: socket.history.clone(),
+            energy: Some(socket.energy.clone()),
+        }
+    }
+
+    fn thermometer(therm: &SmartThermometer) -> Self {
+        Self {
+            events: therm.events.clone(),
+            history: therm.history.clone(),
+            energy: None,
+        }
+    }
+
+    fn light(light: &SmartLight) -> Self {
+        Self {
+            events: light.events.clone(),
+            history: light.history.clone(),
+            energy: None,
+        }
+    }
+}
+
+/// Записать новое состояние соединения и сообщить о потере или восстановлении связи
+async fn apply_state<T: MonitoredValue>(
+    value: &RwLock<T>,
+    observers: &Observers,
+    device_id: &Id,
+    state: ConnectionState,
+) {
+    let old = {
+        let mut value = value.write().await;
+        let old = value.connection_state();
+        value.set_connection_state(state);
+        old
+    };
+
+    // Время без связи в потребление не попадает
+    if state != ConnectionState::Online
+        && let Some(energy) = &observers.energy
+    {
+        energy.interrupt();
+    }
+
+    observers.events.connection_changed(device_id, old, state);
+}
+
+/// Записать полученные от устройства данные в состояние, историю и счетчик и сообщить об изменениях.
+///
+/// Данные устройства другого типа не записываются, устройство считается недоступным
+async fn apply_data<T: MonitoredValue>(
+    value: &RwLock<T>,
+    observers: &Observers,
+    device_id: &Id,
+    data: DeviceData,
+) {
+    let updated = {
+        let mut value = value.write().await;
+        let old = value.to_data();
+        let old_state = value.connection_state();
+
+        match value.update_from(&data) {
+            Ok(()) => {
+                value.set_connection_state(ConnectionState::Online);
+                Ok((old, old_state, value.to_data(), value.reading()))
+            }
+            Err(e) => Err(e),
+        }
+    };
+
+    let (old, old_state, new, (reading, unit)) = match updated {
+        Ok(updated) => updated,
+        Err(e) => {
+            eprintln!("{}: {}", device_id, e);
+            apply_state(value, observers, device_id, ConnectionState::Offline).await;
+            return;
+        }
+    };
+
+    observers.history.record(reading, unit);
+    if let Some(energy) = &observers.energy {
+        energy.observe(reading);
+    }
+
+    observers.events.state_changed(device_id, old, new);
+    observers
+        .events
+        .connection_changed(device_id, old_state, ConnectionState::Online);
+}
+
+/// Запомнить паспорт устройства.
+///
+/// Если за тем же адресом оказалось другое устройство, об этом пишется предупреждение.
+async fn update_info(device_name: &str, slot: &RwLock<Option<DeviceInfo>>, info: DeviceInfo) {
+    let mut current = slot.write().await;
+
+    if let Some(previous) = current.as_ref()
+        && previous.serial != info.serial
+    {
+        eprintln!(
+            "{}: устройство заменено, серийный номер {} -> {}",
+            device_name, previous.serial, info.serial
+        );
+    }
+
+    *current = Some(info);
+}
+
+/// Части устройства, которые нужны для подключения по TCP
+struct TcpDevice<T> {
+    id: Id,
+    name: String,
+    value: Arc<RwLock<T>>,
+    info: Arc<RwLock<Option<DeviceInfo>>>,
+    stream: SharedStream,
+    monitoring: Monitoring,
+    observers: Observers,
+}
+
+impl TcpDevice<SocketData> {
+    fn socket(socket: &SmartSocket) -> Self {
+        Self {
+            id: socket.id.clone(),
+            name: socket.name.clone(),
+            value: Arc::clone(&socket.value),
+            info: Arc::clone(&socket.info),
+            stream: Arc::clone(&socket.stream),
+            monitoring: socket.monitoring.clone(),
+            observers: Observers::socket(socket),
+        }
+    }
+}
+
+impl TcpDevice<LightData> {
+    fn light(light: &SmartLight) -> Self {
+        Self {
+            id: light.id.clone(),
+            name: light.name.clone(),
+            value: Arc::clone(&light.value),
+            info: Arc::clone(&light.info),
+            stream: Arc::clone(&light.stream),
+            monitoring: light.monitoring.clone(),
+            observers: Observers::light(light),
+        }
+    }
+}
+
+impl<T: MonitoredValue + Send + Sync + 'static> TcpDevice<T> {
+    /// Подключиться к устройству и запустить мониторинг.
+    ///
+    /// При неудаче мониторинг все равно запускается
+    /// и продолжает попытки согласно политике переподключения
+    async fn connect(
+        self,
+        addr: SocketAddr,
+        reconnect: ReconnectPolicy,
+        polling: &Polling,
+    ) -> Result<(), String> {
+        let Self {
+            id: device_id,
+            name: device_name,
+            value,
+            info,
+            stream,
+            monitoring,
+            observers,
+        } = self;
+
+        monitoring.stop().await;
+        apply_state(&value, &observers, &device_id, ConnectionState::Connecting).await;
+
+        let result = match dial(addr, polling.get().command_timeout).await {
+            Ok(s) => {
+                *stream.lock().await = Some(s);
+                apply_state(&value, &observers, &device_id, ConnectionState::Online).await;
+                Ok(())
+            }
+            Err(e) => {
+                stream.lock().await.take();
+                Err(format!(
+                    "{}: Ошибка подключения к {}: {}",
+                    device_name, addr, e
+                ))
+            }
+        };
+
+        let task = start_tcp_monitoring(addr, stream, reconnect, polling.clone(), move |event| {
+            let value = value.clone();
+            let info = info.clone();
+            let observers = observers.clone();
+            let device_id = device_id.clone();
+            let device_name = device_name.clone();
+            async move {
+                match event {
+                    MonitoringEvent::Data(data) => {
+                        apply_data(&value, &observers, &device_id, data).await;
+                    }
+                    MonitoringEvent::Info(new_info) => {
+                        update_info(&device_name, &info, new_info).await;
+                    }
+                    MonitoringEvent::State(state) => {
+                        apply_state(&value, &observers, &device_id, state).await;
+                    }
+                    MonitoringEvent::Error(e) => {
+                        eprintln!("{}", e);
+                    }
+                }
+            }
+        });
+
+        monitoring.start(task).await;
+
+        result
+    }
+
+    /// Остановить мониторинг и закрыть соединение
+    async fn disconnect(self) {
+        self.monitoring.stop().await;
+        self.stream.lock().await.take();
+        apply_state(
+            &self.value,
+            &self.observers,
+            &self.id,
+            ConnectionState::Offline,
+        )
+        .await;
+    }
+}
+
+fn start_tcp_monitoring<Fut, F>(
+    addr: SocketAddr,
+    stream: SharedStream,
+    policy: ReconnectPolicy,
+    polling: Polling,
+    mut callback: F,
+) -> JoinHandle<()>
 where
-    F: FnMut(Result<DeviceData, String>) -> Fut + Send + 'static,
+    F: FnMut(MonitoringEvent) -> Fut + Send + 'static,
     Fut: std::future::Future<Output = ()> + Send + 'static,
 {
     tokio::spawn(async move {
+        let mut attempt: u32 = 0;
+        let mut last_seen = Instant::now();
+        // Паспорт запрашивается после каждого подключения: за адресом могли заменить устройство
+        let mut identify = true;
+
         loop {
-            let device_response = send_command(&stream, Commands::GetStatus).await;
+            let settings = polling.get();
+
+            if stream.lock().await.is_none() {
+                if policy.is_exhausted(attempt) {
+                    callback(MonitoringEvent::State(ConnectionState::Offline)).await;
+                    break;
+                }
+
+                callback(MonitoringEvent::State(ConnectionState::Backoff)).await;
+                tokio::time::sleep(policy.delay(attempt)).await;
+                attempt += 1;
+
+                callback(MonitoringEvent::State(ConnectionState::Connecting)).await;
+
+                match dial(addr, settings.command_timeout).await {
+                    Ok(s) => {
+                        *stream.lock().await = Some(s);
+                        attempt = 0;
+                        last_seen = Instant::now();
+                        identify = true;
+                        callback(MonitoringEvent::State(ConnectionState::Online)).await;
+                    }
+                    Err(e) => {
+                        callback(MonitoringEvent::Error(format!("TCP {}: {}", addr, e))).await;
+                        continue;
+                    }
+                }
+            }
+
+            if identify {
+                identify = false;
+
+                match request_info(&stream, settings.command_timeout).await {
+                    Ok(Some(info)) => callback(MonitoringEvent::Info(info)).await,
+                    Ok(None) => {}
+                    Err(e) => {
+                        callback(MonitoringEvent::Error(format!("TCP {}: {}", addr, e))).await;
+
+                        if stream.lock().await.is_none() {
+                            continue;
+                        }
+                    }
+                }
+            }
+
+            let device_response =
+                send_command(&stream, Commands::GetStatus, settings.command_timeout).await;
 
             if let Err(e) = device_response {
-                callback(Err(format!(
+                callback(MonitoringEvent::Error(format!(
                     "{}",
                     SmartHomeErrors::getting_status_error(format!("TCP: {}", e))
                 )))
                 .await;
-                tokio::time::sleep(Duration::from_secs(2)).await;
+
+                if stream.lock().await.is_none() {
+                    // Поток сброшен, сразу переходим к переподключению
+                    continue;
+                }
+
+                // Соединение живо, но устройство не отвечает по существу
+                if last_seen.elapsed() >= settings.offline_threshold {
+                    callback(MonitoringEvent::State(ConnectionState::Offline)).await;
+                }
+
+                tokio::time::sleep(settings.poll_interval).await;
                 continue;
             }
 
+            last_seen = Instant::now();
             let device_response = device_response.unwrap();
 
             if device_response.is_none() {
-                tokio::time::sleep(Duration::from_secs(2)).await;
+                tokio::time::sleep(settings.poll_interval).await;
                 continue;
             }
 
-            callback(Ok(device_response.unwrap())).await;
+            callback(MonitoringEvent::Data(device_response.unwrap())).await;
 
-            tokio::time::sleep(Duration::from_secs(2)).await;
+            tokio::time::sleep(settings.poll_interval).await;
         }
-    });
+    })
 }
 
-async fn start_udp_monitoring<Fut, F>(socket: Arc<Mutex<UdpSocket>>, mut callback: F)
+fn start_udp_monitoring<Fut, F>(
+    mut subscription: Subscription,
+    polling: Polling,
+    mut callback: F,
+) -> JoinHandle<()>
 where
-    F: FnMut(Result<DeviceData, String>) -> Fut + Send + 'static,
+    F: FnMut(MonitoringEvent) -> Fut + Send + 'static,
     Fut: std::future::Future<Output = ()> + Send + 'static,
 {
     tokio::spawn(async move {
-        loop {
-            let mut message_length = [0u8; size_of::<usize>()];
+        let mut last_seen = Instant::now();
 
-            let locked_socket = socket.lock().await;
+        loop {
+            let settings = polling.get();
 
-            if let Err(e) = locked_socket.recv_from(&mut message_length) {
-                callback(Err(format!(
-                    "{}",
-                    SmartHomeErrors::getting_status_error(format!("UDP: {}", e))
-                )))
-                .await;
-                tokio::time::sleep(Duration::from_secs(2)).await;
-                continue;
-            }
+            let delivery = match tokio::time::timeout(UDP_READ_TIMEOUT, subscription.recv()).await {
+                Ok(Some(delivery)) => delivery,
+                Ok(None) => {
+                    callback(MonitoringEvent::Error(format!(
+                        "{}",
+                        SmartHomeErrors::getting_status_error("UDP: порт закрыт".to_string())
+                    )))
+                    .await;
+                    callback(MonitoringEvent::State(ConnectionState::Offline)).await;
+                    break;
+                }
+                Err(_) => {
+                    if last_seen.elapsed() >= settings.offline_threshold {
+                        callback(MonitoringEvent::State(ConnectionState::Offline)).await;
+                    }
 
-            let message_length = usize::from_be_bytes(message_length);
-            let mut message = vec![0u8; message_length + size_of::<usize>()];
+                    continue;
+                }
+            };
 
-            if let Err(e) = locked_socket.recv_from(&mut message) {
-                callback(Err(format!(
-                    "{}",
-                    SmartHomeErrors::getting_status_error(format!("UDP: {}", e))
-                )))
-                .await;
-                tokio::time::sleep(Duration::from_secs(2)).await;
-                continue;
-            }
+            let device_response = match delivery {
+                Ok(response) => {
+                    if let Some(info) = response.info.clone() {
+                        callback(MonitoringEvent::Info(info)).await;
+                    }
 
-            let device_response = decode_result(message[size_of::<usize>()..].to_vec());
+                    decode_result(response).map_err(|e| e.to_string())
+                }
+                Err(e) => Err(e),
+            };
 
             if let Err(e) = device_response {
-                callback(Err(format!(
+                callback(MonitoringEvent::Error(format!(
                     "UDP: Ошибка при декодировании сообщения: {}",
                     e
                 )))
                 .await;
-                tokio::time::sleep(Duration::from_secs(2)).await;
                 continue;
             }
 
             let device_response = device_response.unwrap();
 
             if device_response.is_none() {
-                tokio::time::sleep(Duration::from_secs(2)).await;
                 continue;
             }
 
-            callback(Ok(device_response.unwrap())).await;
+            last_seen = Instant::now();
+            callback(MonitoringEvent::Data(device_response.unwrap())).await;
         }
-    });
+    })
 }
 
 pub trait OnlineDevice {
     fn connect(&self) -> impl std::future::Future<Output = Result<(), String>> + Send;
+
+    /// Остановить мониторинг и закрыть соединение с устройством
+    fn disconnect(&self) -> impl std::future::Future<Output = ()> + Send;
 }
 
 impl OnlineDevice for SmartDeviceType {
@@ -198,77 +933,123 @@ impl OnlineDevice for SmartDeviceType {
             return Err("Connection options is empty".to_string());
         }
 
-        match &mut self.get_connection().unwrap() {
-            ConnectionType::Tcp { ip, port, .. } => {
+        match self.get_connection().unwrap() {
+            ConnectionType::Tcp {
+                ip,
+                port,
+                reconnect,
+                polling,
+            } => {
                 let addr = SocketAddr::new(*ip, *port);
-                match TcpStream::connect(&addr).await {
-                    Ok(s) => {
-                        match self {
-                            SmartDeviceType::Socket(socket) => {
-                                let value = Arc::clone(&socket.value);
-
-                                start_tcp_monitoring(Arc::new(Mutex::new(s)), move |data| {
-                                    let value = value.clone();
-                                    async move {
-                                        match data {
-                                            Ok(data) => {
-                                                value.write().await.update(data.as_socket());
-                                            }
-                                            Err(e) => {
-                                                eprintln!("{}", e);
-                                                value.write().await.is_online = false;
-                                            }
-                                        }
-                                    }
-                                })
-                                .await
-                            }
-                            _ => unimplemented!("Только для SmartSocket"),
-                        }
 
-                        Ok(())
+                match self {
+                    SmartDeviceType::Socket(socket) => {
+                        TcpDevice::socket(socket)
+                            .connect(addr, *reconnect, polling)
+                            .await
+                    }
+                    SmartDeviceType::Light(light) => {
+                        TcpDevice::light(light)
+                            .connect(addr, *reconnect, polling)
+                            .await
+                    }
+                    SmartDeviceType::Thermometer(_) => {
+                        Err(SmartHomeErrors::connection_error(format!(
+                            "{}: по TCP подключаются только розетки и светильники",
+                            device_name
+                        ))
+                        .to_string())
                     }
-                    Err(e) => Err(format!(
-                        "{}: Ошибка подключения к {}: {}",
-                        device_name, addr, e
-                    )),
                 }
             }
             ConnectionType::Udp {
-                bind_ip, bind_port, ..
+                bind_ip,
+                bind_port,
+                route,
+                polling,
             } => {
+                let therm = match self {
+                    SmartDeviceType::Thermometer(therm) => therm,
+                    _ => {
+                        return Err(SmartHomeErrors::connection_error(format!(
+                            "{}: по UDP подключаются только термометры",
+                            device_name
+                        ))
+                        .to_string());
+                    }
+                };
+
                 let addr = SocketAddr::new(*bind_ip, *bind_port);
-                match UdpSocket::bind(addr) {
-                    Ok(s) => {
-                        match self {
-                            SmartDeviceType::Thermometer(therm) => {
-                                let value = Arc::clone(&therm.value);
-                                start_udp_monitoring(Arc::new(Mutex::new(s)), move |data| {
-                                    let value = value.clone();
-                                    async move {
-                                        match data {
-                                            Ok(data) => {
-                                                value.write().await.update(data.as_thermometer());
-                                            }
-                                            Err(e) => {
-                                                eprintln!("{}", e);
-                                                value.write().await.is_online = false;
-                                            }
-                                        }
-                                    }
-                                })
-                                .await
+                // Порт общий для всех устройств с тем же адресом,
+                // старая подписка снимается вместе с мониторингом
+                therm.monitoring.stop().await;
+
+                let subscription = match udp_listener::subscribe(addr, route.clone()).await {
+                    Ok(subscription) => subscription,
+                    Err(e) => {
+                        return Err(format!(
+                            "{}: Failed to bind UDP socket {}: {}",
+                            device_name, addr, e
+                        ));
+                    }
+                };
+
+                let value = Arc::clone(&therm.value);
+                let info = Arc::clone(&therm.info);
+                let observers = Observers::thermometer(therm);
+                let device_id = therm.id.clone();
+                apply_state(&value, &observers, &device_id, ConnectionState::Connecting).await;
+
+                let task = start_udp_monitoring(subscription, polling.clone(), move |event| {
+                    let value = value.clone();
+                    let info = info.clone();
+                    let observers = observers.clone();
+                    let device_id = device_id.clone();
+                    let device_name = device_name.clone();
+                    async move {
+                        match event {
+                            MonitoringEvent::Data(data) => {
+                                apply_data(&value, &observers, &device_id, data).await;
+                            }
+                            MonitoringEvent::Info(new_info) => {
+                                update_info(&device_name, &info, new_info).await;
+                            }
+                            MonitoringEvent::State(state) => {
+                                apply_state(&value, &observers, &device_id, state).await;
+                            }
+                            MonitoringEvent::Error(e) => {
+                                eprintln!("{}", e);
                             }
-                            _ => unimplemented!("Только для SmartThermometer"),
                         }
-
-                        Ok(())
                     }
-                    Err(e) => Err(format!(
-                        "{}: Failed to bind UDP socket {}: {}",
-                        device_name, addr, e
-                    )),
+                });
+
+                therm.monitoring.start(task).await;
+
+                Ok(())
+            }
+        }
+    }
+
+    async fn disconnect(&self) {
+        match self {
+            SmartDeviceType::Socket(socket) => TcpDevice::socket(socket).disconnect().await,
+            SmartDeviceType::Light(light) => TcpDevice::light(light).disconnect().await,
+            SmartDeviceType::Thermometer(therm) => {
+                therm.monitoring.stop().await;
+
+                // Порт закрывается, когда на нем не осталось устройств
+                if let Some(connection @ ConnectionType::Udp { .. }) = &therm.connection {
+                    udp_listener::release(connection.get_addr()).await;
                 }
+
+                apply_state(
+                    &therm.value,
+                    &Observers::thermometer(therm),
+                    &therm.id,
+                    ConnectionState::Offline,
+                )
+                .await;
             }
         }
     }
@@ -0,0 +1,129 @@
+use std::sync::{Arc, RwLock};
+
+use serde::{Deserialize, Deserializer, Serialize, de};
+use tokio::time::Duration;
+
+use super::duration_ms;
+
+/// Параметры опроса устройства
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PollingSettings {
+    /// Пауза между запросами статуса
+    #[serde(rename = "poll_interval_ms", with = "duration_ms")]
+    pub poll_interval: Duration,
+    /// Сколько ждать ответа на команду
+    #[serde(rename = "command_timeout_ms", with = "duration_ms")]
+    pub command_timeout: Duration,
+    /// Через сколько без ответа устройство считается недоступным
+    #[serde(rename = "offline_threshold_ms", with = "duration_ms")]
+    pub offline_threshold: Duration,
+}
+
+/// Параметры опроса в описании дома: пропущенные значения берутся по умолчанию
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct PollingDefinition {
+    #[serde(with = "duration_ms")]
+    poll_interval_ms: Duration,
+    #[serde(with = "duration_ms")]
+    command_timeout_ms: Duration,
+    #[serde(with = "duration_ms")]
+    offline_threshold_ms: Duration,
+}
+
+impl Default for PollingDefinition {
+    fn default() -> Self {
+        let settings = PollingSettings::default();
+
+        Self {
+            poll_interval_ms: settings.poll_interval,
+            command_timeout_ms: settings.command_timeout,
+            offline_threshold_ms: settings.offline_threshold,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PollingSettings {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let definition = PollingDefinition::deserialize(deserializer)?;
+
+        // Без паузы опрос заваливает устройство запросами, а с нулевым
+        // таймаутом не проходит ни одна команда
+        for (name, value) in [
+            ("poll_interval_ms", definition.poll_interval_ms),
+            ("command_timeout_ms", definition.command_timeout_ms),
+            ("offline_threshold_ms", definition.offline_threshold_ms),
+        ] {
+            if value.is_zero() {
+                return Err(de::Error::custom(format!(
+                    "параметр опроса {name} не может быть 0"
+                )));
+            }
+        }
+
+        Ok(Self {
+            poll_interval: definition.poll_interval_ms,
+            command_timeout: definition.command_timeout_ms,
+            offline_threshold: definition.offline_threshold_ms,
+        })
+    }
+}
+
+impl Default for PollingSettings {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(2),
+            command_timeout: Duration::from_secs(5),
+            offline_threshold: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Параметры опроса, общие для всех клонов устройства.
+///
+/// Мониторинг читает их на каждой итерации, поэтому
+/// изменения применяются без переподключения.
+#[derive(Debug, Clone, Default)]
+pub struct Polling {
+    inner: Arc<RwLock<PollingSettings>>,
+}
+
+impl Polling {
+    pub fn new(settings: PollingSettings) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(settings)),
+        }
+    }
+
+    /// Получить текущие параметры
+    pub fn get(&self) -> PollingSettings {
+        *self.inner.read().unwrap()
+    }
+
+    /// Заменить параметры
+    pub fn set(&self, settings: PollingSettings) {
+        *self.inner.write().unwrap() = settings;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clones_share_settings() {
+        let polling = Polling::default();
+        let clone = polling.clone();
+
+        let settings = PollingSettings {
+            poll_interval: Duration::from_millis(100),
+            ..PollingSettings::default()
+        };
+        clone.set(settings);
+
+        assert_eq!(polling.get(), settings);
+    }
+}
@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use tokio::time::Duration;
+
+use super::duration_ms;
+
+/// Политика переподключения TCP-устройства.
+///
+/// Задержка между попытками растет экспоненциально от `initial_delay`
+/// до `max_delay`, к ней добавляется случайный разброс `jitter`,
+/// чтобы устройства не переподключались одновременно.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReconnectPolicy {
+    /// Задержка перед первой попыткой
+    #[serde(rename = "initial_delay_ms", with = "duration_ms")]
+    pub initial_delay: Duration,
+    /// Максимальная задержка между попытками
+    #[serde(rename = "max_delay_ms", with = "duration_ms")]
+    pub max_delay: Duration,
+    /// Во сколько раз растет задержка после каждой неудачной попытки
+    pub multiplier: f64,
+    /// Доля случайного разброса задержки, от 0 до 1
+    pub jitter: f64,
+    /// Максимальное число попыток подряд, `None` - без ограничений
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_attempts: Option<u32>,
+}
+
+impl ReconnectPolicy {
+    /// Не переподключаться
+    pub fn disabled() -> Self {
+        Self {
+            max_attempts: Some(0),
+            ..Self::default()
+        }
+    }
+
+    /// Задержка перед попыткой с номером `attempt`, начиная с нуля
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let base = self.initial_delay.as_secs_f64() * self.multiplier.powi(attempt.min(64) as i32);
+        let base = base.min(self.max_delay.as_secs_f64());
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let spread = if jitter > 0.0 {
+            base * rand::random_range(-jitter..=jitter)
+        } else {
+            0.0
+        };
+
+        Duration::from_secs_f64((base + spread).max(0.0))
+    }
+
+    /// Исчерпаны ли попытки переподключения
+    pub fn is_exhausted(&self, attempt: u32) -> bool {
+        matches!(self.max_attempts, Some(max_attempts) if attempt >= max_attempts)
+    }
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_grows_exponentially() {
+        let policy = ReconnectPolicy {
+            jitter: 0.0,
+            ..ReconnectPolicy::default()
+        };
+
+        assert_eq!(policy.delay(0), Duration::from_millis(500));
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(2), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn delay_is_capped() {
+        let policy = ReconnectPolicy {
+            jitter: 0.0,
+            ..ReconnectPolicy::default()
+        };
+
+        assert_eq!(policy.delay(10), Duration::from_secs(30));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn delay_jitter_bounds() {
+        let policy = ReconnectPolicy::default();
+
+        for _ in 0..100 {
+            let delay = policy.delay(1);
+            assert!(delay >= Duration::from_millis(800));
+            assert!(delay <= Duration::from_millis(1200));
+        }
+    }
+
+    #[test]
+    fn attempts_exhausted() {
+        assert!(ReconnectPolicy::disabled().is_exhausted(0));
+        assert!(!ReconnectPolicy::default().is_exhausted(u32::MAX));
+
+        let policy = ReconnectPolicy {
+            max_attempts: Some(3),
+            ..ReconnectPolicy::default()
+        };
+        assert!(!policy.is_exhausted(2));
+        assert!(policy.is_exhausted(3));
+    }
+}
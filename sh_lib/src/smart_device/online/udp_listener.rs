@@ -0,0 +1,420 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::net::UdpSocket;
+use tokio::sync::{Mutex, mpsc};
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+
+use crate::smart_device::{
+    contracts::{DeviceInfo, DeviceResponse},
+    frame::{Frame, MAX_DATAGRAM_LEN},
+};
+
+/// Пауза после ошибки приема, чтобы не крутить цикл вхолостую
+const RECV_ERROR_DELAY: Duration = Duration::from_millis(100);
+
+/// Открытые UDP-порты, по одному слушателю на адрес
+static LISTENERS: LazyLock<Mutex<HashMap<SocketAddr, Arc<UdpListener>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// По какому признаку посылки на общем UDP-порту относятся к устройству
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UdpRoute {
+    /// Все посылки, не забранные другими устройствами порта
+    #[default]
+    Any,
+    /// Посылки с указанным серийным номером
+    Serial(String),
+    /// Посылки с указанного адреса отправителя
+    Sender(SocketAddr),
+}
+
+/// Отправитель, посылки которого не относятся ни к одному устройству
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnassignedSender {
+    /// Адрес, на котором посылка была принята
+    pub listener: SocketAddr,
+    /// Адрес отправителя
+    pub sender: SocketAddr,
+    /// Паспорт устройства, если он был в посылке
+    pub info: Option<DeviceInfo>,
+    /// Время последней посылки, мс
+    pub last_seen: u64,
+    /// Сколько посылок принято
+    pub packets: u64,
+}
+
+/// Посылка для устройства: ответ или текст ошибки декодирования
+pub(crate) type Delivery = Result<DeviceResponse, String>;
+
+struct Subscriber {
+    id: u64,
+    route: UdpRoute,
+    tx: mpsc::UnboundedSender<Delivery>,
+}
+
+/// Слушатель UDP-порта, раздающий посылки подписанным устройствам
+struct UdpListener {
+    addr: SocketAddr,
+    subscribers: std::sync::Mutex<Vec<Subscriber>>,
+    unassigned: std::sync::Mutex<HashMap<String, UnassignedSender>>,
+    task: std::sync::Mutex<Option<JoinHandle<()>>>,
+    next_id: AtomicU64,
+}
+
+impl UdpListener {
+    /// Передать посылку подходящим устройствам.
+    ///
+    /// Сначала ищется устройство с тем же серийным номером, затем с тем же адресом
+    /// отправителя, затем устройства без фильтра. Если никого нет, отправитель
+    /// запоминается как неназначенный.
+    fn dispatch(&self, sender: SocketAddr, delivery: Delivery) {
+        let info = delivery.as_ref().ok().and_then(|r| r.info.clone());
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|s| !s.tx.is_closed());
+
+        let by_serial = |s: &&Subscriber| match (&s.route, &info) {
+            (UdpRoute::Serial(serial), Some(info)) => *serial == info.serial,
+            _ => false,
+        };
+        let by_sender = |s: &&Subscriber| s.route == UdpRoute::Sender(sender);
+        let any = |s: &&Subscriber| s.route == UdpRoute::Any;
+
+        let mut targets: Vec<&Subscriber> = subscribers.iter().filter(by_serial).collect();
+
+        if targets.is_empty() {
+            targets = subscribers.iter().filter(by_sender).collect();
+        }
+
+        if targets.is_empty() {
+            targets = subscribers.iter().filter(any).collect();
+        }
+
+        if targets.is_empty() {
+            drop(subscribers);
+            self.remember_unassigned(sender, info);
+            return;
+        }
+
+        for target in targets {
+            let _ = target.tx.send(delivery.clone());
+        }
+    }
+
+    fn remember_unassigned(&self, sender: SocketAddr, info: Option<DeviceInfo>) {
+        let key = match &info {
+            Some(info) => info.serial.clone(),
+            None => sender.to_string(),
+        };
+
+        let mut unassigned = self.unassigned.lock().unwrap();
+        let entry = unassigned.entry(key).or_insert_with(|| {
+            eprintln!(
+                "UDP {}: посылка от неизвестного устройства {} (SN: {})",
+                self.addr,
+                sender,
+                info.as_ref().map(|i| i.serial.as_str()).unwrap_or("-")
+            );
+
+            UnassignedSender {
+                listener: self.addr,
+                sender,
+                info: None,
+                last_seen: 0,
+                packets: 0,
+            }
+        });
+
+        entry.sender = sender;
+        entry.info = info;
+        entry.last_seen = chrono::Utc::now().timestamp_millis() as u64;
+        entry.packets += 1;
+    }
+
+    fn subscribe(self: &Arc<Self>, route: UdpRoute) -> Subscription {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        // Устройство с явным фильтром забирает посылки у списка неназначенных
+        self.unassigned
+            .lock()
+            .unwrap()
+            .retain(|key, s| match &route {
+                UdpRoute::Any => false,
+                UdpRoute::Serial(serial) => key != serial,
+                UdpRoute::Sender(addr) => s.sender != *addr,
+            });
+        self.subscribers
+            .lock()
+            .unwrap()
+            .push(Subscriber { id, route, tx });
+
+        Subscription {
+            listener: Arc::clone(self),
+            id,
+            rx,
+        }
+    }
+
+    fn is_unused(&self) -> bool {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|s| !s.tx.is_closed());
+        subscribers.is_empty()
+    }
+}
+
+/// Подписка устройства на посылки общего UDP-порта
+pub(crate) struct Subscription {
+    listener: Arc<UdpListener>,
+    id: u64,
+    rx: mpsc::UnboundedReceiver<Delivery>,
+}
+
+impl Subscription {
+    /// Дождаться следующей посылки, `None` - слушатель порта закрыт
+    pub(crate) async fn recv(&mut self) -> Option<Delivery> {
+        self.rx.recv().await
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.listener
+            .subscribers
+            .lock()
+            .unwrap()
+            .retain(|s| s.id != self.id);
+    }
+}
+
+/// Подписаться на посылки UDP-порта, открыв его при первой подписке
+pub(crate) async fn subscribe(addr: SocketAddr, route: UdpRoute) -> std::io::Result<Subscription> {
+    let mut listeners = LISTENERS.lock().await;
+
+    if let Some(listener) = listeners.get(&addr) {
+        return Ok(listener.subscribe(route));
+    }
+
+    let socket = UdpSocket::bind(addr).await?;
+    let listener = Arc::new(UdpListener {
+        addr,
+        subscribers: std::sync::Mutex::new(Vec::new()),
+        unassigned: std::sync::Mutex::new(HashMap::new()),
+        task: std::sync::Mutex::new(None),
+        next_id: AtomicU64::new(0),
+    });
+
+    let task = tokio::spawn(receive(socket, Arc::downgrade(&listener)));
+    *listener.task.lock().unwrap() = Some(task);
+
+    let subscription = listener.subscribe(route);
+    listeners.insert(addr, listener);
+
+    Ok(subscription)
+}
+
+/// Закрыть UDP-порт, если на нем не осталось устройств
+pub(crate) async fn release(addr: SocketAddr) {
+    let task = {
+        let mut listeners = LISTENERS.lock().await;
+
+        match listeners.get(&addr) {
+            Some(listener) if listener.is_unused() => {
+                let listener = listeners.remove(&addr).unwrap();
+                listener.task.lock().unwrap().take()
+            }
+            _ => None,
+        }
+    };
+
+    if let Some(task) = task {
+        task.abort();
+        let _ = task.await;
+    }
+}
+
+/// Отправители, посылки которых не относятся ни к одному устройству
+pub async fn unassigned_senders() -> Vec<UnassignedSender> {
+    let listeners = LISTENERS.lock().await;
+
+    let mut senders: Vec<UnassignedSender> = listeners
+        .values()
+        .flat_map(|l| {
+            l.unassigned
+                .lock()
+                .unwrap()
+                .values()
+                .cloned()
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    senders.sort_by_key(|s| (s.listener, s.sender));
+
+    senders
+}
+
+async fn receive(socket: UdpSocket, listener: std::sync::Weak<UdpListener>) {
+    // На байт больше допустимого: заполненный целиком буфер значит, что датаграмма обрезана
+    let mut datagram = vec![0u8; MAX_DATAGRAM_LEN + 1];
+
+    loop {
+        let (received, sender) = match socket.recv_from(&mut datagram).await {
+            Ok(received) => received,
+            Err(e) => {
+                eprintln!("UDP: ошибка приема посылки: {}", e);
+                tokio::time::sleep(RECV_ERROR_DELAY).await;
+                continue;
+            }
+        };
+
+        let listener = if let Some(listener) = listener.upgrade() {
+            listener
+        } else {
+            break;
+        };
+
+        // Каждая датаграмма содержит ровно один кадр
+        let delivery = if received > MAX_DATAGRAM_LEN {
+            Err(format!(
+                "датаграмма от {} больше {} байт и отброшена",
+                sender, MAX_DATAGRAM_LEN
+            ))
+        } else {
+            Frame::decode(&datagram[..received])
+                .and_then(|frame| frame.as_response())
+                .map_err(|e| e.to_string())
+        };
+
+        listener.dispatch(sender, delivery);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn free_addr() -> SocketAddr {
+        std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    }
+
+    fn datagram(serial: &str) -> Vec<u8> {
+        let response = DeviceResponse {
+            data: None,
+            info: Some(DeviceInfo {
+                serial: serial.to_string(),
+                ..DeviceInfo::default()
+            }),
+            success: true,
+            error: None,
+        };
+
+        Frame::response(&response).unwrap().encode()
+    }
+
+    async fn next_serial(subscription: &mut Subscription) -> String {
+        let delivery = tokio::time::timeout(Duration::from_secs(2), subscription.recv())
+            .await
+            .unwrap()
+            .unwrap();
+
+        delivery.unwrap().info.unwrap().serial
+    }
+
+    #[tokio::test]
+    async fn routes_by_serial_and_sender() {
+        let addr = free_addr();
+        let mut first = subscribe(addr, UdpRoute::Serial("A".to_string()))
+            .await
+            .unwrap();
+        let mut second = subscribe(addr, UdpRoute::Serial("B".to_string()))
+            .await
+            .unwrap();
+
+        let device = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut by_sender = subscribe(addr, UdpRoute::Sender(device.local_addr().unwrap()))
+            .await
+            .unwrap();
+
+        device.send_to(&datagram("B"), addr).unwrap();
+        device.send_to(&datagram("A"), addr).unwrap();
+        device.send_to(&datagram("C"), addr).unwrap();
+
+        assert_eq!(next_serial(&mut second).await, "B");
+        assert_eq!(next_serial(&mut first).await, "A");
+        assert_eq!(next_serial(&mut by_sender).await, "C");
+
+        drop((first, second, by_sender));
+        release(addr).await;
+        assert!(std::net::UdpSocket::bind(addr).is_ok());
+    }
+
+    #[tokio::test]
+    async fn broken_datagram_does_not_stop_listener() {
+        let addr = free_addr();
+        let mut subscription = subscribe(addr, UdpRoute::Any).await.unwrap();
+
+        let device = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let valid = datagram("A");
+        device.send_to(&valid[..valid.len() - 1], addr).unwrap();
+        device.send_to(&valid, addr).unwrap();
+
+        let broken = tokio::time::timeout(Duration::from_secs(2), subscription.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(broken.unwrap_err().contains("кадр обрезан"));
+        assert_eq!(next_serial(&mut subscription).await, "A");
+
+        drop(subscription);
+        release(addr).await;
+    }
+
+    #[tokio::test]
+    async fn reports_unassigned_senders() {
+        let addr = free_addr();
+        let subscription = subscribe(addr, UdpRoute::Serial("A".to_string()))
+            .await
+            .unwrap();
+
+        let device = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        device.send_to(&datagram("X"), addr).unwrap();
+        device.send_to(&datagram("X"), addr).unwrap();
+
+        let unassigned = tokio::time::timeout(Duration::from_secs(2), async {
+            loop {
+                let found: Vec<UnassignedSender> = unassigned_senders()
+                    .await
+                    .into_iter()
+                    .filter(|s| s.listener == addr && s.packets == 2)
+                    .collect();
+
+                if !found.is_empty() {
+                    return found;
+                }
+
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(unassigned.len(), 1);
+        assert_eq!(unassigned[0].sender, device.local_addr().unwrap());
+        assert_eq!(unassigned[0].info.as_ref().unwrap().serial, "X");
+
+        drop(subscription);
+        release(addr).await;
+    }
+}
@@ -0,0 +1,433 @@
+use std::sync::Arc;
+
+use bincode::{Decode, Encode};
+use tokio::sync::{Mutex, RwLock};
+
+use crate::{
+    errors::SmartHomeErrors,
+    events::DeviceEvents,
+    id::Id,
+    reporter::{
+        DEVICE_REPORT_TIMEOUT, DeviceReport, DeviceValue, Measurement, Report, ReportNode, Unit,
+    },
+    smart_device::{
+        contracts::{Commands, ConnectionState, DeviceData, DeviceInfo},
+        history::{DeviceHistory, HistorySettings},
+        online::{self, ConnectionType, Monitoring, SharedStream},
+    },
+};
+
+use super::{SmartDevice, SmartDeviceType};
+
+/// Наибольшая яркость, %
+pub const MAX_BRIGHTNESS: u8 = 100;
+/// Допустимая цветовая температура, K
+pub const COLOR_TEMPERATURE_RANGE: std::ops::RangeInclusive<u16> = 1000..=10_000;
+
+/// Проверить яркость, пришедшую в команде
+pub fn check_brightness(value: u32) -> Result<u8, SmartHomeErrors> {
+    match u8::try_from(value) {
+        Ok(brightness) if brightness <= MAX_BRIGHTNESS => Ok(brightness),
+        _ => Err(SmartHomeErrors::invalid_value(format!(
+            "яркость {value} вне диапазона 0-{MAX_BRIGHTNESS}"
+        ))),
+    }
+}
+
+/// Проверить цветовую температуру, пришедшую в команде
+pub fn check_color_temperature(value: u32) -> Result<u16, SmartHomeErrors> {
+    match u16::try_from(value) {
+        Ok(kelvin) if COLOR_TEMPERATURE_RANGE.contains(&kelvin) => Ok(kelvin),
+        _ => Err(SmartHomeErrors::invalid_value(format!(
+            "цветовая температура {value} K вне диапазона {}-{} K",
+            COLOR_TEMPERATURE_RANGE.start(),
+            COLOR_TEMPERATURE_RANGE.end()
+        ))),
+    }
+}
+
+#[derive(Clone, Debug, Encode, Decode)]
+pub struct LightData {
+    pub is_on: bool,
+    /// Яркость, 0-100 %. Выключенный светильник помнит яркость
+    pub brightness: u8,
+    /// Цветовая температура, K, `None` - светильник ее не поддерживает
+    pub color_temperature: Option<u16>,
+    pub timestamp: u64,
+    pub is_online: bool,
+    pub connection_state: ConnectionState,
+}
+
+impl LightData {
+    pub fn new(brightness: u8, is_on: bool) -> Self {
+        Self {
+            is_on,
+            brightness: brightness.min(MAX_BRIGHTNESS),
+            color_temperature: None,
+            timestamp: chrono::Utc::now().timestamp_millis() as u64,
+            is_online: false,
+            connection_state: ConnectionState::Offline,
+        }
+    }
+
+    pub fn update(&mut self, data: LightData) {
+        *self = data;
+    }
+
+    /// Установить состояние соединения, `is_online` следует за ним
+    pub fn set_connection_state(&mut self, state: ConnectionState) {
+        self.connection_state = state;
+        self.is_online = state == ConnectionState::Online;
+    }
+
+    /// Показание для истории: яркость включенного светильника, у выключенного - 0
+    pub fn reading(&self) -> f32 {
+        if self.is_on {
+            self.brightness as f32
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SmartLight {
+    pub id: Id,
+    pub name: String,
+    pub value: Arc<RwLock<LightData>>,
+    pub connection: Option<ConnectionType>,
+    /// Паспорт устройства, известен после опроса удаленного устройства
+    pub info: Arc<RwLock<Option<DeviceInfo>>>,
+    pub stream: SharedStream,
+    pub monitoring: Monitoring,
+    /// События устройства, направляются в комнату, в которую оно добавлено
+    pub events: DeviceEvents,
+    /// История показаний, пополняется мониторингом
+    pub history: DeviceHistory,
+}
+
+impl SmartLight {
+    pub fn new(name: impl Into<String>, brightness: u8, is_on: bool) -> Self {
+        let name = name.into();
+        Self {
+            id: Id::from_string(&name),
+            name,
+            value: Arc::new(RwLock::new(LightData::new(brightness, is_on))),
+            connection: None,
+            info: Arc::new(RwLock::new(None)),
+            stream: Arc::new(Mutex::new(None)),
+            monitoring: Monitoring::default(),
+            events: DeviceEvents::default(),
+            history: DeviceHistory::default(),
+        }
+    }
+
+    pub fn new_with_connection(
+        name: impl Into<String>,
+        brightness: u8,
+        is_on: bool,
+        connection: ConnectionType,
+    ) -> Self {
+        Self {
+            connection: Some(connection),
+            ..Self::new(name, brightness, is_on)
+        }
+    }
+
+    /// Светильник с регулируемой цветовой температурой, `kelvin` - начальное значение
+    pub fn with_color_temperature(mut self, kelvin: u16) -> Self {
+        let kelvin = kelvin.clamp(
+            *COLOR_TEMPERATURE_RANGE.start(),
+            *COLOR_TEMPERATURE_RANGE.end(),
+        );
+        let data = LightData {
+            color_temperature: Some(kelvin),
+            ..self.value.try_read().unwrap().clone()
+        };
+        self.value = Arc::new(RwLock::new(data));
+        self
+    }
+
+    /// Задать паспорт устройства
+    pub fn with_info(mut self, info: DeviceInfo) -> Self {
+        self.info = Arc::new(RwLock::new(Some(info)));
+        self
+    }
+
+    /// Задать параметры хранения истории показаний
+    pub fn with_history(mut self, settings: HistorySettings) -> Self {
+        self.history = DeviceHistory::new(settings);
+        self
+    }
+
+    /// Получить паспорт устройства
+    pub async fn get_info(&self) -> Option<DeviceInfo> {
+        self.info.read().await.clone()
+    }
+
+    /// Включить светильник
+    pub async fn turn_on(&mut self) -> Result<(), SmartHomeErrors> {
+        self.apply(Commands::TurnOn, None, |data| data.is_on = true)
+            .await
+    }
+
+    /// Выключить светильник
+    pub async fn turn_off(&mut self) -> Result<(), SmartHomeErrors> {
+        self.apply(Commands::TurnOff, None, |data| data.is_on = false)
+            .await
+    }
+
+    /// Установить яркость, 0-100 %
+    pub async fn set_brightness(&mut self, brightness: u8) -> Result<(), SmartHomeErrors> {
+        let brightness = check_brightness(brightness.into())?;
+
+        self.apply(Commands::SetBrightness, Some(brightness.into()), |data| {
+            data.brightness = brightness
+        })
+        .await
+    }
+
+    /// Установить цветовую температуру, K
+    pub async fn set_color_temperature(&mut self, kelvin: u16) -> Result<(), SmartHomeErrors> {
+        if self.value.read().await.color_temperature.is_none() {
+            return Err(SmartHomeErrors::invalid_value(format!(
+                "светильник {} не поддерживает цветовую температуру",
+                self.name
+            )));
+        }
+        let kelvin = check_color_temperature(kelvin.into())?;
+
+        self.apply(Commands::SetColorTemperature, Some(kelvin.into()), |data| {
+            data.color_temperature = Some(kelvin)
+        })
+        .await
+    }
+
+    /// Выполнить команду.
+    ///
+    /// Если светильник подключен по TCP, команда отправляется на устройство,
+    /// а локальное состояние меняется только после подтверждения.
+    async fn apply(
+        &self,
+        cmd: Commands,
+        value: Option<u32>,
+        change: impl FnOnce(&mut LightData),
+    ) -> Result<(), SmartHomeErrors> {
+        if let Some(ConnectionType::Tcp { polling, .. }) = &self.connection {
+            let timeout = polling.get().command_timeout;
+
+            match value {
+                Some(value) => {
+                    online::send_command_with_value(&self.stream, cmd, value, timeout).await?
+                }
+                None => online::send_command(&self.stream, cmd, timeout).await?,
+            };
+        }
+
+        let (old, new) = {
+            let mut value = self.value.write().await;
+            let old = value.clone();
+            change(&mut value);
+            value.timestamp = chrono::Utc::now().timestamp_millis() as u64;
+            (old, value.clone())
+        };
+
+        self.history.record(new.reading(), Unit::Percent);
+        self.events
+            .state_changed(&self.id, DeviceData::Light(old), DeviceData::Light(new));
+
+        Ok(())
+    }
+
+    /// Проверить, включен ли светильник
+    pub async fn is_on(&self) -> bool {
+        self.value.read().await.is_on
+    }
+
+    /// Получить данные светильника
+    pub async fn get_data(&self) -> LightData {
+        self.value.read().await.clone()
+    }
+}
+
+impl SmartDevice for SmartLight {
+    fn get_id(&self) -> &Id {
+        &self.id
+    }
+
+    fn get_name(&self) -> &String {
+        &self.name
+    }
+
+    fn get_connection(&self) -> Option<&ConnectionType> {
+        self.connection.as_ref()
+    }
+}
+
+impl SmartLight {
+    /// Отчет по светильнику
+    pub async fn device_report(&self) -> DeviceReport {
+        let value = self.get_data().await;
+
+        DeviceReport {
+            id: self.id.to_string(),
+            name: self.name.clone(),
+            value: Some(DeviceValue::Light {
+                is_on: value.is_on,
+                brightness: Measurement::percent(value.brightness as f32),
+                color_temperature: value
+                    .color_temperature
+                    .map(|kelvin| Measurement::kelvin(kelvin as f32)),
+            }),
+            online: value.is_online,
+            connection_state: value.connection_state,
+            timestamp: value.timestamp,
+            cost: None,
+        }
+    }
+}
+
+impl Report for SmartLight {
+    async fn report(&self) -> ReportNode {
+        ReportNode::Device(
+            DeviceReport::within(
+                self.id.to_string(),
+                &self.name,
+                DEVICE_REPORT_TIMEOUT,
+                self.device_report(),
+            )
+            .await,
+        )
+    }
+}
+
+impl From<SmartLight> for SmartDeviceType {
+    fn from(value: SmartLight) -> Self {
+        SmartDeviceType::Light(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::errors::ErrorInfo;
+    use crate::smart_device::contracts::DeviceResponse;
+    use crate::smart_device::frame::{Frame, MessageType, negotiate};
+    use crate::smart_device::online::OnlineDevice;
+
+    #[tokio::test]
+    async fn light_status() {
+        let mut light = SmartLight::new("Люстра", 80, false);
+        assert_eq!(light.get_status_report().await, "Люстра: Выкл");
+
+        light.turn_on().await.unwrap();
+        assert_eq!(light.get_status_report().await, "Люстра: Вкл, 80 %");
+
+        let mut lamp = SmartLight::new("Лампа", 40, true).with_color_temperature(2700);
+        lamp.set_color_temperature(4000).await.unwrap();
+        assert_eq!(lamp.get_status_report().await, "Лампа: Вкл, 40 %, 4000 K");
+    }
+
+    #[tokio::test]
+    async fn light_brightness_is_recorded_in_history() {
+        let mut light = SmartLight::new("Люстра", 100, true);
+        light.set_brightness(30).await.unwrap();
+        light.turn_off().await.unwrap();
+
+        let summary = light.history.summary(0, u64::MAX).unwrap();
+        assert_eq!((summary.min, summary.max, summary.last), (0.0, 30.0, 0.0));
+        assert_eq!(light.history.unit(), Some(Unit::Percent));
+        // Выключенный светильник помнит яркость
+        assert_eq!(light.get_data().await.brightness, 30);
+    }
+
+    #[tokio::test]
+    async fn light_rejects_invalid_values() {
+        let mut light = SmartLight::new("Люстра", 50, true);
+
+        match light.set_brightness(101).await {
+            Err(SmartHomeErrors::InvalidValue(ErrorInfo { code, .. })) => {
+                assert_eq!(code, "1008")
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+        assert!(light.set_color_temperature(3000).await.is_err());
+
+        let mut lamp = light.clone().with_color_temperature(3000);
+        assert!(lamp.set_color_temperature(500).await.is_err());
+        assert_eq!(lamp.get_data().await.brightness, 50);
+        assert_eq!(lamp.get_data().await.color_temperature, Some(3000));
+    }
+
+    #[tokio::test]
+    async fn remote_light_sends_values() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (commands_tx, mut commands_rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            while let Ok(request) = Frame::read_from(&mut stream).await {
+                let reply = match request.message_type {
+                    MessageType::Hello => negotiate(&request),
+                    _ => {
+                        let (cmd, value) = request.as_command_value().unwrap();
+                        if !matches!(cmd, Commands::GetStatus | Commands::GetInfo) {
+                            commands_tx.send((cmd as i32, value)).unwrap();
+                        }
+
+                        Frame::response(&DeviceResponse {
+                            data: None,
+                            info: None,
+                            success: true,
+                            error: None,
+                        })
+                        .unwrap()
+                    }
+                };
+
+                reply.write_to(&mut stream).await.unwrap();
+            }
+        });
+
+        let mut light = SmartLight::new_with_connection(
+            "Люстра",
+            100,
+            false,
+            ConnectionType::tcp(addr.ip(), addr.port()),
+        );
+        SmartDeviceType::from(light.clone())
+            .connect()
+            .await
+            .unwrap();
+
+        light.turn_on().await.unwrap();
+        light.set_brightness(25).await.unwrap();
+
+        let mut received = vec![];
+        for _ in 0..2 {
+            let command = tokio::time::timeout(Duration::from_secs(5), commands_rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            received.push(command);
+        }
+        assert_eq!(
+            received,
+            [
+                (Commands::TurnOn as i32, None),
+                (Commands::SetBrightness as i32, Some(25))
+            ]
+        );
+        assert_eq!(light.get_data().await.brightness, 25);
+
+        SmartDeviceType::from(light.clone()).disconnect().await;
+        assert!(light.turn_off().await.is_err());
+    }
+}
@@ -1,9 +1,22 @@
 use std::sync::Arc;
 
 use bincode::{Decode, Encode};
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock};
 
-use crate::{id::Id, reporter::Report, smart_device::online::ConnectionType};
+use crate::{
+    errors::SmartHomeErrors,
+    events::DeviceEvents,
+    id::Id,
+    reporter::{
+        DEVICE_REPORT_TIMEOUT, DeviceReport, DeviceValue, Measurement, Report, ReportNode, Unit,
+    },
+    smart_device::{
+        contracts::{Commands, ConnectionState, DeviceData, DeviceInfo},
+        energy::EnergyMeter,
+        history::{DeviceHistory, HistorySettings},
+        online::{self, ConnectionType, Monitoring, SharedStream},
+    },
+};
 
 use super::{SmartDevice, SmartDeviceType};
 
@@ -13,6 +26,7 @@ pub struct SocketData {
     pub is_on: bool,
     pub timestamp: u64,
     pub is_online: bool,
+    pub connection_state: ConnectionState,
 }
 
 impl SocketData {
@@ -22,12 +36,19 @@ impl SocketData {
             is_on,
             is_online: false,
             timestamp: chrono::Utc::now().timestamp_millis() as u64,
+            connection_state: ConnectionState::Offline,
         }
     }
 
     pub fn update(&mut self, data: SocketData) {
         *self = data;
     }
+
+    /// Установить состояние соединения, `is_online` следует за ним
+    pub fn set_connection_state(&mut self, state: ConnectionState) {
+        self.connection_state = state;
+        self.is_online = state == ConnectionState::Online;
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -36,6 +57,16 @@ pub struct SmartSocket {
     pub name: String,
     pub value: Arc<RwLock<SocketData>>,
     pub connection: Option<ConnectionType>,
+    /// Паспорт устройства, известен после опроса удаленного устройства
+    pub info: Arc<RwLock<Option<DeviceInfo>>>,
+    pub stream: SharedStream,
+    pub monitoring: Monitoring,
+    /// События устройства, направляются в комнату, в которую оно добавлено
+    pub events: DeviceEvents,
+    /// История показаний, пополняется мониторингом
+    pub history: DeviceHistory,
+    /// Потребленная энергия, учитывается с первого показания или переключения
+    pub energy: EnergyMeter,
 }
 
 impl SmartSocket {
@@ -46,6 +77,12 @@ impl SmartSocket {
             name,
             value: Arc::new(RwLock::new(SocketData::new(power, is_on))),
             connection: None,
+            info: Arc::new(RwLock::new(None)),
+            stream: Arc::new(Mutex::new(None)),
+            monitoring: Monitoring::default(),
+            events: DeviceEvents::default(),
+            history: DeviceHistory::default(),
+            energy: EnergyMeter::default(),
         }
     }
 
@@ -61,21 +98,66 @@ impl SmartSocket {
             name,
             value: Arc::new(RwLock::new(SocketData::new(power, is_on))),
             connection: Some(connection),
+            info: Arc::new(RwLock::new(None)),
+            stream: Arc::new(Mutex::new(None)),
+            monitoring: Monitoring::default(),
+            events: DeviceEvents::default(),
+            history: DeviceHistory::default(),
+            energy: EnergyMeter::default(),
         }
     }
 
+    /// Задать паспорт устройства
+    pub fn with_info(mut self, info: DeviceInfo) -> Self {
+        self.info = Arc::new(RwLock::new(Some(info)));
+        self
+    }
+
+    /// Задать параметры хранения истории показаний
+    pub fn with_history(mut self, settings: HistorySettings) -> Self {
+        self.history = DeviceHistory::new(settings);
+        self
+    }
+
+    /// Получить паспорт устройства
+    pub async fn get_info(&self) -> Option<DeviceInfo> {
+        self.info.read().await.clone()
+    }
+
     /// Включить розетку
-    pub async fn turn_on(&mut self) {
-        let mut value = self.value.write().await;
-        value.is_on = true;
-        value.timestamp = chrono::Utc::now().timestamp_millis() as u64;
+    pub async fn turn_on(&mut self) -> Result<(), SmartHomeErrors> {
+        self.switch(Commands::TurnOn).await
     }
 
     /// Выключить розетку
-    pub async fn turn_off(&mut self) {
-        let mut value = self.value.write().await;
-        value.is_on = false;
-        value.timestamp = chrono::Utc::now().timestamp_millis() as u64;
+    pub async fn turn_off(&mut self) -> Result<(), SmartHomeErrors> {
+        self.switch(Commands::TurnOff).await
+    }
+
+    /// Переключить розетку.
+    ///
+    /// Если розетка подключена по TCP, команда отправляется на устройство,
+    /// а локальное состояние меняется только после подтверждения.
+    async fn switch(&self, cmd: Commands) -> Result<(), SmartHomeErrors> {
+        if let Some(ConnectionType::Tcp { polling, .. }) = &self.connection {
+            online::send_command(&self.stream, cmd, polling.get().command_timeout).await?;
+        }
+
+        let (old, new) = {
+            let mut value = self.value.write().await;
+            let old = value.clone();
+            value.is_on = matches!(cmd, Commands::TurnOn);
+            value.timestamp = chrono::Utc::now().timestamp_millis() as u64;
+            (old, value.clone())
+        };
+
+        let reading = if new.is_on { new.power } else { 0.0 };
+        self.history.record(reading, Unit::Watt);
+        self.energy.observe(reading);
+        self.events
+            .state_changed(&self.id, DeviceData::Socket(old), DeviceData::Socket(new));
+
+        Ok(())
     }
 
     /// Проверить, включена ли розетка
@@ -105,17 +187,37 @@ impl SmartDevice for SmartSocket {
     }
 }
 
+impl SmartSocket {
+    /// Отчет по розетке, мощность выключенной розетки - 0
+    pub async fn device_report(&self) -> DeviceReport {
+        let value = self.get_data().await;
+
+        DeviceReport {
+            id: self.id.to_string(),
+            name: self.name.clone(),
+            value: Some(DeviceValue::Socket {
+                is_on: value.is_on,
+                power: Measurement::watts(value.power),
+                energy: Measurement::kilowatt_hours(self.energy.total_kwh()),
+            }),
+            online: value.is_online,
+            connection_state: value.connection_state,
+            timestamp: value.timestamp,
+            cost: None,
+        }
+    }
+}
+
 impl Report for SmartSocket {
-    /// Получить статус розетки
-    async fn get_status_report(&self) -> String {
-        let value = self.value.read().await;
-        format!(
-            "{}: {}",
-            self.name,
-            match value.is_on {
-                true => format!("Вкл, {} Вт", value.power),
-                false => "Выкл".to_string(),
-            }
+    async fn report(&self) -> ReportNode {
+        ReportNode::Device(
+            DeviceReport::within(
+                self.id.to_string(),
+                &self.name,
+                DEVICE_REPORT_TIMEOUT,
+                self.device_report(),
+            )
+            .await,
         )
     }
 }
@@ -129,7 +231,95 @@ impl From<SmartSocket> for SmartDeviceType {
 #[cfg(test)]
 mod tests {
 
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use tokio::net::{TcpListener, TcpStream};
+
     use super::*;
+    use crate::errors::ErrorInfo;
+    use crate::smart_device::contracts::{DeviceInfo, DeviceResponse};
+    use crate::smart_device::frame::{Frame, MessageType, PROTOCOL_VERSION, negotiate};
+    use crate::smart_device::online::{OnlineDevice, Polling, PollingSettings, ReconnectPolicy};
+    use crate::smart_device::smart_light::LightData;
+
+    fn ack() -> DeviceResponse {
+        DeviceResponse {
+            data: None,
+            info: None,
+            success: true,
+            error: None,
+        }
+    }
+
+    /// Отвечать одним и тем же ответом на любую команду, пока соединение открыто
+    async fn serve(mut stream: TcpStream, response: DeviceResponse) {
+        while let Ok(request) = Frame::read_from(&mut stream).await {
+            let reply = match request.message_type {
+                MessageType::Hello => negotiate(&request),
+                _ => Frame::response(&response).unwrap(),
+            };
+
+            reply.write_to(&mut stream).await.unwrap();
+        }
+    }
+
+    /// Поднять устройство, отвечающее одним и тем же ответом на любую команду
+    async fn spawn_device(response: DeviceResponse) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            serve(stream, response).await;
+        });
+
+        addr
+    }
+
+    /// Адрес, на котором никто не слушает
+    async fn free_addr() -> SocketAddr {
+        TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    }
+
+    fn fast_reconnect() -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(50),
+            ..ReconnectPolicy::default()
+        }
+    }
+
+    async fn wait_for_state(socket: &SmartSocket, state: ConnectionState) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while socket.value.read().await.connection_state != state {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    async fn connected_socket(response: DeviceResponse, is_on: bool) -> SmartSocket {
+        let addr = spawn_device(response).await;
+        let socket = SmartSocket::new_with_connection(
+            String::from("Розетка"),
+            1000.0,
+            is_on,
+            ConnectionType::tcp(addr.ip(), addr.port()),
+        );
+
+        SmartDeviceType::from(socket.clone())
+            .connect()
+            .await
+            .unwrap();
+
+        socket
+    }
 
     #[tokio::test]
     async fn socket_power_zero_if_off() {
@@ -160,17 +350,30 @@ mod tests {
     #[tokio::test]
     async fn socket_turn_on() {
         let mut socket = SmartSocket::new(String::from("Розетка"), 1000.0, false);
-        socket.turn_on().await;
+        socket.turn_on().await.unwrap();
         assert_eq!(socket.get_status_report().await, "Розетка: Вкл, 1000 Вт");
     }
 
     #[tokio::test]
     async fn socket_turn_off() {
         let mut socket = SmartSocket::new(String::from("Розетка"), 1000.0, true);
-        socket.turn_off().await;
+        socket.turn_off().await.unwrap();
         assert_eq!(socket.get_status_report().await, "Розетка: Выкл");
     }
 
+    #[tokio::test]
+    async fn socket_switch_is_recorded_in_history() {
+        let mut socket = SmartSocket::new(String::from("Розетка"), 1000.0, false);
+        let copy = socket.clone();
+        socket.turn_on().await.unwrap();
+        socket.turn_off().await.unwrap();
+
+        let summary = copy.history.summary(0, u64::MAX).unwrap();
+        assert_eq!((summary.min, summary.max, summary.last), (0.0, 1000.0, 0.0));
+        assert_eq!(summary.count, 2);
+        assert_eq!(copy.history.unit(), Some(Unit::Watt));
+    }
+
     #[tokio::test]
     async fn socket_is_on_true() {
         let socket = SmartSocket::new(String::from("Розетка"), 1000.0, true);
@@ -182,4 +385,321 @@ mod tests {
         let socket = SmartSocket::new(String::from("Розетка"), 1000.0, false);
         assert!(!socket.is_on().await);
     }
+
+    #[tokio::test]
+    async fn socket_connected_to_light_goes_offline() {
+        // За адресом отвечает светильник: протокол тот же, данные другого типа
+        let socket = connected_socket(
+            DeviceResponse {
+                data: Some(DeviceData::Light(LightData::new(80, true))),
+                ..ack()
+            },
+            false,
+        )
+        .await;
+
+        wait_for_state(&socket, ConnectionState::Offline).await;
+        assert!(socket.monitoring.is_running().await);
+        assert!(!socket.is_on().await);
+    }
+
+    #[tokio::test]
+    async fn remote_socket_turn_on() {
+        let mut socket = connected_socket(
+            DeviceResponse {
+                data: None,
+                info: None,
+                success: true,
+                error: None,
+            },
+            false,
+        )
+        .await;
+
+        socket.turn_on().await.unwrap();
+        assert!(socket.is_on().await);
+    }
+
+    #[tokio::test]
+    async fn remote_socket_rejects_command() {
+        let mut socket = connected_socket(
+            DeviceResponse {
+                data: None,
+                info: None,
+                success: false,
+                error: Some(String::from("Unknown command")),
+            },
+            true,
+        )
+        .await;
+
+        match socket.turn_off().await {
+            Err(SmartHomeErrors::EmulatorError(ErrorInfo { code, .. })) => {
+                assert_eq!(code, "1005");
+            }
+            _ => panic!(),
+        }
+        assert!(socket.is_on().await);
+    }
+
+    #[tokio::test]
+    async fn remote_socket_not_connected() {
+        let mut socket = SmartSocket::new_with_connection(
+            String::from("Розетка"),
+            1000.0,
+            false,
+            ConnectionType::tcp("127.0.0.1".parse().unwrap(), 3001),
+        );
+
+        match socket.turn_on().await {
+            Err(SmartHomeErrors::ConnectionError(ErrorInfo { code, .. })) => {
+                assert_eq!(code, "1006");
+            }
+            _ => panic!(),
+        }
+        assert!(!socket.is_on().await);
+    }
+
+    #[tokio::test]
+    async fn remote_socket_disconnect() {
+        let mut socket = connected_socket(
+            DeviceResponse {
+                data: None,
+                info: None,
+                success: true,
+                error: None,
+            },
+            false,
+        )
+        .await;
+
+        assert!(socket.monitoring.is_running().await);
+
+        SmartDeviceType::from(socket.clone()).disconnect().await;
+
+        assert!(!socket.monitoring.is_running().await);
+        assert!(socket.turn_on().await.is_err());
+        assert!(!socket.value.read().await.is_online);
+    }
+
+    #[tokio::test]
+    async fn remote_socket_connection_state() {
+        let socket = connected_socket(ack(), false).await;
+
+        let value = socket.value.read().await;
+        assert_eq!(value.connection_state, ConnectionState::Online);
+        assert!(value.is_online);
+    }
+
+    #[tokio::test]
+    async fn remote_socket_reconnects_after_failed_connect() {
+        let addr = free_addr().await;
+        let mut socket = SmartSocket::new_with_connection(
+            String::from("Розетка"),
+            1000.0,
+            false,
+            ConnectionType::Tcp {
+                ip: addr.ip(),
+                port: addr.port(),
+                reconnect: fast_reconnect(),
+                polling: Polling::default(),
+            },
+        );
+
+        assert!(
+            SmartDeviceType::from(socket.clone())
+                .connect()
+                .await
+                .is_err()
+        );
+        assert!(!socket.value.read().await.is_online);
+
+        let listener = TcpListener::bind(addr).await.unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            serve(stream, ack()).await;
+        });
+
+        wait_for_state(&socket, ConnectionState::Online).await;
+        socket.turn_on().await.unwrap();
+        assert!(socket.is_on().await);
+    }
+
+    #[tokio::test]
+    async fn remote_socket_reconnects_after_drop() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (reconnected_tx, reconnected_rx) = tokio::sync::oneshot::channel();
+
+        tokio::spawn(async move {
+            // Первое соединение обрывается после согласования версии,
+            // как будто устройство перезапустилось
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let hello = Frame::read_from(&mut stream).await.unwrap();
+            negotiate(&hello).write_to(&mut stream).await.unwrap();
+            drop(stream);
+
+            let (stream, _) = listener.accept().await.unwrap();
+            reconnected_tx.send(()).unwrap();
+            serve(stream, ack()).await;
+        });
+
+        let mut socket = SmartSocket::new_with_connection(
+            String::from("Розетка"),
+            1000.0,
+            false,
+            ConnectionType::Tcp {
+                ip: addr.ip(),
+                port: addr.port(),
+                reconnect: fast_reconnect(),
+                polling: Polling::default(),
+            },
+        );
+        SmartDeviceType::from(socket.clone())
+            .connect()
+            .await
+            .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), reconnected_rx)
+            .await
+            .unwrap()
+            .unwrap();
+        wait_for_state(&socket, ConnectionState::Online).await;
+        socket.turn_on().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn remote_socket_offline_without_reconnect() {
+        let addr = free_addr().await;
+        let socket = SmartSocket::new_with_connection(
+            String::from("Розетка"),
+            1000.0,
+            false,
+            ConnectionType::Tcp {
+                ip: addr.ip(),
+                port: addr.port(),
+                reconnect: ReconnectPolicy::disabled(),
+                polling: Polling::default(),
+            },
+        );
+
+        assert!(
+            SmartDeviceType::from(socket.clone())
+                .connect()
+                .await
+                .is_err()
+        );
+
+        wait_for_state(&socket, ConnectionState::Offline).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!socket.monitoring.is_running().await);
+    }
+
+    #[tokio::test]
+    async fn remote_socket_command_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            // Устройство согласует версию, но не отвечает на команды
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let hello = Frame::read_from(&mut stream).await.unwrap();
+            negotiate(&hello).write_to(&mut stream).await.unwrap();
+            tokio::time::sleep(Duration::from_secs(60)).await;
+        });
+
+        let mut socket = SmartSocket::new_with_connection(
+            String::from("Розетка"),
+            1000.0,
+            false,
+            ConnectionType::Tcp {
+                ip: addr.ip(),
+                port: addr.port(),
+                reconnect: ReconnectPolicy::disabled(),
+                polling: Polling::new(PollingSettings {
+                    command_timeout: Duration::from_millis(100),
+                    ..PollingSettings::default()
+                }),
+            },
+        );
+        SmartDeviceType::from(socket.clone())
+            .connect()
+            .await
+            .unwrap();
+
+        let result = tokio::time::timeout(Duration::from_secs(2), socket.turn_on())
+            .await
+            .unwrap();
+
+        match result {
+            Err(SmartHomeErrors::ConnectionError(ErrorInfo { code, .. })) => {
+                assert_eq!(code, "1006")
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+        assert!(!socket.is_on().await);
+    }
+
+    #[tokio::test]
+    async fn remote_socket_rejects_incompatible_peer() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            // Устройство поддерживает только более новую версию протокола
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let _ = Frame::read_from(&mut stream).await.unwrap();
+            Frame::new(MessageType::Hello, vec![PROTOCOL_VERSION + 1])
+                .write_to(&mut stream)
+                .await
+                .unwrap();
+        });
+
+        let socket = SmartSocket::new_with_connection(
+            String::from("Розетка"),
+            1000.0,
+            false,
+            ConnectionType::Tcp {
+                ip: addr.ip(),
+                port: addr.port(),
+                reconnect: ReconnectPolicy::disabled(),
+                polling: Polling::default(),
+            },
+        );
+
+        let err = SmartDeviceType::from(socket.clone())
+            .connect()
+            .await
+            .unwrap_err();
+        assert!(err.contains("несовместимая версия протокола"), "{err}");
+
+        wait_for_state(&socket, ConnectionState::Offline).await;
+    }
+
+    #[tokio::test]
+    async fn remote_socket_reads_info() {
+        let info = DeviceInfo {
+            serial: String::from("SOCKET-0001"),
+            model: String::from("SH-SOCKET-EMU"),
+            firmware: String::from("1.0.0"),
+        };
+        let socket = connected_socket(
+            DeviceResponse {
+                info: Some(info.clone()),
+                ..ack()
+            },
+            false,
+        )
+        .await;
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while socket.get_info().await.is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(socket.get_info().await, Some(info));
+    }
 }
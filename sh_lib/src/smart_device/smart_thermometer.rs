@@ -3,7 +3,16 @@ use std::sync::Arc;
 use bincode::{Decode, Encode};
 use tokio::sync::RwLock;
 
-use crate::{id::Id, reporter::Report, smart_device::online::ConnectionType};
+use crate::{
+    events::DeviceEvents,
+    id::Id,
+    reporter::{DEVICE_REPORT_TIMEOUT, DeviceReport, DeviceValue, Measurement, Report, ReportNode},
+    smart_device::{
+        contracts::{ConnectionState, DeviceInfo},
+        history::{DeviceHistory, HistorySettings},
+        online::{ConnectionType, Monitoring},
+    },
+};
 
 use super::{SmartDevice, SmartDeviceType};
 
@@ -12,6 +21,7 @@ pub struct ThermometerData {
     pub temp: f32,
     pub timestamp: u64,
     pub is_online: bool,
+    pub connection_state: ConnectionState,
 }
 
 impl ThermometerData {
@@ -20,12 +30,19 @@ impl ThermometerData {
             temp,
             timestamp: chrono::Utc::now().timestamp_millis() as u64,
             is_online: false,
+            connection_state: ConnectionState::Offline,
         }
     }
 
     pub fn update(&mut self, data: ThermometerData) {
         *self = data;
     }
+
+    /// Установить состояние соединения, `is_online` следует за ним
+    pub fn set_connection_state(&mut self, state: ConnectionState) {
+        self.connection_state = state;
+        self.is_online = state == ConnectionState::Online;
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -34,6 +51,13 @@ pub struct SmartThermometer {
     pub name: String,
     pub value: Arc<RwLock<ThermometerData>>,
     pub connection: Option<ConnectionType>,
+    /// Паспорт устройства, известен после опроса удаленного устройства
+    pub info: Arc<RwLock<Option<DeviceInfo>>>,
+    pub monitoring: Monitoring,
+    /// События устройства, направляются в комнату, в которую оно добавлено
+    pub events: DeviceEvents,
+    /// История показаний, пополняется мониторингом
+    pub history: DeviceHistory,
 }
 
 impl SmartThermometer {
@@ -44,6 +68,10 @@ impl SmartThermometer {
             name,
             value: Arc::new(RwLock::new(ThermometerData::new(temp))),
             connection: None,
+            info: Arc::new(RwLock::new(None)),
+            monitoring: Monitoring::default(),
+            events: DeviceEvents::default(),
+            history: DeviceHistory::default(),
         }
     }
 
@@ -58,9 +86,30 @@ impl SmartThermometer {
             name,
             value: Arc::new(RwLock::new(ThermometerData::new(temp))),
             connection: Some(connection),
+            info: Arc::new(RwLock::new(None)),
+            monitoring: Monitoring::default(),
+            events: DeviceEvents::default(),
+            history: DeviceHistory::default(),
         }
     }
 
+    /// Задать паспорт устройства
+    pub fn with_info(mut self, info: DeviceInfo) -> Self {
+        self.info = Arc::new(RwLock::new(Some(info)));
+        self
+    }
+
+    /// Задать параметры хранения истории показаний
+    pub fn with_history(mut self, settings: HistorySettings) -> Self {
+        self.history = DeviceHistory::new(settings);
+        self
+    }
+
+    /// Получить паспорт устройства
+    pub async fn get_info(&self) -> Option<DeviceInfo> {
+        self.info.read().await.clone()
+    }
+
     /// Получить данные термометра
     pub async fn get_data(&self) -> ThermometerData {
         self.value.read().await.clone()
@@ -81,11 +130,36 @@ impl SmartDevice for SmartThermometer {
     }
 }
 
+impl SmartThermometer {
+    /// Отчет по термометру
+    pub async fn device_report(&self) -> DeviceReport {
+        let value = self.value.read().await.clone();
+
+        DeviceReport {
+            id: self.id.to_string(),
+            name: self.name.clone(),
+            value: Some(DeviceValue::Thermometer {
+                temperature: Measurement::celsius(value.temp),
+            }),
+            online: value.is_online,
+            connection_state: value.connection_state,
+            timestamp: value.timestamp,
+            cost: None,
+        }
+    }
+}
+
 impl Report for SmartThermometer {
-    /// Получить статус термометра
-    async fn get_status_report(&self) -> String {
-        let value = self.value.read().await;
-        format!("{}: {} C°", self.name, value.temp)
+    async fn report(&self) -> ReportNode {
+        ReportNode::Device(
+            DeviceReport::within(
+                self.id.to_string(),
+                &self.name,
+                DEVICE_REPORT_TIMEOUT,
+                self.device_report(),
+            )
+            .await,
+        )
     }
 }
 
@@ -97,7 +171,13 @@ impl From<SmartThermometer> for SmartDeviceType {
 
 #[cfg(test)]
 mod thermometer_tests {
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
     use super::*;
+    use crate::smart_device::contracts::{DeviceData, DeviceResponse};
+    use crate::smart_device::frame::Frame;
+    use crate::smart_device::online::{OnlineDevice, PollingSettings, UdpRoute};
 
     #[tokio::test]
     async fn thermometer_get_temp() {
@@ -122,4 +202,220 @@ mod thermometer_tests {
         let thermometer = SmartThermometer::new(String::from("Термометр"), -10.0);
         assert_eq!(thermometer.get_status_report().await, "Термометр: -10 C°");
     }
+
+    #[tokio::test]
+    async fn thermometer_disconnect_releases_port() {
+        let addr = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let thermometer = SmartThermometer::new_with_connection(
+            String::from("Термометр"),
+            20.0,
+            ConnectionType::udp(addr.ip(), addr.port()),
+        );
+        let device = SmartDeviceType::from(thermometer.clone());
+
+        device.connect().await.unwrap();
+        assert!(thermometer.monitoring.is_running().await);
+        assert!(UdpSocket::bind(addr).is_err());
+
+        device.disconnect().await;
+        assert!(!thermometer.monitoring.is_running().await);
+        assert!(UdpSocket::bind(addr).is_ok());
+    }
+
+    #[tokio::test]
+    async fn thermometer_rejects_tcp_connection() {
+        let device = SmartDeviceType::from(SmartThermometer::new_with_connection(
+            String::from("Термометр"),
+            20.0,
+            ConnectionType::tcp("127.0.0.1".parse().unwrap(), 3001),
+        ));
+
+        let err = device.connect().await.unwrap_err();
+        assert!(err.contains("только розетки и светильники"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn thermometer_offline_after_threshold() {
+        let addr = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let thermometer = SmartThermometer::new_with_connection(
+            String::from("Термометр"),
+            20.0,
+            ConnectionType::udp(addr.ip(), addr.port()).with_polling(PollingSettings {
+                offline_threshold: Duration::from_millis(100),
+                ..PollingSettings::default()
+            }),
+        );
+        let device = SmartDeviceType::from(thermometer.clone());
+
+        device.connect().await.unwrap();
+        assert_eq!(
+            thermometer.value.read().await.connection_state,
+            ConnectionState::Connecting
+        );
+
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while thermometer.value.read().await.connection_state != ConnectionState::Offline {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        device.disconnect().await;
+    }
+
+    #[tokio::test]
+    async fn thermometer_stays_online_after_broken_datagram() {
+        let addr = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let thermometer = SmartThermometer::new_with_connection(
+            String::from("Термометр"),
+            20.0,
+            ConnectionType::udp(addr.ip(), addr.port()),
+        );
+        let device = SmartDeviceType::from(thermometer.clone());
+        device.connect().await.unwrap();
+
+        let response = DeviceResponse {
+            data: Some(DeviceData::Thermometer(ThermometerData::new(23.5))),
+            info: None,
+            success: true,
+            error: None,
+        };
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender
+            .send_to(
+                &Frame::response(&response).unwrap().with_checksum().encode(),
+                addr,
+            )
+            .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while thermometer.value.read().await.connection_state != ConnectionState::Online {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        // Уход в офлайн решает только offline_threshold, а не одна битая посылка
+        sender.send_to(b"broken", addr).unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(
+            thermometer.value.read().await.connection_state,
+            ConnectionState::Online
+        );
+
+        device.disconnect().await;
+    }
+
+    #[tokio::test]
+    async fn thermometer_reads_info_from_datagram() {
+        let addr = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let thermometer = SmartThermometer::new_with_connection(
+            String::from("Термометр"),
+            20.0,
+            ConnectionType::udp(addr.ip(), addr.port()),
+        );
+        let device = SmartDeviceType::from(thermometer.clone());
+        device.connect().await.unwrap();
+
+        let info = DeviceInfo {
+            serial: String::from("THERM-0001"),
+            model: String::from("SH-THERM-EMU"),
+            firmware: String::from("1.0.0"),
+        };
+        let response = DeviceResponse {
+            data: Some(DeviceData::Thermometer(ThermometerData::new(23.5))),
+            info: Some(info.clone()),
+            success: true,
+            error: None,
+        };
+        let datagram = Frame::response(&response).unwrap().with_checksum().encode();
+        UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .send_to(&datagram, addr)
+            .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while thermometer.value.read().await.temp != 23.5 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(thermometer.get_info().await, Some(info));
+        assert_eq!(thermometer.value.read().await.temp, 23.5);
+
+        device.disconnect().await;
+    }
+
+    #[tokio::test]
+    async fn thermometers_share_port() {
+        let addr = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let thermometer = |name: &str, serial: &str| {
+            SmartThermometer::new_with_connection(
+                name,
+                0.0,
+                ConnectionType::udp(addr.ip(), addr.port())
+                    .with_route(UdpRoute::Serial(serial.to_string())),
+            )
+        };
+        let kitchen = thermometer("Кухня", "THERM-1");
+        let bedroom = thermometer("Спальня", "THERM-2");
+
+        SmartDeviceType::from(kitchen.clone())
+            .connect()
+            .await
+            .unwrap();
+        SmartDeviceType::from(bedroom.clone())
+            .connect()
+            .await
+            .unwrap();
+
+        let device = UdpSocket::bind("127.0.0.1:0").unwrap();
+        for (serial, temp) in [("THERM-1", 21.0), ("THERM-2", 18.0)] {
+            let response = DeviceResponse {
+                data: Some(DeviceData::Thermometer(ThermometerData::new(temp))),
+                info: Some(DeviceInfo {
+                    serial: serial.to_string(),
+                    ..DeviceInfo::default()
+                }),
+                success: true,
+                error: None,
+            };
+            let datagram = Frame::response(&response).unwrap().encode();
+            device.send_to(&datagram, addr).unwrap();
+        }
+
+        tokio::time::timeout(Duration::from_secs(3), async {
+            while kitchen.value.read().await.temp != 21.0 || bedroom.value.read().await.temp != 18.0
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        // Порт закрывается только после отключения последнего термометра
+        SmartDeviceType::from(kitchen).disconnect().await;
+        assert!(UdpSocket::bind(addr).is_err());
+        SmartDeviceType::from(bedroom).disconnect().await;
+        assert!(UdpSocket::bind(addr).is_ok());
+    }
 }
@@ -1,9 +1,25 @@
+use crate::automation::{
+    Action, Automation, AutomationJob, Condition, DeviceRef, Metric, Rule, RuleError,
+};
 use crate::errors::SmartHomeErrors;
+use crate::events::{EventBus, SmartHomeEvent, Subscription};
 use crate::id::Id;
-use crate::reporter::Report;
-use crate::{smart_device::SmartDeviceType, smart_room::SmartRoom};
-use std::collections::HashMap;
-use std::fmt::Write;
+use crate::reporter::{
+    DEVICE_REPORT_TIMEOUT, HomeReport, Measurement, Report, ReportNode, RoomReport, sort_by_name,
+};
+use crate::scene::{Scene, SceneError, SceneJob};
+use crate::schedule::{Schedule, ScheduleError, ScheduleJob, Scheduler, Target};
+use crate::subscriber::Subscribe;
+use crate::tariff::{Cost, TariffPlan};
+use crate::thermostat::ThermostatJob;
+use crate::{
+    smart_device::{SmartDevice, SmartDeviceType, SmartSocket, energy::EnergyUsage},
+    smart_room::SmartRoom,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::task::JoinSet;
 
 /// Умный дом
 #[derive(Debug)]
@@ -11,6 +27,12 @@ pub struct SmartHome {
     id: Id,
     name: String,
     rooms: HashMap<String, SmartRoom>,
+    /// Канал событий дома, в него пересылаются события всех комнат
+    events: EventBus,
+    tariff: Option<TariffPlan>,
+    automation: Automation,
+    scheduler: Scheduler,
+    scenes: BTreeMap<String, Scene>,
 }
 
 impl SmartHome {
@@ -21,12 +43,23 @@ impl SmartHome {
             id: Id::from_string(&name),
             name,
             rooms: HashMap::new(),
+            events: EventBus::new(),
+            tariff: None,
+            automation: Automation::default(),
+            scheduler: Scheduler::default(),
+            scenes: BTreeMap::new(),
         }
     }
 
     /// Создать дом с комнатами
     pub fn new_with_rooms(name: impl Into<String>, rooms: &[SmartRoom]) -> Self {
         let name = name.into();
+        let events = EventBus::new();
+
+        for room in rooms {
+            room.events().attach(&events);
+        }
+
         Self {
             id: Id::from_string(&name),
             name,
@@ -35,6 +68,11 @@ impl SmartHome {
                     .iter()
                     .map(|room| (room.get_id().to_string(), room.clone())),
             ),
+            events,
+            tariff: None,
+            automation: Automation::default(),
+            scheduler: Scheduler::default(),
+            scenes: BTreeMap::new(),
         }
     }
 
@@ -47,6 +85,302 @@ impl SmartHome {
         &self.name
     }
 
+    /// Тариф дома, без него стоимость энергии не рассчитывается
+    pub fn tariff(&self) -> Option<&TariffPlan> {
+        self.tariff.as_ref()
+    }
+
+    /// Задать или снять тариф. Описание тарифа проверяет вызывающий, см. [`TariffPlan::validate`]
+    pub fn set_tariff(&mut self, tariff: Option<TariffPlan>) {
+        self.tariff = tariff;
+    }
+
+    /// Правила автоматизации и журнал их срабатываний
+    pub fn automation(&self) -> &Automation {
+        &self.automation
+    }
+
+    /// Проверить правило и его ссылки: устройства есть в доме,
+    /// измеряют нужную величину, а действия и условия на розетки указывают на розетки
+    pub fn check_rule(&self, rule: &Rule) -> Result<(), RuleError> {
+        rule.validate()?;
+
+        let device = |device: &DeviceRef| {
+            self.get_device(
+                &Id::with_inner(&device.room_id),
+                &Id::with_inner(&device.device_id),
+            )
+            .map_err(|_| RuleError(format!("устройство {device} не найдено")))
+        };
+        let measures = |device: &SmartDeviceType, metric: Metric| {
+            if matches!(
+                (metric, device),
+                (Metric::Temperature, SmartDeviceType::Thermometer(_))
+                    | (Metric::Power, SmartDeviceType::Socket(_))
+            ) {
+                Ok(())
+            } else {
+                Err(RuleError(format!(
+                    "{metric} не измеряется устройством {}",
+                    device.get_name()
+                )))
+            }
+        };
+        let socket = |device: &SmartDeviceType| match device {
+            SmartDeviceType::Socket(_) => Ok(()),
+            _ => Err(RuleError(format!(
+                "устройство {} не розетка",
+                device.get_name()
+            ))),
+        };
+
+        measures(device(&rule.trigger.device)?, rule.trigger.metric)?;
+
+        for condition in &rule.conditions {
+            match condition {
+                Condition::Reading {
+                    device: d, metric, ..
+                } => measures(device(d)?, *metric)?,
+                Condition::Socket { device: d, .. } => socket(device(d)?)?,
+            }
+        }
+
+        for action in &rule.actions {
+            socket(device(&action.device)?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Проверить правила по текущим показаниям устройств.
+    ///
+    /// Вызывается при изменении показаний и периодически. Показания подключаемых
+    /// устройств не на связи не учитываются. Возвращает задания сработавших правил
+    pub async fn automate_at(&self, now: u64) -> Vec<AutomationJob> {
+        let mut readings = HashMap::new();
+
+        for device_ref in self.automation.watched_devices() {
+            let device = match self.get_device(
+                &Id::with_inner(&device_ref.room_id),
+                &Id::with_inner(&device_ref.device_id),
+            ) {
+                Ok(device) => device,
+                Err(_) => continue,
+            };
+
+            let data = device.get_data().await;
+            if device.get_connection().is_none() || data.is_online() {
+                readings.insert(device_ref, data);
+            }
+        }
+
+        self.automation
+            .evaluate_at(now, &readings)
+            .into_iter()
+            .map(|firing| {
+                let sockets = firing
+                    .actions
+                    .iter()
+                    .map(|action| self.action_socket(&action.device))
+                    .collect();
+
+                AutomationJob {
+                    firing,
+                    sockets,
+                    automation: self.automation.clone(),
+                }
+            })
+            .collect()
+    }
+
+    /// Проверить правила по текущим показаниям в текущий момент
+    pub async fn automate(&self) -> Vec<AutomationJob> {
+        self.automate_at(chrono::Utc::now().timestamp_millis() as u64)
+            .await
+    }
+
+    /// Проверить термостаты комнат в момент `now`, задания - для розеток, которые нужно переключить
+    pub async fn regulate_at(&self, now: u64) -> Vec<ThermostatJob> {
+        let mut jobs = vec![];
+
+        for room in self.rooms.values() {
+            jobs.extend(room.thermostat_job_at(now).await);
+        }
+
+        jobs
+    }
+
+    /// Проверить термостаты комнат в текущий момент
+    pub async fn regulate(&self) -> Vec<ThermostatJob> {
+        self.regulate_at(chrono::Utc::now().timestamp_millis() as u64)
+            .await
+    }
+
+    /// Розетка для действия или причина, по которой ее нет
+    fn action_socket(&self, device: &DeviceRef) -> Result<SmartSocket, String> {
+        match self.get_device(
+            &Id::with_inner(&device.room_id),
+            &Id::with_inner(&device.device_id),
+        ) {
+            Ok(SmartDeviceType::Socket(socket)) => Ok(socket.clone()),
+            Ok(device) => Err(format!("{} не розетка", device.get_name())),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    /// Расписания команд розеткам
+    pub fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    /// Проверить расписание и его ссылки: комнаты есть в доме, а отдельные устройства - розетки
+    pub fn check_schedule(&self, schedule: &Schedule) -> Result<(), ScheduleError> {
+        schedule.validate()?;
+
+        for action in &schedule.actions {
+            match &action.target {
+                Target::Home => {}
+                Target::Room { room_id } => {
+                    if self.get_room(&Id::with_inner(room_id)).is_none() {
+                        return Err(ScheduleError(format!("комната {room_id} не найдена")));
+                    }
+                }
+                Target::Socket { device } => match self.get_device(
+                    &Id::with_inner(&device.room_id),
+                    &Id::with_inner(&device.device_id),
+                ) {
+                    Ok(SmartDeviceType::Socket(_)) => {}
+                    Ok(d) => {
+                        return Err(ScheduleError(format!(
+                            "устройство {} не розетка",
+                            d.get_name()
+                        )));
+                    }
+                    Err(_) => {
+                        return Err(ScheduleError(format!("устройство {device} не найдено")));
+                    }
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Задания расписаний, которым пора выполниться в момент `now`.
+    ///
+    /// Команды комнатам и дому разворачиваются по розеткам, которые есть на момент запуска
+    pub fn schedule_at(&self, now: u64) -> Vec<ScheduleJob> {
+        self.scheduler
+            .due_at(now)
+            .into_iter()
+            .map(|run| {
+                let mut actions = vec![];
+
+                for action in &run.actions {
+                    let devices = match &action.target {
+                        Target::Home => Self::socket_refs(self.rooms.values()),
+                        Target::Room { room_id } => {
+                            Self::socket_refs(self.get_room(&Id::with_inner(room_id)))
+                        }
+                        Target::Socket { device } => vec![device.clone()],
+                    };
+
+                    actions.extend(devices.into_iter().map(|device| Action {
+                        device,
+                        command: action.command,
+                    }));
+                }
+
+                let sockets = actions
+                    .iter()
+                    .map(|action| self.action_socket(&action.device))
+                    .collect();
+
+                ScheduleJob {
+                    run,
+                    actions,
+                    sockets,
+                }
+            })
+            .collect()
+    }
+
+    /// Сцены, упорядоченные по имени
+    pub fn scenes(&self) -> Vec<&Scene> {
+        let mut scenes: Vec<&Scene> = self.scenes.values().collect();
+        scenes.sort_by(|a, b| (&a.name, &a.id).cmp(&(&b.name, &b.id)));
+        scenes
+    }
+
+    pub fn scene(&self, scene_id: &str) -> Option<&Scene> {
+        self.scenes.get(scene_id)
+    }
+
+    /// Добавить или заменить сцену. Ссылки проверяет вызывающий, см. [`SmartHome::check_scene`]
+    pub fn put_scene(&mut self, scene: Scene) {
+        self.scenes.insert(scene.id.clone(), scene);
+    }
+
+    pub fn remove_scene(&mut self, scene_id: &str) -> Option<Scene> {
+        self.scenes.remove(scene_id)
+    }
+
+    /// Проверить сцену и ее ссылки: все устройства есть в доме и являются розетками
+    pub fn check_scene(&self, scene: &Scene) -> Result<(), SceneError> {
+        scene.validate()?;
+
+        for state in &scene.states {
+            match self.get_device(
+                &Id::with_inner(&state.device.room_id),
+                &Id::with_inner(&state.device.device_id),
+            ) {
+                Ok(SmartDeviceType::Socket(_)) => {}
+                Ok(device) => {
+                    return Err(SceneError(format!(
+                        "устройство {} не розетка",
+                        device.get_name()
+                    )));
+                }
+                Err(_) => {
+                    return Err(SceneError(format!(
+                        "устройство {} не найдено",
+                        state.device
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Задание для применения сцены, `None` - сцены нет.
+    /// Устройства, удаленные после создания сцены, попадают в результат с ошибкой
+    pub fn scene_job(&self, scene_id: &str) -> Option<SceneJob> {
+        let scene = self.scenes.get(scene_id)?.clone();
+        let sockets = scene
+            .states
+            .iter()
+            .map(|state| self.action_socket(&state.device))
+            .collect();
+
+        Some(SceneJob { scene, sockets })
+    }
+
+    /// Розетки комнат, упорядоченные по комнате и устройству
+    fn socket_refs<'a>(rooms: impl IntoIterator<Item = &'a SmartRoom>) -> Vec<DeviceRef> {
+        let mut devices: Vec<DeviceRef> = rooms
+            .into_iter()
+            .flat_map(|room| {
+                room.get_devices()
+                    .values()
+                    .filter(|device| matches!(device, SmartDeviceType::Socket(_)))
+                    .map(|device| DeviceRef::new(room.get_id(), device.get_id()))
+            })
+            .collect();
+        devices.sort();
+        devices
+    }
+
     /// Получить ссылку на комнату в доме
     pub fn get_room(&self, id: &Id) -> Option<&SmartRoom> {
         self.rooms.get(&id.to_string())
@@ -60,7 +394,16 @@ impl SmartHome {
     /// Добавить комнату
     pub fn add_room(&mut self, room: SmartRoom) -> Id {
         let room_id = room.get_id().clone();
+        let name = room.get_name().clone();
+
+        room.events().attach(&self.events);
         self.rooms.insert(room_id.to_string(), room);
+
+        self.events.emit(SmartHomeEvent::RoomAdded {
+            room_id: room_id.clone(),
+            name,
+        });
+
         room_id
     }
 
@@ -76,7 +419,29 @@ impl SmartHome {
 
     /// Удалить комнату
     pub fn delete_room(&mut self, id: &Id) -> Option<SmartRoom> {
-        self.rooms.remove(&id.to_string())
+        let room = self.rooms.remove(&id.to_string())?;
+
+        room.events().detach();
+        self.events.emit(SmartHomeEvent::RoomRemoved {
+            room_id: id.clone(),
+        });
+
+        Some(room)
+    }
+
+    /// Подписать обработчик на события дома и всех его комнат
+    pub fn subscribe<S>(&self, subscriber: S) -> Subscription
+    where
+        S: Subscribe + Send + 'static,
+    {
+        self.events.subscribe(subscriber)
+    }
+
+    /// Получатель событий дома и всех его комнат для асинхронного чтения.
+    ///
+    /// Отстающий получатель теряет старые события и получает `RecvError::Lagged`.
+    pub fn listen(&self) -> broadcast::Receiver<SmartHomeEvent> {
+        self.events.listen()
     }
 
     /// Получить устройство
@@ -95,20 +460,105 @@ impl SmartHome {
     }
 }
 
-impl Report for SmartHome {
-    async fn get_status_report(&self) -> String {
-        let name = self.name.clone();
-        let rooms = self.rooms.values().clone();
-        async move {
-            let mut output = format!(r#"Отчет по дому "{}"{}"#, name, "\n");
-
-            for room in rooms {
-                write!(output, "{}", room.get_status_report().await).unwrap();
+impl SmartHome {
+    /// Отчет по дому, комнаты упорядочены по имени, затем по id
+    pub async fn home_report(&self) -> HomeReport {
+        self.home_report_within(DEVICE_REPORT_TIMEOUT).await
+    }
+
+    /// Отчет по дому, все комнаты и устройства опрашиваются одновременно.
+    /// Устройство, не ответившее за `timeout`, попадает в отчет недоступным
+    pub async fn home_report_within(&self, timeout: Duration) -> HomeReport {
+        let mut tasks = JoinSet::new();
+
+        for room in self.rooms.values() {
+            let room = room.clone();
+            tasks.spawn(async move { room.room_report_within(timeout).await });
+        }
+
+        let mut rooms = Vec::with_capacity(self.rooms.len());
+
+        while let Some(report) = tasks.join_next().await {
+            rooms.push(report.expect("сбор отчета комнаты не паникует"));
+        }
+
+        sort_by_name(&mut rooms, |r| (&r.name, &r.id));
+
+        let now = chrono::Utc::now().timestamp_millis() as u64;
+        let cost = self.tariff.as_ref().map(|plan| {
+            for report in &mut rooms {
+                self.fill_costs(plan, report, now);
             }
 
-            output
+            self.energy_cost_at(plan, 0, now, now)
+        });
+
+        HomeReport {
+            id: self.id.to_string(),
+            name: self.name.clone(),
+            rooms,
+            energy: Measurement::kilowatt_hours(self.energy_usage().total_kwh),
+            cost,
+        }
+    }
+
+    /// Дописать в отчет комнаты стоимость энергии ее розеток с начала учета
+    fn fill_costs(&self, plan: &TariffPlan, report: &mut RoomReport, now: u64) {
+        let room = match self.get_room(&Id::with_inner(&report.id)) {
+            Some(room) => room,
+            None => return,
+        };
+
+        for device in &mut report.devices {
+            device.cost = room
+                .get_device(&Id::with_inner(&device.id))
+                .and_then(SmartDeviceType::energy)
+                .map(|meter| plan.meter_cost(meter, 0, now, now));
+        }
+
+        report.cost = Some(room.energy_cost_at(plan, 0, now, now));
+    }
+
+    /// Стоимость энергии розеток дома за `[from, to)` по тарифу дома
+    pub fn energy_cost(&self, from: u64, to: u64) -> Option<Cost> {
+        let now = chrono::Utc::now().timestamp_millis() as u64;
+
+        self.tariff
+            .as_ref()
+            .map(|plan| self.energy_cost_at(plan, from, to, now))
+    }
+
+    /// Стоимость энергии розеток дома за `[from, to)` по тарифу `plan`
+    pub fn energy_cost_at(&self, plan: &TariffPlan, from: u64, to: u64, now: u64) -> Cost {
+        let mut cost = Cost::zero(&plan.currency);
+
+        for room in self.rooms.values() {
+            cost.add(&room.energy_cost_at(plan, from, to, now));
         }
-        .await
+
+        cost
+    }
+
+    /// Потребление розеток дома, текущие интервалы учитываются до момента `now`
+    pub fn energy_usage_at(&self, now: u64) -> EnergyUsage {
+        let mut usage = EnergyUsage::default();
+
+        for room in self.rooms.values() {
+            usage.merge(&room.energy_usage_at(now));
+        }
+
+        usage
+    }
+
+    /// Потребление розеток дома на текущий момент
+    pub fn energy_usage(&self) -> EnergyUsage {
+        self.energy_usage_at(chrono::Utc::now().timestamp_millis() as u64)
+    }
+}
+
+impl Report for SmartHome {
+    async fn report(&self) -> ReportNode {
+        ReportNode::Home(self.home_report().await)
     }
 }
 
@@ -184,4 +634,50 @@ mod tests {
             _ => panic!(),
         }
     }
+
+    #[tokio::test]
+    async fn home_receives_events_of_its_rooms() {
+        use crate::smart_device::SmartSocket;
+
+        let mut home = SmartHome::new_with_rooms("Дом", &[SmartRoom::new("Комната")]);
+        let mut events = home.listen();
+        let room_id = Id::from_string("Комната");
+
+        let room = home.get_room_mut(&room_id).unwrap();
+        let device_id = room.add_device(SmartSocket::new("Розетка", 1000.0, false));
+
+        if let SmartDeviceType::Socket(socket) = room.get_device_mut(&device_id).unwrap() {
+            socket.turn_on().await.unwrap();
+        }
+
+        let mut removed = home.delete_room(&room_id).unwrap();
+        // Удаленная комната больше не пересылает события в дом
+        if let SmartDeviceType::Socket(socket) = removed.get_device_mut(&device_id).unwrap() {
+            socket.turn_off().await.unwrap();
+        }
+
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            SmartHomeEvent::DeviceAdded { .. }
+        ));
+        match events.recv().await.unwrap() {
+            SmartHomeEvent::StateChanged {
+                room_id: changed_room,
+                device_id: changed_device,
+                old,
+                new,
+            } => {
+                assert_eq!(changed_room, room_id);
+                assert_eq!(changed_device, device_id);
+                assert!(!old.as_socket().is_on);
+                assert!(new.as_socket().is_on);
+            }
+            event => panic!("{event:?}"),
+        }
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            SmartHomeEvent::RoomRemoved { .. }
+        ));
+        assert!(events.try_recv().is_err());
+    }
 }
@@ -1,38 +1,30 @@
 use core::fmt;
-use std::fmt::Write;
-use std::{collections::HashMap, vec};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+use tokio::task::JoinSet;
 
 use crate::id::Id;
 use crate::{
-    reporter::Report,
-    smart_device::{SmartDevice, SmartDeviceType},
+    events::{EventBus, SmartHomeEvent, Subscription},
+    reporter::{DEVICE_REPORT_TIMEOUT, Measurement, Report, ReportNode, RoomReport, sort_by_name},
+    smart_device::{SmartDevice, SmartDeviceType, energy::EnergyUsage},
     subscriber::Subscribe,
+    tariff::{Cost, TariffPlan},
+    thermostat::{Thermostat, ThermostatConfig, ThermostatError, ThermostatJob},
 };
 
+/// Умная комната.
+///
+/// Клоны комнаты разделяют канал событий и подписчиков.
+#[derive(Clone)]
 pub struct SmartRoom {
     id: Id,
     name: String,
     devices: HashMap<String, SmartDeviceType>,
-    subscribers: Vec<Box<dyn Subscribe + Send + Sync>>,
-}
-
-impl Subscribe for SmartRoom {
-    fn on_event(&mut self, name: String) {
-        for subscriber in self.subscribers.iter_mut() {
-            subscriber.on_event(name.clone());
-        }
-    }
-}
-
-impl Clone for SmartRoom {
-    fn clone(&self) -> Self {
-        Self {
-            id: self.id.clone(),
-            name: self.name.clone(),
-            devices: self.devices.clone(),
-            subscribers: vec![],
-        }
-    }
+    events: EventBus,
+    thermostat: Option<Thermostat>,
 }
 
 impl fmt::Debug for SmartRoom {
@@ -40,6 +32,7 @@ impl fmt::Debug for SmartRoom {
         f.debug_struct("SmartRoom")
             .field("name", &self.name)
             .field("devices", &self.devices)
+            .field("thermostat", &self.thermostat)
             .finish()
     }
 }
@@ -52,20 +45,29 @@ impl SmartRoom {
             id: Id::from_string(&name),
             name,
             devices: HashMap::new(),
-            subscribers: Vec::new(),
+            events: EventBus::new(),
+            thermostat: None,
         }
     }
 
     /// Создать комнату с устройствами
     pub fn new_with_devices(name: impl Into<String>, devices: &[SmartDeviceType]) -> Self {
         let name = name.into();
+        let id = Id::from_string(&name);
+        let events = EventBus::new();
+
+        for device in devices {
+            device.events().attach(&events, &id);
+        }
+
         Self {
-            id: Id::from_string(&name),
+            id,
             name,
             devices: HashMap::from_iter(
                 devices.iter().map(|d| (d.get_id().to_string(), d.clone())),
             ),
-            subscribers: Vec::new(),
+            events,
+            thermostat: None,
         }
     }
 
@@ -99,51 +101,205 @@ impl SmartRoom {
         &mut self.devices
     }
 
+    /// Добавить устройство в комнату
     pub fn add_device<T>(&mut self, device: T) -> Id
     where
         T: SmartDevice + Into<SmartDeviceType>,
     {
-        let device_name = device.get_name().clone();
+        let name = device.get_name().clone();
         let id = device.get_id().clone();
+        let device = device.into();
 
-        self.devices.insert(id.to_string(), device.into());
+        device.events().attach(&self.events, &self.id);
+        self.devices.insert(id.to_string(), device);
 
-        for subscriber in &mut self.subscribers {
-            subscriber.on_event(device_name.clone());
-        }
+        self.events.emit(SmartHomeEvent::DeviceAdded {
+            room_id: self.id.clone(),
+            device_id: id.clone(),
+            name,
+        });
 
         id
     }
 
     /// Удалить устройство из комнаты
     pub fn delete_device(&mut self, id: &Id) -> Option<SmartDeviceType> {
-        self.devices.remove(&id.to_string())
+        let device = self.devices.remove(&id.to_string())?;
+
+        device.events().detach();
+        self.events.emit(SmartHomeEvent::DeviceRemoved {
+            room_id: self.id.clone(),
+            device_id: id.clone(),
+        });
+
+        Some(device)
     }
 
-    /// Подписаться на уведомления
-    pub fn subscribe<S>(&mut self, subscriber: S)
+    /// Подписаться на события комнаты
+    pub fn subscribe<S>(&self, subscriber: S) -> Subscription
     where
-        S: Subscribe + Send + Sync + 'static,
+        S: Subscribe + Send + 'static,
     {
-        self.subscribers.push(Box::new(subscriber));
+        self.events.subscribe(subscriber)
+    }
+
+    /// Получатель событий комнаты для асинхронного чтения
+    pub fn listen(&self) -> broadcast::Receiver<SmartHomeEvent> {
+        self.events.listen()
+    }
+
+    /// Канал событий комнаты
+    pub(crate) fn events(&self) -> &EventBus {
+        &self.events
     }
 }
 
-impl Report for SmartRoom {
-    /// Вывести отчет о состоянии комнаты
-    async fn get_status_report(&self) -> String {
-        let name = self.name.clone();
-        let devices = self.devices.clone();
-        async move {
-            let mut output = format!(r#"Отчет по комнате "{}"{}"#, name, "\n");
-
-            for (i, device) in devices.values().enumerate() {
-                writeln!(output, "{}. {}", i + 1, device.get_status_report().await).unwrap();
+impl SmartRoom {
+    pub fn thermostat(&self) -> Option<&Thermostat> {
+        self.thermostat.as_ref()
+    }
+
+    /// Установить, заменить или убрать термостат. Состояние прежнего термостата сбрасывается.
+    /// Ссылки на устройства проверяет вызывающий, см. [`SmartRoom::check_thermostat`]
+    pub fn set_thermostat(&mut self, config: Option<ThermostatConfig>) {
+        self.thermostat = config.map(Thermostat::new);
+    }
+
+    /// Проверить настройки термостата: термометр и розетка есть в этой комнате
+    pub fn check_thermostat(&self, config: &ThermostatConfig) -> Result<(), ThermostatError> {
+        config.validate()?;
+
+        match self.devices.get(&config.thermometer_id) {
+            Some(SmartDeviceType::Thermometer(_)) => {}
+            Some(device) => {
+                return Err(ThermostatError(format!(
+                    "устройство {} не термометр",
+                    device.get_name()
+                )));
+            }
+            None => {
+                return Err(ThermostatError(format!(
+                    "термометр {} не найден",
+                    config.thermometer_id
+                )));
             }
+        }
 
-            output
+        match self.devices.get(&config.socket_id) {
+            Some(SmartDeviceType::Socket(_)) => Ok(()),
+            Some(device) => Err(ThermostatError(format!(
+                "устройство {} не розетка",
+                device.get_name()
+            ))),
+            None => Err(ThermostatError(format!(
+                "розетка {} не найдена",
+                config.socket_id
+            ))),
         }
-        .await
+    }
+
+    /// Проверить термостат по текущим показаниям в момент `now`.
+    ///
+    /// Показания термометра не на связи не учитываются, и розетка выключается.
+    /// Возвращает задание, если розетку нужно переключить
+    pub async fn thermostat_job_at(&self, now: u64) -> Option<ThermostatJob> {
+        let thermostat = self.thermostat.as_ref()?;
+        let config = thermostat.config();
+
+        let temperature = match self.devices.get(&config.thermometer_id) {
+            Some(SmartDeviceType::Thermometer(thermometer)) => {
+                let data = thermometer.get_data().await;
+                (thermometer.get_connection().is_none() || data.is_online).then_some(data.temp)
+            }
+            _ => None,
+        };
+
+        let socket = match self.devices.get(&config.socket_id) {
+            Some(SmartDeviceType::Socket(socket)) => Some(socket.clone()),
+            _ => None,
+        };
+        let socket_on = match &socket {
+            Some(socket) => Some(socket.is_on().await),
+            None => None,
+        };
+
+        let command = thermostat.decide_at(now, temperature, socket_on)?;
+
+        Some(ThermostatJob {
+            thermostat: thermostat.clone(),
+            socket: socket?,
+            command,
+            at: now,
+        })
+    }
+}
+
+impl SmartRoom {
+    /// Отчет по комнате, устройства упорядочены по имени, затем по id
+    pub async fn room_report(&self) -> RoomReport {
+        self.room_report_within(DEVICE_REPORT_TIMEOUT).await
+    }
+
+    /// Отчет по комнате, показания устройств собираются одновременно.
+    /// Устройство, не ответившее за `timeout`, попадает в отчет недоступным
+    pub async fn room_report_within(&self, timeout: Duration) -> RoomReport {
+        let mut tasks = JoinSet::new();
+
+        // Клоны разделяют состояние с оригиналами
+        for device in self.devices.values() {
+            let device = device.clone();
+            tasks.spawn(async move { device.device_report_within(timeout).await });
+        }
+
+        let mut devices = Vec::with_capacity(self.devices.len());
+
+        while let Some(report) = tasks.join_next().await {
+            devices.push(report.expect("сбор отчета устройства не паникует"));
+        }
+
+        sort_by_name(&mut devices, |d| (&d.name, &d.id));
+
+        RoomReport {
+            id: self.id.to_string(),
+            name: self.name.clone(),
+            devices,
+            energy: Measurement::kilowatt_hours(self.energy_usage().total_kwh),
+            cost: None,
+            thermostat: self.thermostat.as_ref().map(Thermostat::report),
+        }
+    }
+
+    /// Потребление розеток комнаты, текущие интервалы учитываются до момента `now`
+    pub fn energy_usage_at(&self, now: u64) -> EnergyUsage {
+        let mut usage = EnergyUsage::default();
+
+        for meter in self.devices.values().filter_map(SmartDeviceType::energy) {
+            usage.merge(&meter.usage_at(now));
+        }
+
+        usage
+    }
+
+    /// Стоимость энергии розеток комнаты за `[from, to)` по тарифу `plan`
+    pub fn energy_cost_at(&self, plan: &TariffPlan, from: u64, to: u64, now: u64) -> Cost {
+        let mut cost = Cost::zero(&plan.currency);
+
+        for meter in self.devices.values().filter_map(SmartDeviceType::energy) {
+            cost.add(&plan.meter_cost(meter, from, to, now));
+        }
+
+        cost
+    }
+
+    /// Потребление розеток комнаты на текущий момент
+    pub fn energy_usage(&self) -> EnergyUsage {
+        self.energy_usage_at(chrono::Utc::now().timestamp_millis() as u64)
+    }
+}
+
+impl Report for SmartRoom {
+    async fn report(&self) -> ReportNode {
+        ReportNode::Room(self.room_report().await)
     }
 }
 
@@ -196,4 +352,105 @@ mod tests {
             "Термометр: 25 C°"
         );
     }
+
+    #[test]
+    fn clone_keeps_subscribers() {
+        use std::sync::{Arc, Mutex};
+
+        let room = SmartRoom::new("Комната");
+        let added = Arc::new(Mutex::new(Vec::new()));
+
+        room.subscribe({
+            let added = added.clone();
+            move |event: &SmartHomeEvent| {
+                if let SmartHomeEvent::DeviceAdded { name, .. } = event {
+                    added.lock().unwrap().push(name.clone());
+                }
+            }
+        });
+
+        let mut copy = room.clone();
+        let id = copy.add_device(SmartSocket::new("Розетка", 1000.0, true));
+
+        assert_eq!(*added.lock().unwrap(), vec!["Розетка".to_string()]);
+        assert!(copy.delete_device(&id).is_some());
+        assert!(copy.delete_device(&id).is_none());
+    }
+
+    #[tokio::test]
+    async fn removed_device_stops_reporting() {
+        let mut room = SmartRoom::new("Комната");
+        let mut events = room.listen();
+
+        let id = room.add_device(SmartSocket::new("Розетка", 1000.0, false));
+        let SmartDeviceType::Socket(mut socket) = room.delete_device(&id).unwrap() else {
+            panic!()
+        };
+        socket.turn_on().await.unwrap();
+
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            SmartHomeEvent::DeviceAdded { .. }
+        ));
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            SmartHomeEvent::DeviceRemoved { .. }
+        ));
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn locked_devices_are_reported_unavailable_concurrently() {
+        let mut room = SmartRoom::new("Комната");
+        let thermometer = SmartThermometer::new("Термометр", 24.0);
+        let socket = SmartSocket::new("Розетка", 1000.0, true);
+        room.add_device(thermometer.clone());
+        room.add_device(socket.clone());
+        room.add_device(SmartSocket::new("Чайник", 2000.0, true));
+
+        // Мониторинг застрял посреди обновления
+        let _therm_lock = thermometer.value.write().await;
+        let _socket_lock = socket.value.write().await;
+
+        let timeout = std::time::Duration::from_millis(200);
+        let started = tokio::time::Instant::now();
+        let report = room.room_report_within(timeout).await;
+
+        // Устройства ждут одновременно, а не по очереди
+        assert!(started.elapsed() < timeout * 2, "{:?}", started.elapsed());
+
+        let values: Vec<(&str, bool)> = report
+            .devices
+            .iter()
+            .map(|d| (d.name.as_str(), d.value.is_some()))
+            .collect();
+        assert_eq!(
+            values,
+            vec![("Розетка", false), ("Термометр", false), ("Чайник", true)]
+        );
+    }
+
+    #[tokio::test]
+    async fn energy_is_rolled_up_from_sockets() {
+        let kettle = SmartSocket::new("Чайник", 2000.0, false);
+        let lamp = SmartSocket::new("Лампа", 60.0, false);
+        let room = SmartRoom::new_with_devices(
+            "Кухня",
+            &[
+                kettle.clone().into(),
+                lamp.clone().into(),
+                SmartThermometer::new("Термометр", 24.0).into(),
+            ],
+        );
+
+        let hour = 60 * 60 * 1000;
+        kettle.energy.observe_at(0, 2000.0);
+        kettle.energy.observe_at(hour / 2, 0.0);
+        lamp.energy.observe_at(0, 60.0);
+
+        let usage = room.energy_usage_at(10 * hour);
+        assert!((usage.total_kwh - 1.6).abs() < 1e-9, "{}", usage.total_kwh);
+        assert_eq!(usage.daily.len(), 1);
+        assert_eq!(usage.daily[0].kwh, usage.total_kwh);
+    }
 }
@@ -1,12 +1,15 @@
+use crate::events::SmartHomeEvent;
+
+/// Обработчик событий умного дома
 pub trait Subscribe {
-    fn on_event(&mut self, name: String);
+    fn on_event(&mut self, event: &SmartHomeEvent);
 }
 
 impl<F> Subscribe for F
 where
-    F: FnMut(String),
+    F: FnMut(&SmartHomeEvent),
 {
-    fn on_event(&mut self, name: String) {
-        self(name);
+    fn on_event(&mut self, event: &SmartHomeEvent) {
+        self(event);
     }
 }
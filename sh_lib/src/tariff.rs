@@ -0,0 +1,438 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::smart_device::energy::{EnergyMeter, EnergyProfile, PowerSegment};
+
+/// Длительность суток, мс
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+/// Минут в сутках
+const DAY_MINUTES: u32 = 24 * 60;
+
+/// Наибольшее смещение часового пояса от UTC, минут
+const MAX_UTC_OFFSET_MINUTES: i32 = 14 * 60;
+
+/// Зона суток `[start, end)` со своей ценой.
+///
+/// Время задается в минутах от полуночи по местному времени;
+/// зона с `start > end` переходит через полночь, с `start == end` - занимает все сутки.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TariffBand {
+    pub start_minute: u32,
+    pub end_minute: u32,
+    /// Цена кВт·ч
+    pub price: f64,
+}
+
+impl TariffBand {
+    fn contains(&self, minute: u32) -> bool {
+        match self.start_minute.cmp(&self.end_minute) {
+            std::cmp::Ordering::Less => (self.start_minute..self.end_minute).contains(&minute),
+            std::cmp::Ordering::Greater => minute >= self.start_minute || minute < self.end_minute,
+            std::cmp::Ordering::Equal => true,
+        }
+    }
+}
+
+/// Способ расчета цены кВт·ч
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Tariff {
+    /// Одна цена в любое время
+    Flat { price: f64 },
+    /// Ночная цена в ночной зоне, дневная - в остальное время
+    DayNight {
+        day_price: f64,
+        night_price: f64,
+        night_start_minute: u32,
+        night_end_minute: u32,
+    },
+    /// Цена первой подходящей зоны, вне зон - базовая цена
+    TimeOfUse {
+        base_price: f64,
+        bands: Vec<TariffBand>,
+    },
+}
+
+/// Тариф дома
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TariffPlan {
+    /// Код валюты ISO 4217, например "RUB"
+    pub currency: String,
+    /// Смещение местного времени от UTC в минутах, по нему определяются зоны суток
+    #[serde(default)]
+    pub utc_offset_minutes: i32,
+    #[serde(flatten)]
+    pub tariff: Tariff,
+}
+
+/// Ошибка в описании тарифа
+#[derive(Debug, Clone, PartialEq)]
+pub struct TariffError(pub String);
+
+impl fmt::Display for TariffError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Некорректный тариф: {}", self.0)
+    }
+}
+
+impl std::error::Error for TariffError {}
+
+/// Стоимость потребленной энергии
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Cost {
+    pub kwh: f64,
+    pub amount: f64,
+    pub currency: String,
+    /// Часть интервала старше хранимой истории мощности не учтена, стоимость занижена
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub partial: bool,
+}
+
+impl Cost {
+    pub fn zero(currency: impl Into<String>) -> Self {
+        Self {
+            kwh: 0.0,
+            amount: 0.0,
+            currency: currency.into(),
+            partial: false,
+        }
+    }
+
+    /// Прибавить стоимость в той же валюте
+    pub fn add(&mut self, other: &Cost) {
+        self.kwh += other.kwh;
+        self.amount += other.amount;
+        self.partial |= other.partial;
+    }
+}
+
+impl fmt::Display for Cost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.2} {}", self.amount, self.currency)?;
+
+        if self.partial {
+            write!(f, " (неполная)")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl TariffPlan {
+    pub fn new(currency: impl Into<String>, tariff: Tariff) -> Self {
+        Self {
+            currency: currency.into(),
+            utc_offset_minutes: 0,
+            tariff,
+        }
+    }
+
+    /// Задать смещение местного времени от UTC в минутах
+    pub fn with_utc_offset(mut self, minutes: i32) -> Self {
+        self.utc_offset_minutes = minutes;
+        self
+    }
+
+    /// Проверить валюту, цены, границы зон и смещение
+    pub fn validate(&self) -> Result<(), TariffError> {
+        let currency_ok =
+            self.currency.len() == 3 && self.currency.chars().all(|c| c.is_ascii_uppercase());
+        if !currency_ok {
+            return Err(TariffError(format!(
+                "код валюты \"{}\" должен состоять из трех заглавных латинских букв",
+                self.currency
+            )));
+        }
+
+        if self.utc_offset_minutes.abs() > MAX_UTC_OFFSET_MINUTES {
+            return Err(TariffError(format!(
+                "смещение от UTC {} мин вне диапазона ±{}",
+                self.utc_offset_minutes, MAX_UTC_OFFSET_MINUTES
+            )));
+        }
+
+        let bands = self.bands();
+        let mut prices = bands
+            .iter()
+            .map(|band| band.price)
+            .chain([self.base_price()]);
+
+        if prices.any(|price| !price.is_finite() || price < 0.0) {
+            return Err(TariffError(
+                "цена должна быть неотрицательным числом".to_string(),
+            ));
+        }
+
+        if bands
+            .iter()
+            .any(|band| band.start_minute >= DAY_MINUTES || band.end_minute >= DAY_MINUTES)
+        {
+            return Err(TariffError(format!(
+                "граница зоны должна быть меньше {} мин",
+                DAY_MINUTES
+            )));
+        }
+
+        if matches!(&self.tariff, Tariff::TimeOfUse { bands, .. } if bands.is_empty()) {
+            return Err(TariffError("не задано ни одной зоны".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Цена вне зон
+    fn base_price(&self) -> f64 {
+        match &self.tariff {
+            Tariff::Flat { price } => *price,
+            Tariff::DayNight { day_price, .. } => *day_price,
+            Tariff::TimeOfUse { base_price, .. } => *base_price,
+        }
+    }
+
+    /// Зоны в порядке приоритета
+    fn bands(&self) -> Vec<TariffBand> {
+        match &self.tariff {
+            Tariff::Flat { .. } => vec![],
+            Tariff::DayNight {
+                night_price,
+                night_start_minute,
+                night_end_minute,
+                ..
+            } => vec![TariffBand {
+                start_minute: *night_start_minute,
+                end_minute: *night_end_minute,
+                price: *night_price,
+            }],
+            Tariff::TimeOfUse { bands, .. } => bands.clone(),
+        }
+    }
+
+    /// Миллисекунды от местной полуночи
+    fn local_ms(&self, timestamp: u64) -> i64 {
+        (timestamp as i64 + self.utc_offset_minutes as i64 * 60_000).rem_euclid(DAY_MS)
+    }
+
+    fn price_at_local(&self, bands: &[TariffBand], local_ms: i64) -> f64 {
+        let minute = (local_ms / 60_000) as u32;
+
+        bands
+            .iter()
+            .find(|band| band.contains(minute))
+            .map(|band| band.price)
+            .unwrap_or_else(|| self.base_price())
+    }
+
+    /// Цена кВт·ч в момент `timestamp` (мс от начала эпохи)
+    pub fn price_at(&self, timestamp: u64) -> f64 {
+        self.price_at_local(&self.bands(), self.local_ms(timestamp))
+    }
+
+    /// Стоимость интервалов мощности
+    pub fn cost(&self, segments: &[PowerSegment]) -> Cost {
+        let mut profile = EnergyProfile::default();
+
+        for segment in segments {
+            profile.add(segment);
+        }
+
+        self.profile_cost(&profile)
+    }
+
+    /// Стоимость энергии, разложенной по минутам суток UTC.
+    ///
+    /// Энергия сначала суммируется по ценам, чтобы не копить ошибку округления
+    /// по каждой минуте
+    pub fn profile_cost(&self, profile: &EnergyProfile) -> Cost {
+        let bands = self.bands();
+        let mut by_price: Vec<(f64, f64)> = vec![];
+
+        for (minute, watt_ms) in profile.watt_ms.iter().enumerate() {
+            if *watt_ms == 0.0 {
+                continue;
+            }
+
+            let price = self.price_at_local(&bands, self.local_ms(minute as u64 * 60_000));
+
+            match by_price.iter_mut().find(|(known, _)| *known == price) {
+                Some((_, total)) => *total += watt_ms,
+                None => by_price.push((price, *watt_ms)),
+            }
+        }
+
+        let mut cost = Cost::zero(&self.currency);
+        cost.partial = profile.partial;
+
+        for (price, watt_ms) in by_price {
+            let kwh = watt_ms / 3_600_000_000.0;
+
+            cost.kwh += kwh;
+            cost.amount += kwh * price;
+        }
+
+        cost
+    }
+
+    /// Стоимость энергии, потребленной по счетчику за `[from, to)`;
+    /// текущий интервал счетчика учитывается до момента `now`
+    pub fn meter_cost(&self, meter: &EnergyMeter, from: u64, to: u64, now: u64) -> Cost {
+        self.profile_cost(&meter.profile_at(from, to, now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: u64 = 60 * 60 * 1000;
+
+    /// 2026-01-31 00:00 UTC
+    const JAN_31: u64 = 1_769_817_600_000;
+
+    fn kilowatt(start: u64, end: u64) -> PowerSegment {
+        PowerSegment {
+            start,
+            end,
+            power: 1000.0,
+        }
+    }
+
+    fn day_night() -> TariffPlan {
+        TariffPlan::new(
+            "RUB",
+            Tariff::DayNight {
+                day_price: 8.0,
+                night_price: 3.0,
+                night_start_minute: 23 * 60,
+                night_end_minute: 7 * 60,
+            },
+        )
+    }
+
+    #[test]
+    fn flat_cost_is_energy_times_price() {
+        let plan = TariffPlan::new("RUB", Tariff::Flat { price: 6.5 });
+        let cost = plan.cost(&[kilowatt(JAN_31, JAN_31 + 2 * HOUR)]);
+
+        assert_eq!(cost.kwh, 2.0);
+        assert_eq!(cost.amount, 13.0);
+        assert_eq!(cost.to_string(), "13.00 RUB");
+    }
+
+    #[test]
+    fn segment_is_split_at_night_boundaries() {
+        // 22:00-08:00 UTC: 1 ч днем, 8 ч ночью, 1 ч днем
+        let cost = day_night().cost(&[kilowatt(JAN_31 + 22 * HOUR, JAN_31 + 32 * HOUR)]);
+        assert_eq!(cost.kwh, 10.0);
+        assert_eq!(cost.amount, 2.0 * 8.0 + 8.0 * 3.0);
+
+        // Для UTC+3 те же часы приходятся на 01:00-11:00 местного времени
+        let cost = day_night()
+            .with_utc_offset(180)
+            .cost(&[kilowatt(JAN_31 + 22 * HOUR, JAN_31 + 32 * HOUR)]);
+        assert_eq!(cost.amount, 6.0 * 3.0 + 4.0 * 8.0);
+    }
+
+    #[test]
+    fn meter_cost_covers_whole_history() {
+        let meter = EnergyMeter::default();
+        let step = 2000;
+        // Больше интервалов, чем хранит счетчик: мощность меняется на каждом показании
+        let steps = 12_000;
+
+        for i in 0..=steps {
+            meter.observe_at(JAN_31 + i * step, if i % 2 == 0 { 1000.0 } else { 2000.0 });
+        }
+
+        let now = JAN_31 + steps * step;
+        let plan = day_night();
+        let cost = plan.meter_cost(&meter, 0, now, now);
+
+        // 00:00-06:40 ночью, в среднем по 1,5 кВт
+        assert!(!cost.partial);
+        assert!((cost.kwh - meter.usage_at(now).total_kwh).abs() < 1e-9);
+        assert!((cost.kwh - 10.0).abs() < 1e-9, "{}", cost.kwh);
+        assert!((cost.amount - 30.0).abs() < 1e-9, "{}", cost.amount);
+
+        let early = plan.meter_cost(&meter, JAN_31, JAN_31 + HOUR, now);
+        assert!(early.partial);
+        assert!(early.to_string().ends_with("RUB (неполная)"), "{early}");
+    }
+
+    #[test]
+    fn first_matching_band_wins() {
+        let plan = TariffPlan::new(
+            "EUR",
+            Tariff::TimeOfUse {
+                base_price: 0.2,
+                bands: vec![
+                    TariffBand {
+                        start_minute: 17 * 60,
+                        end_minute: 19 * 60,
+                        price: 0.5,
+                    },
+                    TariffBand {
+                        start_minute: 12 * 60,
+                        end_minute: 20 * 60,
+                        price: 0.3,
+                    },
+                ],
+            },
+        );
+
+        assert_eq!(plan.price_at(JAN_31 + 11 * HOUR), 0.2);
+        assert_eq!(plan.price_at(JAN_31 + 12 * HOUR), 0.3);
+        assert_eq!(plan.price_at(JAN_31 + 18 * HOUR), 0.5);
+        assert_eq!(plan.price_at(JAN_31 + 20 * HOUR), 0.2);
+
+        let cost = plan.cost(&[kilowatt(JAN_31 + 16 * HOUR, JAN_31 + 21 * HOUR)]);
+        assert!((cost.amount - (0.3 + 1.0 + 0.3 + 0.2)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn invalid_plans_are_rejected() {
+        assert!(day_night().validate().is_ok());
+        assert!(
+            TariffPlan::new("rub", Tariff::Flat { price: 1.0 })
+                .validate()
+                .is_err()
+        );
+        assert!(
+            TariffPlan::new("RUB", Tariff::Flat { price: -1.0 })
+                .validate()
+                .is_err()
+        );
+        assert!(day_night().with_utc_offset(15 * 60).validate().is_err());
+        assert!(
+            TariffPlan::new(
+                "RUB",
+                Tariff::TimeOfUse {
+                    base_price: 1.0,
+                    bands: vec![],
+                },
+            )
+            .validate()
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn plan_is_described_in_toml() {
+        let plan: TariffPlan = toml::from_str(
+            r#"
+            currency = "RUB"
+            utc_offset_minutes = 180
+            kind = "day_night"
+            day_price = 8.0
+            night_price = 3.0
+            night_start_minute = 1380
+            night_end_minute = 420
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(plan, day_night().with_utc_offset(180));
+    }
+}
@@ -0,0 +1,406 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{automation::SocketCommand, smart_device::SmartSocket};
+
+/// Режим термостата
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThermostatMode {
+    /// Розетка выключена
+    Off,
+    /// Розетка питает обогреватель
+    Heat,
+    /// Розетка питает охладитель
+    Cool,
+}
+
+impl fmt::Display for ThermostatMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThermostatMode::Off => write!(f, "выключен"),
+            ThermostatMode::Heat => write!(f, "нагрев"),
+            ThermostatMode::Cool => write!(f, "охлаждение"),
+        }
+    }
+}
+
+/// Настройки термостата комнаты. Термометр и розетка - устройства той же комнаты.
+///
+/// В режиме нагрева розетка включается, когда температура опускается до
+/// `setpoint - hysteresis`, и выключается, когда поднимается до `setpoint + hysteresis`;
+/// в режиме охлаждения - наоборот.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThermostatConfig {
+    pub thermometer_id: String,
+    pub socket_id: String,
+    pub mode: ThermostatMode,
+    /// Уставка, °C
+    pub setpoint: f32,
+    #[serde(default)]
+    pub hysteresis: f32,
+    /// Сколько розетка остается включенной, прежде чем термостат ее выключит, мс
+    #[serde(default)]
+    pub min_on_ms: u64,
+    /// Сколько розетка остается выключенной, прежде чем термостат ее включит, мс
+    #[serde(default)]
+    pub min_off_ms: u64,
+}
+
+/// Ошибка в настройках термостата
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThermostatError(pub String);
+
+impl fmt::Display for ThermostatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Некорректный термостат: {}", self.0)
+    }
+}
+
+impl std::error::Error for ThermostatError {}
+
+impl ThermostatConfig {
+    /// Проверить уставку и гистерезис. Устройства проверяет комната
+    pub fn validate(&self) -> Result<(), ThermostatError> {
+        if !self.setpoint.is_finite() {
+            return Err(ThermostatError("уставка должна быть числом".to_string()));
+        }
+
+        if !self.hysteresis.is_finite() || self.hysteresis < 0.0 {
+            return Err(ThermostatError(
+                "гистерезис должен быть неотрицательным числом".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Состояние термостата на момент последней проверки
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ThermostatReport {
+    #[serde(flatten)]
+    pub config: ThermostatConfig,
+    /// Температура, `None` - термометр не на связи или проверок еще не было
+    pub temperature: Option<f32>,
+    /// Включена ли розетка
+    pub output_on: bool,
+    /// Почему термостат не управляет розеткой штатно
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fault: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct ThermostatState {
+    /// Когда розетка последний раз подтвердила переключение термостатом, мс
+    last_switch: Option<u64>,
+    temperature: Option<f32>,
+    output_on: bool,
+    fault: Option<String>,
+}
+
+/// Термостат: по показаниям термометра включает и выключает розетку.
+///
+/// Если термометр не на связи, розетка выключается сразу, без выдержки
+/// `min_on_ms`. Клоны разделяют состояние.
+#[derive(Debug, Clone)]
+pub struct Thermostat {
+    config: ThermostatConfig,
+    state: Arc<Mutex<ThermostatState>>,
+}
+
+impl Thermostat {
+    pub fn new(config: ThermostatConfig) -> Self {
+        Self {
+            config,
+            state: Arc::default(),
+        }
+    }
+
+    pub fn config(&self) -> &ThermostatConfig {
+        &self.config
+    }
+
+    /// Команда розетке в момент `now` или `None`, если переключать ее не нужно.
+    ///
+    /// `temperature` - `None`, если термометр не на связи, `socket_on` - `None`, если розетки нет.
+    /// Выдержка отсчитывается от последнего успешного переключения, см. [`ThermostatJob::run`]
+    pub fn decide_at(
+        &self,
+        now: u64,
+        temperature: Option<f32>,
+        socket_on: Option<bool>,
+    ) -> Option<SocketCommand> {
+        let config = &self.config;
+        let mut state = self.state.lock().unwrap();
+        state.temperature = temperature;
+
+        let Some(is_on) = socket_on else {
+            state.output_on = false;
+            state.fault = Some(format!("розетка {} не найдена", config.socket_id));
+            return None;
+        };
+        state.output_on = is_on;
+
+        state.fault = match (config.mode, temperature) {
+            (ThermostatMode::Off, _) | (_, Some(_)) => None,
+            (_, None) => Some(format!("термометр {} не на связи", config.thermometer_id)),
+        };
+
+        let low = config.setpoint - config.hysteresis;
+        let high = config.setpoint + config.hysteresis;
+        let wanted = match (config.mode, temperature) {
+            (ThermostatMode::Off, _) | (_, None) => false,
+            (ThermostatMode::Heat, Some(t)) if t <= low => true,
+            (ThermostatMode::Heat, Some(t)) if t >= high => false,
+            (ThermostatMode::Cool, Some(t)) if t >= high => true,
+            (ThermostatMode::Cool, Some(t)) if t <= low => false,
+            // Внутри полосы гистерезиса состояние не меняется
+            _ => is_on,
+        };
+        if wanted == is_on {
+            return None;
+        }
+
+        let fail_safe = !wanted && (config.mode == ThermostatMode::Off || temperature.is_none());
+        let min_ms = if is_on {
+            config.min_on_ms
+        } else {
+            config.min_off_ms
+        };
+        let too_soon = state
+            .last_switch
+            .is_some_and(|last| now.saturating_sub(last) < min_ms);
+        if too_soon && !fail_safe {
+            return None;
+        }
+
+        Some(if wanted {
+            SocketCommand::TurnOn
+        } else {
+            SocketCommand::TurnOff
+        })
+    }
+
+    /// Розетка выполнила команду, принятую в момент `at`
+    pub(crate) fn switched(&self, at: u64, command: SocketCommand) {
+        let mut state = self.state.lock().unwrap();
+        state.last_switch = Some(at);
+        state.output_on = command == SocketCommand::TurnOn;
+    }
+
+    pub fn report(&self) -> ThermostatReport {
+        let state = self.state.lock().unwrap();
+
+        ThermostatReport {
+            config: self.config.clone(),
+            temperature: state.temperature,
+            output_on: state.output_on,
+            fault: state.fault.clone(),
+        }
+    }
+}
+
+/// Команда термостата розетке.
+///
+/// Розетка - клон устройства комнаты, поэтому команда выполняется без блокировки дома.
+#[derive(Debug)]
+pub struct ThermostatJob {
+    pub(crate) thermostat: Thermostat,
+    pub(crate) socket: SmartSocket,
+    pub(crate) command: SocketCommand,
+    /// Когда принято решение, от этого момента отсчитывается выдержка
+    pub(crate) at: u64,
+}
+
+impl ThermostatJob {
+    pub fn command(&self) -> SocketCommand {
+        self.command
+    }
+
+    /// Переключить розетку, ошибка попадает в состояние термостата.
+    ///
+    /// Выдержка начинается только после успешного переключения: если команда не прошла,
+    /// термостат повторит ее при следующей проверке
+    pub async fn run(mut self) -> Result<(), String> {
+        let result = match self.command {
+            SocketCommand::TurnOn => self.socket.turn_on().await,
+            SocketCommand::TurnOff => self.socket.turn_off().await,
+        }
+        .map_err(|err| err.to_string());
+
+        match &result {
+            Ok(()) => self.thermostat.switched(self.at, self.command),
+            Err(err) => {
+                self.thermostat.state.lock().unwrap().fault =
+                    Some(format!("розетка не переключена: {err}"));
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        id::Id,
+        smart_device::{SmartDevice, SmartThermometer, online::ConnectionType},
+        smart_room::SmartRoom,
+    };
+
+    const SECOND: u64 = 1000;
+
+    fn heater() -> ThermostatConfig {
+        ThermostatConfig {
+            thermometer_id: Id::from_string("Термометр").to_string(),
+            socket_id: Id::from_string("Обогреватель").to_string(),
+            mode: ThermostatMode::Heat,
+            setpoint: 21.0,
+            hysteresis: 0.5,
+            min_on_ms: 60 * SECOND,
+            min_off_ms: 30 * SECOND,
+        }
+    }
+
+    #[test]
+    fn heat_follows_hysteresis_band_and_minimum_times() {
+        let thermostat = Thermostat::new(heater());
+        // Розетка выполняет каждую команду без ошибок
+        let decide_at = |now, temperature, socket_on| {
+            let command = thermostat.decide_at(now, temperature, socket_on);
+
+            if let Some(command) = command {
+                thermostat.switched(now, command);
+            }
+
+            command
+        };
+
+        assert_eq!(
+            decide_at(0, Some(20.4), Some(false)),
+            Some(SocketCommand::TurnOn)
+        );
+        // Внутри полосы состояние не меняется
+        assert_eq!(decide_at(10 * SECOND, Some(21.2), Some(true)), None);
+        // Уставка достигнута, но розетка включена меньше минуты
+        assert_eq!(decide_at(20 * SECOND, Some(21.6), Some(true)), None);
+        assert_eq!(
+            decide_at(60 * SECOND, Some(21.6), Some(true)),
+            Some(SocketCommand::TurnOff)
+        );
+
+        assert_eq!(decide_at(70 * SECOND, Some(20.0), Some(false)), None);
+        assert_eq!(
+            decide_at(90 * SECOND, Some(20.0), Some(false)),
+            Some(SocketCommand::TurnOn)
+        );
+    }
+
+    #[test]
+    fn cool_mode_inverts_band() {
+        let thermostat = Thermostat::new(ThermostatConfig {
+            mode: ThermostatMode::Cool,
+            ..heater()
+        });
+
+        assert_eq!(thermostat.decide_at(0, Some(21.0), Some(false)), None);
+        assert_eq!(
+            thermostat.decide_at(SECOND, Some(21.5), Some(false)),
+            Some(SocketCommand::TurnOn)
+        );
+    }
+
+    #[test]
+    fn lost_sensor_turns_socket_off_at_once() {
+        let thermostat = Thermostat::new(heater());
+
+        assert_eq!(
+            thermostat.decide_at(0, Some(19.0), Some(false)),
+            Some(SocketCommand::TurnOn)
+        );
+        // Выдержка min_on_ms не мешает выключению по безопасности
+        assert_eq!(
+            thermostat.decide_at(SECOND, None, Some(true)),
+            Some(SocketCommand::TurnOff)
+        );
+
+        let report = thermostat.report();
+        assert_eq!(report.temperature, None);
+        assert!(report.fault.unwrap().contains("не на связи"));
+    }
+
+    #[tokio::test]
+    async fn failed_switch_does_not_start_lockout() {
+        // Розетка не подключена, команды на нее не проходят
+        let socket = SmartSocket::new_with_connection(
+            "Обогреватель",
+            1500.0,
+            false,
+            ConnectionType::tcp("127.0.0.1".parse().unwrap(), 1),
+        );
+        let mut room = SmartRoom::new_with_devices(
+            "Спальня",
+            &[
+                socket.clone().into(),
+                SmartThermometer::new("Термометр", 19.0).into(),
+            ],
+        );
+        room.set_thermostat(Some(heater()));
+
+        let job = room.thermostat_job_at(0).await.unwrap();
+        assert!(job.run().await.is_err());
+
+        let report = room.room_report().await.thermostat.unwrap();
+        assert!(!report.output_on);
+        assert!(report.fault.unwrap().contains("не переключена"));
+
+        // Выдержка min_off_ms не началась: команда повторяется сразу
+        let job = room.thermostat_job_at(SECOND).await.unwrap();
+        assert_eq!(job.command(), SocketCommand::TurnOn);
+    }
+
+    #[tokio::test]
+    async fn room_drives_heater_from_thermometer() {
+        let socket = SmartSocket::new("Обогреватель", 1500.0, false);
+        let mut room = SmartRoom::new_with_devices(
+            "Спальня",
+            &[
+                socket.clone().into(),
+                SmartThermometer::new("Термометр", 19.0).into(),
+            ],
+        );
+
+        let missing = ThermostatConfig {
+            thermometer_id: Id::from_string("Обогреватель").to_string(),
+            ..heater()
+        };
+        assert!(room.check_thermostat(&missing).is_err());
+
+        room.check_thermostat(&heater()).unwrap();
+        room.set_thermostat(Some(heater()));
+
+        let job = room.thermostat_job_at(0).await.unwrap();
+        assert_eq!(job.command(), SocketCommand::TurnOn);
+        job.run().await.unwrap();
+        assert!(socket.is_on().await);
+
+        let report = room.room_report().await.thermostat.unwrap();
+        assert_eq!(report.temperature, Some(19.0));
+        assert!(report.output_on);
+
+        // Термометр удален - розетка выключается
+        room.delete_device(&Id::from_string("Термометр"));
+        let job = room.thermostat_job_at(SECOND).await.unwrap();
+        assert_eq!(job.command(), SocketCommand::TurnOff);
+        job.run().await.unwrap();
+        assert!(!socket.is_on().await);
+        assert!(socket.get_connection().is_none());
+    }
+}
@@ -0,0 +1,201 @@
+use dotenv::dotenv;
+use sh_lib::errors::SmartHomeErrors;
+use sh_lib::smart_device::SmartLight;
+use sh_lib::smart_device::contracts::{
+    Commands, ConnectionState, DeviceData, DeviceInfo, DeviceResponse,
+};
+use sh_lib::smart_device::frame::{Frame, MessageType, negotiate};
+use sh_lib::smart_device::smart_light::{check_brightness, check_color_temperature};
+use std::env;
+use std::error::Error;
+use std::sync::Arc;
+use tokio::io::BufReader;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::RwLock;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    dotenv().ok();
+    let pid = std::process::id();
+
+    let port = env::var("SH_LIGHT_EMULATOR_PORT").unwrap_or("3002".to_string());
+    let serial = env::var("SH_LIGHT_EMULATOR_SERIAL").unwrap_or(pid.to_string());
+
+    let light_arc = Arc::new(RwLock::new(
+        SmartLight::new(format!("Светильник SN: {}", serial), 100, false)
+            .with_color_temperature(2700)
+            .with_info(DeviceInfo {
+                serial: serial.clone(),
+                model: String::from("SH-LIGHT-EMU"),
+                firmware: String::from(env!("CARGO_PKG_VERSION")),
+            }),
+    ));
+
+    light_arc
+        .write()
+        .await
+        .value
+        .write()
+        .await
+        .set_connection_state(ConnectionState::Online);
+
+    let listen_addr = format!("0.0.0.0:{}", port);
+    let listener = TcpListener::bind(&listen_addr).await?;
+
+    println!(
+        "Светильник SN: {} слушает подключение на {}",
+        serial, &listen_addr
+    );
+
+    loop {
+        let (mut stream, addr) = listener.accept().await?;
+        println!("Светильник SN: {} принял подключение от {}", serial, addr);
+        let light_arc = light_arc.clone();
+        let serial = serial.clone();
+
+        tokio::spawn(async move {
+            handle_connection(&mut stream, &light_arc, addr, &serial).await;
+        });
+    }
+}
+
+async fn handle_connection(
+    stream: &mut TcpStream,
+    light: &Arc<RwLock<SmartLight>>,
+    addr: std::net::SocketAddr,
+    serial: &str,
+) {
+    let (reader, mut writer) = stream.split();
+    let mut reader = BufReader::new(reader);
+
+    loop {
+        let request = match Frame::read_from(&mut reader).await {
+            Ok(request) => request,
+            Err(e) => {
+                println!(
+                    "Светильник SN: {} потерял соединение с {}. Err: {}",
+                    serial, addr, e
+                );
+                break;
+            }
+        };
+
+        if request.message_type == MessageType::Hello {
+            let reply = negotiate(&request);
+            let rejected = reply.message_type == MessageType::Error;
+
+            if rejected {
+                println!(
+                    "Светильник SN: {} отклонил подключение от {}: {}",
+                    serial,
+                    addr,
+                    String::from_utf8_lossy(&reply.payload)
+                );
+            }
+
+            if reply.write_to(&mut writer).await.is_err() || rejected {
+                break;
+            }
+
+            continue;
+        }
+
+        let (cmd, value) = match request.as_command_value() {
+            Ok(command) => command,
+            Err(e) => {
+                let _ = Frame::error(e.to_string()).write_to(&mut writer).await;
+                continue;
+            }
+        };
+
+        println!("Светильник SN: {} получил команду {:?}", serial, cmd);
+
+        let result = match (cmd, value) {
+            (Commands::TurnOn, _) => {
+                let mut light = light.write().await;
+                acknowledge(light.turn_on().await)
+            }
+            (Commands::TurnOff, _) => {
+                let mut light = light.write().await;
+                acknowledge(light.turn_off().await)
+            }
+            (Commands::SetBrightness, Some(value)) => {
+                let mut light = light.write().await;
+                match check_brightness(value) {
+                    Ok(brightness) => acknowledge(light.set_brightness(brightness).await),
+                    Err(e) => acknowledge(Err(e)),
+                }
+            }
+            (Commands::SetColorTemperature, Some(value)) => {
+                let mut light = light.write().await;
+                match check_color_temperature(value) {
+                    Ok(kelvin) => acknowledge(light.set_color_temperature(kelvin).await),
+                    Err(e) => acknowledge(Err(e)),
+                }
+            }
+            (Commands::SetBrightness | Commands::SetColorTemperature, None) => DeviceResponse {
+                success: false,
+                error: Some(String::from("Missing command value")),
+                data: None,
+                info: None,
+            },
+            (Commands::GetStatus, _) => {
+                let light = light.read().await;
+                get_light_data(&light).await
+            }
+            (Commands::GetInfo, _) => {
+                let light = light.read().await;
+                get_light_info(&light).await
+            }
+            (Commands::Unknown, _) => DeviceResponse {
+                success: false,
+                error: Some(String::from("Unknown command")),
+                data: None,
+                info: None,
+            },
+        };
+
+        println!("Светильник SN: {} отправил ответ: {:?}", serial, result);
+
+        Frame::response(&result)
+            .unwrap()
+            .write_to(&mut writer)
+            .await
+            .unwrap();
+    }
+}
+
+fn acknowledge(result: Result<(), SmartHomeErrors>) -> DeviceResponse {
+    match result {
+        Ok(_) => DeviceResponse {
+            success: true,
+            error: None,
+            data: None,
+            info: None,
+        },
+        Err(e) => DeviceResponse {
+            success: false,
+            error: Some(e.to_string()),
+            data: None,
+            info: None,
+        },
+    }
+}
+
+async fn get_light_data(light: &SmartLight) -> DeviceResponse {
+    DeviceResponse {
+        success: true,
+        error: None,
+        data: Some(DeviceData::Light(light.get_data().await)),
+        info: None,
+    }
+}
+
+async fn get_light_info(light: &SmartLight) -> DeviceResponse {
+    DeviceResponse {
+        success: true,
+        error: None,
+        data: None,
+        info: light.get_info().await,
+    }
+}
@@ -1,11 +1,13 @@
 use dotenv::dotenv;
 use sh_lib::smart_device::SmartSocket;
-use sh_lib::smart_device::contracts::{Commands, DecodeEncode, DeviceData, DeviceResponse};
+use sh_lib::smart_device::contracts::{
+    Commands, ConnectionState, DeviceData, DeviceInfo, DeviceResponse,
+};
+use sh_lib::smart_device::frame::{Frame, MessageType, negotiate};
 use std::env;
 use std::error::Error;
 use std::sync::Arc;
-use tokio::io::AsyncWriteExt;
-use tokio::io::{AsyncReadExt, BufReader};
+use tokio::io::BufReader;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::RwLock;
 
@@ -15,30 +17,40 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let pid = std::process::id();
 
     let port = env::var("SH_SOCKET_EMULATOR_PORT").unwrap_or("3001".to_string());
-
-    let socket_arc = Arc::new(RwLock::new(SmartSocket::new(
-        format!("Розетка SN: {}", pid),
-        0.0,
-        false,
-    )));
-
-    socket_arc.write().await.value.write().await.is_online = true;
+    let serial = env::var("SH_SOCKET_EMULATOR_SERIAL").unwrap_or(pid.to_string());
+
+    let socket_arc = Arc::new(RwLock::new(
+        SmartSocket::new(format!("Розетка SN: {}", serial), 0.0, false).with_info(DeviceInfo {
+            serial: serial.clone(),
+            model: String::from("SH-SOCKET-EMU"),
+            firmware: String::from(env!("CARGO_PKG_VERSION")),
+        }),
+    ));
+
+    socket_arc
+        .write()
+        .await
+        .value
+        .write()
+        .await
+        .set_connection_state(ConnectionState::Online);
 
     let listen_addr = format!("0.0.0.0:{}", port);
     let listener = TcpListener::bind(&listen_addr).await?;
 
     println!(
         "Розетка SN: {} слушает подключение на {}",
-        pid, &listen_addr
+        serial, &listen_addr
     );
 
     loop {
         let (mut stream, addr) = listener.accept().await?;
-        println!("Розетка SN: {} приняла подключение от {}", pid, addr);
+        println!("Розетка SN: {} приняла подключение от {}", serial, addr);
         let socket_arc = socket_arc.clone();
+        let serial = serial.clone();
 
         tokio::spawn(async move {
-            handle_connection(&mut stream, &socket_arc, addr).await;
+            handle_connection(&mut stream, &socket_arc, addr, &serial).await;
         });
     }
 }
@@ -47,35 +59,64 @@ async fn handle_connection(
     stream: &mut TcpStream,
     socket: &Arc<RwLock<SmartSocket>>,
     addr: std::net::SocketAddr,
+    serial: &str,
 ) {
-    let pid = std::process::id();
     let (reader, mut writer) = stream.split();
     let mut reader = BufReader::new(reader);
 
     loop {
-        let mut buf = [0u8; 4];
-
-        if let Err(e) = reader.read_exact(&mut buf).await {
-            println!(
-                "Розетка SN: {} потеряла соединение с {}. Err: {}",
-                pid, addr, e
-            );
-            break;
+        let request = match Frame::read_from(&mut reader).await {
+            Ok(request) => request,
+            Err(e) => {
+                println!(
+                    "Розетка SN: {} потеряла соединение с {}. Err: {}",
+                    serial, addr, e
+                );
+                break;
+            }
+        };
+
+        if request.message_type == MessageType::Hello {
+            let reply = negotiate(&request);
+            let rejected = reply.message_type == MessageType::Error;
+
+            if rejected {
+                println!(
+                    "Розетка SN: {} отклонила подключение от {}: {}",
+                    serial,
+                    addr,
+                    String::from_utf8_lossy(&reply.payload)
+                );
+            }
+
+            if reply.write_to(&mut writer).await.is_err() || rejected {
+                break;
+            }
+
+            continue;
         }
 
-        let result = match Commands::from(i32::from_be_bytes(buf)) {
+        let cmd = match request.as_command() {
+            Ok(cmd) => cmd,
+            Err(e) => {
+                let _ = Frame::error(e.to_string()).write_to(&mut writer).await;
+                continue;
+            }
+        };
+
+        let result = match cmd {
             Commands::TurnOn => {
-                println!("Розетка SN: {} получила команду TurnOn", pid);
+                println!("Розетка SN: {} получила команду TurnOn", serial);
                 let mut socket = socket.write().await;
                 turn_on(&mut socket).await
             }
             Commands::TurnOff => {
-                println!("Розетка SN: {} получила команду TurnOff", pid);
+                println!("Розетка SN: {} получила команду TurnOff", serial);
                 let mut socket = socket.write().await;
                 turn_off(&mut socket).await
             }
             Commands::GetStatus => {
-                println!("Розетка SN: {} получила команду GetStatus", pid);
+                println!("Розетка SN: {} получила команду GetStatus", serial);
 
                 // Для демонстрации смены состояния
                 {
@@ -92,48 +133,79 @@ async fn handle_connection(
                 let socket = socket.read().await;
                 get_socket_data(&socket).await
             }
-            Commands::Unknown => DeviceResponse {
-                success: false,
-                error: Some(String::from("Unknown command")),
-                data: None,
-            },
+            Commands::GetInfo => {
+                println!("Розетка SN: {} получила команду GetInfo", serial);
+                let socket = socket.read().await;
+                get_socket_info(&socket).await
+            }
+            Commands::Unknown | Commands::SetBrightness | Commands::SetColorTemperature => {
+                DeviceResponse {
+                    success: false,
+                    error: Some(String::from("Unknown command")),
+                    data: None,
+                    info: None,
+                }
+            }
         };
 
-        let encoded: Vec<u8> = result.encode().unwrap();
-        println!("Розетка SN: {} отправила ответ: {:?}", pid, result);
-
-        let size_bytes = encoded.len().to_be_bytes().to_vec();
-        let d = [size_bytes, encoded].concat();
+        println!("Розетка SN: {} отправила ответ: {:?}", serial, result);
 
-        writer.write_all(&d).await.unwrap();
-        writer.flush().await.unwrap();
+        Frame::response(&result)
+            .unwrap()
+            .write_to(&mut writer)
+            .await
+            .unwrap();
     }
 }
 
 async fn turn_on(socket: &mut SmartSocket) -> DeviceResponse {
-    socket.turn_on().await;
-
-    DeviceResponse {
-        success: true,
-        error: None,
-        data: None,
+    match socket.turn_on().await {
+        Ok(_) => DeviceResponse {
+            success: true,
+            error: None,
+            data: None,
+            info: None,
+        },
+        Err(e) => DeviceResponse {
+            success: false,
+            error: Some(e.to_string()),
+            data: None,
+            info: None,
+        },
     }
 }
 
 async fn turn_off(socket: &mut SmartSocket) -> DeviceResponse {
-    socket.turn_off().await;
+    match socket.turn_off().await {
+        Ok(_) => DeviceResponse {
+            success: true,
+            error: None,
+            data: None,
+            info: None,
+        },
+        Err(e) => DeviceResponse {
+            success: false,
+            error: Some(e.to_string()),
+            data: None,
+            info: None,
+        },
+    }
+}
 
+async fn get_socket_data(socket: &SmartSocket) -> DeviceResponse {
     DeviceResponse {
         success: true,
         error: None,
-        data: None,
+        data: Some(DeviceData::Socket(socket.get_data().await)),
+        info: None,
     }
 }
 
-async fn get_socket_data(socket: &SmartSocket) -> DeviceResponse {
+async fn get_socket_info(socket: &SmartSocket) -> DeviceResponse {
     DeviceResponse {
         success: true,
         error: None,
-        data: Some(DeviceData::Socket(socket.get_data().await)),
+        data: None,
+        info: socket.get_info().await,
     }
 }
@@ -3,7 +3,8 @@ use std::env;
 use dotenv::dotenv;
 use tokio::net::UdpSocket;
 
-use sh_lib::smart_device::contracts::{DecodeEncode, DeviceData, DeviceResponse};
+use sh_lib::smart_device::contracts::{ConnectionState, DeviceData, DeviceInfo, DeviceResponse};
+use sh_lib::smart_device::frame::Frame;
 
 #[tokio::main]
 async fn main() {
@@ -19,16 +20,27 @@ async fn main() {
         .parse()
         .unwrap();
 
+    let serial = env::var("SH_THERM_EMULATOR_SERIAL").unwrap_or(pid.to_string());
+
     let thermometer =
-        sh_lib::smart_device::smart_thermometer::SmartThermometer::new(pid.to_string(), 0.0);
-    thermometer.value.write().await.is_online = true;
+        sh_lib::smart_device::smart_thermometer::SmartThermometer::new(serial.clone(), 0.0)
+            .with_info(DeviceInfo {
+                serial: serial.clone(),
+                model: String::from("SH-THERM-EMU"),
+                firmware: String::from(env!("CARGO_PKG_VERSION")),
+            });
+    thermometer
+        .value
+        .write()
+        .await
+        .set_connection_state(ConnectionState::Online);
 
     let udp_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
     let target_addr = format!("{}:{}", target_ip, target_port);
 
     println!(
         "Термометр SN: {} будет писать статус в {}",
-        pid, &target_addr
+        serial, &target_addr
     );
 
     loop {
@@ -43,27 +55,27 @@ async fn main() {
 
         let therm_data = DeviceResponse {
             data: Some(DeviceData::Thermometer(thermometer.get_data().await)),
+            // Термометр только отправляет данные, поэтому паспорт идет в каждой посылке
+            info: thermometer.get_info().await,
             success: true,
             error: None,
         };
 
         tokio::time::sleep(tokio::time::Duration::from_millis(interval)).await;
 
-        match therm_data.encode() {
+        // UDP не гарантирует целостность, поэтому кадр идет с контрольной суммой
+        match Frame::response(&therm_data).and_then(|frame| frame.with_checksum().to_datagram()) {
             Err(e) => {
                 eprintln!("❌ Failed to encode device response: {}", e);
                 continue;
             }
-            Ok(encoded) => {
-                let size_bytes = encoded.len().to_be_bytes().to_vec();
-                let d = [size_bytes, encoded].concat();
-
+            Ok(d) => {
                 if let Err(e) = udp_socket.send_to(&d, &target_addr).await {
                     eprintln!("❌ Failed to send device response: {}", e);
                     continue;
                 }
 
-                println!("Термометр SN: {} отправил данные: {:?}", pid, therm_data);
+                println!("Термометр SN: {} отправил данные: {:?}", serial, therm_data);
             }
         }
     }
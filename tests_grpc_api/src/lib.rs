@@ -4,14 +4,35 @@ mod smart_home_contracts {
 
 use smart_home_contracts::home_service_client::HomeServiceClient;
 
+use hyper_util::{client::legacy::Client, rt::TokioExecutor};
 use smart_home_contracts::{
-    AddDeviceRequest, AddHomeRequest, AddRoomRequest, DeleteDeviceRequest, DeleteHomeRequest,
-    DeleteRoomRequest, DeviceType, Item, ListDevicesRequest, ListHomesRequest, ListRoomsRequest,
+    AddDeviceRequest, AddHomeRequest, AddRoomRequest, ApplySceneRequest, ControlDeviceRequest,
+    CreateRuleRequest, CreateSceneRequest, CreateScheduleRequest, DeleteDeviceRequest,
+    DeleteHomeRequest, DeleteRoomRequest, DeleteRuleRequest, DeleteSceneRequest,
+    DeleteScheduleRequest, DeleteThermostatRequest, DeviceType, GetDeviceHistoryRequest,
+    GetEnergyCostRequest, GetEnergyUsageRequest, GetReportRequest, GetRuleLogRequest,
+    GetTariffRequest, GetThermostatRequest, Item, ListDevicesRequest, ListHomesRequest,
+    ListRoomsRequest, ListRulesRequest, ListScenesRequest, ListSchedulesRequest,
+    ListUnassignedDevicesRequest, PauseScheduleRequest, SetTariffRequest, SetThermostatRequest,
+    UnassignedDevice, UpdateConnectionSettingsRequest, UpdateRuleRequest, WatchHomeRequest,
 };
-use tonic::{Response, Status};
+pub use smart_home_contracts::{
+    Comparison, ConnectionSettings, DayNightTariff, DeviceCommand, DeviceRef, FlatTariff,
+    HomeEventType, ItemType, Metric, Rule, RuleAction, RuleExecution, RuleTrigger, Scene,
+    SceneResult, SceneState, Schedule, ScheduleAction, Tariff, Thermostat, ThermostatMode,
+    ThermostatStatus, WatchHomeResponse, item::Value as ItemValue, schedule::When as ScheduleWhen,
+    tariff::Kind as TariffKind,
+};
+use tonic::{Response, Status, Streaming};
+use tonic_web::GrpcWebClientLayer;
 use uuid::Uuid;
 
-use crate::smart_home_contracts::{DeleteDeviceResponse, DeleteHomeResponse, DeleteRoomResponse};
+use crate::smart_home_contracts::{
+    ControlDeviceResponse, DeleteDeviceResponse, DeleteHomeResponse, DeleteRoomResponse,
+    DeleteRuleResponse, DeleteSceneResponse, DeleteScheduleResponse, DeleteThermostatResponse,
+    GetDeviceHistoryResponse, GetEnergyCostResponse, GetEnergyUsageResponse, PauseScheduleResponse,
+    SetTariffResponse, SetThermostatResponse, UpdateConnectionSettingsResponse, UpdateRuleResponse,
+};
 
 const ADDR_GRPC_API: &str = "http://127.0.0.1:50051";
 
@@ -110,6 +131,69 @@ pub async fn add_device(home_id: String, room_id: String) -> String {
     client.add_device(req).await.unwrap().into_inner().device_id
 }
 
+pub async fn add_thermometer(
+    home_id: String,
+    room_id: String,
+    connection: ConnectionSettings,
+) -> Result<String, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(AddDeviceRequest {
+        home_id,
+        room_id,
+        name: Uuid::new_v4().to_string(),
+        device_type: DeviceType::Thermo as i32,
+        connection: Some(connection),
+    });
+
+    client
+        .add_device(req)
+        .await
+        .map(|response| response.into_inner().device_id)
+}
+
+pub async fn add_light(
+    home_id: String,
+    room_id: String,
+    connection: Option<ConnectionSettings>,
+) -> Result<String, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(AddDeviceRequest {
+        home_id,
+        room_id,
+        name: Uuid::new_v4().to_string(),
+        device_type: DeviceType::Light as i32,
+        connection,
+    });
+
+    client
+        .add_device(req)
+        .await
+        .map(|response| response.into_inner().device_id)
+}
+
+pub async fn list_unassigned_devices() -> Vec<UnassignedDevice> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+
+    client
+        .list_unassigned_devices(tonic::Request::new(ListUnassignedDevicesRequest {}))
+        .await
+        .unwrap()
+        .into_inner()
+        .devices
+}
+
 pub async fn list_devices(home_id: String, room_id: String) -> Vec<Item> {
     let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
         .connect()
@@ -140,3 +224,436 @@ pub async fn delete_device(
 
     client.delete_device(req).await
 }
+
+pub async fn control_device(
+    home_id: String,
+    room_id: String,
+    device_id: String,
+    command: DeviceCommand,
+) -> Result<Response<ControlDeviceResponse>, Status> {
+    control_device_value(home_id, room_id, device_id, command, 0).await
+}
+
+pub async fn control_device_value(
+    home_id: String,
+    room_id: String,
+    device_id: String,
+    command: DeviceCommand,
+    value: u32,
+) -> Result<Response<ControlDeviceResponse>, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(ControlDeviceRequest {
+        home_id,
+        room_id,
+        device_id,
+        command: command as i32,
+        value,
+    });
+
+    client.control_device(req).await
+}
+
+pub async fn get_device_history(
+    home_id: String,
+    room_id: String,
+    device_id: String,
+    from: u64,
+    to: u64,
+) -> Result<Response<GetDeviceHistoryResponse>, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(GetDeviceHistoryRequest {
+        home_id,
+        room_id,
+        device_id,
+        from,
+        to,
+        step_ms: 0,
+    });
+
+    client.get_device_history(req).await
+}
+
+pub async fn update_connection_settings(
+    home_id: String,
+    room_id: String,
+    device_id: String,
+    settings: ConnectionSettings,
+) -> Result<Response<UpdateConnectionSettingsResponse>, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(UpdateConnectionSettingsRequest {
+        home_id,
+        room_id,
+        device_id,
+        settings: Some(settings),
+    });
+
+    client.update_connection_settings(req).await
+}
+
+pub async fn get_report(home_id: String) -> Result<Vec<Item>, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(GetReportRequest { home_id });
+
+    client
+        .get_report(req)
+        .await
+        .map(|response| response.into_inner().items)
+}
+
+pub async fn get_energy_usage(
+    home_id: String,
+    room_id: String,
+    device_id: String,
+) -> Result<Response<GetEnergyUsageResponse>, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(GetEnergyUsageRequest {
+        home_id,
+        room_id,
+        device_id,
+    });
+
+    client.get_energy_usage(req).await
+}
+
+pub async fn set_tariff(
+    home_id: String,
+    tariff: Option<Tariff>,
+) -> Result<Response<SetTariffResponse>, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(SetTariffRequest { home_id, tariff });
+
+    client.set_tariff(req).await
+}
+
+pub async fn get_tariff(home_id: String) -> Result<Option<Tariff>, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(GetTariffRequest { home_id });
+
+    client
+        .get_tariff(req)
+        .await
+        .map(|response| response.into_inner().tariff)
+}
+
+pub async fn get_energy_cost(
+    home_id: String,
+    room_id: String,
+    device_id: String,
+) -> Result<Response<GetEnergyCostResponse>, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(GetEnergyCostRequest {
+        home_id,
+        room_id,
+        device_id,
+        from: 0,
+        to: 0,
+    });
+
+    client.get_energy_cost(req).await
+}
+
+pub async fn create_rule(home_id: String, rule: Rule) -> Result<String, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(CreateRuleRequest {
+        home_id,
+        rule: Some(rule),
+    });
+
+    client
+        .create_rule(req)
+        .await
+        .map(|response| response.into_inner().rule_id)
+}
+
+pub async fn update_rule(
+    home_id: String,
+    rule: Rule,
+) -> Result<Response<UpdateRuleResponse>, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(UpdateRuleRequest {
+        home_id,
+        rule: Some(rule),
+    });
+
+    client.update_rule(req).await
+}
+
+pub async fn delete_rule(
+    home_id: String,
+    rule_id: String,
+) -> Result<Response<DeleteRuleResponse>, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(DeleteRuleRequest { home_id, rule_id });
+
+    client.delete_rule(req).await
+}
+
+pub async fn list_rules(home_id: String) -> Vec<Rule> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(ListRulesRequest { home_id });
+
+    client.list_rules(req).await.unwrap().into_inner().rules
+}
+
+pub async fn get_rule_log(home_id: String, rule_id: String) -> Result<Vec<RuleExecution>, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(GetRuleLogRequest { home_id, rule_id });
+
+    client
+        .get_rule_log(req)
+        .await
+        .map(|response| response.into_inner().executions)
+}
+
+pub async fn create_schedule(home_id: String, schedule: Schedule) -> Result<String, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(CreateScheduleRequest {
+        home_id,
+        schedule: Some(schedule),
+    });
+
+    client
+        .create_schedule(req)
+        .await
+        .map(|response| response.into_inner().schedule_id)
+}
+
+pub async fn list_schedules(home_id: String) -> Vec<Schedule> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(ListSchedulesRequest { home_id });
+
+    client
+        .list_schedules(req)
+        .await
+        .unwrap()
+        .into_inner()
+        .schedules
+}
+
+pub async fn pause_schedule(
+    home_id: String,
+    schedule_id: String,
+    paused: bool,
+) -> Result<Response<PauseScheduleResponse>, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(PauseScheduleRequest {
+        home_id,
+        schedule_id,
+        paused,
+    });
+
+    client.pause_schedule(req).await
+}
+
+pub async fn delete_schedule(
+    home_id: String,
+    schedule_id: String,
+) -> Result<Response<DeleteScheduleResponse>, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(DeleteScheduleRequest {
+        home_id,
+        schedule_id,
+    });
+
+    client.delete_schedule(req).await
+}
+
+pub async fn create_scene(home_id: String, scene: Scene) -> Result<String, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(CreateSceneRequest {
+        home_id,
+        scene: Some(scene),
+    });
+
+    client
+        .create_scene(req)
+        .await
+        .map(|response| response.into_inner().scene_id)
+}
+
+pub async fn list_scenes(home_id: String) -> Vec<Scene> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(ListScenesRequest { home_id });
+
+    client.list_scenes(req).await.unwrap().into_inner().scenes
+}
+
+pub async fn delete_scene(
+    home_id: String,
+    scene_id: String,
+) -> Result<Response<DeleteSceneResponse>, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(DeleteSceneRequest { home_id, scene_id });
+
+    client.delete_scene(req).await
+}
+
+pub async fn apply_scene(home_id: String, scene_id: String) -> Result<Vec<SceneResult>, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(ApplySceneRequest { home_id, scene_id });
+
+    client
+        .apply_scene(req)
+        .await
+        .map(|response| response.into_inner().results)
+}
+
+pub async fn set_thermostat(
+    home_id: String,
+    room_id: String,
+    thermostat: Thermostat,
+) -> Result<Response<SetThermostatResponse>, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(SetThermostatRequest {
+        home_id,
+        room_id,
+        thermostat: Some(thermostat),
+    });
+
+    client.set_thermostat(req).await
+}
+
+pub async fn get_thermostat(home_id: String, room_id: String) -> Result<ThermostatStatus, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(GetThermostatRequest { home_id, room_id });
+
+    client
+        .get_thermostat(req)
+        .await
+        .map(|response| response.into_inner().status.unwrap_or_default())
+}
+
+pub async fn delete_thermostat(
+    home_id: String,
+    room_id: String,
+) -> Result<Response<DeleteThermostatResponse>, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(DeleteThermostatRequest { home_id, room_id });
+
+    client.delete_thermostat(req).await
+}
+
+pub async fn watch_home(home_id: String) -> Result<Streaming<WatchHomeResponse>, Status> {
+    let channel = tonic::transport::Channel::from_static(ADDR_GRPC_API)
+        .connect()
+        .await
+        .unwrap();
+    let mut client = HomeServiceClient::new(channel);
+    let req = tonic::Request::new(WatchHomeRequest { home_id });
+
+    client
+        .watch_home(req)
+        .await
+        .map(|response| response.into_inner())
+}
+
+/// Подписаться на события дома через gRPC-Web поверх HTTP1, как это делает браузер
+pub async fn watch_home_web(home_id: String) -> Result<Streaming<WatchHomeResponse>, Status> {
+    let http = Client::builder(TokioExecutor::new()).build_http();
+    let service = tower::ServiceBuilder::new()
+        .layer(GrpcWebClientLayer::new())
+        .service(http);
+    let mut client = HomeServiceClient::with_origin(service, ADDR_GRPC_API.try_into().unwrap());
+    let req = tonic::Request::new(WatchHomeRequest { home_id });
+
+    client
+        .watch_home(req)
+        .await
+        .map(|response| response.into_inner())
+}
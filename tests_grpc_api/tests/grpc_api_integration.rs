@@ -1,8 +1,21 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use tests_grpc_api::{
-    add_device, add_home, add_room, delete_device, delete_home, delete_room, list_devices,
-    list_homes, list_rooms,
+    Comparison, ConnectionSettings, DayNightTariff, DeviceCommand, DeviceRef, FlatTariff,
+    HomeEventType, ItemType, ItemValue, Metric, Rule, RuleAction, RuleTrigger, Scene, SceneState,
+    Schedule, ScheduleAction, ScheduleWhen, Tariff, TariffKind, Thermostat, ThermostatMode,
+    WatchHomeResponse, add_device, add_home, add_light, add_room, add_thermometer, apply_scene,
+    control_device, control_device_value, create_rule, create_scene, create_schedule,
+    delete_device, delete_home, delete_room, delete_rule, delete_scene, delete_schedule,
+    delete_thermostat, get_device_history, get_energy_cost, get_energy_usage, get_report,
+    get_rule_log, get_tariff, get_thermostat, list_devices, list_homes, list_rooms, list_rules,
+    list_scenes, list_schedules, list_unassigned_devices, pause_schedule, set_tariff,
+    set_thermostat, update_connection_settings, update_rule, watch_home, watch_home_web,
 };
+use tonic::Streaming;
 
+// Сгенерированный код: у варианта oneof Item.value суффикс совпадает с именем перечисления
+#[allow(clippy::enum_variant_names)]
 mod smart_home_contracts {
     tonic::include_proto!("smart_home.v1");
 }
@@ -101,3 +114,880 @@ async fn test_delete_missing_device() {
         Err(err) => assert!(err.code() == tonic::Code::NotFound),
     };
 }
+
+#[tokio::test]
+async fn test_control_device() {
+    let home_id = add_home().await;
+    let room_id = add_room(home_id.clone()).await;
+    let device_id = add_device(home_id.clone(), room_id.clone()).await;
+
+    let item = control_device(home_id, room_id, device_id.clone(), DeviceCommand::TurnOn)
+        .await
+        .unwrap()
+        .into_inner()
+        .item
+        .unwrap();
+
+    assert_eq!(item.id, device_id);
+    match item.value {
+        Some(ItemValue::SocketValue(value)) => assert!(value.is_on),
+        _ => panic!("Expected socket value"),
+    }
+}
+
+#[tokio::test]
+async fn test_control_light() {
+    let home_id = add_home().await;
+    let room_id = add_room(home_id.clone()).await;
+    let device_id = add_light(home_id.clone(), room_id.clone(), None)
+        .await
+        .unwrap();
+
+    control_device(
+        home_id.clone(),
+        room_id.clone(),
+        device_id.clone(),
+        DeviceCommand::TurnOn,
+    )
+    .await
+    .unwrap();
+    let item = control_device_value(
+        home_id.clone(),
+        room_id.clone(),
+        device_id.clone(),
+        DeviceCommand::SetBrightness,
+        40,
+    )
+    .await
+    .unwrap()
+    .into_inner()
+    .item
+    .unwrap();
+
+    assert_eq!(item.item_type(), ItemType::Light);
+    match item.value {
+        Some(ItemValue::LightValue(value)) => {
+            assert!(value.is_on);
+            assert_eq!(value.brightness, 40);
+            assert_eq!(value.color_temperature, 0);
+        }
+        _ => panic!("Expected light value"),
+    }
+
+    for (command, value) in [
+        (DeviceCommand::SetBrightness, 101),
+        // Светильник без цветовой температуры
+        (DeviceCommand::SetColorTemperature, 2700),
+    ] {
+        match control_device_value(
+            home_id.clone(),
+            room_id.clone(),
+            device_id.clone(),
+            command,
+            value,
+        )
+        .await
+        {
+            Ok(_) => panic!("Expected error"),
+            Err(err) => assert!(err.code() == tonic::Code::InvalidArgument),
+        };
+    }
+
+    // Розетка не управляется яркостью
+    let socket_id = add_device(home_id.clone(), room_id.clone()).await;
+    match control_device_value(
+        home_id,
+        room_id,
+        socket_id,
+        DeviceCommand::SetBrightness,
+        50,
+    )
+    .await
+    {
+        Ok(_) => panic!("Expected error"),
+        Err(err) => assert!(err.code() == tonic::Code::InvalidArgument),
+    };
+}
+
+#[tokio::test]
+async fn test_add_device_with_invalid_address() {
+    let home_id = add_home().await;
+    let room_id = add_room(home_id.clone()).await;
+
+    for (ip, port) in [("localhost", "3002"), ("127.0.0.1", "70000")] {
+        let connection = ConnectionSettings {
+            ip: ip.to_string(),
+            port: port.to_string(),
+            ..ConnectionSettings::default()
+        };
+
+        match add_light(home_id.clone(), room_id.clone(), Some(connection.clone())).await {
+            Ok(_) => panic!("Expected error"),
+            Err(err) => assert!(err.code() == tonic::Code::InvalidArgument),
+        };
+        match add_thermometer(home_id.clone(), room_id.clone(), connection).await {
+            Ok(_) => panic!("Expected error"),
+            Err(err) => assert!(err.code() == tonic::Code::InvalidArgument),
+        };
+    }
+
+    delete_home(home_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_control_remote_light() {
+    let home_id = add_home().await;
+    let room_id = add_room(home_id.clone()).await;
+    let connection = ConnectionSettings {
+        ip: "127.0.0.1".to_string(),
+        port: "3002".to_string(),
+        ..ConnectionSettings::default()
+    };
+    let device_id = add_light(home_id.clone(), room_id.clone(), Some(connection))
+        .await
+        .unwrap();
+
+    // Поддержку цветовой температуры светильник сообщает при опросе
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !list_devices(home_id.clone(), room_id.clone())
+            .await
+            .iter()
+            .any(|item| match &item.value {
+                Some(ItemValue::LightValue(value)) => {
+                    value.is_online && value.color_temperature != 0
+                }
+                _ => false,
+            })
+        {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    let item = control_device_value(
+        home_id.clone(),
+        room_id.clone(),
+        device_id,
+        DeviceCommand::SetColorTemperature,
+        4000,
+    )
+    .await
+    .unwrap()
+    .into_inner()
+    .item
+    .unwrap();
+
+    match item.value {
+        Some(ItemValue::LightValue(value)) => assert_eq!(value.color_temperature, 4000),
+        _ => panic!("Expected light value"),
+    }
+
+    delete_home(home_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_device_history() {
+    let home_id = add_home().await;
+    let room_id = add_room(home_id.clone()).await;
+    let device_id = add_device(home_id.clone(), room_id.clone()).await;
+
+    let empty = get_device_history(home_id.clone(), room_id.clone(), device_id.clone(), 0, 0)
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(empty.summary.is_none());
+    assert!(empty.points.is_empty());
+
+    for command in [DeviceCommand::TurnOn, DeviceCommand::TurnOff] {
+        control_device(home_id.clone(), room_id.clone(), device_id.clone(), command)
+            .await
+            .unwrap();
+    }
+
+    let history = get_device_history(home_id.clone(), room_id.clone(), device_id.clone(), 0, 0)
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(history.unit, "W");
+    assert_eq!(history.summary.unwrap().count, 2);
+    assert!(!history.points.is_empty());
+
+    match get_device_history(home_id, room_id, device_id, 10, 5).await {
+        Ok(_) => panic!("Expected error"),
+        Err(err) => assert!(err.code() == tonic::Code::InvalidArgument),
+    };
+}
+
+#[tokio::test]
+async fn test_energy_usage() {
+    let home_id = add_home().await;
+    let room_id = add_room(home_id.clone()).await;
+    let device_id = add_device(home_id.clone(), room_id.clone()).await;
+
+    control_device(
+        home_id.clone(),
+        room_id.clone(),
+        device_id.clone(),
+        DeviceCommand::TurnOn,
+    )
+    .await
+    .unwrap();
+
+    // Розетка, добавленная через API, потребляет 0 Вт
+    for (room_id, device_id) in [
+        (String::new(), String::new()),
+        (room_id.clone(), String::new()),
+        (room_id.clone(), device_id),
+    ] {
+        let usage = get_energy_usage(home_id.clone(), room_id, device_id)
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(usage.total_kwh, 0.0);
+        assert!(usage.daily.is_empty() && usage.monthly.is_empty());
+    }
+
+    match get_energy_usage(home_id, "missing-id".to_string(), String::new()).await {
+        Ok(_) => panic!("Expected error"),
+        Err(err) => assert!(err.code() == tonic::Code::NotFound),
+    };
+}
+
+#[tokio::test]
+async fn test_tariff_and_energy_cost() {
+    let home_id = add_home().await;
+    let room_id = add_room(home_id.clone()).await;
+    let device_id = add_device(home_id.clone(), room_id.clone()).await;
+
+    match get_energy_cost(home_id.clone(), String::new(), String::new()).await {
+        Ok(_) => panic!("Expected error"),
+        Err(err) => assert!(err.code() == tonic::Code::FailedPrecondition),
+    };
+
+    let invalid = Tariff {
+        currency: "rub".to_string(),
+        utc_offset_minutes: 0,
+        kind: Some(TariffKind::Flat(FlatTariff { price: 6.5 })),
+    };
+    match set_tariff(home_id.clone(), Some(invalid)).await {
+        Ok(_) => panic!("Expected error"),
+        Err(err) => assert!(err.code() == tonic::Code::InvalidArgument),
+    };
+
+    let tariff = Tariff {
+        currency: "RUB".to_string(),
+        utc_offset_minutes: 180,
+        kind: Some(TariffKind::DayNight(DayNightTariff {
+            day_price: 6.5,
+            night_price: 3.2,
+            night_start_minute: 23 * 60,
+            night_end_minute: 7 * 60,
+        })),
+    };
+    set_tariff(home_id.clone(), Some(tariff.clone()))
+        .await
+        .unwrap();
+    assert_eq!(get_tariff(home_id.clone()).await.unwrap(), Some(tariff));
+
+    // Розетка, добавленная через API, потребляет 0 Вт
+    for (room_id, device_id) in [
+        (String::new(), String::new()),
+        (room_id.clone(), String::new()),
+        (room_id.clone(), device_id),
+    ] {
+        let cost = get_energy_cost(home_id.clone(), room_id, device_id)
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!((cost.kwh, cost.amount), (0.0, 0.0));
+        assert_eq!(cost.currency, "RUB");
+    }
+
+    set_tariff(home_id.clone(), None).await.unwrap();
+    assert_eq!(get_tariff(home_id).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_automation_rules() {
+    let home_id = add_home().await;
+    let room_id = add_room(home_id.clone()).await;
+    let device_id = add_device(home_id.clone(), room_id.clone()).await;
+    let socket = DeviceRef {
+        room_id: room_id.clone(),
+        device_id: device_id.clone(),
+    };
+
+    // Розетка, добавленная через API, выключена и потребляет 0 Вт
+    let rule = Rule {
+        name: "Включить простаивающую розетку".to_string(),
+        trigger: Some(RuleTrigger {
+            device: Some(socket.clone()),
+            metric: Metric::Power.into(),
+            comparison: Comparison::Below.into(),
+            threshold: 1.0,
+            ..RuleTrigger::default()
+        }),
+        actions: vec![RuleAction {
+            device: Some(socket.clone()),
+            command: DeviceCommand::TurnOn.into(),
+        }],
+        ..Rule::default()
+    };
+
+    let missing = Rule {
+        actions: vec![RuleAction {
+            device: Some(DeviceRef {
+                room_id: room_id.clone(),
+                device_id: "missing-id".to_string(),
+            }),
+            command: DeviceCommand::TurnOff.into(),
+        }],
+        ..rule.clone()
+    };
+    match create_rule(home_id.clone(), missing).await {
+        Ok(_) => panic!("Expected error"),
+        Err(err) => assert!(err.code() == tonic::Code::InvalidArgument),
+    };
+
+    let rule_id = create_rule(home_id.clone(), rule.clone()).await.unwrap();
+    match create_rule(home_id.clone(), rule.clone()).await {
+        Ok(_) => panic!("Expected error"),
+        Err(err) => assert!(err.code() == tonic::Code::AlreadyExists),
+    };
+
+    // Правила проверяются по таймеру раз в секунду
+    let mut log = vec![];
+    for _ in 0..50 {
+        log = get_rule_log(home_id.clone(), rule_id.clone())
+            .await
+            .unwrap();
+        if !log.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(log.len(), 1, "{log:?}");
+    assert_eq!(log[0].results[0].error, "");
+
+    let report = get_report(home_id.clone()).await.unwrap();
+    let device = report.iter().find(|item| item.id == device_id).unwrap();
+    match &device.value {
+        Some(ItemValue::SocketValue(value)) => assert!(value.is_on),
+        _ => panic!("Expected socket value"),
+    }
+
+    let mut rules = list_rules(home_id.clone()).await;
+    assert_eq!(rules.len(), 1);
+    rules[0].disabled = true;
+    update_rule(home_id.clone(), rules[0].clone())
+        .await
+        .unwrap();
+    assert!(list_rules(home_id.clone()).await[0].disabled);
+
+    delete_rule(home_id.clone(), rule_id.clone()).await.unwrap();
+    assert!(list_rules(home_id.clone()).await.is_empty());
+    match delete_rule(home_id, rule_id).await {
+        Ok(_) => panic!("Expected error"),
+        Err(err) => assert!(err.code() == tonic::Code::NotFound),
+    };
+}
+
+#[tokio::test]
+async fn test_schedules() {
+    let home_id = add_home().await;
+    let room_id = add_room(home_id.clone()).await;
+    let device_id = add_device(home_id.clone(), room_id.clone()).await;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+
+    // Однократно включить все розетки комнаты
+    let once = Schedule {
+        name: "Включить комнату".to_string(),
+        when: Some(ScheduleWhen::OnceAt(now + 1500)),
+        actions: vec![ScheduleAction {
+            room_id: room_id.clone(),
+            command: DeviceCommand::TurnOn.into(),
+            ..ScheduleAction::default()
+        }],
+        ..Schedule::default()
+    };
+
+    let past = Schedule {
+        when: Some(ScheduleWhen::OnceAt(now - 1000)),
+        ..once.clone()
+    };
+    match create_schedule(home_id.clone(), past).await {
+        Ok(_) => panic!("Expected error"),
+        Err(err) => assert!(err.code() == tonic::Code::InvalidArgument),
+    };
+
+    let weekdays = Schedule {
+        name: "Обогреватель по будням".to_string(),
+        when: Some(ScheduleWhen::Cron("30 6 * * MON-FRI".to_string())),
+        time_zone: "Europe/Moscow".to_string(),
+        actions: vec![ScheduleAction {
+            room_id: room_id.clone(),
+            device_id: device_id.clone(),
+            command: DeviceCommand::TurnOn.into(),
+        }],
+        ..Schedule::default()
+    };
+    for invalid in [
+        Schedule {
+            time_zone: "Mars/Olympus".to_string(),
+            ..weekdays.clone()
+        },
+        Schedule {
+            when: Some(ScheduleWhen::Cron("30 25 * * *".to_string())),
+            ..weekdays.clone()
+        },
+        Schedule {
+            actions: vec![ScheduleAction {
+                device_id: "missing-id".to_string(),
+                ..weekdays.actions[0].clone()
+            }],
+            ..weekdays.clone()
+        },
+    ] {
+        match create_schedule(home_id.clone(), invalid).await {
+            Ok(_) => panic!("Expected error"),
+            Err(err) => assert!(err.code() == tonic::Code::InvalidArgument),
+        };
+    }
+
+    let once_id = create_schedule(home_id.clone(), once.clone())
+        .await
+        .unwrap();
+    let weekdays_id = create_schedule(home_id.clone(), weekdays.clone())
+        .await
+        .unwrap();
+    match create_schedule(home_id.clone(), weekdays).await {
+        Ok(_) => panic!("Expected error"),
+        Err(err) => assert!(err.code() == tonic::Code::AlreadyExists),
+    };
+
+    // Расписания проверяются по таймеру раз в секунду
+    let mut last_run = 0;
+    for _ in 0..50 {
+        let schedules = list_schedules(home_id.clone()).await;
+        last_run = schedules
+            .iter()
+            .find(|schedule| schedule.id == once_id)
+            .unwrap()
+            .last_run;
+        if last_run != 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(last_run >= now + 1500);
+
+    let report = get_report(home_id.clone()).await.unwrap();
+    let device = report.iter().find(|item| item.id == device_id).unwrap();
+    match &device.value {
+        Some(ItemValue::SocketValue(value)) => assert!(value.is_on),
+        _ => panic!("Expected socket value"),
+    }
+
+    let schedules = list_schedules(home_id.clone()).await;
+    let weekdays = schedules.iter().find(|s| s.id == weekdays_id).unwrap();
+    assert!(weekdays.next_run > now);
+    assert_eq!(
+        schedules.iter().find(|s| s.id == once_id).unwrap().next_run,
+        0
+    );
+
+    pause_schedule(home_id.clone(), weekdays_id.clone(), true)
+        .await
+        .unwrap();
+    let schedules = list_schedules(home_id.clone()).await;
+    let weekdays = schedules.iter().find(|s| s.id == weekdays_id).unwrap();
+    assert!(weekdays.paused);
+    assert_eq!(weekdays.next_run, 0);
+
+    delete_schedule(home_id.clone(), weekdays_id.clone())
+        .await
+        .unwrap();
+    assert_eq!(list_schedules(home_id.clone()).await.len(), 1);
+    match pause_schedule(home_id, weekdays_id, false).await {
+        Ok(_) => panic!("Expected error"),
+        Err(err) => assert!(err.code() == tonic::Code::NotFound),
+    };
+}
+
+#[tokio::test]
+async fn test_scenes() {
+    let home_id = add_home().await;
+    let room_id = add_room(home_id.clone()).await;
+    let first = add_device(home_id.clone(), room_id.clone()).await;
+    let second = add_device(home_id.clone(), room_id.clone()).await;
+
+    let state = |device_id: &str, is_on: bool| SceneState {
+        device: Some(DeviceRef {
+            room_id: room_id.clone(),
+            device_id: device_id.to_string(),
+        }),
+        is_on,
+    };
+
+    let scene = Scene {
+        name: "Все включить".to_string(),
+        states: vec![state(&first, true), state(&second, true)],
+        ..Scene::default()
+    };
+
+    for states in [
+        vec![state("missing-id", true)],
+        vec![state(&first, true), state(&first, false)],
+    ] {
+        let invalid = Scene {
+            states,
+            ..scene.clone()
+        };
+        match create_scene(home_id.clone(), invalid).await {
+            Ok(_) => panic!("Expected error"),
+            Err(err) => assert!(err.code() == tonic::Code::InvalidArgument),
+        };
+    }
+
+    let scene_id = create_scene(home_id.clone(), scene.clone()).await.unwrap();
+    match create_scene(home_id.clone(), scene).await {
+        Ok(_) => panic!("Expected error"),
+        Err(err) => assert!(err.code() == tonic::Code::AlreadyExists),
+    };
+    assert_eq!(list_scenes(home_id.clone()).await[0].states.len(), 2);
+
+    // Устройство удалено после создания сцены: остальные все равно переключаются
+    delete_device(home_id.clone(), room_id.clone(), second.clone())
+        .await
+        .unwrap();
+    let results = apply_scene(home_id.clone(), scene_id.clone())
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].error, "");
+    assert_ne!(results[1].error, "");
+
+    let report = get_report(home_id.clone()).await.unwrap();
+    let device = report.iter().find(|item| item.id == first).unwrap();
+    match &device.value {
+        Some(ItemValue::SocketValue(value)) => assert!(value.is_on),
+        _ => panic!("Expected socket value"),
+    }
+
+    delete_scene(home_id.clone(), scene_id.clone())
+        .await
+        .unwrap();
+    match apply_scene(home_id, scene_id).await {
+        Ok(_) => panic!("Expected error"),
+        Err(err) => assert!(err.code() == tonic::Code::NotFound),
+    };
+}
+
+#[tokio::test]
+async fn test_thermostat() {
+    let home_id = add_home().await;
+    let room_id = add_room(home_id.clone()).await;
+    let heater = add_device(home_id.clone(), room_id.clone()).await;
+    // Посылки с этим серийным номером не приходят, термометр не на связи
+    let thermometer = add_thermometer(
+        home_id.clone(),
+        room_id.clone(),
+        ConnectionSettings {
+            ip: "127.0.0.1".to_string(),
+            port: "4001".to_string(),
+            serial: "thermostat-serial".to_string(),
+            ..ConnectionSettings::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let thermostat = Thermostat {
+        thermometer_id: thermometer.clone(),
+        socket_id: heater.clone(),
+        mode: ThermostatMode::Heat.into(),
+        setpoint: 21.0,
+        hysteresis: 0.5,
+        ..Thermostat::default()
+    };
+
+    match get_thermostat(home_id.clone(), room_id.clone()).await {
+        Ok(_) => panic!("Expected error"),
+        Err(err) => assert!(err.code() == tonic::Code::NotFound),
+    };
+    for invalid in [
+        Thermostat {
+            thermometer_id: heater.clone(),
+            ..thermostat.clone()
+        },
+        Thermostat {
+            mode: ThermostatMode::Unspecified.into(),
+            ..thermostat.clone()
+        },
+    ] {
+        match set_thermostat(home_id.clone(), room_id.clone(), invalid).await {
+            Ok(_) => panic!("Expected error"),
+            Err(err) => assert!(err.code() == tonic::Code::InvalidArgument),
+        };
+    }
+
+    set_thermostat(home_id.clone(), room_id.clone(), thermostat.clone())
+        .await
+        .unwrap();
+
+    // Без показаний термометра обогреватель не включается
+    let status = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let status = get_thermostat(home_id.clone(), room_id.clone())
+                .await
+                .unwrap();
+            if !status.fault.is_empty() {
+                break status;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert!(!status.sensor_online);
+    assert!(!status.output_on);
+    assert_eq!(status.thermostat, Some(thermostat));
+
+    let report = get_report(home_id.clone()).await.unwrap();
+    let room = report.iter().find(|item| item.id == room_id).unwrap();
+    match &room.value {
+        Some(ItemValue::ThermostatValue(value)) => assert!(!value.output_on),
+        _ => panic!("Expected thermostat value"),
+    }
+
+    delete_thermostat(home_id.clone(), room_id.clone())
+        .await
+        .unwrap();
+    match delete_thermostat(home_id, room_id).await {
+        Ok(_) => panic!("Expected error"),
+        Err(err) => assert!(err.code() == tonic::Code::NotFound),
+    };
+}
+
+#[tokio::test]
+async fn test_control_missing_device() {
+    let home_id = add_home().await;
+    let room_id = add_room(home_id.clone()).await;
+
+    match control_device(
+        home_id,
+        room_id,
+        "missing-id".to_string(),
+        DeviceCommand::TurnOff,
+    )
+    .await
+    {
+        Ok(_) => panic!("Expected error"),
+        Err(err) => assert!(err.code() == tonic::Code::NotFound),
+    };
+}
+
+#[tokio::test]
+async fn test_update_settings_without_connection() {
+    let home_id = add_home().await;
+    let room_id = add_room(home_id.clone()).await;
+    let device_id = add_device(home_id.clone(), room_id.clone()).await;
+
+    let settings = ConnectionSettings {
+        poll_interval_ms: 500,
+        ..ConnectionSettings::default()
+    };
+
+    match update_connection_settings(home_id, room_id, device_id, settings).await {
+        Ok(_) => panic!("Expected error"),
+        Err(err) => assert!(err.code() == tonic::Code::FailedPrecondition),
+    };
+}
+
+#[tokio::test]
+async fn test_unassigned_thermometer() {
+    let home_id = add_home().await;
+    let room_id = add_room(home_id.clone()).await;
+
+    // Эмулятор пишет на 4001 со своим серийным номером, поэтому его посылки не назначены
+    let connection = ConnectionSettings {
+        ip: "127.0.0.1".to_string(),
+        port: "4001".to_string(),
+        serial: "unknown-serial".to_string(),
+        ..ConnectionSettings::default()
+    };
+    add_thermometer(home_id.clone(), room_id.clone(), connection.clone())
+        .await
+        .unwrap();
+    // Второй термометр на том же порту не мешает первому
+    add_thermometer(
+        home_id.clone(),
+        room_id.clone(),
+        ConnectionSettings {
+            serial: "another-serial".to_string(),
+            ..connection
+        },
+    )
+    .await
+    .unwrap();
+
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while !list_unassigned_devices()
+            .await
+            .iter()
+            .any(|d| d.listen_addr == "127.0.0.1:4001" && d.device_info.is_some())
+        {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    delete_home(home_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_get_report() {
+    let home_id = add_home().await;
+    let room_id = add_room(home_id.clone()).await;
+    let device_id = add_device(home_id.clone(), room_id.clone()).await;
+    let other_room_id = add_room(home_id.clone()).await;
+
+    let items = get_report(home_id.clone()).await.unwrap();
+
+    assert_eq!(items.len(), 4);
+    assert_eq!(items[0].id, home_id);
+    assert_eq!(items[0].item_type(), ItemType::Home);
+
+    let room = items.iter().find(|i| i.id == room_id).unwrap();
+    assert_eq!(room.item_type(), ItemType::Room);
+    assert_eq!(room.parent_id, home_id);
+
+    let other_room = items.iter().find(|i| i.id == other_room_id).unwrap();
+    assert_eq!(other_room.parent_id, home_id);
+
+    let device = items.iter().find(|i| i.id == device_id).unwrap();
+    assert_eq!(device.item_type(), ItemType::Socket);
+    assert_eq!(device.parent_id, room_id);
+    assert!(matches!(device.value, Some(ItemValue::SocketValue(_))));
+
+    // Устройство идет сразу за своей комнатой
+    let room_pos = items.iter().position(|i| i.id == room_id).unwrap();
+    assert_eq!(items[room_pos + 1].id, device_id);
+}
+
+#[tokio::test]
+async fn test_get_report_is_stable() {
+    let home_id = add_home().await;
+    for _ in 0..3 {
+        let room_id = add_room(home_id.clone()).await;
+        add_device(home_id.clone(), room_id.clone()).await;
+        add_device(home_id.clone(), room_id).await;
+    }
+
+    let first: Vec<String> = get_report(home_id.clone())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|i| i.id)
+        .collect();
+    let second: Vec<String> = get_report(home_id)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|i| i.id)
+        .collect();
+
+    assert_eq!(first, second);
+}
+
+#[tokio::test]
+async fn test_get_report_missing_home() {
+    match get_report("missing-id".to_string()).await {
+        Ok(_) => panic!("Expected error"),
+        Err(err) => assert!(err.code() == tonic::Code::NotFound),
+    };
+}
+
+/// Следующее сообщение потока, `None` - поток завершен
+async fn next_event(stream: &mut Streaming<WatchHomeResponse>) -> Option<WatchHomeResponse> {
+    tokio::time::timeout(Duration::from_secs(5), stream.message())
+        .await
+        .expect("No event in 5 seconds")
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_watch_home() {
+    let home_id = add_home().await;
+    let mut stream = watch_home(home_id.clone()).await.unwrap();
+
+    let room_id = add_room(home_id.clone()).await;
+    let device_id = add_device(home_id.clone(), room_id.clone()).await;
+    control_device(
+        home_id.clone(),
+        room_id.clone(),
+        device_id.clone(),
+        DeviceCommand::TurnOn,
+    )
+    .await
+    .unwrap();
+    delete_device(home_id.clone(), room_id.clone(), device_id.clone())
+        .await
+        .unwrap();
+
+    let event = next_event(&mut stream).await.unwrap();
+    assert_eq!(event.event_type(), HomeEventType::RoomAdded);
+    assert_eq!(event.item.as_ref().unwrap().id, room_id);
+    assert_eq!(event.item.as_ref().unwrap().parent_id, home_id);
+
+    let event = next_event(&mut stream).await.unwrap();
+    assert_eq!(event.event_type(), HomeEventType::DeviceAdded);
+    assert_eq!(event.item.as_ref().unwrap().item_type(), ItemType::Socket);
+
+    let event = next_event(&mut stream).await.unwrap();
+    assert_eq!(event.event_type(), HomeEventType::StateChanged);
+    match event.item.unwrap().value {
+        Some(ItemValue::SocketValue(value)) => assert!(value.is_on),
+        value => panic!("Expected socket value, got {value:?}"),
+    }
+
+    let event = next_event(&mut stream).await.unwrap();
+    assert_eq!(event.event_type(), HomeEventType::DeviceRemoved);
+    assert_eq!(event.item.as_ref().unwrap().id, device_id);
+
+    delete_home(home_id).await.unwrap();
+    assert!(next_event(&mut stream).await.is_none());
+}
+
+#[tokio::test]
+async fn test_watch_home_over_grpc_web() {
+    let home_id = add_home().await;
+    let mut stream = watch_home_web(home_id.clone()).await.unwrap();
+
+    let room_id = add_room(home_id.clone()).await;
+    delete_room(home_id.clone(), room_id.clone()).await.unwrap();
+
+    let event = next_event(&mut stream).await.unwrap();
+    assert_eq!(event.event_type(), HomeEventType::RoomAdded);
+    assert_eq!(event.item.unwrap().id, room_id);
+
+    let event = next_event(&mut stream).await.unwrap();
+    assert_eq!(event.event_type(), HomeEventType::RoomRemoved);
+
+    delete_home(home_id).await.unwrap();
+    assert!(next_event(&mut stream).await.is_none());
+}
+
+#[tokio::test]
+async fn test_watch_missing_home() {
+    match watch_home("missing-id".to_string()).await {
+        Ok(_) => panic!("Expected error"),
+        Err(err) => assert_eq!(err.code(), tonic::Code::NotFound),
+    };
+}